@@ -0,0 +1,53 @@
+/*
+ * C API for the zbusctl library, built with the `ffi` feature:
+ *
+ *     cargo rustc --release --lib --features ffi --crate-type cdylib
+ *
+ * Requests and responses are JSON strings in UTF-8.
+ */
+
+#ifndef ZBUSCTL_H
+#define ZBUSCTL_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/*
+ * Make a D-Bus method call and wait for the reply. `request` is a JSON
+ * object:
+ *
+ *     {
+ *         "service": "org.example.Test",
+ *         "object": "/org/example/Test",
+ *         "interface": "org.example.Test",
+ *         "method": "Echo",
+ *         "args": ["string:hello", "int32:42"],   (optional)
+ *         "system": false,                        (optional, default session)
+ *         "timeout": 5.0,                         (optional, in seconds)
+ *         "no_redact": false                      (optional)
+ *     }
+ *
+ * with `args` in zbusctl's `type:value` syntax. The redaction rules of the
+ * project in the current directory are applied to the reply, unless
+ * `no_redact` is true. The response is either
+ *
+ *     {"reply": [{"signature": "s", "value": "hello"}, ...]}
+ *
+ * or
+ *
+ *     {"error": "message"}
+ *
+ * and must be released with zbusctl_free(). Calls may be made from any
+ * thread.
+ */
+char *zbusctl_call(const char *request);
+
+/* Release a response returned by zbusctl_call(). */
+void zbusctl_free(char *response);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* ZBUSCTL_H */
@@ -0,0 +1,126 @@
+use zbus_xml::Method;
+
+/// The annotation with which a method names the polkit action that guards it.
+pub const POLKIT_ACTION: &str = "org.freedesktop.policykit.action_id";
+
+/// How to find out whether the current user may call a method, without
+/// doing anything the method would do.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Probe {
+    /// Call it: it only reads.
+    Call,
+    /// Ask polkit about the action it is annotated with.
+    Polkit(String),
+    /// Leave it alone: calling it could change something.
+    Skip,
+}
+
+// The method names that, by convention, only read state. `CanPowerOff` and
+// the like only report whether something could be done.
+const READ_ONLY_PREFIXES: &[&str] = &["Get", "List", "Is", "Can"];
+
+// Choose how to probe a method. An annotated polkit action is asked about
+// rather than calling the method, since polkit answers without side effects
+// for any method; methods listed in `safe` are called whatever their name.
+pub fn choose_probe(method: &Method, safe: &[String]) -> Probe {
+    if let Some(annotation) = method
+        .annotations()
+        .iter()
+        .find(|annotation| annotation.name() == POLKIT_ACTION)
+    {
+        return Probe::Polkit(annotation.value().to_string());
+    }
+    let name = method.name();
+    if safe.iter().any(|member| *member == name.as_str()) || is_read_only(name.as_str()) {
+        Probe::Call
+    } else {
+        Probe::Skip
+    }
+}
+
+fn is_read_only(name: &str) -> bool {
+    READ_ONLY_PREFIXES.iter().any(|prefix| {
+        name.strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_uppercase))
+    })
+}
+
+// What an error reply to a probing call says about access: `denied` or
+// `auth-required` for the errors buses, services and polkit refuse calls
+// with, `failed` for any other, which leaves the question open since the
+// call may have been refused for its made-up arguments before access was
+// checked.
+pub fn access_from_error(name: &str) -> &'static str {
+    match name {
+        "org.freedesktop.DBus.Error.InteractiveAuthorizationRequired" => "auth-required",
+        "org.freedesktop.DBus.Error.AccessDenied"
+        | "org.freedesktop.DBus.Error.AuthFailed"
+        | "org.freedesktop.PolicyKit1.Error.NotAuthorized" => "denied",
+        name if name.ends_with(".PermissionDenied") || name.ends_with(".NotAuthorized") => "denied",
+        _ => "failed",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zbus_xml::Node;
+
+    fn methods(xml: &str) -> Node<'static> {
+        Node::from_reader(xml.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_choose_probe() {
+        let node = methods(
+            r#"<node><interface name="org.example.Test">
+                <method name="GetName"/>
+                <method name="CanReboot"/>
+                <method name="Getaway"/>
+                <method name="Reboot">
+                    <annotation name="org.freedesktop.policykit.action_id" value="org.example.reboot"/>
+                </method>
+                <method name="Reset"/>
+                <method name="Echo"/>
+            </interface></node>"#,
+        );
+        let probes = node.interfaces()[0]
+            .methods()
+            .iter()
+            .map(|method| choose_probe(method, &["Echo".to_string()]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            probes,
+            vec![
+                Probe::Call,
+                Probe::Call,
+                Probe::Skip,
+                Probe::Polkit("org.example.reboot".to_string()),
+                Probe::Skip,
+                Probe::Call,
+            ],
+            "Only read-only, annotated and listed methods should be probed"
+        );
+    }
+
+    #[test]
+    fn test_access_from_error() {
+        assert_eq!(
+            access_from_error("org.freedesktop.DBus.Error.AccessDenied"),
+            "denied"
+        );
+        assert_eq!(
+            access_from_error("org.freedesktop.systemd1.PermissionDenied"),
+            "denied"
+        );
+        assert_eq!(
+            access_from_error("org.freedesktop.DBus.Error.InteractiveAuthorizationRequired"),
+            "auth-required"
+        );
+        assert_eq!(
+            access_from_error("org.freedesktop.DBus.Error.InvalidArgs"),
+            "failed",
+            "Other errors should say nothing about access"
+        );
+    }
+}
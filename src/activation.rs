@@ -0,0 +1,349 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use zbus::Result;
+use zbus::names::WellKnownName;
+
+/// A parsed D-Bus activation `.service` file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ServiceFile {
+    pub path: PathBuf,
+    pub name: Option<String>,
+    pub exec: Option<String>,
+    pub user: Option<String>,
+    pub systemd_service: Option<String>,
+}
+
+impl ServiceFile {
+    // Parse the contents of a service file. Only keys in the `[D-BUS Service]`
+    // group are read; comments, blank lines and other groups are ignored.
+    pub fn parse(path: &Path, contents: &str) -> Result<Self> {
+        let mut service = ServiceFile {
+            path: path.to_path_buf(),
+            ..Default::default()
+        };
+        let mut in_service_group = false;
+
+        for (lineno, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with('[') && line.ends_with(']') {
+                in_service_group = line == "[D-BUS Service]";
+                continue;
+            }
+            if !in_service_group {
+                continue;
+            }
+
+            let (key, value) = line.split_once('=').ok_or_else(|| {
+                zbus::Error::Failure(format!(
+                    "Invalid line {} in '{}': expected <key>=<value>",
+                    lineno + 1,
+                    path.display()
+                ))
+            })?;
+            let value = Some(value.trim().to_string());
+            match key.trim() {
+                "Name" => service.name = value,
+                "Exec" => service.exec = value,
+                "User" => service.user = value,
+                "SystemdService" => service.systemd_service = value,
+                _ => {}
+            }
+        }
+
+        Ok(service)
+    }
+
+    // Read and parse a service file from disk.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path).map_err(|e| {
+            zbus::Error::Failure(format!("Failed to read '{}': {}", path.display(), e))
+        })?;
+        Self::parse(path, &contents)
+    }
+}
+
+// The standard directories searched for activation files, in priority order,
+// following the D-Bus specification.
+pub fn service_dirs(system: bool) -> Vec<PathBuf> {
+    if system {
+        return [
+            "/usr/local/share/dbus-1/system-services",
+            "/usr/share/dbus-1/system-services",
+            "/lib/dbus-1/system-services",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+    }
+
+    let mut dirs = Vec::new();
+    if let Some(runtime) = env::var_os("XDG_RUNTIME_DIR") {
+        dirs.push(PathBuf::from(runtime).join("dbus-1/services"));
+    }
+    match env::var_os("XDG_DATA_HOME") {
+        Some(data_home) => dirs.push(PathBuf::from(data_home).join("dbus-1/services")),
+        None => {
+            if let Some(home) = env::var_os("HOME") {
+                dirs.push(PathBuf::from(home).join(".local/share/dbus-1/services"));
+            }
+        }
+    }
+    let data_dirs = env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+    for dir in data_dirs.split(':').filter(|dir| !dir.is_empty()) {
+        dirs.push(PathBuf::from(dir).join("dbus-1/services"));
+    }
+    dirs.push(PathBuf::from("/usr/share/dbus-1/services"));
+    dirs.dedup();
+    dirs
+}
+
+// Load every `.service` file from the given directories. Missing directories
+// are skipped; files that fail to parse are returned as errors alongside the
+// successfully parsed ones so callers can decide how to report them.
+pub fn load_service_files(dirs: &[PathBuf]) -> (Vec<ServiceFile>, Vec<zbus::Error>) {
+    let mut services = Vec::new();
+    let mut errors = Vec::new();
+
+    for dir in dirs {
+        let Ok(entries) = fs::read_dir(dir) else {
+            continue;
+        };
+        let mut paths = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "service"))
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        for path in paths {
+            match ServiceFile::load(&path) {
+                Ok(service) => services.push(service),
+                Err(e) => errors.push(e),
+            }
+        }
+    }
+
+    (services, errors)
+}
+
+/// Something wrong with an activation file, or with how the bus sees the
+/// activation files. Errors stop the service from being activated; warnings
+/// are likely mistakes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Problem {
+    /// The file the problem is in, if it is in one.
+    pub file: Option<PathBuf>,
+    pub error: bool,
+    pub message: String,
+}
+
+// Check service files, given in priority order, against the D-Bus
+// specification, and against the names the bus says it can activate when
+// those are known.
+pub fn lint_service_files(
+    services: &[ServiceFile],
+    system: bool,
+    activatable: Option<&BTreeSet<String>>,
+) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut first_with_name = BTreeMap::<String, PathBuf>::new();
+    for service in services {
+        let mut problem = |error: bool, message: String| {
+            problems.push(Problem {
+                file: Some(service.path.clone()),
+                error,
+                message,
+            })
+        };
+
+        match service.name {
+            None => problem(true, "missing Name".to_string()),
+            Some(ref name) => {
+                if let Err(e) = WellKnownName::try_from(name.as_str()) {
+                    problem(true, format!("invalid Name '{}': {}", name, e));
+                }
+                // The system bus finds files by name; the session bus only
+                // recommends it.
+                let expected = format!("{}.service", name);
+                if service.path.file_name().is_some_and(|f| *f != *expected) {
+                    problem(
+                        system,
+                        format!("file name does not match Name, expected '{}'", expected),
+                    );
+                }
+                match first_with_name.get(name) {
+                    Some(first) => problem(
+                        false,
+                        format!(
+                            "Name '{}' is also in '{}', which takes precedence",
+                            name,
+                            first.display()
+                        ),
+                    ),
+                    None => {
+                        first_with_name.insert(name.clone(), service.path.clone());
+                    }
+                }
+            }
+        }
+
+        match (&service.exec, &service.systemd_service) {
+            (None, None) => problem(true, "missing Exec and SystemdService".to_string()),
+            (None, Some(_)) if system => problem(
+                true,
+                "missing Exec, which the system bus requires even with SystemdService (use Exec=/bin/false)"
+                    .to_string(),
+            ),
+            (Some(exec), _) => {
+                let program = exec.split_whitespace().next().unwrap_or_default();
+                if program.starts_with('/') && !Path::new(program).exists() {
+                    problem(false, format!("Exec program '{}' does not exist", program));
+                }
+            }
+            _ => {}
+        }
+        if system && service.user.is_none() {
+            problem(
+                true,
+                "missing User, which the system bus requires".to_string(),
+            );
+        }
+    }
+
+    if let Some(activatable) = activatable {
+        for (name, file) in &first_with_name {
+            if !activatable.contains(name) {
+                problems.push(Problem {
+                    file: Some(file.clone()),
+                    error: false,
+                    message: format!(
+                        "the bus cannot activate '{}'; it may need to reload its configuration",
+                        name
+                    ),
+                });
+            }
+        }
+        for name in activatable {
+            if name != "org.freedesktop.DBus" && !first_with_name.contains_key(name) {
+                problems.push(Problem {
+                    file: None,
+                    error: false,
+                    message: format!(
+                        "the bus can activate '{}', but no file in the standard directories names it",
+                        name
+                    ),
+                });
+            }
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_service_file() {
+        let contents = "# comment\n\
+                        [D-BUS Service]\n\
+                        Name=org.example.Foo\n\
+                        Exec=/usr/libexec/foo --daemon\n\
+                        User=root\n\
+                        SystemdService=dbus-org.example.Foo.service\n";
+        let service = ServiceFile::parse(Path::new("foo.service"), contents).unwrap();
+        assert_eq!(service.name.as_deref(), Some("org.example.Foo"));
+        assert_eq!(service.exec.as_deref(), Some("/usr/libexec/foo --daemon"));
+        assert_eq!(service.user.as_deref(), Some("root"));
+        assert_eq!(
+            service.systemd_service.as_deref(),
+            Some("dbus-org.example.Foo.service")
+        );
+    }
+
+    #[test]
+    fn test_parse_service_file_ignores_other_groups() {
+        let contents = "[Other]\nName=wrong\n[D-BUS Service]\nName=org.example.Foo\n";
+        let service = ServiceFile::parse(Path::new("foo.service"), contents).unwrap();
+        assert_eq!(service.name.as_deref(), Some("org.example.Foo"));
+        assert_eq!(service.exec, None);
+    }
+
+    #[test]
+    fn test_parse_service_file_invalid_line() {
+        let contents = "[D-BUS Service]\nName\n";
+        let result = ServiceFile::parse(Path::new("foo.service"), contents);
+        assert!(result.is_err(), "Line without '=' should fail");
+    }
+
+    fn service(path: &str, name: Option<&str>, exec: Option<&str>) -> ServiceFile {
+        ServiceFile {
+            path: PathBuf::from(path),
+            name: name.map(str::to_string),
+            exec: exec.map(str::to_string),
+            user: Some("root".to_string()),
+            systemd_service: None,
+        }
+    }
+
+    #[test]
+    fn test_lint_service_files() {
+        let services = [
+            service(
+                "/a/org.example.A.service",
+                Some("org.example.A"),
+                Some("/bin/sh"),
+            ),
+            service("/a/wrong.service", Some("org.example.B"), Some("/bin/sh")),
+            service("/b/org.example.A.service", Some("org.example.A"), None),
+            service("/b/nameless.service", None, Some("/no/such/program -x")),
+        ];
+        let activatable =
+            BTreeSet::from(["org.example.A".to_string(), "org.example.C".to_string()]);
+
+        let problems = lint_service_files(&services, true, Some(&activatable))
+            .into_iter()
+            .map(|p| {
+                let file = p.file.map(|f| f.display().to_string());
+                (file.unwrap_or_default(), p.error, p.message)
+            })
+            .collect::<Vec<_>>();
+        let messages = problems
+            .iter()
+            .map(|(file, error, message)| format!("{} {} {}", file, error, message))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                "/a/wrong.service true file name does not match Name, expected 'org.example.B.service'",
+                "/b/org.example.A.service false Name 'org.example.A' is also in '/a/org.example.A.service', which takes precedence",
+                "/b/org.example.A.service true missing Exec and SystemdService",
+                "/b/nameless.service true missing Name",
+                "/b/nameless.service false Exec program '/no/such/program' does not exist",
+                "/a/wrong.service false the bus cannot activate 'org.example.B'; it may need to reload its configuration",
+                " false the bus can activate 'org.example.C', but no file in the standard directories names it",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lint_session_service_files() {
+        let services = [ServiceFile {
+            user: None,
+            ..service("/a/other.service", Some("org.example.A"), Some("/bin/sh"))
+        }];
+        let problems = lint_service_files(&services, false, None);
+        assert_eq!(problems.len(), 1);
+        assert!(
+            !problems[0].error,
+            "A file name mismatch should only be a warning on the session bus"
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use crate::events::{MessageEvent, MessageType};
+use crate::redact::glob_match;
+use serde::Serialize;
+use std::str::FromStr;
+
+/// A kind of method call worth flagging when auditing the bus, such as one
+/// that powers the machine off or formats a disk. Names may contain `*`,
+/// which stands for any run of characters. Calls that name no interface are
+/// matched by member alone, as the service may still dispatch them to the
+/// interface the rule is about.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct AuditRule {
+    pub interface: String,
+    pub member: String,
+    /// Why calls matching the rule matter, shown with each one flagged.
+    pub reason: String,
+}
+
+impl AuditRule {
+    pub fn new(interface: &str, member: &str, reason: &str) -> Self {
+        Self {
+            interface: interface.to_string(),
+            member: member.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+
+    pub fn matches(&self, event: &MessageEvent) -> bool {
+        event.message_type == MessageType::MethodCall
+            && event
+                .interface
+                .as_deref()
+                .is_none_or(|interface| glob_match(&self.interface, interface))
+            && event
+                .member
+                .as_deref()
+                .is_some_and(|member| glob_match(&self.member, member))
+    }
+}
+
+impl FromStr for AuditRule {
+    type Err = String;
+
+    // `INTERFACE.MEMBER`, optionally followed by `=REASON`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, reason) = match s.split_once('=') {
+            Some((name, reason)) => (name, reason.trim()),
+            None => (s, ""),
+        };
+        match name.rsplit_once('.') {
+            Some((interface, member)) if !interface.is_empty() && !member.is_empty() => {
+                let reason = match reason {
+                    "" => format!("matches the audit rule {}", name),
+                    reason => reason.to_string(),
+                };
+                Ok(Self::new(interface, member, &reason))
+            }
+            _ => Err(format!(
+                "Invalid audit rule '{}': expected INTERFACE.MEMBER or INTERFACE.MEMBER=REASON",
+                s
+            )),
+        }
+    }
+}
+
+/// The security-relevant calls `monitor --audit` flags out of the box.
+pub fn builtin_rules() -> Vec<AuditRule> {
+    vec![
+        AuditRule::new(
+            "org.freedesktop.PolicyKit1.Authority",
+            "RegisterAuthenticationAgent*",
+            "registers a polkit authentication agent, which answers authentication requests",
+        ),
+        AuditRule::new(
+            "org.freedesktop.PolicyKit1.Authority",
+            "AuthenticationAgentResponse*",
+            "answers a polkit authentication request, which grants the action when accepted",
+        ),
+        AuditRule::new(
+            "org.freedesktop.systemd1.Manager",
+            "StartTransientUnit",
+            "starts a transient systemd unit, which can run any command",
+        ),
+        AuditRule::new(
+            "org.freedesktop.login1.Manager",
+            "PowerOff",
+            "powers the machine off",
+        ),
+        AuditRule::new(
+            "org.freedesktop.login1.Manager",
+            "Reboot",
+            "reboots the machine",
+        ),
+        AuditRule::new(
+            "org.freedesktop.login1.Manager",
+            "Halt",
+            "halts the machine",
+        ),
+        AuditRule::new(
+            "org.freedesktop.login1.Manager",
+            "KExec",
+            "boots another kernel",
+        ),
+        AuditRule::new(
+            "org.freedesktop.UDisks2.Block",
+            "Format",
+            "formats a block device, destroying its data",
+        ),
+    ]
+}
+
+/// Flags the calls in a stream of messages that match the built-in audit
+/// rules or rules of the user's own.
+#[derive(Debug)]
+pub struct Auditor {
+    rules: Vec<AuditRule>,
+}
+
+impl Auditor {
+    // The built-in rules, followed by `rules`.
+    pub fn new(rules: Vec<AuditRule>) -> Self {
+        Self {
+            rules: builtin_rules().into_iter().chain(rules).collect(),
+        }
+    }
+
+    // The first rule the message matches, if any.
+    pub fn check(&self, event: &MessageEvent) -> Option<&AuditRule> {
+        self.rules.iter().find(|rule| rule.matches(event))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zbus::message::Message;
+
+    fn call(interface: Option<&str>, member: &str) -> MessageEvent {
+        let builder = Message::method_call("/org/freedesktop/login1", member).unwrap();
+        let builder = match interface {
+            Some(interface) => builder.interface(interface).unwrap(),
+            None => builder,
+        };
+        MessageEvent::new(&builder.build(&()).unwrap())
+    }
+
+    #[test]
+    fn test_builtin_rules() {
+        let auditor = Auditor::new(Vec::new());
+        let flagged = auditor
+            .check(&call(Some("org.freedesktop.login1.Manager"), "PowerOff"))
+            .unwrap();
+        assert_eq!(flagged.reason, "powers the machine off");
+        assert!(
+            auditor
+                .check(&call(
+                    Some("org.freedesktop.PolicyKit1.Authority"),
+                    "AuthenticationAgentResponse2"
+                ))
+                .is_some()
+        );
+        assert!(
+            auditor.check(&call(None, "PowerOff")).is_some(),
+            "Calls without an interface should be matched by member"
+        );
+        assert!(
+            auditor
+                .check(&call(
+                    Some("org.freedesktop.login1.Manager"),
+                    "ListSessions"
+                ))
+                .is_none()
+        );
+
+        let signal = Message::signal(
+            "/org/freedesktop/login1",
+            "org.freedesktop.login1.Manager",
+            "PowerOff",
+        )
+        .unwrap()
+        .build(&())
+        .unwrap();
+        assert!(
+            auditor.check(&MessageEvent::new(&signal)).is_none(),
+            "Only method calls should be flagged"
+        );
+    }
+
+    #[test]
+    fn test_user_rules() {
+        let rule: AuditRule = "org.example.Admin.*=touches the admin service"
+            .parse()
+            .unwrap();
+        assert_eq!(rule.interface, "org.example.Admin");
+        assert_eq!(rule.member, "*");
+        assert_eq!(rule.reason, "touches the admin service");
+        let auditor = Auditor::new(vec![rule]);
+        assert!(
+            auditor
+                .check(&call(Some("org.example.Admin"), "Wipe"))
+                .is_some()
+        );
+
+        let rule: AuditRule = "org.example.Admin.Wipe".parse().unwrap();
+        assert_eq!(rule.reason, "matches the audit rule org.example.Admin.Wipe");
+        for bad in ["Wipe", ".Wipe", "org.example.Admin.", "=reason"] {
+            assert!(
+                bad.parse::<AuditRule>().is_err(),
+                "{} should be rejected",
+                bad
+            );
+        }
+    }
+}
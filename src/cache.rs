@@ -0,0 +1,233 @@
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::fs;
+use std::future::Future;
+use std::io::Write;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use zbus::Result;
+use zvariant::serialized::{Context, Data};
+use zvariant::{LE, Type};
+
+/// Replies to read-only requests, such as introspection and `GetAll`, kept
+/// on disk for a while so scripts polling the same data don't send the same
+/// request over and over. Each reply is one file in `dir`, named after the
+/// request and marshaled the way it came over the bus; it is fresh for `ttl`
+/// after it was written, and deleted once it is found to be older. Replies
+/// may hold secrets, so only their owner may read them.
+#[derive(Clone, Debug)]
+pub struct Cache {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+impl Cache {
+    pub fn new(dir: impl Into<PathBuf>, ttl: Duration) -> Self {
+        Self {
+            dir: dir.into(),
+            ttl,
+        }
+    }
+
+    // The part of the cache for one of several buses (or other scopes whose
+    // replies must not mix), kept in a subdirectory of its own.
+    pub fn scoped(&self, scope: &str) -> Self {
+        Self {
+            dir: self.dir.join(file_name(scope)),
+            ttl: self.ttl,
+        }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(file_name(key))
+    }
+
+    // The cached reply to a request, unless there is none or it has expired.
+    // A reply that cannot be read back is treated as missing.
+    pub fn get<T: DeserializeOwned + Type>(&self, key: &str) -> Option<T> {
+        let path = self.path(key);
+        if self.expired(&path)? {
+            let _ = fs::remove_file(&path);
+            return None;
+        }
+        let data = Data::new(fs::read(&path).ok()?, context());
+        data.deserialize().ok().map(|(value, _)| value)
+    }
+
+    // Whether the file at `path` is older than the time to live, or `None` if
+    // there is no such file.
+    fn expired(&self, path: &Path) -> Option<bool> {
+        let modified = fs::metadata(path).ok()?.modified().ok()?;
+        // A file from the future is as good as new.
+        Some(modified.elapsed().is_ok_and(|age| age > self.ttl))
+    }
+
+    // Store the reply to a request, replacing the file in one step so that
+    // concurrent readers never see half of it. Expired replies to other
+    // requests are deleted along the way, so the cache does not grow with
+    // every request ever made.
+    pub fn put<T: Serialize + Type>(&self, key: &str, value: &T) -> Result<()> {
+        let failed = |e: std::io::Error| {
+            zbus::Error::Failure(format!(
+                "Failed to write the cache in '{}': {}",
+                self.dir.display(),
+                e
+            ))
+        };
+        let data = zvariant::to_bytes(context(), value)?;
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&self.dir)
+            .map_err(failed)?;
+        self.prune();
+        let path = self.path(key);
+        let partial = self
+            .dir
+            .join(format!("{}.tmp{}", file_name(key), std::process::id()));
+        fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&partial)
+            .and_then(|mut file| file.write_all(data.bytes()))
+            .map_err(failed)?;
+        fs::rename(&partial, &path).map_err(failed)
+    }
+
+    // Delete the expired files in the cache, including any left half
+    // written. Files that cannot be deleted are left for next time.
+    fn prune(&self) {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return;
+        };
+        for path in entries.flatten().map(|entry| entry.path()) {
+            if path.is_file() && self.expired(&path) == Some(true) {
+                let _ = fs::remove_file(&path);
+            }
+        }
+    }
+
+    // Forget the reply to a request, once a change has made it stale.
+    pub fn remove(&self, key: &str) -> Result<()> {
+        match fs::remove_file(self.path(key)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(zbus::Error::Failure(format!(
+                    "Failed to update the cache in '{}': {}",
+                    self.dir.display(),
+                    e
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // The cached reply to a request, or the result of making it, which is
+    // cached if it succeeds. Failing to write the cache does not fail the
+    // request.
+    pub async fn get_or_fetch<T, F>(&self, key: &str, fetch: F) -> Result<T>
+    where
+        T: Serialize + DeserializeOwned + Type,
+        F: Future<Output = Result<T>>,
+    {
+        if let Some(value) = self.get(key) {
+            return Ok(value);
+        }
+        let value = fetch.await?;
+        let _ = self.put(key, &value);
+        Ok(value)
+    }
+}
+
+fn context() -> Context {
+    Context::new_dbus(LE, 0)
+}
+
+// Make a string safe to use as a file name, and distinct from every other
+// string: anything but ASCII letters, digits, `.`, `_` and `-` is written as
+// `%XX`.
+fn file_name(key: &str) -> String {
+    key.bytes()
+        .map(|byte| match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'.' | b'_' | b'-' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_lite::future::block_on;
+    use std::cell::Cell;
+    use std::collections::HashMap;
+    use std::os::unix::fs::PermissionsExt;
+    use zvariant::OwnedValue;
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(
+            file_name("org.example.Test /org/example Introspect"),
+            "org.example.Test%20%2Forg%2Fexample%20Introspect"
+        );
+        assert_ne!(
+            file_name("a/b"),
+            file_name("a%2Fb"),
+            "Escaped keys should not collide"
+        );
+    }
+
+    #[test]
+    fn test_get_or_fetch() {
+        let dir = std::env::temp_dir().join(format!("zbusctl-cache-{}", std::process::id()));
+        let fetches = Cell::new(0);
+        let fetch = || async {
+            fetches.set(fetches.get() + 1);
+            let mut values = HashMap::new();
+            values.insert("Count".to_string(), OwnedValue::from(7u32));
+            Ok(values)
+        };
+
+        let cache = Cache::new(&dir, Duration::from_secs(60));
+        let first: HashMap<String, OwnedValue> =
+            block_on(cache.get_or_fetch("GetAll", fetch())).unwrap();
+        let second: HashMap<String, OwnedValue> =
+            block_on(cache.get_or_fetch("GetAll", fetch())).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(fetches.get(), 1, "A fresh reply should come from the cache");
+
+        let expired = Cache::new(&dir, Duration::ZERO);
+        std::thread::sleep(Duration::from_millis(10));
+        let _: HashMap<String, OwnedValue> =
+            block_on(expired.get_or_fetch("GetAll", fetch())).unwrap();
+        assert_eq!(fetches.get(), 2, "An expired reply should be fetched again");
+
+        cache.remove("GetAll").unwrap();
+        let _: HashMap<String, OwnedValue> =
+            block_on(cache.get_or_fetch("GetAll", fetch())).unwrap();
+        assert_eq!(fetches.get(), 3, "A removed reply should be fetched again");
+        cache.remove("Missing").unwrap();
+
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&dir), 0o700);
+        assert_eq!(
+            mode(&cache.path("GetAll")),
+            0o600,
+            "Cached replies should only be readable by their owner"
+        );
+
+        std::thread::sleep(Duration::from_millis(10));
+        expired.put("Introspect", &"<node/>").unwrap();
+        assert!(
+            !cache.path("GetAll").exists(),
+            "Expired replies should be deleted"
+        );
+        assert!(cache.path("Introspect").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,175 @@
+use zbus::Result;
+use zvariant::serialized::{Context, Data, Format};
+use zvariant::{Endian, OwnedStructure, Signature, Structure};
+
+/// Byte order of marshaled data.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ByteOrder {
+    #[default]
+    Little,
+    Big,
+}
+
+/// Serialization format of marshaled data.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Framing {
+    /// The D-Bus wire format.
+    #[default]
+    Dbus,
+    /// The GVariant format, as used by GLib and ostree.
+    Gvariant,
+}
+
+/// How raw bodies are laid out when encoding or decoding them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Encoding {
+    pub byte_order: ByteOrder,
+    pub framing: Framing,
+}
+
+impl Encoding {
+    fn context(&self) -> Context {
+        let endian = match self.byte_order {
+            ByteOrder::Little => Endian::Little,
+            ByteOrder::Big => Endian::Big,
+        };
+        let format = match self.framing {
+            Framing::Dbus => Format::DBus,
+            Framing::Gvariant => Format::GVariant,
+        };
+        Context::new(format, endian, 0)
+    }
+}
+
+// Parse a body signature such as "a{sv}" or "su", which may hold several
+// complete types, into the structure signature used to (de)serialize it.
+pub fn body_signature(signature: &str) -> Result<Signature> {
+    let signature = Signature::try_from(format!("({})", signature).as_str())
+        .map_err(|e| zbus::Error::Failure(format!("Invalid signature '{}': {}", signature, e)))?;
+    if signature == Signature::structure(Vec::<Signature>::new()) {
+        return Err(zbus::Error::Failure(
+            "Signature must contain at least one type".to_string(),
+        ));
+    }
+    Ok(signature)
+}
+
+// Unmarshal a raw message body with the given body signature. Returns the
+// decoded fields and the number of bytes consumed, which may be fewer than
+// were supplied if the data has trailing garbage.
+pub fn decode(
+    bytes: Vec<u8>,
+    signature: &str,
+    encoding: Encoding,
+) -> Result<(Structure<'static>, usize)> {
+    let signature = body_signature(signature)?;
+    let data = Data::new(bytes, encoding.context());
+    let (structure, consumed) =
+        data.deserialize_for_dynamic_signature::<_, OwnedStructure>(&signature)?;
+    Ok((structure.0, consumed))
+}
+
+// Marshal a body built by `build_body`. If a body signature is given, the
+// body's actual signature must match it.
+pub fn encode(body: &Structure, signature: Option<&str>, encoding: Encoding) -> Result<Vec<u8>> {
+    if let Some(signature) = signature {
+        let expected = body_signature(signature)?;
+        if *body.signature() != expected {
+            return Err(zbus::Error::Failure(format!(
+                "Arguments have signature '{}', expected '{}'",
+                body.signature().to_string_no_parens(),
+                expected.to_string_no_parens()
+            )));
+        }
+    }
+
+    let data = zvariant::to_bytes(encoding.context(), body)?;
+    Ok(data.bytes().to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zvariant::{LE, Value};
+
+    #[test]
+    fn test_decode_multiple_fields() {
+        let data = zvariant::to_bytes(Context::new_dbus(LE, 0), &("hello", 7u32)).unwrap();
+        let (structure, consumed) =
+            decode(data.bytes().to_vec(), "su", Encoding::default()).unwrap();
+        assert_eq!(consumed, data.len());
+        assert_eq!(
+            structure.fields(),
+            &[Value::from("hello"), Value::U32(7)][..]
+        );
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        let body = crate::build_body(vec!["string:hello", "array:int32:1,2,3"]).unwrap();
+        let bytes = encode(&body, Some("sai"), Encoding::default()).unwrap();
+        let (structure, consumed) = decode(bytes.clone(), "sai", Encoding::default()).unwrap();
+        assert_eq!(consumed, bytes.len());
+        assert_eq!(structure, body);
+    }
+
+    #[test]
+    fn test_encode_big_endian() {
+        let body = crate::build_body(vec!["uint32:1"]).unwrap();
+        let encoding = Encoding {
+            byte_order: ByteOrder::Big,
+            framing: Framing::Dbus,
+        };
+        assert_eq!(encode(&body, None, encoding).unwrap(), vec![0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn test_gvariant_round_trip() {
+        let body = crate::build_body(vec!["string:hello", "uint32:7"]).unwrap();
+        let encoding = Encoding {
+            byte_order: ByteOrder::Little,
+            framing: Framing::Gvariant,
+        };
+        let bytes = encode(&body, None, encoding).unwrap();
+        // GVariant strings are not length-prefixed, unlike D-Bus ones.
+        assert!(bytes.starts_with(b"hello\0"));
+        let (structure, _) = decode(bytes, "su", encoding).unwrap();
+        assert_eq!(structure, body);
+    }
+
+    #[test]
+    fn test_encode_dict_order() {
+        let encoded = |arg: &str| {
+            let body = crate::build_body(vec![arg]).unwrap();
+            encode(&body, None, Encoding::default()).unwrap()
+        };
+        assert_eq!(
+            encoded("dict:string:int32:z,1,a,2,m,3"),
+            encoded("dict:string:int32:m,3,z,1,a,2"),
+            "Dictionary entries should be marshaled in key order"
+        );
+
+        let body = crate::build_body(vec!["dict:string:int32:z,1,a,2,m,3"]).unwrap();
+        let json = crate::format::value_to_json(&body.fields()[0], Default::default()).unwrap();
+        let keys = json.as_object().unwrap().keys().collect::<Vec<_>>();
+        assert_eq!(keys, vec!["a", "m", "z"]);
+    }
+
+    #[test]
+    fn test_encode_signature_mismatch() {
+        let body = crate::build_body(vec!["string:hello"]).unwrap();
+        assert!(encode(&body, Some("u"), Encoding::default()).is_err());
+    }
+
+    #[test]
+    fn test_decode_invalid_signature() {
+        assert!(decode(vec![0; 4], "a{", Encoding::default()).is_err());
+        assert!(decode(vec![0; 4], "", Encoding::default()).is_err());
+    }
+
+    #[test]
+    fn test_decode_short_data() {
+        let result = decode(vec![1, 0], "u", Encoding::default());
+        assert!(result.is_err(), "Decoding a truncated uint32 should fail");
+    }
+}
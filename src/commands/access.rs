@@ -0,0 +1,242 @@
+use super::introspect::{Source, WalkArgs, walk};
+use super::{
+    connect_pool, connect_to, format_table, print_json, print_output, send_method_call, wants_json,
+};
+use crate::{Bus, GlobalArgs};
+use clap::Args;
+use serde::Serialize;
+use std::collections::HashMap;
+use zbus::message::Message;
+use zbus::names::BusName;
+use zbus::{Connection, Result};
+use zbus_xml::{Method, Node};
+use zbusctl::access::{Probe, access_from_error, choose_probe};
+use zbusctl::introspect::order_arguments;
+use zvariant::{ObjectPath, StructureBuilder, Value};
+
+#[derive(Args)]
+pub struct ProbeAccessArgs {
+    #[arg(short, long, help = "D-Bus service name")]
+    service: String,
+
+    #[arg(
+        short,
+        long,
+        default_value = "/",
+        help = "D-Bus object path to start from; every object below it is probed too"
+    )]
+    object: String,
+
+    #[arg(
+        long,
+        value_name = "METHOD",
+        help = "Also call METHOD, which you know to be safe to call with empty arguments (can be repeated)"
+    )]
+    safe: Vec<String>,
+
+    #[command(flatten)]
+    walk: WalkArgs,
+}
+
+#[derive(Serialize)]
+struct Outcome {
+    object: String,
+    interface: String,
+    method: String,
+    result: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+}
+
+// Report, for each method of a service, whether the current user may call it.
+// Only methods that can be probed without side effects are: read-only ones
+// are called with zero and empty arguments, and those annotated with a
+// polkit action are checked with polkit without calling them. The standard
+// org.freedesktop.DBus interfaces are left out, as every object has them.
+pub async fn run_probe_access_command(global: &GlobalArgs, args: ProbeAccessArgs) -> Result<()> {
+    let service = BusName::try_from(args.service.as_str())?;
+    ObjectPath::try_from(args.object.as_str())?;
+
+    let connections = connect_pool(global).await?;
+    let jobs = global.jobs();
+
+    let source = Source::Bus {
+        connections: connections.clone(),
+        cache: global.cache(),
+        service: service.to_owned(),
+    };
+    let objects = walk(global, &source, &args.object, args.walk, false)
+        .await?
+        .into_iter()
+        .map(|object| (object.path, object.node))
+        .collect::<Vec<_>>();
+
+    let methods = methods(&objects)
+        .map(|(path, interface, method)| {
+            (path, interface, method, choose_probe(method, &args.safe))
+        })
+        .collect::<Vec<_>>();
+    // Polkit is on the system bus, whichever bus the service is on.
+    let polkit = match methods
+        .iter()
+        .any(|(.., probe)| matches!(probe, Probe::Polkit(_)))
+    {
+        true => Some(connect_to(global, Bus::System).await),
+        false => None,
+    };
+
+    let progress = global.progress("methods");
+    progress.expect(methods.len());
+    let results = jobs
+        .run_all(methods.iter().map(|(path, interface, method, probe)| {
+            progress.track(async {
+                match probe {
+                    Probe::Call => call(connections.get(), &service, path, interface, method).await,
+                    Probe::Polkit(action) => match polkit {
+                        Some(Ok(ref system)) => check_polkit(system, action).await,
+                        Some(Err(ref e)) => ("failed", Some(format!("polkit: {}", e))),
+                        None => unreachable!("polkit is connected when a method needs it"),
+                    },
+                    Probe::Skip => (
+                        "skipped",
+                        Some("may change state; add --safe to call it".to_string()),
+                    ),
+                }
+            })
+        }))
+        .await;
+    drop(progress);
+
+    let outcomes = methods
+        .iter()
+        .zip(results)
+        .map(|((path, interface, method, _), (result, detail))| Outcome {
+            object: path.to_string(),
+            interface: interface.to_string(),
+            method: method.name().to_string(),
+            result,
+            detail,
+        })
+        .collect::<Vec<_>>();
+
+    if wants_json(global) {
+        return print_json(global, &outcomes);
+    }
+    let rows = outcomes
+        .iter()
+        .map(|o| {
+            [
+                o.object.clone(),
+                format!("{}.{}", o.interface, o.method),
+                o.result.to_string(),
+                o.detail.clone().unwrap_or_default(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    print_output(
+        global,
+        &format_table(&["OBJECT", "METHOD", "RESULT", "DETAIL"], &rows),
+    )
+}
+
+// Every method of the objects, with the object path and interface name, in
+// the order they were introspected.
+fn methods<'a>(
+    objects: &'a [(String, Node<'static>)],
+) -> impl Iterator<Item = (&'a str, String, &'a Method<'static>)> {
+    objects.iter().flat_map(|(path, node)| {
+        node.interfaces()
+            .iter()
+            .filter(|interface| !interface.name().starts_with("org.freedesktop.DBus."))
+            .flat_map(move |interface| {
+                interface
+                    .methods()
+                    .iter()
+                    .map(move |method| (path.as_str(), interface.name().to_string(), method))
+            })
+    })
+}
+
+// Call a read-only method with zero or empty values for all its arguments.
+// Any reply shows the call was let through; an error only shows it was
+// refused if it is one of the errors access is refused with.
+async fn call(
+    connection: &Connection,
+    service: &BusName<'_>,
+    path: &str,
+    interface: &str,
+    method: &Method<'_>,
+) -> (&'static str, Option<String>) {
+    let (_, defaults) = match order_arguments(method, &[]) {
+        Ok(arguments) => arguments,
+        Err(reason) => return ("skipped", Some(reason)),
+    };
+    let build = || -> Result<Message> {
+        let builder = Message::method_call(path, method.name())?
+            .destination(service)?
+            .interface(interface)?;
+        if defaults.is_empty() {
+            return builder.build(&());
+        }
+        let body = defaults
+            .iter()
+            .cloned()
+            .fold(StructureBuilder::new(), |body, field| {
+                body.append_field(field)
+            })
+            .build()?;
+        builder.build(&body)
+    };
+    let msg = match build() {
+        Ok(msg) => msg,
+        Err(e) => return ("skipped", Some(e.to_string())),
+    };
+    match send_method_call(connection, &msg, None).await {
+        Ok(_) => ("allowed", None),
+        Err(zbus::Error::MethodError(name, text, _)) => (
+            access_from_error(name.as_str()),
+            Some(match text {
+                Some(text) => format!("{}: {}", name, text),
+                None => name.to_string(),
+            }),
+        ),
+        Err(e) => ("failed", Some(e.to_string())),
+    }
+}
+
+// Ask polkit whether this process is authorized for an action, without
+// letting it ask the user to authenticate.
+async fn check_polkit(system: &Connection, action: &str) -> (&'static str, Option<String>) {
+    let Some(name) = system.unique_name() else {
+        return (
+            "failed",
+            Some("polkit: no unique name on the system bus".to_string()),
+        );
+    };
+    let subject = (
+        "system-bus-name",
+        HashMap::from([("name", Value::from(name.as_str()))]),
+    );
+    let details = HashMap::<&str, &str>::new();
+    let reply = system
+        .call_method(
+            Some("org.freedesktop.PolicyKit1"),
+            "/org/freedesktop/PolicyKit1/Authority",
+            Some("org.freedesktop.PolicyKit1.Authority"),
+            "CheckAuthorization",
+            &(subject, action, details, 0u32, ""),
+        )
+        .await
+        .and_then(|reply| {
+            reply
+                .body()
+                .deserialize::<(bool, bool, HashMap<String, String>)>()
+        });
+    let detail = Some(format!("polkit action {}", action));
+    match reply {
+        Ok((true, _, _)) => ("allowed", detail),
+        Ok((false, true, _)) => ("auth-required", detail),
+        Ok((false, false, _)) => ("denied", detail),
+        Err(e) => ("failed", Some(format!("polkit: {}", e))),
+    }
+}
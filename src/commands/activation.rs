@@ -0,0 +1,127 @@
+use super::{connect, print_json, wants_json};
+use crate::{Bus, GlobalArgs};
+use clap::{Args, Subcommand};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use zbus::Result;
+use zbus::fdo::DBusProxy;
+use zbusctl::activation::{Problem, lint_service_files, load_service_files, service_dirs};
+
+#[derive(Args)]
+pub struct ActivationArgs {
+    #[command(subcommand)]
+    command: ActivationCommand,
+}
+
+#[derive(Subcommand)]
+enum ActivationCommand {
+    // Check activation files
+    #[command(
+        about = "Check the activation files of a bus for mistakes, and against the names the bus can activate"
+    )]
+    Lint(LintArgs),
+}
+
+#[derive(Args)]
+struct LintArgs {
+    #[arg(
+        long = "dir",
+        value_name = "DIR",
+        help = "Check the files in DIR instead of the standard directories, without asking the bus (can be repeated)"
+    )]
+    dirs: Vec<PathBuf>,
+}
+
+pub async fn run_activation_command(global: &GlobalArgs, args: ActivationArgs) -> Result<()> {
+    match args.command {
+        ActivationCommand::Lint(lint) => lint_activation_files(global, lint).await,
+    }
+}
+
+// Report the problems in the `.service` files of a bus one per line, as
+// `lint-script` does, and fail if any of them stops a service from being
+// activated. The bus's own list of activatable names is only compared with
+// the standard directories, which are the ones it reads.
+async fn lint_activation_files(global: &GlobalArgs, args: LintArgs) -> Result<()> {
+    let system = global.bus() == Bus::System;
+    let standard = args.dirs.is_empty();
+    let dirs = match standard {
+        true => service_dirs(system),
+        false => args.dirs,
+    };
+    let (services, errors) = load_service_files(&dirs);
+
+    let activatable = match standard {
+        true => match activatable_names(global).await {
+            Ok(names) => Some(names),
+            Err(e) => {
+                eprintln!(
+                    "Warning: not checking against the {} bus: {}",
+                    global.bus().name(),
+                    e
+                );
+                None
+            }
+        },
+        false => None,
+    };
+
+    let problems = errors
+        .into_iter()
+        .map(|e| Problem {
+            file: None,
+            error: true,
+            message: e.to_string(),
+        })
+        .chain(lint_service_files(&services, system, activatable.as_ref()))
+        .collect::<Vec<_>>();
+
+    let severity = |problem: &Problem| if problem.error { "error" } else { "warning" };
+    if wants_json(global) {
+        let json = problems
+            .iter()
+            .map(|problem| {
+                serde_json::json!({
+                    "file": problem.file,
+                    "severity": severity(problem),
+                    "message": problem.message,
+                })
+            })
+            .collect::<Vec<_>>();
+        print_json(global, &json)?;
+    } else {
+        for problem in &problems {
+            match problem.file {
+                Some(ref file) => println!(
+                    "{}: {}: {}",
+                    file.display(),
+                    severity(problem),
+                    problem.message
+                ),
+                None => println!("{}: {}", severity(problem), problem.message),
+            }
+        }
+    }
+
+    let errors = problems.iter().filter(|problem| problem.error).count();
+    if errors > 0 {
+        return Err(zbus::Error::Failure(format!(
+            "{} error{} in {} activation files",
+            errors,
+            if errors == 1 { "" } else { "s" },
+            global.bus().name()
+        )));
+    }
+    Ok(())
+}
+
+async fn activatable_names(global: &GlobalArgs) -> Result<BTreeSet<String>> {
+    let connection = connect(global).await?;
+    let proxy = DBusProxy::new(&connection).await?;
+    Ok(proxy
+        .list_activatable_names()
+        .await?
+        .into_iter()
+        .map(|name| name.to_string())
+        .collect())
+}
@@ -0,0 +1,323 @@
+use super::{connect, print_warning, send_method_call, serve_until_interrupted};
+use crate::GlobalArgs;
+use clap::Args;
+use futures_lite::StreamExt;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use zbus::fdo::{DBusProxy, MonitoringProxy};
+use zbus::message::Message;
+use zbus::names::{BusName, InterfaceName, MemberName, WellKnownName};
+use zbus::{Connection, MatchRule, MessageStream, Result};
+use zbusctl::events::MessageEvent;
+use zbusctl::format::{FloatFormat, body_to_json, to_json};
+use zbusctl::numbers::{Numbers, WithNumbers};
+use zbusctl::redact::Redactor;
+use zbusctl::serve::{Handler, Interface, Method, handler, serve_object_with};
+use zbusctl::{ParseOptions, build_body_with};
+use zvariant::{ObjectPath, Structure, StructureBuilder};
+
+const AGENT_PATH: &str = "/dev/zbusctl/Agent";
+const AGENT_INTERFACE: &str = "dev.zbusctl.Agent";
+
+#[derive(Args)]
+pub struct AgentArgs {
+    #[arg(
+        long,
+        default_value = "dev.zbusctl.Agent",
+        help = "Well-known name to request for the agent"
+    )]
+    name: String,
+}
+
+pub async fn run_agent_command(global: &GlobalArgs, args: AgentArgs) -> Result<()> {
+    let name = WellKnownName::try_from(args.name.as_str())?;
+
+    let connection = connect(global).await?;
+    let global = Arc::new(global.clone());
+    let options = global.parse_options();
+    let floats = global.float_format;
+    let numbers = global.numbers();
+    let redactor = Arc::new(global.redactor());
+    let callers = Arc::new(Callers::new(&connection).await?);
+
+    let mut methods: HashMap<&str, Method> = HashMap::new();
+    let calls = connection.clone();
+    methods.insert(
+        "Call",
+        Method::new(
+            "ssssas",
+            callers.guard({
+                let redactor = redactor.clone();
+                move |msg| {
+                    let calls = calls.clone();
+                    let redactor = redactor.clone();
+                    async move {
+                        let (service, object, interface, method, call_args): (
+                            String,
+                            String,
+                            String,
+                            String,
+                            Vec<String>,
+                        ) = msg
+                            .body()
+                            .deserialize()
+                            .map_err(|e| zbus::fdo::Error::InvalidArgs(e.to_string()))?;
+                        call(
+                            &calls,
+                            [&service, &object, &interface, &method],
+                            &call_args,
+                            &options,
+                            &redactor,
+                            floats,
+                            numbers,
+                        )
+                        .await
+                        .map_err(|e| zbus::fdo::Error::Failed(e.to_string()))
+                    }
+                }
+            }),
+        )?,
+    );
+    methods.insert(
+        "Parse",
+        Method::new(
+            "as",
+            callers.guard(move |msg| async move {
+                let call_args: Vec<String> = msg
+                    .body()
+                    .deserialize()
+                    .map_err(|e| zbus::fdo::Error::InvalidArgs(e.to_string()))?;
+                parse(&call_args, &options, floats, numbers)
+                    .map_err(|e| zbus::fdo::Error::InvalidArgs(e.to_string()))
+            }),
+        )?,
+    );
+    methods.insert(
+        "Monitor",
+        Method::new(
+            "asud",
+            callers.guard(move |msg| {
+                let global = global.clone();
+                let redactor = redactor.clone();
+                async move {
+                    let (rules, count, seconds): (Vec<String>, u32, f64) = msg
+                        .body()
+                        .deserialize()
+                        .map_err(|e| zbus::fdo::Error::InvalidArgs(e.to_string()))?;
+                    let (rules, duration) = match_rules(&rules, count, seconds)
+                        .map_err(|e| zbus::fdo::Error::InvalidArgs(e.to_string()))?;
+                    monitor(&global, &rules, count, duration, &redactor)
+                        .await
+                        .map_err(|e| zbus::fdo::Error::Failed(e.to_string()))
+                }
+            }),
+        )?,
+    );
+
+    let interfaces = vec![Interface::new(AGENT_INTERFACE, methods)?];
+    let mut served =
+        serve_object_with(&connection, AGENT_PATH, interfaces, Some(print_warning)).await?;
+    connection.request_name(name.clone()).await?;
+    eprintln!(
+        "Serving {} at {} as {}; press Ctrl-C to stop",
+        AGENT_INTERFACE, AGENT_PATH, name
+    );
+
+    serve_until_interrupted(&mut served).await
+}
+
+// Who may use the agent. It makes calls and monitors the bus with its own
+// credentials, so only connections of the user it runs as are served; the
+// bus says which user each caller is.
+struct Callers {
+    dbus: DBusProxy<'static>,
+    uid: u32,
+}
+
+impl Callers {
+    async fn new(connection: &Connection) -> Result<Self> {
+        let dbus = DBusProxy::new(connection).await?;
+        let own = connection
+            .unique_name()
+            .ok_or_else(|| zbus::Error::Failure("The agent has no unique name".to_string()))?;
+        let uid = dbus
+            .get_connection_unix_user(BusName::from(own.clone()))
+            .await?;
+        Ok(Self { dbus, uid })
+    }
+
+    // Refuse calls from connections of other users, and from ones the bus
+    // cannot name a user for.
+    async fn check(&self, msg: &Message) -> zbus::fdo::Result<()> {
+        let header = msg.header();
+        let sender = header
+            .sender()
+            .ok_or_else(|| zbus::fdo::Error::AccessDenied("The call has no sender".to_string()))?;
+        let uid = self
+            .dbus
+            .get_connection_unix_user(BusName::from(sender.clone()))
+            .await?;
+        if uid != self.uid {
+            return Err(zbus::fdo::Error::AccessDenied(format!(
+                "The agent only serves user {}, not user {}",
+                self.uid, uid
+            )));
+        }
+        Ok(())
+    }
+
+    // A handler running an agent method for permitted callers, replying with
+    // the JSON string it returns.
+    fn guard<F, Fut>(self: &Arc<Self>, method: F) -> Handler
+    where
+        F: Fn(Message) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = zbus::fdo::Result<String>> + Send + 'static,
+    {
+        let callers = self.clone();
+        handler(move |msg| {
+            let callers = callers.clone();
+            let reply = method(msg.clone());
+            async move {
+                callers.check(&msg).await?;
+                json_reply(reply.await?)
+            }
+        })
+    }
+}
+
+// The reply to an agent method: a single JSON string.
+fn json_reply(json: String) -> zbus::fdo::Result<Option<Structure<'static>>> {
+    let body = StructureBuilder::new()
+        .add_field(json)
+        .build()
+        .map_err(zbus::Error::from)?;
+    Ok(Some(body))
+}
+
+// Make a method call for a client, returning the reply's fields as JSON, in
+// the same form `call --output json` prints each of them, with what the
+// redaction rules hide in it hidden.
+async fn call(
+    connection: &Connection,
+    [service, object, interface, method]: [&str; 4],
+    call_args: &[String],
+    options: &ParseOptions,
+    redactor: &Redactor,
+    floats: FloatFormat,
+    numbers: Numbers,
+) -> Result<String> {
+    let msg = Message::method_call(ObjectPath::try_from(object)?, MemberName::try_from(method)?)?
+        .destination(BusName::try_from(service)?)?
+        .interface(InterfaceName::try_from(interface)?)?;
+    let msg = if call_args.is_empty() {
+        msg.build(&())?
+    } else {
+        msg.build(&build_body_with(
+            call_args.iter().map(|s| s.as_str()).collect(),
+            options,
+        )?)?
+    };
+
+    let reply = send_method_call(connection, &msg, None).await?;
+    let mut json = body_to_json(&reply.body());
+    if let Some(redaction) = redactor.reply_redaction(&MessageEvent::new(&msg)) {
+        redactor.redact_reply(&redaction, &mut json);
+    }
+    numbers.apply_to_body(&mut json)?;
+    to_json(&json, false, floats)
+        .map_err(|e| zbus::Error::Failure(format!("Failed to convert response to JSON: {}", e)))
+}
+
+// Parse arguments as `call` would, returning the resulting fields as JSON.
+fn parse(
+    call_args: &[String],
+    options: &ParseOptions,
+    floats: FloatFormat,
+    numbers: Numbers,
+) -> Result<String> {
+    let body = build_body_with(call_args.iter().map(|s| s.as_str()).collect(), options)?;
+    let fields = WithNumbers {
+        value: body.fields(),
+        policy: numbers,
+    };
+    to_json(&fields, false, floats)
+        .map_err(|e| zbus::Error::Failure(format!("Failed to convert arguments to JSON: {}", e)))
+}
+
+// Check the rules of a `Monitor` request, which must end: after `count`
+// messages, after `seconds`, or whichever comes first. Returns the rules and
+// how long to monitor for, if there is a limit.
+fn match_rules(
+    rules: &[String],
+    count: u32,
+    seconds: f64,
+) -> Result<(Vec<MatchRule<'static>>, Option<Duration>)> {
+    let duration = Duration::try_from_secs_f64(seconds)
+        .map_err(|e| zbus::Error::Failure(format!("Invalid duration '{}': {}", seconds, e)))?;
+    if count == 0 && duration.is_zero() {
+        return Err(zbus::Error::Failure(
+            "Give a number of messages or a number of seconds to monitor for".to_string(),
+        ));
+    }
+    let rules = rules
+        .iter()
+        .map(|rule| {
+            MatchRule::try_from(rule.as_str())
+                .map(|rule| rule.into_owned())
+                .map_err(|e| zbus::Error::Failure(format!("Invalid match rule '{}': {}", rule, e)))
+        })
+        .collect::<Result<_>>()?;
+    Ok((rules, Some(duration).filter(|d| !d.is_zero())))
+}
+
+// Monitor the bus for a client, on a connection of its own, returning the
+// messages seen as a JSON array of the events `monitor --output json` prints,
+// with what the redaction rules hide in them hidden. Monitoring stops after
+// `count` messages or `duration`, whichever comes first; a count of zero or
+// no duration means no limit.
+async fn monitor(
+    global: &GlobalArgs,
+    rules: &[MatchRule<'_>],
+    count: u32,
+    duration: Option<Duration>,
+    redactor: &Redactor,
+) -> Result<String> {
+    let connection = connect(global).await?;
+    let mut stream = MessageStream::from(&connection);
+    MonitoringProxy::new(&connection)
+        .await?
+        .become_monitor(rules, 0)
+        .await?;
+    let unique_name = connection.unique_name().map(|name| name.to_string());
+
+    let mut events = Vec::new();
+    let collect = async {
+        while let Some(msg) = stream.next().await {
+            let msg = msg?;
+            // The bus's own messages to the monitor are not traffic.
+            if msg.header().destination().map(|d| d.to_string()) == unique_name {
+                continue;
+            }
+            let mut event = MessageEvent::new(&msg);
+            redactor.redact_event(&mut event);
+            global.numbers().apply_to_body(&mut event.body)?;
+            events.push(event);
+            if count > 0 && events.len() >= count as usize {
+                break;
+            }
+        }
+        Ok::<_, zbus::Error>(())
+    };
+    match duration {
+        Some(duration) => {
+            if let Ok(result) = tokio::time::timeout(duration, collect).await {
+                result?;
+            }
+        }
+        None => collect.await?,
+    }
+    to_json(&events, false, global.float_format)
+        .map_err(|e| zbus::Error::Failure(format!("Failed to convert messages to JSON: {}", e)))
+}
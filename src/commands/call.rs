@@ -0,0 +1,614 @@
+use super::introspect::introspect;
+#[cfg(feature = "queue")]
+use super::queue::queue_call;
+use super::{
+    config_dir, connect, decode_failure, find_on_other_bus, format_table, is_unknown_service,
+    print_json, print_output, send_method_call, wants_json,
+};
+use crate::GlobalArgs;
+use clap::Args;
+use std::io::IsTerminal;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use zbus::Result;
+use zbus::message::Message;
+use zbus::names::{BusName, InterfaceName, MemberName};
+use zbusctl::build_body_with;
+use zbusctl::debug_log::DebugLog;
+use zbusctl::events::MessageEvent;
+use zbusctl::format::{BytesFormat, write_bytes};
+use zbusctl::golden::{compare, load_golden, save_golden};
+use zbusctl::identifiers::random_uuid;
+use zbusctl::introspect::{find_method, order_arguments, parse_node, verify_reply};
+#[cfg(feature = "scripting")]
+use zbusctl::message_file::{load_message, save_message};
+use zbusctl::numbers::WithNumbers;
+use zbusctl::paginate::{Pages, Paginate, next_args};
+use zbusctl::redact::{Redaction, Redactor};
+use zbusctl::shapes::Registry;
+use zvariant::{ObjectPath, Signature, Structure, StructureBuilder, Value};
+
+#[cfg(feature = "scripting")]
+const PROPERTIES: &str = "org.freedesktop.DBus.Properties";
+
+#[derive(Args)]
+pub struct CallArgs {
+    #[arg(short, long, help = "D-Bus service name")]
+    pub service: String,
+
+    #[arg(short, long, help = "D-Bus object path")]
+    pub object: String,
+
+    #[arg(short, long, help = "D-Bus interface name")]
+    pub interface: String,
+
+    #[arg(short, long, help = "D-Bus method name")]
+    pub method: String,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = BytesFormat::Json,
+        help = "Output format for byte array (ay) replies"
+    )]
+    bytes: BytesFormat,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Append every sent and received message to FILE as JSON Lines"
+    )]
+    debug_log: Option<PathBuf>,
+
+    #[cfg(feature = "scripting")]
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Save the raw reply message to FILE for later use with `render`"
+    )]
+    save_reply: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "REPLY_FIELD=ARG",
+        help = "Call repeatedly, passing reply field REPLY_FIELD back as argument ARG, until it is empty; array fields are concatenated"
+    )]
+    #[cfg_attr(feature = "scripting", arg(conflicts_with = "save_reply"))]
+    paginate: Option<Paginate>,
+
+    #[arg(
+        long,
+        help = "If the service is missing from the bus but present on the other one, call it there"
+    )]
+    auto_bus: bool,
+
+    #[arg(
+        long,
+        value_name = "ID",
+        num_args = 0..=1,
+        require_equals = true,
+        help = "Tag the call with a correlation ID (a new UUID unless --correlate=ID): printed on stderr, added to --debug-log entries and put in place of {correlation} in arguments"
+    )]
+    correlate: Option<Option<String>>,
+
+    #[arg(
+        long,
+        help = "Fail if the reply's signature differs from the method's introspection data, instead of warning"
+    )]
+    strict: bool,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Compare the reply with the JSON in FILE instead of printing it, printing the differences and failing if there are any"
+    )]
+    golden: Option<PathBuf>,
+
+    #[arg(
+        long,
+        requires = "golden",
+        help = "Write the reply to the --golden file instead of comparing it"
+    )]
+    update_golden: bool,
+
+    #[cfg(feature = "queue")]
+    #[arg(
+        long,
+        conflicts_with_all = ["named", "paginate", "strict", "golden"],
+        help = "If the bus or the service can't be reached, queue the call for `flush-queue` instead of failing"
+    )]
+    #[cfg_attr(feature = "scripting", arg(conflicts_with = "save_reply"))]
+    queue_if_unreachable: bool,
+
+    #[command(flatten)]
+    shapes: ShapeArgs,
+
+    #[arg(
+        long = "arg",
+        value_name = "NAME=TYPE:VALUE",
+        conflicts_with = "args",
+        help = "Pass an argument by the name it has in the introspection data (can be repeated); trailing arguments left out get zero or empty values"
+    )]
+    named: Vec<String>,
+
+    #[arg(help = "D-Bus method arguments")]
+    pub args: Option<Vec<String>>,
+}
+
+#[cfg(feature = "scripting")]
+#[derive(Args)]
+pub struct RenderArgs {
+    #[arg(help = "Reply message saved with `call --save-reply`")]
+    file: PathBuf,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = BytesFormat::Json,
+        help = "Output format for byte array (ay) replies"
+    )]
+    bytes: BytesFormat,
+
+    #[arg(
+        long,
+        value_name = "INTERFACE.METHOD",
+        help = "The call the reply answers, for reply shapes and redaction rules"
+    )]
+    call: Option<String>,
+
+    #[command(flatten)]
+    shapes: ShapeArgs,
+}
+
+// Options controlling how well-known reply shapes are recognized.
+#[derive(Args)]
+pub struct ShapeArgs {
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Load extra reply shapes from FILE (default: $XDG_CONFIG_HOME/zbusctl/formats.json)"
+    )]
+    formats: Option<PathBuf>,
+
+    #[arg(long, help = "Don't recognize well-known reply shapes")]
+    no_shapes: bool,
+}
+
+impl ShapeArgs {
+    // The built-in shapes plus those from the registry file, or nothing when
+    // shapes are disabled. A missing default registry file is not an error.
+    fn registry(&self) -> Result<Option<Registry>> {
+        if self.no_shapes {
+            return Ok(None);
+        }
+        let mut registry = Registry::builtin();
+        match self.formats {
+            Some(ref path) => registry.load_file(path)?,
+            None => {
+                if let Some(path) = config_dir().map(|c| c.join("formats.json"))
+                    && path.exists()
+                {
+                    registry.load_file(&path)?;
+                }
+            }
+        }
+        Ok(Some(registry))
+    }
+}
+
+pub async fn run_call_command(global: &GlobalArgs, mut args: CallArgs) -> Result<()> {
+    // A correlation ID ties the call to its entries in the logs of batch and
+    // parallel runs. The bus has no room for header fields of our own, so the
+    // service only sees it where the arguments ask for it.
+    let correlation_id = match args.correlate.take() {
+        Some(Some(id)) => Some(id),
+        Some(None) => Some(random_uuid()?),
+        None => None,
+    };
+    if let Some(ref id) = correlation_id {
+        for arg in args.args.iter_mut().flatten().chain(args.named.iter_mut()) {
+            *arg = arg.replace("{correlation}", id);
+        }
+        eprintln!("Correlation ID: {}", id);
+    }
+
+    // Parse and validate everything before connecting, so that a typo fails
+    // immediately instead of after a full connection handshake.
+    let service = BusName::try_from(args.service.as_str())?;
+    let object = ObjectPath::try_from(args.object.as_str())?;
+    let interface = InterfaceName::try_from(args.interface.as_str())?;
+    let method = MemberName::try_from(args.method.as_str())?;
+
+    // Build the method call message up front so it can be logged exactly as
+    // sent. `defaults` are appended for arguments left out by name.
+    let build_call =
+        |call_args: Option<&[String]>, defaults: &[Value<'static>]| -> Result<Message> {
+            let builder = Message::method_call(object.clone(), method.clone())?
+                .destination(service.clone())?
+                .interface(interface.clone())?;
+            let Some(call_args) = call_args else {
+                return builder.build(&());
+            };
+            let body = build_body_with(
+                call_args.iter().map(|s| s.as_str()).collect(),
+                &global.parse_options(),
+            )?;
+            if defaults.is_empty() {
+                return builder.build(&body);
+            }
+            let body = body
+                .into_fields()
+                .into_iter()
+                .chain(defaults.iter().cloned())
+                .fold(StructureBuilder::new(), |body, field| {
+                    body.append_field(field)
+                })
+                .build()?;
+            builder.build(&body)
+        };
+    // Named arguments can only be ordered once the method's introspection
+    // data has been fetched.
+    let msg = if args.named.is_empty() {
+        Some(build_call(args.args.as_deref(), &[])?)
+    } else {
+        None
+    };
+
+    let registry = args.shapes.registry()?;
+    let debug_log = args
+        .debug_log
+        .as_ref()
+        .map(|path| {
+            DebugLog::open(path).map(|log| {
+                log.with_correlation_id(correlation_id.clone())
+                    .with_redactor(global.redactor())
+            })
+        })
+        .transpose()?;
+    let call = (args.interface.as_str(), args.method.as_str());
+    let redactor = global.redactor();
+
+    // Establish D-Bus connection
+    let mut connection = match (connect(global).await, &msg) {
+        (Ok(connection), _) => connection,
+        // Without named arguments the call is already built.
+        #[cfg(feature = "queue")]
+        (Err(e), Some(msg)) if args.queue_if_unreachable => return queue_call(global, msg, &e),
+        (Err(e), _) => return Err(e),
+    };
+
+    let mut call_args = args.args.clone();
+    let mut defaults = Vec::new();
+    let mut node = None;
+    let msg = match msg {
+        Some(msg) => msg,
+        None => {
+            let xml =
+                introspect(&connection, global.cache().as_ref(), &service, &args.object).await?;
+            let parsed = parse_node(&args.object, &xml)?;
+            let (ordered, omitted) = find_method(&parsed, &args.interface, &args.method)
+                .and_then(|m| order_arguments(m, &args.named))
+                .map_err(|reason| {
+                    zbus::Error::Failure(format!("{} on '{}': {}", service, object, reason))
+                })?;
+            call_args = Some(ordered);
+            defaults = omitted;
+            node = Some(parsed);
+            build_call(call_args.as_deref(), &defaults)?
+        }
+    };
+    let redaction = redactor.reply_redaction(&MessageEvent::new(&msg));
+    let redaction = redaction.as_ref();
+    if args.strict && node.is_none() {
+        let xml = introspect(&connection, global.cache().as_ref(), &service, &args.object).await?;
+        node = Some(parse_node(&args.object, &xml)?);
+    }
+
+    // Replies are decoded by the signature they actually have, so a service
+    // that gets its own introspection data wrong still works. The mismatch
+    // is reported when the introspection data is at hand: as a warning, or
+    // with --strict as an error.
+    let check_reply = |reply: &Message| -> Result<()> {
+        let Some(ref node) = node else {
+            return Ok(());
+        };
+        let signature = reply.body().signature().to_string_no_parens();
+        match verify_reply(node, &args.interface, &args.method, &signature) {
+            Ok(()) => Ok(()),
+            Err(reason) if args.strict => Err(zbus::Error::Failure(format!(
+                "{} on '{}': {}",
+                service, object, reason
+            ))),
+            Err(reason) => {
+                eprintln!("Warning: {}", reason);
+                Ok(())
+            }
+        }
+    };
+    let mut send = async |msg: &Message| -> Result<Message> {
+        match send_method_call(&connection, msg, debug_log.as_ref()).await {
+            Err(e) if is_unknown_service(&e) => {
+                let Some((bus, other)) = find_on_other_bus(global, &service).await else {
+                    return Err(e);
+                };
+                if !args.auto_bus {
+                    return Err(zbus::Error::Failure(format!(
+                        "{} is not on the {} bus, but it is on the {} bus; use --bus {} or --auto-bus",
+                        service,
+                        global.bus().name(),
+                        bus.name(),
+                        bus.name()
+                    )));
+                }
+                eprintln!(
+                    "Note: {} is on the {} bus, calling it there",
+                    service,
+                    bus.name()
+                );
+                connection = other;
+                send_method_call(&connection, msg, debug_log.as_ref()).await
+            }
+            result => result,
+        }
+    };
+
+    if let Some(paginate) = args.paginate {
+        let mut call_args = call_args.unwrap_or_default();
+        let mut pages = Pages::new(paginate.token_field);
+        let mut msg = msg;
+        loop {
+            let reply = send(&msg).await?;
+            check_reply(&reply)?;
+            let body = reply.body();
+            let fields = body
+                .deserialize::<Structure>()
+                .map_err(|e| decode_failure(&reply, "a paged reply", &e))?;
+            let Some(token) = pages.append(fields.fields())? else {
+                break;
+            };
+            call_args = next_args(&call_args, paginate.token_arg, &token)?;
+            msg = build_call(Some(&call_args), &defaults)?;
+        }
+        let mut value = pages.into_value()?;
+        if let Some(redaction) = redaction {
+            value = redactor.redact_value(redaction, &value);
+        }
+        if let Some(ref path) = args.golden {
+            let json = reply_json(global, &value, registry.as_ref(), Some(call))?;
+            return check_golden(global, path, args.update_golden, &json);
+        }
+        return render_value(global, &value, registry.as_ref(), Some(call));
+    }
+
+    // Make the D-Bus method call
+    let result = match send(&msg).await {
+        #[cfg(feature = "queue")]
+        Err(e) if args.queue_if_unreachable && is_unknown_service(&e) => {
+            return queue_call(global, &msg, &e);
+        }
+        result => result?,
+    };
+    check_reply(&result)?;
+
+    #[cfg(feature = "scripting")]
+    if let Some(ref path) = args.save_reply {
+        save_message(path, &result)?;
+    }
+
+    if let Some(ref path) = args.golden {
+        let json = match *result.body().signature() {
+            Signature::Unit => serde_json::Value::Null,
+            _ => {
+                let body = result.body();
+                let response = body.deserialize::<Structure>().map_err(|e| {
+                    decode_failure(&result, "one or more complete D-Bus values", &e)
+                })?;
+                let hidden = redaction.map(|r| redactor.redact_value(r, &response.fields()[0]));
+                let value = hidden.as_ref().unwrap_or(&response.fields()[0]);
+                reply_json(global, value, registry.as_ref(), Some(call))?
+            }
+        };
+        return check_golden(global, path, args.update_golden, &json);
+    }
+
+    let hide = redaction.map(|redaction| (&redactor, redaction));
+    render_reply(
+        global,
+        &result,
+        args.bytes,
+        registry.as_ref(),
+        Some(call),
+        hide,
+    )
+}
+
+#[cfg(feature = "scripting")]
+pub async fn run_render_command(global: &GlobalArgs, args: RenderArgs) -> Result<()> {
+    let registry = args.shapes.registry()?;
+    let call = args
+        .call
+        .as_deref()
+        .map(|call| {
+            call.rsplit_once('.').ok_or_else(|| {
+                zbus::Error::Failure(format!(
+                    "Invalid call '{}': expected INTERFACE.METHOD",
+                    call
+                ))
+            })
+        })
+        .transpose()?;
+    let reply = load_message(&args.file)?;
+
+    // A saved reply does not say which call it answers, so unless `--call`
+    // does, there is no telling what the rules would hide and all of it is.
+    // So is a reply to a property call, whose arguments are not known.
+    let redactor = global.redactor();
+    let redaction = match call {
+        _ if redactor.is_empty() => None,
+        Some((interface, method)) if interface != PROPERTIES => redactor
+            .redacts_member(Some(interface), Some(method))
+            .then_some(Redaction::Body),
+        _ => Some(Redaction::Body),
+    };
+    let hide = redaction.as_ref().map(|redaction| (&redactor, redaction));
+    render_reply(global, &reply, args.bytes, registry.as_ref(), call, hide)
+}
+
+// Print the body of a reply message. `call` is the interface and member that
+// produced the reply, when known, for matching well-known reply shapes, and
+// `hide` what the redaction rules hide in it.
+fn render_reply(
+    global: &GlobalArgs,
+    result: &Message,
+    bytes_format: BytesFormat,
+    registry: Option<&Registry>,
+    call: Option<(&str, &str)>,
+    hide: Option<(&Redactor, &Redaction)>,
+) -> Result<()> {
+    // Unpack the result body.
+    let result_body = result.body().clone();
+
+    // Byte array replies can be very large (firmware images, icons); stream
+    // them straight from the message instead of building a JSON value.
+    if bytes_format != BytesFormat::Json
+        && hide.is_none()
+        && result_body.signature().to_string() == "ay"
+    {
+        let bytes = result_body.deserialize::<&[u8]>()?;
+        let mut stdout = std::io::stdout().lock();
+        write_bytes(&mut stdout, bytes, bytes_format)
+            .and_then(|_| stdout.flush())
+            .map_err(|e| zbus::Error::Failure(format!("Failed to write response: {}", e)))?;
+        return Ok(());
+    }
+
+    // Methods without return values have nothing to display.
+    if *result_body.signature() == Signature::Unit {
+        return Ok(());
+    }
+
+    let response = result_body
+        .deserialize::<Structure>()
+        .map_err(|e| decode_failure(result, "one or more complete D-Bus values", &e))?;
+
+    let hidden =
+        hide.map(|(redactor, redaction)| redactor.redact_value(redaction, &response.fields()[0]));
+    let value = hidden.as_ref().unwrap_or(&response.fields()[0]);
+    render_value(global, value, registry, call)
+}
+
+// Print a reply value, with named fields if it has a well-known shape.
+fn render_value(
+    global: &GlobalArgs,
+    value: &Value,
+    registry: Option<&Registry>,
+    call: Option<(&str, &str)>,
+) -> Result<()> {
+    // Recognized shapes get named fields: a table for humans at a terminal,
+    // an array of objects otherwise.
+    let (interface, member) = call.unzip();
+    if let Some(shape) = registry.and_then(|r| r.find(value, interface, member)) {
+        let rows = shape.rows(value, global.numbers())?;
+        if !wants_json(global) && std::io::stdout().is_terminal() {
+            let header = shape
+                .fields
+                .iter()
+                .map(|f| f.to_uppercase())
+                .collect::<Vec<_>>();
+            let cells = rows
+                .iter()
+                .map(|row| {
+                    row.iter()
+                        .map(|(_, v)| match v {
+                            serde_json::Value::String(s) => s.clone(),
+                            v => v.to_string(),
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>();
+            return print_output(global, &format_table(&header, &cells));
+        }
+        let objects = rows
+            .into_iter()
+            .map(|row| row.into_iter().collect::<serde_json::Map<_, _>>())
+            .collect::<Vec<_>>();
+        return print_json(global, &objects);
+    }
+
+    // Display the result
+    print_json(
+        global,
+        &WithNumbers {
+            value,
+            policy: global.numbers(),
+        },
+    )
+}
+
+// A reply as JSON, the way it is printed for scripts: with named fields if it
+// has a well-known shape, and numbers as the global options ask. Comparing
+// the values rather than the text leaves formatting and key order out of it.
+fn reply_json(
+    global: &GlobalArgs,
+    value: &Value,
+    registry: Option<&Registry>,
+    call: Option<(&str, &str)>,
+) -> Result<serde_json::Value> {
+    let (interface, member) = call.unzip();
+    let json = match registry.and_then(|r| r.find(value, interface, member)) {
+        Some(shape) => {
+            let objects = shape
+                .rows(value, global.numbers())?
+                .into_iter()
+                .map(|row| row.into_iter().collect::<serde_json::Map<_, _>>())
+                .collect::<Vec<_>>();
+            serde_json::to_value(objects)
+        }
+        None => serde_json::to_value(WithNumbers {
+            value,
+            policy: global.numbers(),
+        }),
+    };
+    json.map_err(|e| zbus::Error::Failure(format!("Failed to convert response to JSON: {}", e)))
+}
+
+// Snapshot testing of a reply: save it as the golden file, or compare it with
+// the one saved before and fail, after printing where it differs, if it has
+// changed.
+fn check_golden(
+    global: &GlobalArgs,
+    path: &Path,
+    update: bool,
+    actual: &serde_json::Value,
+) -> Result<()> {
+    if update {
+        return save_golden(path, actual);
+    }
+    let differences = compare(&load_golden(path)?, actual);
+    if differences.is_empty() {
+        return Ok(());
+    }
+
+    if wants_json(global) {
+        print_json(global, &differences)?;
+    } else {
+        let cell = |value: &Option<serde_json::Value>| match value {
+            None => "-".to_string(),
+            Some(value) => value.to_string(),
+        };
+        let rows = differences
+            .iter()
+            .map(|d| [d.path.clone(), cell(&d.expected), cell(&d.actual)])
+            .collect::<Vec<_>>();
+        print_output(
+            global,
+            &format_table(&["PATH", "EXPECTED", "ACTUAL"], &rows),
+        )?;
+    }
+    Err(zbus::Error::Failure(format!(
+        "The reply differs from '{}' in {} place{}",
+        path.display(),
+        differences.len(),
+        if differences.len() == 1 { "" } else { "s" }
+    )))
+}
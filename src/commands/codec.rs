@@ -0,0 +1,125 @@
+use super::print_json;
+use crate::GlobalArgs;
+use clap::Args;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use zbus::Result;
+use zbusctl::build_body_with;
+use zbusctl::codec::{ByteOrder, Encoding, Framing, decode, encode};
+use zbusctl::numbers::WithNumbers;
+
+// Options selecting the layout of raw data for encode and decode.
+#[derive(Args)]
+struct EncodingArgs {
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = ByteOrder::Little,
+        help = "Byte order of the data"
+    )]
+    endian: ByteOrder,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = Framing::Dbus,
+        help = "Serialization format of the data"
+    )]
+    framing: Framing,
+}
+
+impl EncodingArgs {
+    fn encoding(&self) -> Encoding {
+        Encoding {
+            byte_order: self.endian,
+            framing: self.framing,
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct DecodeArgs {
+    #[arg(short = 'g', long, help = "D-Bus signature of the data, e.g. a{sv}")]
+    signature: String,
+
+    #[command(flatten)]
+    encoding: EncodingArgs,
+
+    #[arg(help = "File to read the data from (default: stdin)")]
+    file: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct EncodeArgs {
+    #[arg(
+        short = 'g',
+        long,
+        help = "Expected D-Bus signature of the arguments, e.g. a{ss}"
+    )]
+    signature: Option<String>,
+
+    #[command(flatten)]
+    encoding: EncodingArgs,
+
+    #[arg(
+        required = true,
+        help = "Values to encode, in the same format as `call`"
+    )]
+    args: Vec<String>,
+}
+
+pub async fn run_decode_command(global: &GlobalArgs, args: DecodeArgs) -> Result<()> {
+    let bytes = match args.file {
+        Some(ref path) if path.as_os_str() != "-" => std::fs::read(path).map_err(|e| {
+            zbus::Error::Failure(format!("Failed to read '{}': {}", path.display(), e))
+        })?,
+        _ => {
+            let mut bytes = Vec::new();
+            std::io::stdin()
+                .read_to_end(&mut bytes)
+                .map_err(|e| zbus::Error::Failure(format!("Failed to read stdin: {}", e)))?;
+            bytes
+        }
+    };
+    let len = bytes.len();
+
+    let (structure, consumed) = decode(bytes, &args.signature, args.encoding.encoding())?;
+    if consumed < len {
+        eprintln!("Warning: ignoring {} trailing bytes", len - consumed);
+    }
+
+    // A single value is printed as-is; several are printed as an array.
+    let fields = structure.fields();
+    let policy = global.numbers();
+    if fields.len() == 1 {
+        print_json(
+            global,
+            &WithNumbers {
+                value: &fields[0],
+                policy,
+            },
+        )
+    } else {
+        print_json(
+            global,
+            &WithNumbers {
+                value: fields,
+                policy,
+            },
+        )
+    }
+}
+
+pub async fn run_encode_command(global: &GlobalArgs, args: EncodeArgs) -> Result<()> {
+    let body = build_body_with(
+        args.args.iter().map(|s| s.as_str()).collect(),
+        &global.parse_options(),
+    )?;
+    let bytes = encode(&body, args.signature.as_deref(), args.encoding.encoding())?;
+
+    let mut stdout = std::io::stdout().lock();
+    stdout
+        .write_all(&bytes)
+        .and_then(|_| stdout.flush())
+        .map_err(|e| zbus::Error::Failure(format!("Failed to write output: {}", e)))
+}
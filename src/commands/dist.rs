@@ -0,0 +1,46 @@
+use crate::GlobalArgs;
+use clap::{Args, ValueEnum};
+use clap_complete::Shell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use zbus::Result;
+
+#[derive(Args)]
+pub struct DistAssetsArgs {
+    #[arg(long, value_name = "DIR", help = "Directory to write the files to")]
+    out: PathBuf,
+}
+
+fn create_dir(dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir)
+        .map_err(|e| zbus::Error::Failure(format!("Failed to create '{}': {}", dir.display(), e)))
+}
+
+// Write the files packagers install alongside the binary: a man page for
+// zbusctl and each of its subcommands in `man/`, and a completion script for
+// every shell clap supports in `completions/`. Both are generated from the
+// same definitions as --help, so they never fall out of date.
+pub async fn run_dist_assets_command(_global: &GlobalArgs, args: DistAssetsArgs) -> Result<()> {
+    let man = args.out.join("man");
+    create_dir(&man)?;
+    clap_mangen::generate_to(crate::cli(), &man).map_err(|e| {
+        zbus::Error::Failure(format!(
+            "Failed to write man pages to '{}': {}",
+            man.display(),
+            e
+        ))
+    })?;
+
+    let completions = args.out.join("completions");
+    create_dir(&completions)?;
+    for &shell in Shell::value_variants() {
+        let mut command = crate::cli();
+        clap_complete::generate_to(shell, &mut command, "zbusctl", &completions).map_err(|e| {
+            zbus::Error::Failure(format!("Failed to write {} completions: {}", shell, e))
+        })?;
+    }
+
+    println!("Wrote man pages to {}", man.display());
+    println!("Wrote shell completions to {}", completions.display());
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use super::connect;
+use crate::GlobalArgs;
+use clap::Args;
+use zbus::Result;
+use zbus::message::Message;
+use zbus::names::{BusName, InterfaceName, MemberName};
+use zbusctl::build_body_with;
+use zvariant::ObjectPath;
+
+#[derive(Args)]
+pub struct EmitArgs {
+    #[arg(short, long, help = "D-Bus object path the signal comes from")]
+    object: String,
+
+    #[arg(short, long, help = "D-Bus interface name")]
+    interface: String,
+
+    #[arg(long, help = "D-Bus signal name")]
+    signal: String,
+
+    #[arg(
+        short,
+        long,
+        help = "Send the signal only to this bus name, instead of to everyone listening"
+    )]
+    destination: Option<String>,
+
+    #[arg(help = "Signal arguments, in the same syntax as `call` arguments")]
+    args: Option<Vec<String>>,
+}
+
+// Emit a signal from our own connection, with a body built as `call` builds
+// its arguments. `send` returns once the message is written, so the bus has
+// it before the process exits.
+pub async fn run_emit_command(global: &GlobalArgs, args: EmitArgs) -> Result<()> {
+    let object = ObjectPath::try_from(args.object.as_str())?;
+    let interface = InterfaceName::try_from(args.interface.as_str())?;
+    let signal = MemberName::try_from(args.signal.as_str())?;
+
+    let mut builder = Message::signal(object, interface, signal)?;
+    if let Some(ref destination) = args.destination {
+        builder = builder.destination(BusName::try_from(destination.as_str())?)?;
+    }
+    let msg = match args.args {
+        Some(ref signal_args) => builder.build(&build_body_with(
+            signal_args.iter().map(|s| s.as_str()).collect(),
+            &global.parse_options(),
+        )?)?,
+        None => builder.build(&())?,
+    };
+
+    let connection = connect(global).await?;
+    connection.send(&msg).await
+}
@@ -0,0 +1,59 @@
+use super::{print_json, print_output, wants_json};
+use crate::GlobalArgs;
+use clap::Args;
+use zbus::Result;
+use zbusctl::explain::{TypeDescription, describe, split_signature};
+
+#[derive(Args)]
+pub struct ExplainSignatureArgs {
+    #[arg(help = "D-Bus type signature, such as 'a{sa{sv}}' or the arguments of a method")]
+    signature: String,
+}
+
+pub async fn run_explain_signature_command(
+    global: &GlobalArgs,
+    args: ExplainSignatureArgs,
+) -> Result<()> {
+    let types = split_signature(&args.signature)?;
+    let several = types.len() > 1;
+    let descriptions = types
+        .iter()
+        .enumerate()
+        .map(|(i, signature)| TypeDescription {
+            role: several.then(|| format!("argument {}", i + 1)),
+            ..describe(signature)
+        })
+        .collect::<Vec<_>>();
+
+    if wants_json(global) {
+        return print_json(global, &descriptions);
+    }
+
+    let mut out = descriptions
+        .iter()
+        .map(TypeDescription::outline)
+        .collect::<String>();
+    if descriptions.is_empty() {
+        out.push_str("nothing: the signature is empty\n");
+    }
+
+    // An example of the whole list, or of the types that can't be written.
+    let missing = descriptions
+        .iter()
+        .filter(|d| d.example.is_none())
+        .map(|d| d.signature.as_str())
+        .collect::<Vec<_>>();
+    if missing.is_empty() && !descriptions.is_empty() {
+        let examples = descriptions
+            .iter()
+            .filter_map(|d| d.example.as_deref())
+            .collect::<Vec<_>>();
+        out.push_str(&format!("\nExample arguments: {}\n", examples.join(" ")));
+    } else if !missing.is_empty() {
+        out.push_str(&format!(
+            "\nNo zbusctl argument syntax for: {}\n",
+            missing.join(" ")
+        ));
+    }
+    print_output(global, &out)
+}
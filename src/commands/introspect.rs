@@ -0,0 +1,507 @@
+use super::{connect_pool, format_table, print_event, print_json, print_output, wants_json};
+use crate::GlobalArgs;
+use clap::Args;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use zbus::fdo::IntrospectableProxy;
+use zbus::names::BusName;
+use zbus::{Connection, Result};
+use zbus_xml::{ArgDirection, Node, PropertyAccess};
+use zbusctl::cache::Cache;
+use zbusctl::format::to_json;
+use zbusctl::introspect::{
+    bundle, child_paths, invalid_children, load_bundle, node_file, node_xml, parse_node,
+};
+use zbusctl::pool::Pool;
+use zvariant::ObjectPath;
+
+#[derive(Args)]
+pub struct IntrospectArgs {
+    #[arg(
+        short,
+        long,
+        required_unless_present = "from_bundle",
+        help = "D-Bus service name"
+    )]
+    service: Option<String>,
+
+    #[arg(short, long, default_value = "/", help = "D-Bus object path")]
+    object: String,
+
+    #[arg(short, long, help = "Also introspect every object below the path")]
+    recursive: bool,
+
+    #[command(flatten)]
+    walk: WalkArgs,
+
+    #[arg(long, help = "Print the introspection XML instead of a member list")]
+    xml: bool,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "Save each object's XML as DIR/<object path>/introspect.xml"
+    )]
+    save_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Save the XML of every object to FILE as a single bundle"
+    )]
+    bundle: Option<PathBuf>,
+
+    #[arg(
+        long,
+        requires = "recursive",
+        conflicts_with_all = ["xml", "bundle"],
+        help = "Print each object as a line of JSON as soon as it is introspected, instead of all of them at the end"
+    )]
+    stream: bool,
+
+    #[command(flatten)]
+    source: SourceArgs,
+}
+
+// Options selecting where introspection data comes from.
+#[derive(Args)]
+pub struct SourceArgs {
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Read introspection data saved with --save-dir or --bundle instead of the bus"
+    )]
+    pub from_bundle: Option<PathBuf>,
+}
+
+// Options bounding a walk of the object tree, against services that report
+// deep or enormous trees.
+#[derive(Args, Clone, Copy)]
+pub struct WalkArgs {
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 64,
+        help = "Walk at most N levels below the starting object"
+    )]
+    pub max_depth: usize,
+
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 4096,
+        help = "Walk at most N children of each object"
+    )]
+    pub max_children: usize,
+}
+
+// An object found by `walk`, with the children that were walked.
+pub struct Walked {
+    pub path: String,
+    pub xml: String,
+    pub node: Node<'static>,
+    #[cfg(feature = "inspect")]
+    pub children: Vec<String>,
+}
+
+// Where introspection data comes from: a live service, or data saved earlier
+// so commands can run without access to the bus, as in CI. Objects of a live
+// service are introspected over the connections of the pool in turn.
+pub enum Source {
+    Bus {
+        connections: Pool,
+        cache: Option<Cache>,
+        service: BusName<'static>,
+    },
+    Bundle(BTreeMap<String, Node<'static>>),
+}
+
+impl Source {
+    pub async fn open(
+        global: &GlobalArgs,
+        args: &SourceArgs,
+        service: Option<&str>,
+    ) -> Result<Self> {
+        if let Some(ref path) = args.from_bundle {
+            return Ok(Source::Bundle(load_bundle(path)?));
+        }
+        let service = service.ok_or_else(|| {
+            zbus::Error::Failure("A service name is required without --from-bundle".to_string())
+        })?;
+        Ok(Source::Bus {
+            service: BusName::try_from(service.to_string())?,
+            connections: connect_pool(global).await?,
+            cache: global.cache(),
+        })
+    }
+
+    // The introspection XML of the object at `path`.
+    pub async fn xml(&self, path: &str) -> Result<String> {
+        match self {
+            Source::Bus {
+                connections,
+                cache,
+                service,
+            } => introspect(connections.get(), cache.as_ref(), service, path).await,
+            Source::Bundle(nodes) => match nodes.get(path) {
+                Some(node) => node_xml(node),
+                None => Err(zbus::Error::Failure(format!(
+                    "Object '{}' is not in the bundle",
+                    path
+                ))),
+            },
+        }
+    }
+}
+
+pub async fn run_introspect_command(global: &GlobalArgs, args: IntrospectArgs) -> Result<()> {
+    if let Some(ref service) = args.service {
+        BusName::try_from(service.as_str())?;
+    }
+    ObjectPath::try_from(args.object.as_str())?;
+
+    let source = Source::open(global, &args.source, args.service.as_deref()).await?;
+    let bundle_name = match args.source.from_bundle {
+        Some(ref path) => args
+            .service
+            .clone()
+            .unwrap_or_else(|| path.display().to_string()),
+        None => args.service.clone().unwrap_or_default(),
+    };
+
+    let limits = match args.recursive {
+        true => args.walk,
+        false => WalkArgs {
+            max_depth: 0,
+            ..args.walk
+        },
+    };
+    // Each object is printed, and saved, as soon as it is found, and then
+    // forgotten.
+    if args.stream {
+        return walk_each(global, &source, &args.object, limits, false, |object| {
+            if let Some(ref dir) = args.save_dir {
+                save_xml(dir, &object.path, &object.xml)?;
+            }
+            let json = node_to_json(&object.path, &object.node);
+            print_event(&to_json(&json, false, global.float_format).map_err(|e| {
+                zbus::Error::Failure(format!("Failed to convert object to JSON: {}", e))
+            })?)
+        })
+        .await;
+    }
+
+    let objects = walk(global, &source, &args.object, limits, false)
+        .await?
+        .into_iter()
+        .map(|object| (object.path, object.xml, object.node))
+        .collect::<Vec<_>>();
+
+    if let Some(ref dir) = args.save_dir {
+        for (path, xml, _) in &objects {
+            save_xml(dir, path, xml)?;
+        }
+    }
+
+    let nodes = objects
+        .iter()
+        .map(|(path, _, node)| (path.clone(), node.clone()))
+        .collect::<Vec<_>>();
+    if let Some(ref file) = args.bundle {
+        fs::write(file, bundle(&bundle_name, &nodes)?).map_err(|e| {
+            zbus::Error::Failure(format!("Failed to write '{}': {}", file.display(), e))
+        })?;
+    }
+
+    // Saving is usually the point of these options, so only print when asked.
+    if (args.save_dir.is_some() || args.bundle.is_some()) && !args.xml {
+        return Ok(());
+    }
+
+    if args.xml {
+        return match objects.as_slice() {
+            [(_, xml, _)] => print_output(global, xml),
+            _ => print_output(global, &bundle(&bundle_name, &nodes)?),
+        };
+    }
+
+    if wants_json(global) {
+        let json = nodes
+            .iter()
+            .map(|(path, node)| node_to_json(path, node))
+            .collect::<Vec<_>>();
+        return match json.as_slice() {
+            [object] => print_json(global, object),
+            _ => print_json(global, &json),
+        };
+    }
+
+    let mut out = String::new();
+    for (path, node) in &nodes {
+        if nodes.len() > 1 {
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            out.push_str(&format!("{}\n", path));
+        }
+        out.push_str(&format_members(node));
+    }
+    print_output(global, &out)
+}
+
+// Save the XML of the object at `path` as `node_file` names it below `dir`.
+fn save_xml(dir: &Path, path: &str, xml: &str) -> Result<()> {
+    let file = node_file(dir, path);
+    if let Some(parent) = file.parent() {
+        fs::create_dir_all(parent).map_err(|e| {
+            zbus::Error::Failure(format!("Failed to create '{}': {}", parent.display(), e))
+        })?;
+    }
+    fs::write(&file, xml)
+        .map_err(|e| zbus::Error::Failure(format!("Failed to write '{}': {}", file.display(), e)))
+}
+
+// Introspect the object at `root` and those below it, within `limits`. The
+// tree is walked breadth-first so objects come out in a stable, top-down
+// order, each level's objects introspected concurrently. Each object is
+// visited once, however many times it is listed, and child names that are
+// not relative paths are skipped with a warning, so a service reporting
+// itself as its own child cannot make the walk go on forever; the limits
+// bound how deep and how wide it goes. A child named by several elements,
+// such as `org/freedesktop/DBus`, counts as one level down. With `skip_failures`, an object below
+// the root that cannot be introspected is kept out of the result with a
+// warning rather than failing the walk.
+pub async fn walk(
+    global: &GlobalArgs,
+    source: &Source,
+    root: &str,
+    limits: WalkArgs,
+    skip_failures: bool,
+) -> Result<Vec<Walked>> {
+    let mut objects = Vec::new();
+    walk_each(global, source, root, limits, skip_failures, |object| {
+        objects.push(object);
+        Ok(())
+    })
+    .await?;
+    Ok(objects)
+}
+
+// Walk the tree as `walk` does, handing each object to `visit` as soon as its
+// level has been introspected instead of keeping them all, so a service with
+// a huge tree can be streamed without holding it in memory. Only the paths
+// seen so far are kept, to visit each object once.
+pub async fn walk_each(
+    global: &GlobalArgs,
+    source: &Source,
+    root: &str,
+    limits: WalkArgs,
+    skip_failures: bool,
+    mut visit: impl FnMut(Walked) -> Result<()>,
+) -> Result<()> {
+    let jobs = global.jobs();
+    let progress = global.progress("objects");
+    let mut visited = HashSet::from([root.to_string()]);
+    let mut cut_off = 0;
+    let mut level = vec![root.to_string()];
+    for depth in 0.. {
+        if level.is_empty() {
+            break;
+        }
+        progress.expect(level.len());
+        let xmls = jobs
+            .run_all(level.iter().map(|path| progress.track(source.xml(path))))
+            .await;
+        let mut next = Vec::new();
+        for (path, xml) in level.into_iter().zip(xmls) {
+            let parsed = xml.and_then(|xml| parse_node(&path, &xml).map(|node| (xml, node)));
+            let (xml, node) = match parsed {
+                Ok(parsed) => parsed,
+                Err(e) if skip_failures && path != root => {
+                    eprintln!("Warning: failed to introspect '{}': {}", path, e);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            for name in invalid_children(&node) {
+                eprintln!(
+                    "Warning: skipping child '{}' of '{}': not a relative object path",
+                    name, path
+                );
+            }
+            let mut children = child_paths(&path, &node);
+            children.sort();
+            children.dedup();
+            if depth == limits.max_depth {
+                cut_off += children.len();
+                children.clear();
+            } else if children.len() > limits.max_children {
+                eprintln!(
+                    "Warning: '{}' lists {} children; only the first {} are walked, raise --max-children to walk them all",
+                    path,
+                    children.len(),
+                    limits.max_children
+                );
+                children.truncate(limits.max_children);
+            }
+            children.retain(|child| visited.insert(child.clone()));
+            next.extend(children.iter().cloned());
+            visit(Walked {
+                path,
+                xml,
+                node,
+                #[cfg(feature = "inspect")]
+                children,
+            })?;
+        }
+        level = next;
+    }
+    drop(progress);
+
+    if cut_off > 0 && limits.max_depth > 0 {
+        eprintln!(
+            "Warning: left out {} objects more than {} levels below '{}'; raise --max-depth to include them",
+            cut_off, limits.max_depth, root
+        );
+    }
+    Ok(())
+}
+
+// Fetch the introspection XML of one object, from the cache if one is given
+// and holds it.
+pub async fn introspect(
+    connection: &Connection,
+    cache: Option<&Cache>,
+    service: &BusName<'_>,
+    path: &str,
+) -> Result<String> {
+    let fetch = async {
+        let proxy = IntrospectableProxy::builder(connection)
+            .destination(service)?
+            .path(path)?
+            .build()
+            .await?;
+        Ok(proxy.introspect().await?)
+    };
+    match cache {
+        Some(cache) => {
+            let key = format!("{} {} Introspect", service, path);
+            cache.get_or_fetch(&key, fetch).await
+        }
+        None => fetch.await,
+    }
+}
+
+// The signature of the arguments of a method going in one direction. Method
+// arguments without a direction are inputs.
+fn arg_signature(args: &[zbus_xml::Arg], direction: ArgDirection) -> String {
+    args.iter()
+        .filter(|arg| arg.direction().unwrap_or(ArgDirection::In) == direction)
+        .map(|arg| arg.ty().to_string())
+        .collect()
+}
+
+fn access_name(access: PropertyAccess) -> &'static str {
+    match access {
+        PropertyAccess::Read => "read",
+        PropertyAccess::Write => "write",
+        PropertyAccess::ReadWrite => "readwrite",
+    }
+}
+
+// List the members of every interface on a node, one per row.
+fn format_members(node: &Node) -> String {
+    let mut rows = Vec::new();
+    for interface in node.interfaces() {
+        rows.push([
+            interface.name().to_string(),
+            "interface".to_string(),
+            "-".to_string(),
+            "-".to_string(),
+        ]);
+        for method in interface.methods() {
+            rows.push([
+                format!(".{}", method.name()),
+                "method".to_string(),
+                or_dash(arg_signature(method.args(), ArgDirection::In)),
+                or_dash(arg_signature(method.args(), ArgDirection::Out)),
+            ]);
+        }
+        for property in interface.properties() {
+            rows.push([
+                format!(".{}", property.name()),
+                "property".to_string(),
+                property.ty().to_string(),
+                access_name(property.access()).to_string(),
+            ]);
+        }
+        for signal in interface.signals() {
+            let signature = signal.args().iter().map(|a| a.ty().to_string()).collect();
+            rows.push([
+                format!(".{}", signal.name()),
+                "signal".to_string(),
+                or_dash(signature),
+                "-".to_string(),
+            ]);
+        }
+    }
+    format_table(&["NAME", "TYPE", "SIGNATURE", "RESULT/ACCESS"], &rows)
+}
+
+fn or_dash(s: String) -> String {
+    if s.is_empty() { "-".to_string() } else { s }
+}
+
+fn node_to_json(path: &str, node: &Node) -> serde_json::Value {
+    let interfaces = node
+        .interfaces()
+        .iter()
+        .map(|interface| {
+            let methods = interface
+                .methods()
+                .iter()
+                .map(|m| {
+                    serde_json::json!({
+                        "name": m.name().as_str(),
+                        "in": arg_signature(m.args(), ArgDirection::In),
+                        "out": arg_signature(m.args(), ArgDirection::Out),
+                    })
+                })
+                .collect::<Vec<_>>();
+            let properties = interface
+                .properties()
+                .iter()
+                .map(|p| {
+                    serde_json::json!({
+                        "name": p.name().as_str(),
+                        "type": p.ty().to_string(),
+                        "access": access_name(p.access()),
+                    })
+                })
+                .collect::<Vec<_>>();
+            let signals = interface
+                .signals()
+                .iter()
+                .map(|s| {
+                    serde_json::json!({
+                        "name": s.name().as_str(),
+                        "signature": s.args().iter().map(|a| a.ty().to_string()).collect::<String>(),
+                    })
+                })
+                .collect::<Vec<_>>();
+            serde_json::json!({
+                "name": interface.name().as_str(),
+                "methods": methods,
+                "properties": properties,
+                "signals": signals,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    serde_json::json!({
+        "path": path,
+        "interfaces": interfaces,
+        "children": child_paths(path, node),
+    })
+}
@@ -0,0 +1,104 @@
+use super::introspect::{Source, SourceArgs};
+use super::verify::verify;
+use crate::{Commands, GlobalArgs, ZBusCtl};
+use clap::{Args, Parser};
+use std::fs;
+use std::path::PathBuf;
+use zbus::Result;
+use zbusctl::script::find_invocations;
+
+#[derive(Args)]
+pub struct LintScriptArgs {
+    #[arg(required = true, help = "Shell scripts to check")]
+    scripts: Vec<PathBuf>,
+
+    #[command(flatten)]
+    source: SourceArgs,
+}
+
+pub async fn run_lint_script_command(global: &GlobalArgs, args: LintScriptArgs) -> Result<()> {
+    // Opened on first use, so scripts without calls don't need a bus or bundle.
+    let mut bundle = None;
+    let mut errors = 0;
+
+    for script in &args.scripts {
+        let contents = fs::read_to_string(script).map_err(|e| {
+            zbus::Error::Failure(format!("Failed to read '{}': {}", script.display(), e))
+        })?;
+
+        for invocation in find_invocations(&contents) {
+            let location = format!("{}:{}", script.display(), invocation.line);
+            if invocation.dynamic {
+                println!("{}: skipped: arguments depend on shell expansion", location);
+                continue;
+            }
+
+            // Parse the words exactly as zbusctl itself would, so bad options
+            // are caught as well as bad calls.
+            let parsed = match ZBusCtl::try_parse_from(&invocation.words) {
+                Ok(parsed) => parsed,
+                Err(e) if !e.use_stderr() => continue,
+                Err(e) => {
+                    let message = e.to_string();
+                    let message = message.lines().next().unwrap_or_default();
+                    println!("{}: {}", location, message);
+                    errors += 1;
+                    continue;
+                }
+            };
+            let Commands::Call(call) = parsed.command else {
+                continue;
+            };
+
+            // Calls are checked on the bus the script would use, unless a
+            // bundle stands in for it.
+            let live;
+            let source = if args.source.from_bundle.is_some() {
+                if bundle.is_none() {
+                    bundle = Some(Source::open(global, &args.source, None).await?);
+                }
+                bundle.as_ref().unwrap()
+            } else {
+                live = match Source::open(&parsed.global, &args.source, Some(&call.service)).await {
+                    Ok(source) => source,
+                    Err(e) => {
+                        println!("{}: error: {}", location, e);
+                        errors += 1;
+                        continue;
+                    }
+                };
+                &live
+            };
+
+            let result = verify(
+                source,
+                &call.service,
+                &call.object,
+                &call.interface,
+                &call.method,
+                call.args.as_deref(),
+                &parsed.global.parse_options(),
+            )
+            .await;
+            match result {
+                Ok(_) => println!(
+                    "{}: ok: {}.{} on {} {}",
+                    location, call.interface, call.method, call.service, call.object
+                ),
+                Err(e) => {
+                    println!("{}: error: {}", location, e);
+                    errors += 1;
+                }
+            }
+        }
+    }
+
+    if errors > 0 {
+        return Err(zbus::Error::Failure(format!(
+            "{} invalid zbusctl invocation{}",
+            errors,
+            if errors == 1 { "" } else { "s" }
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,413 @@
+#[cfg(feature = "namespaces")]
+use crate::namespace;
+use crate::{Bus, GlobalArgs, pager};
+use clap::ValueEnum;
+use futures_lite::StreamExt;
+use std::io::IsTerminal;
+use std::io::Write;
+use std::path::PathBuf;
+use zbus::fdo::DBusProxy;
+use zbus::message::{EndianSig, Message, Type};
+use zbus::names::BusName;
+use zbus::{Connection, MessageStream, Result};
+use zbusctl::debug_log::{DebugLog, Direction};
+use zbusctl::format::{OutputFormat, hexdump, to_json};
+use zbusctl::jobs::Jobs;
+use zbusctl::pool::Pool;
+use zbusctl::queue::BusTarget;
+#[cfg(feature = "serve")]
+use zbusctl::serve::ServedObject;
+#[cfg(feature = "yaml")]
+use zbusctl::yaml::to_yaml;
+
+#[cfg(feature = "inspect")]
+pub mod access;
+#[cfg(feature = "inspect")]
+pub mod activation;
+#[cfg(feature = "agent")]
+pub mod agent;
+pub mod call;
+#[cfg(feature = "scripting")]
+pub mod codec;
+#[cfg(feature = "dist")]
+pub mod dist;
+#[cfg(feature = "scripting")]
+pub mod emit;
+#[cfg(feature = "inspect")]
+pub mod explain;
+pub mod introspect;
+#[cfg(feature = "inspect")]
+pub mod lint;
+#[cfg(feature = "capture")]
+pub mod monitor;
+#[cfg(feature = "inspect")]
+pub mod names;
+#[cfg(feature = "inspect")]
+pub mod policy;
+pub mod props;
+#[cfg(feature = "queue")]
+pub mod queue;
+#[cfg(feature = "scripting")]
+pub mod ready;
+#[cfg(feature = "serve")]
+pub mod replay;
+#[cfg(feature = "capture")]
+pub mod scrub;
+#[cfg(feature = "serve")]
+pub mod serve_properties;
+#[cfg(feature = "inspect")]
+pub mod stats;
+#[cfg(feature = "inspect")]
+pub mod status;
+#[cfg(feature = "scripting")]
+pub mod template;
+#[cfg(feature = "testbus")]
+pub mod testbus;
+#[cfg(feature = "inspect")]
+pub mod tree;
+#[cfg(feature = "inspect")]
+pub mod verify;
+#[cfg(feature = "scripting")]
+pub mod watch;
+#[cfg(feature = "webhook")]
+pub mod webhook;
+
+// zbusctl's configuration directory, $XDG_CONFIG_HOME/zbusctl.
+pub fn config_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+        .map(|config| config.join("zbusctl"))
+}
+
+// zbusctl's cache directory, $XDG_CACHE_HOME/zbusctl.
+#[cfg(feature = "cache")]
+pub fn cache_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".cache")))
+        .map(|cache| cache.join("zbusctl"))
+}
+
+// zbusctl's state directory, $XDG_STATE_HOME/zbusctl, for what must outlive
+// a command but is not configuration, such as queued calls.
+#[cfg(any(feature = "queue", feature = "webhook"))]
+pub fn state_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".local/state")))
+        .map(|state| state.join("zbusctl"))
+}
+
+// Connect to the bus selected by the global options.
+pub async fn connect(global: &GlobalArgs) -> Result<Connection> {
+    connect_to(global, global.bus()).await
+}
+
+// Open `--connections` connections to the bus selected by the global
+// options, all at once, for a command that makes many requests.
+pub async fn connect_pool(global: &GlobalArgs) -> Result<Pool> {
+    let connections = usize::from(global.connections);
+    let connections = Jobs::new(connections)
+        .run_all((0..connections).map(|_| connect(global)))
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>>>()?;
+    Pool::new(connections)
+}
+
+// Connect to a specific bus, with the other global options applied. With
+// `--peer`, every connection goes to the peer instead.
+pub async fn connect_to(global: &GlobalArgs, bus: Bus) -> Result<Connection> {
+    let Some(ref peer) = global.peer else {
+        return connect_target(global, &global.target(bus)).await;
+    };
+    let mut builder = zbus::connection::Builder::address(peer.as_str())?.p2p();
+    if let Some(timeout) = global.timeout()? {
+        builder = builder.method_timeout(timeout);
+    }
+    builder.build().await
+}
+
+// Connect to a bus as it was reached before, perhaps from another
+// environment, with the global method timeout applied.
+pub async fn connect_target(global: &GlobalArgs, target: &BusTarget) -> Result<Connection> {
+    let bus = Bus::from_str(&target.bus, true)
+        .map_err(|e| zbus::Error::Failure(format!("Invalid bus '{}': {}", target.bus, e)))?;
+    let mut builder = if target.netns.is_some() || target.mntns.is_some() {
+        namespace_builder(target, bus)?
+    } else {
+        match (bus, &target.address) {
+            (_, Some(address)) => zbus::connection::Builder::address(address.as_str())?,
+            (Bus::Session, None) => zbus::connection::Builder::session()?,
+            (Bus::System, None) => zbus::connection::Builder::system()?,
+        }
+    };
+    if let Some(timeout) = global.timeout()? {
+        builder = builder.method_timeout(timeout);
+    }
+    builder.build().await
+}
+
+// A connection to a bus in the namespaces of other processes.
+#[cfg(feature = "namespaces")]
+fn namespace_builder(target: &BusTarget, bus: Bus) -> Result<zbus::connection::Builder<'static>> {
+    let stream =
+        namespace::connect(target.netns, target.mntns, bus == Bus::System).map_err(|e| {
+            zbus::Error::Failure(format!(
+                "Failed to connect to the {} bus in the target namespaces: {}",
+                bus.name(),
+                e
+            ))
+        })?;
+    stream.set_nonblocking(true)?;
+    Ok(zbus::connection::Builder::unix_stream(
+        tokio::net::UnixStream::from_std(stream)?,
+    ))
+}
+
+// Without namespace support, a bus reached from other namespaces, such as
+// one a queued call was made for, cannot be reached again.
+#[cfg(not(feature = "namespaces"))]
+fn namespace_builder(target: &BusTarget, _bus: Bus) -> Result<zbus::connection::Builder<'static>> {
+    Err(zbus::Error::Failure(format!(
+        "Cannot reach the {} bus: zbusctl was built without the `namespaces` feature",
+        target.describe()
+    )))
+}
+
+// Send a method call and wait for its reply, logging every message seen on
+// the connection along the way. Error replies are returned as
+// `zbus::Error::MethodError`, just like `Connection::call_method`, and the
+// connection's method timeout applies in the same way.
+pub async fn send_method_call(
+    connection: &Connection,
+    msg: &Message,
+    debug_log: Option<&DebugLog>,
+) -> Result<Message> {
+    // Subscribe before sending so the reply cannot be missed.
+    let mut stream = MessageStream::from(connection);
+    let serial = msg.primary_header().serial_num();
+
+    connection.send(msg).await?;
+    if let Some(log) = debug_log {
+        log.log(Direction::Sent, msg)?;
+    }
+
+    let wait_for_reply = async {
+        while let Some(reply) = stream.next().await {
+            let reply = reply?;
+            if let Some(log) = debug_log {
+                log.log(Direction::Received, &reply)?;
+            }
+
+            let header = reply.header();
+            if header.reply_serial() != Some(serial) {
+                continue;
+            }
+            return match header.message_type() {
+                Type::Error => Err(reply.into()),
+                _ => Ok(reply),
+            };
+        }
+
+        Err(zbus::Error::Failure(
+            "Connection closed before a reply was received".to_string(),
+        ))
+    };
+
+    match connection.method_timeout() {
+        Some(timeout) => tokio::time::timeout(timeout, wait_for_reply)
+            .await
+            .map_err(|_| {
+                zbus::Error::Failure(format!(
+                    "No reply received within {} seconds",
+                    timeout.as_secs_f64()
+                ))
+            })?,
+        None => wait_for_reply.await,
+    }
+}
+
+// Whether an error says that nothing owns, or can be started for, the
+// destination name.
+pub fn is_unknown_service(error: &zbus::Error) -> bool {
+    matches!(
+        error,
+        zbus::Error::MethodError(name, _, _)
+            if name.as_str() == "org.freedesktop.DBus.Error.ServiceUnknown"
+                || name.as_str() == "org.freedesktop.DBus.Error.NameHasNoOwner"
+    )
+}
+
+// Look for a well-known name on the bus we are not using, running or
+// activatable, returning a connection to that bus if it is there. New users
+// often mix up the buses; this lets us tell them, or retry on the right one.
+pub async fn find_on_other_bus(
+    global: &GlobalArgs,
+    name: &BusName<'_>,
+) -> Option<(Bus, Connection)> {
+    if matches!(name, BusName::Unique(_)) {
+        return None;
+    }
+    let bus = match global.bus() {
+        Bus::Session => Bus::System,
+        Bus::System => Bus::Session,
+    };
+    let connection = connect_to(global, bus).await.ok()?;
+    let proxy = DBusProxy::new(&connection).await.ok()?;
+    let found = proxy.name_has_owner(name.clone()).await.ok()?
+        || proxy
+            .list_activatable_names()
+            .await
+            .ok()?
+            .iter()
+            .any(|activatable| activatable.as_str() == name.as_str());
+    found.then_some((bus, connection))
+}
+
+// Whether the user asked for machine-readable output explicitly, in which case
+// commands that default to tables print JSON instead.
+pub fn wants_json(global: &GlobalArgs) -> bool {
+    global.output.is_some()
+}
+
+// Convert a value to JSON, or YAML, in the selected output format. Without an
+// explicit format, JSON is pretty-printed for humans at a terminal and compact
+// when piped into other tools.
+pub fn to_json_string<T: serde::Serialize + ?Sized>(
+    global: &GlobalArgs,
+    value: &T,
+) -> Result<String> {
+    let convert_error = |e: serde_json::Error| {
+        zbus::Error::Failure(format!("Failed to convert response to JSON: {}", e))
+    };
+    let pretty = match global.output {
+        #[cfg(feature = "capture")]
+        Some(OutputFormat::DbusMonitor) => {
+            return Err(zbus::Error::Failure(
+                "Only monitor can write --output dbus-monitor".to_string(),
+            ));
+        }
+        Some(OutputFormat::Json) => false,
+        Some(OutputFormat::JsonPretty) => true,
+        #[cfg(feature = "yaml")]
+        Some(OutputFormat::Yaml) => {
+            let value = serde_json::to_value(value).map_err(convert_error)?;
+            return Ok(to_yaml(&value, global.float_format).trim_end().to_string());
+        }
+        None => std::io::stdout().is_terminal(),
+    };
+    to_json(value, pretty, global.float_format).map_err(convert_error)
+}
+
+// Report a problem that doesn't stop the command, such as one met by a
+// library module while serving.
+pub fn print_warning(warning: &str) {
+    eprintln!("Warning: {}", warning);
+}
+
+// Serve until interrupted with Ctrl-C, or until serving stops by itself, as
+// it does when the bus goes away, which is an error: a service that is no
+// longer on the bus should not look like it is still running.
+#[cfg(feature = "serve")]
+pub async fn serve_until_interrupted(served: &mut ServedObject) -> Result<()> {
+    tokio::select! {
+        interrupted = tokio::signal::ctrl_c() => interrupted
+            .map_err(|e| zbus::Error::Failure(format!("Failed to wait for Ctrl-C: {}", e))),
+        error = served.closed() => Err(error),
+    }
+}
+
+// Print a value as JSON followed by a newline.
+pub fn print_json<T: serde::Serialize + ?Sized>(global: &GlobalArgs, value: &T) -> Result<()> {
+    let json = to_json_string(global, value)?;
+    print_output(global, &format!("{}\n", json))
+}
+
+// Write one event of a command that streams them, such as `monitor` or
+// `watch`, flushing it at once. Stdout is line-buffered even when piped,
+// but an event can span lines, and a consumer like `grep --line-buffered`
+// should see all of it as soon as it happens.
+pub fn print_event(event: &str) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    writeln!(stdout, "{}", event)
+        .and_then(|_| stdout.flush())
+        .map_err(|e| zbus::Error::Failure(format!("Failed to write output: {}", e)))
+}
+
+// Print command output, through the pager unless disabled or running
+// non-interactively.
+pub fn print_output(global: &GlobalArgs, output: &str) -> Result<()> {
+    pager::page(output, !global.no_pager && global.interactive())
+        .map_err(|e| zbus::Error::Failure(format!("Failed to write output: {}", e)))
+}
+
+// Format items one per line.
+#[cfg(feature = "inspect")]
+pub fn format_list<I, T>(items: I) -> String
+where
+    I: IntoIterator<Item = T>,
+    T: std::fmt::Display,
+{
+    items
+        .into_iter()
+        .map(|item| format!("{}\n", item))
+        .collect()
+}
+
+// Format rows as left-aligned columns under a header. The last column is not
+// padded so long values such as command lines don't leave trailing spaces.
+pub fn format_table<H, R, S>(header: &[H], rows: &[R]) -> String
+where
+    H: AsRef<str>,
+    R: AsRef<[S]>,
+    S: AsRef<str>,
+{
+    let n = header.len();
+    let mut widths = header.iter().map(|h| h.as_ref().len()).collect::<Vec<_>>();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row.as_ref()) {
+            *width = (*width).max(cell.as_ref().len());
+        }
+    }
+
+    let header = header.iter().map(|h| h.as_ref()).collect::<Vec<_>>();
+    let rows = rows
+        .iter()
+        .map(|row| row.as_ref().iter().map(|c| c.as_ref()).collect::<Vec<_>>());
+    let mut out = String::new();
+    for row in std::iter::once(header).chain(rows) {
+        for (i, cell) in row.iter().enumerate() {
+            if i + 1 == n {
+                out.push_str(cell);
+            } else {
+                out.push_str(&format!("{:<width$}  ", cell, width = widths[i]));
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+// Describe a reply that could not be decoded: what was expected, the actual
+// signature and a hexdump of the body, so exotic reply shapes can be reported
+// with enough detail to reproduce them.
+pub fn decode_failure(msg: &Message, expected: &str, error: &zbus::Error) -> zbus::Error {
+    let body = msg.body();
+    let bytes = body.data().bytes();
+    let endian = match msg.primary_header().endian_sig() {
+        EndianSig::Big => "big-endian",
+        EndianSig::Little => "little-endian",
+    };
+
+    zbus::Error::Failure(format!(
+        "Failed to decode reply: {}\n  expected:  {}\n  signature: {}\n  body ({} bytes, {}):\n{}",
+        error,
+        expected,
+        body.signature().to_string_no_parens(),
+        bytes.len(),
+        endian,
+        hexdump(bytes)
+    ))
+}
@@ -0,0 +1,881 @@
+use super::{connect, print_event, to_json_string, wants_json};
+use crate::GlobalArgs;
+use crate::sandbox;
+use crate::terminal::Keys;
+use clap::Args;
+use futures_lite::StreamExt;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{IsTerminal, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::OnceCell;
+use tokio::task::JoinHandle;
+use zbus::fdo::{DBusProxy, MonitoringProxy};
+use zbus::message::{Message, Type};
+use zbus::names::BusName;
+use zbus::{Connection, MatchRule, MessageStream, Result};
+use zbusctl::audit::{AuditRule, Auditor};
+use zbusctl::credentials::Credentials;
+use zbusctl::events::{GapEvent, MessageEvent, NoteEvent, now};
+use zbusctl::format::{OutputFormat, to_json};
+use zbusctl::journal::{JOURNAL_SOCKET, Journal, event_fields, gap_fields};
+
+const BUS_NAME: &str = "org.freedesktop.DBus";
+
+#[derive(Args)]
+pub struct MonitorArgs {
+    #[arg(
+        long = "match",
+        value_name = "RULE",
+        help = "Only show messages matching RULE, such as \"type='signal',sender='org.freedesktop.NetworkManager'\" (can be repeated)"
+    )]
+    rules: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "PID",
+        help = "Only show messages sent by or to the process PID, including on connections it opens later"
+    )]
+    pid: Option<u32>,
+
+    #[arg(
+        long,
+        help = "Connect again when the connection to the bus is lost, marking the gap in the output"
+    )]
+    reconnect: bool,
+
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value_t = 1.0,
+        requires = "reconnect",
+        help = "How long to wait before the first attempt to reconnect, doubled after each failed one up to 30 seconds"
+    )]
+    retry_delay: f64,
+
+    #[arg(
+        long,
+        value_name = "N",
+        requires = "reconnect",
+        help = "Give up after N failed attempts to reconnect in a row (default: keep trying)"
+    )]
+    max_retries: Option<u32>,
+
+    #[arg(
+        long,
+        help = "Flag security-relevant calls, such as ones that power the machine off or format a disk"
+    )]
+    audit: bool,
+
+    #[arg(
+        long,
+        value_name = "INTERFACE.METHOD[=REASON]",
+        requires = "audit",
+        help = "Also flag calls to METHOD of INTERFACE, which may contain * (can be repeated)"
+    )]
+    audit_rule: Vec<AuditRule>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "audit",
+        help = "Append each flagged call to FILE, one JSON object per line"
+    )]
+    audit_log: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Show the process ID, user and security label of each message's sender"
+    )]
+    resolve: bool,
+
+    #[arg(
+        long,
+        help = "Write each message to the systemd journal, with its header fields as structured fields, instead of printing it"
+    )]
+    journal: bool,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        default_value = ".",
+        help = "Write the messages exported with the e key at a terminal to a new file in DIR"
+    )]
+    export_dir: PathBuf,
+
+    #[arg(
+        long,
+        help = "Keep zbusctl from running programs or changing files, except for exports to --export-dir, before reading from the bus"
+    )]
+    sandbox: bool,
+}
+
+impl MonitorArgs {
+    pub fn sandboxed(&self) -> bool {
+        self.sandbox
+    }
+}
+
+// A message as the audit log and JSON output record it: its event, with the
+// audit rule it matched and its sender's credentials when there are any.
+#[derive(Serialize)]
+struct Shown<'a> {
+    #[serde(flatten)]
+    event: &'a MessageEvent,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    audit: Option<&'a AuditRule>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sender_credentials: Option<&'a Credentials>,
+}
+
+// What the keys pressed at a terminal have asked of the monitor: whether
+// messages are shown or held back while output is paused or a note is typed,
+// and the marks set. From the first mark on, every message, gap and note is
+// kept, as the JSON line it would be written as without a terminal, so the
+// span between marks can be exported.
+struct Session {
+    export_dir: PathBuf,
+    paused: bool,
+    note: Option<Vec<u8>>,
+    held: usize,
+    marks: Vec<usize>,
+    recorded: Vec<String>,
+}
+
+const KEYS_HELP: &str = "--- keys: p pause or resume, m mark, a add a note, e export the span between the last two marks, q quit ---";
+
+impl Session {
+    fn new(export_dir: PathBuf) -> Self {
+        Self {
+            export_dir,
+            paused: false,
+            note: None,
+            held: 0,
+            marks: Vec::new(),
+            recorded: Vec::new(),
+        }
+    }
+
+    // Show a message or gap, as `line`, unless output is held back, and keep
+    // it, as the line `json` makes, once a mark is set.
+    fn show(&mut self, line: String, json: impl FnOnce() -> Result<String>) -> Result<()> {
+        if !self.marks.is_empty() {
+            self.recorded.push(json()?);
+        }
+        if self.paused || self.note.is_some() {
+            self.held += 1;
+            return Ok(());
+        }
+        print_event(&line)
+    }
+
+    // Act on a key, returning whether to keep monitoring.
+    fn press(&mut self, key: u8) -> Result<bool> {
+        const CTRL_C: u8 = 0x03;
+        const ESCAPE: u8 = 0x1b;
+        const BACKSPACE: [u8; 2] = [0x08, 0x7f];
+        if let Some(note) = &mut self.note {
+            match key {
+                b'\r' | b'\n' => {
+                    let text = String::from_utf8_lossy(note).trim().to_string();
+                    self.note = None;
+                    echo(b"\n")?;
+                    if !text.is_empty() {
+                        let note = NoteEvent::new(text);
+                        if !self.marks.is_empty() {
+                            self.recorded
+                                .push(serde_json::to_string(&note).map_err(|e| {
+                                    zbus::Error::Failure(format!(
+                                        "Failed to convert note to JSON: {}",
+                                        e
+                                    ))
+                                })?);
+                        }
+                        print_event(&note.summary())?;
+                    }
+                    self.resume()?;
+                }
+                CTRL_C | ESCAPE => {
+                    self.note = None;
+                    echo(b"\n")?;
+                    self.resume()?;
+                }
+                key if BACKSPACE.contains(&key) && !note.is_empty() => {
+                    // Remove the last character, however many bytes it is.
+                    while note.pop().is_some_and(|byte| byte & 0xc0 == 0x80) {}
+                    echo(b"\x08 \x08")?;
+                }
+                key => {
+                    note.push(key);
+                    echo(&[key])?;
+                }
+            }
+            return Ok(true);
+        }
+        match key {
+            b'p' | b' ' => {
+                self.paused = !self.paused;
+                match self.paused {
+                    true => print_event("--- paused; press p to resume ---")?,
+                    false => self.resume()?,
+                }
+            }
+            b'm' => {
+                self.marks.push(self.recorded.len());
+                print_event(&format!(
+                    "--- mark {} at time={:.6} ---",
+                    self.marks.len(),
+                    now()
+                ))?;
+            }
+            b'a' => {
+                self.note = Some(Vec::new());
+                echo(b"note: ")?;
+            }
+            b'e' => self.export()?,
+            b'q' | CTRL_C => return Ok(false),
+            b'?' | b'h' => print_event(KEYS_HELP)?,
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    // Say how many messages were held back, if output is no longer.
+    fn resume(&mut self) -> Result<()> {
+        if self.paused || self.held == 0 {
+            return Ok(());
+        }
+        let held = std::mem::take(&mut self.held);
+        print_event(&format!("--- {} messages were not shown ---", held))
+    }
+
+    // The lines kept between the last two marks, or since the only one, and
+    // which marks they are between.
+    fn span(&self) -> Option<(&[String], String)> {
+        match *self.marks.as_slice() {
+            [] => None,
+            [start] => Some((&self.recorded[start..], "since mark 1".to_string())),
+            [.., start, end] => Some((
+                &self.recorded[start..end],
+                format!(
+                    "between marks {} and {}",
+                    self.marks.len() - 1,
+                    self.marks.len()
+                ),
+            )),
+        }
+    }
+
+    // Write the span between the last two marks to a new file, readable by
+    // its owner alone, as the audit log is.
+    fn export(&self) -> Result<()> {
+        let Some((lines, between)) = self.span() else {
+            return print_event("--- nothing to export; press m to set a mark first ---");
+        };
+        let path = self
+            .export_dir
+            .join(format!("zbusctl-monitor-{:.0}.jsonl", now() * 1000.0));
+        let mut contents = lines.join("\n");
+        contents.push('\n');
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)
+            .and_then(|mut file| file.write_all(contents.as_bytes()))
+            .map_err(|e| {
+                zbus::Error::Failure(format!("Failed to export to '{}': {}", path.display(), e))
+            })?;
+        print_event(&format!(
+            "--- exported {} lines {} to {} ---",
+            lines.len(),
+            between,
+            path.display()
+        ))
+    }
+}
+
+// Echo what is typed into a note.
+fn echo(bytes: &[u8]) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    stdout
+        .write_all(bytes)
+        .and_then(|_| stdout.flush())
+        .map_err(|e| zbus::Error::Failure(format!("Failed to write output: {}", e)))
+}
+
+// The longest wait between attempts to reconnect.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+// What the bus says about the connections on messages, looked up as each is
+// first seen, on a connection of its own as the monitor cannot make calls.
+// Lookups run alongside the monitor, each name's once however many messages
+// wait on it, so a slow answer does not stop messages being read off the
+// bus. What is known about a name is forgotten when a `NameOwnerChanged`
+// signal says it changed owner or was released, so names gone from the bus
+// do not pile up.
+struct Peers {
+    proxy: DBusProxy<'static>,
+    credentials: Cache<Arc<Credentials>>,
+    owners: Cache<String>,
+}
+
+// What is known about each name, or being looked up.
+type Cache<T> = Mutex<HashMap<String, Arc<OnceCell<Option<T>>>>>;
+
+impl Peers {
+    async fn new(global: &GlobalArgs) -> Result<Arc<Self>> {
+        let connection = connect(global).await?;
+        Ok(Arc::new(Self {
+            proxy: DBusProxy::new(&connection).await?,
+            credentials: Mutex::new(HashMap::new()),
+            owners: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    // Forget what is known about a name whose owner the message says changed.
+    // Messages must be seen here in the order they arrived.
+    fn observe(&self, msg: &Message) {
+        let header = msg.header();
+        if header
+            .member()
+            .is_some_and(|m| m.as_str() == "NameOwnerChanged")
+            && header.sender().is_some_and(|s| s.as_str() == BUS_NAME)
+            && let Ok((name, _, new_owner)) = msg.body().deserialize::<(String, String, String)>()
+        {
+            self.owners.lock().unwrap().remove(&name);
+            if new_owner.is_empty() {
+                self.credentials.lock().unwrap().remove(&name);
+            }
+        }
+    }
+
+    // The credentials of the connection behind a bus name, unique or
+    // well-known. Names that are gone by the time they are looked up have
+    // none.
+    async fn credentials(&self, name: &str) -> Option<Arc<Credentials>> {
+        let unique = if name.starts_with(':') || name == BUS_NAME {
+            name.to_string()
+        } else {
+            let owner = self
+                .owners
+                .lock()
+                .unwrap()
+                .entry(name.to_string())
+                .or_default()
+                .clone();
+            owner
+                .get_or_init(|| async {
+                    let name = BusName::try_from(name).ok()?;
+                    let owner = self.proxy.get_name_owner(name).await.ok()?;
+                    Some(owner.to_string())
+                })
+                .await
+                .clone()?
+        };
+        let credentials = self
+            .credentials
+            .lock()
+            .unwrap()
+            .entry(unique.clone())
+            .or_default()
+            .clone();
+        credentials
+            .get_or_init(|| async {
+                let name = BusName::try_from(unique.as_str()).ok()?;
+                let credentials = match self.proxy.get_connection_credentials(name.clone()).await {
+                    Ok(credentials) => Credentials::from(&credentials),
+                    // Buses older than GetConnectionCredentials still have
+                    // the single-value methods.
+                    Err(zbus::fdo::Error::UnknownMethod(_)) => Credentials {
+                        pid: self
+                            .proxy
+                            .get_connection_unix_process_id(name.clone())
+                            .await
+                            .ok(),
+                        uid: self.proxy.get_connection_unix_user(name).await.ok(),
+                        security_label: None,
+                    },
+                    Err(_) => return None,
+                };
+                Some(Arc::new(credentials))
+            })
+            .await
+            .clone()
+    }
+
+    // Whether the message was sent by or to the process `pid`. The bus
+    // itself is not any process's.
+    async fn involves(&self, msg: &Message, pid: u32) -> bool {
+        let header = msg.header();
+        for name in [
+            header.sender().map(|s| s.to_string()),
+            header.destination().map(|d| d.to_string()),
+        ]
+        .into_iter()
+        .flatten()
+        .filter(|name| name != BUS_NAME)
+        {
+            if self
+                .credentials(&name)
+                .await
+                .is_some_and(|credentials| credentials.pid == Some(pid))
+            {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+// What was looked up about a message before it can be shown: whether it
+// passed `--pid`, and its sender's credentials when they are wanted.
+struct Lookup {
+    shown: bool,
+    sender: Option<Arc<Credentials>>,
+}
+
+impl Lookup {
+    async fn new(peers: Arc<Peers>, msg: Message, pid: Option<u32>, sender: bool) -> Self {
+        let shown = match pid {
+            Some(pid) => peers.involves(&msg, pid).await,
+            None => true,
+        };
+        let sender = match msg.header().sender() {
+            Some(name) if shown && sender => peers.credentials(name.as_str()).await,
+            _ => None,
+        };
+        Self { shown, sender }
+    }
+}
+
+// A message waiting to be shown, with its event, redacted, the audit rule it
+// matched, and what is being looked up about it, if anything.
+struct Pending {
+    msg: Message,
+    event: MessageEvent,
+    audit: Option<AuditRule>,
+    lookup: Option<JoinHandle<Lookup>>,
+}
+
+// A one-line summary of a message, for reading traffic as it goes by, with
+// the values of its event.
+fn summary(msg: &Message, event: &MessageEvent) -> String {
+    let header = msg.header();
+    let peer = |name: Option<String>| name.unwrap_or_else(|| "-".to_string());
+    let route = format!(
+        "{} → {}",
+        peer(header.sender().map(|s| s.to_string())),
+        peer(header.destination().map(|s| s.to_string()))
+    );
+    let serial = header.primary().serial_num();
+    let what = match header.message_type() {
+        Type::MethodCall | Type::Signal => format!(
+            "{} {}.{}",
+            peer(header.path().map(|p| p.to_string())),
+            peer(header.interface().map(|i| i.to_string())),
+            peer(header.member().map(|m| m.to_string()))
+        ),
+        Type::MethodReturn => format!(
+            "reply to #{}",
+            peer(header.reply_serial().map(|s| s.to_string()))
+        ),
+        Type::Error => format!(
+            "{} to #{}",
+            peer(header.error_name().map(|e| e.to_string())),
+            peer(header.reply_serial().map(|s| s.to_string()))
+        ),
+    };
+    let kind = match header.message_type() {
+        Type::MethodCall => "call",
+        Type::MethodReturn => "return",
+        Type::Error => "error",
+        Type::Signal => "signal",
+    };
+    let values = event
+        .body
+        .as_array()
+        .map(|fields| {
+            fields
+                .iter()
+                .map(|field| field["value"].to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .unwrap_or_default();
+    format!("{:<6} #{} {} {} {}", kind, serial, route, what, values)
+        .trim_end()
+        .to_string()
+}
+
+// Show every message going through the bus, as `busctl monitor` and
+// `dbus-monitor` do, until interrupted. The connection is turned into a
+// monitor, which only receives from then on, so it is a connection of its
+// own. Humans at a terminal get a line per message; otherwise each message
+// is written as a `MessageEvent`, one per line, as `call --debug-log` writes
+// them, or with `--output dbus-monitor` in the layout `dbus-monitor` prints.
+// With `--reconnect`, losing the connection, such as when the bus restarts,
+// is not the end: monitoring starts again on a new connection, with a
+// `GapEvent` in the output where messages were missed. When stdin is a
+// terminal too, keys pause the output, set marks, add notes and export the
+// messages between marks; see `Session`.
+pub async fn run_monitor_command(global: &GlobalArgs, args: MonitorArgs) -> Result<()> {
+    let rules = args
+        .rules
+        .iter()
+        .map(|rule| {
+            MatchRule::try_from(rule.as_str())
+                .map_err(|e| zbus::Error::Failure(format!("Invalid match rule '{}': {}", rule, e)))
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    let retry_delay = Duration::try_from_secs_f64(args.retry_delay).map_err(|e| {
+        zbus::Error::Failure(format!("Invalid duration '{}': {}", args.retry_delay, e))
+    })?;
+    // Connections are looked up for `--pid`, for `--resolve` and for the
+    // senders of audited calls. That needs names to be seen change owner
+    // even when the rules given would not show it, so a rule for it is added
+    // to them and hidden again below.
+    let lookups = args.pid.is_some() || args.resolve || args.audit;
+    let mut monitored = rules.clone();
+    if lookups && !rules.is_empty() {
+        monitored.push(
+            MatchRule::builder()
+                .msg_type(Type::Signal)
+                .sender(BUS_NAME)?
+                .member("NameOwnerChanged")?
+                .build(),
+        );
+    }
+
+    let human = !wants_json(global) && std::io::stdout().is_terminal();
+    let redactor = global.redactor();
+    let auditor = args.audit.then(|| Auditor::new(args.audit_rule.clone()));
+    let mut audit_log = match &args.audit_log {
+        Some(path) => Some(open_audit_log(path)?),
+        None => None,
+    };
+    let journal = match args.journal {
+        true => Some(Journal::connect().map_err(|e| {
+            zbus::Error::Failure(format!(
+                "Failed to connect to the journal at {}: {}",
+                JOURNAL_SOCKET, e
+            ))
+        })?),
+        false => None,
+    };
+    let send = |journal: &Journal, fields: Vec<(&str, String)>| {
+        journal
+            .send(&fields)
+            .map_err(|e| zbus::Error::Failure(format!("Failed to write to the journal: {}", e)))
+    };
+    let print = |line: String| print_event(&line);
+    let to_json_line = |shown: &Shown| {
+        to_json(shown, false, global.float_format)
+            .map_err(|e| zbus::Error::Failure(format!("Failed to convert message to JSON: {}", e)))
+    };
+
+    // Show a message once what was looked up about it is known, unless that
+    // says it is not wanted.
+    let mut show = |pending: Pending, lookup: Option<Lookup>, session: &mut Option<Session>| {
+        let sender = match lookup {
+            Some(Lookup { shown: false, .. }) => return Ok(()),
+            Some(lookup) => lookup.sender,
+            None => None,
+        };
+        let Pending {
+            msg,
+            mut event,
+            audit,
+            ..
+        } = pending;
+        if let (Some(audit), Some(log)) = (&audit, &mut audit_log) {
+            let mut event = event.clone();
+            global.numbers().apply_to_body(&mut event.body)?;
+            let line = to_json_line(&Shown {
+                event: &event,
+                audit: Some(audit),
+                sender_credentials: sender.as_deref(),
+            })?;
+            writeln!(log, "{}", line)
+                .and_then(|_| log.flush())
+                .map_err(|e| {
+                    zbus::Error::Failure(format!("Failed to write the audit log: {}", e))
+                })?;
+        }
+        if let Some(journal) = &journal {
+            let message = match &audit {
+                Some(audit) => format!("AUDIT {}: {}", summary(&msg, &event), audit.reason),
+                None => summary(&msg, &event),
+            };
+            global.numbers().apply_to_body(&mut event.body)?;
+            return send(
+                journal,
+                event_fields(&event, message, audit.as_ref(), sender.as_deref()),
+            );
+        }
+        let line = match global.output {
+            Some(OutputFormat::DbusMonitor) => {
+                let mut lines = String::new();
+                if let Some(audit) = &audit {
+                    lines.push_str(&format!("# AUDIT: {}\n", audit.reason));
+                }
+                if let Some(sender) = &sender {
+                    lines.push_str(&format!("# SENDER: {}\n", sender));
+                }
+                lines.push_str(event.to_dbus_monitor().trim_end());
+                lines
+            }
+            _ if human => {
+                let mut line = summary(&msg, &event);
+                if let Some(sender) = &sender {
+                    line.push_str(&format!("  [{}]", sender));
+                }
+                match &audit {
+                    Some(audit) => format!("\x1b[1;31mAUDIT {}: {}\x1b[0m", line, audit.reason),
+                    None => line,
+                }
+            }
+            _ => {
+                global.numbers().apply_to_body(&mut event.body)?;
+                let shown = Shown {
+                    event: &event,
+                    audit: audit.as_ref(),
+                    sender_credentials: sender.as_deref(),
+                };
+                to_json_string(global, &shown)?
+            }
+        };
+        match session {
+            Some(session) => session.show(line, || {
+                global.numbers().apply_to_body(&mut event.body)?;
+                to_json_line(&Shown {
+                    event: &event,
+                    audit: audit.as_ref(),
+                    sender_credentials: sender.as_deref(),
+                })
+            }),
+            None => print(line),
+        }
+    };
+
+    // Everything the monitor writes to is open by now, but for exports, and
+    // connecting to the bus is still allowed, so the sandbox is in place
+    // before the first message from the bus is read.
+    if args.sandbox {
+        sandbox::restrict(&[&args.export_dir])
+            .map_err(|e| zbus::Error::Failure(format!("Failed to sandbox the monitor: {}", e)))?;
+    }
+    let (mut connection, mut stream, mut peers) = start(global, &monitored, lookups).await?;
+    // Keys are not read if the terminal cannot be set up for them; the
+    // monitor works as it would without a terminal on stdin.
+    let mut keys = match human && !args.journal && global.interactive() {
+        true => Keys::open().ok(),
+        false => None,
+    };
+    let mut session = keys.as_ref().map(|_| Session::new(args.export_dir.clone()));
+    if session.is_some() {
+        print_event(KEYS_HELP)?;
+    }
+    loop {
+        let unique_name = connection.unique_name().map(|name| name.to_string());
+        // Messages waiting for their lookups, in the order they arrived,
+        // which is the order they are shown in.
+        let mut pending: VecDeque<Pending> = VecDeque::new();
+        let reason = loop {
+            let next = tokio::select! {
+                biased;
+                lookup = async { wait(&mut pending.front_mut().unwrap().lookup).await }, if !pending.is_empty() => {
+                    show(pending.pop_front().unwrap(), lookup, &mut session)?;
+                    continue;
+                }
+                key = async { keys.as_mut().unwrap().next().await }, if keys.is_some() => {
+                    match key {
+                        Some(key) => {
+                            if !session.as_mut().unwrap().press(key)? {
+                                return Ok(());
+                            }
+                        }
+                        None => keys = None,
+                    }
+                    continue;
+                }
+                next = stream.next() => next,
+            };
+            if !matches!(next, Some(Ok(_))) {
+                while let Some(mut waiting) = pending.pop_front() {
+                    let lookup = wait(&mut waiting.lookup).await;
+                    show(waiting, lookup, &mut session)?;
+                }
+            }
+            let msg = match next {
+                Some(Ok(msg)) => msg,
+                Some(Err(e)) if args.reconnect => break e.to_string(),
+                Some(Err(e)) => return Err(e),
+                None if args.reconnect => break "the bus closed the connection".to_string(),
+                None => return Ok(()),
+            };
+            // Only the bus talks to a monitor: the reply to BecomeMonitor and
+            // the loss of its name. That is not traffic worth showing.
+            if msg.header().destination().map(|d| d.to_string()) == unique_name {
+                continue;
+            }
+            if let Some(peers) = &peers {
+                peers.observe(&msg);
+            }
+            if monitored.len() > rules.len()
+                && !rules.iter().any(|rule| rule.matches(&msg).unwrap_or(false))
+            {
+                continue;
+            }
+
+            let mut event = MessageEvent::new(&msg);
+            redactor.redact_event(&mut event);
+            let audit = auditor
+                .as_ref()
+                .and_then(|auditor| auditor.check(&event))
+                .cloned();
+            let sender = args.resolve || audit.is_some();
+            let lookup = peers
+                .as_ref()
+                .filter(|_| args.pid.is_some() || sender)
+                .map(|peers| {
+                    tokio::spawn(Lookup::new(peers.clone(), msg.clone(), args.pid, sender))
+                });
+            let waiting = Pending {
+                msg,
+                event,
+                audit,
+                lookup,
+            };
+            if pending.is_empty() && waiting.lookup.is_none() {
+                show(waiting, None, &mut session)?;
+            } else {
+                pending.push_back(waiting);
+            }
+        };
+
+        let since = now();
+        let mut delay = retry_delay;
+        let mut failures = 0;
+        (connection, stream, peers) = loop {
+            tokio::time::sleep(delay).await;
+            match start(global, &monitored, lookups).await {
+                Ok(started) => break started,
+                Err(e) => {
+                    failures += 1;
+                    if args.max_retries.is_some_and(|max| failures > max) {
+                        return Err(zbus::Error::Failure(format!(
+                            "Lost the connection to the {} bus ({}) and failed to reconnect: {}",
+                            global.bus().name(),
+                            reason,
+                            e
+                        )));
+                    }
+                    delay = (delay * 2).min(MAX_RETRY_DELAY);
+                }
+            }
+        };
+        let gap = GapEvent::new(since, reason);
+        if let Some(journal) = &journal {
+            send(journal, gap_fields(&gap))?;
+            continue;
+        }
+        if let Some(session) = &mut session {
+            session.show(gap.summary(), || {
+                to_json(&gap, false, global.float_format).map_err(|e| {
+                    zbus::Error::Failure(format!("Failed to convert gap to JSON: {}", e))
+                })
+            })?;
+            continue;
+        }
+        print(match global.output {
+            Some(OutputFormat::DbusMonitor) => gap.summary(),
+            _ if human => gap.summary(),
+            _ => to_json_string(global, &gap)?,
+        })?;
+    }
+}
+
+// Open the audit log to add to it. It is only ever appended to, never
+// truncated, and is readable by its owner alone, as the calls it records may
+// say more than their callers would like.
+fn open_audit_log(path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .append(true)
+        .create(true)
+        .mode(0o600)
+        .open(path)
+        .map_err(|e| {
+            zbus::Error::Failure(format!(
+                "Failed to open audit log '{}': {}",
+                path.display(),
+                e
+            ))
+        })
+}
+
+// Connect and become a monitor of the messages matching `rules`, with a
+// connection of its own for `lookups`. The stream is subscribed to before
+// becoming a monitor so no message is missed.
+async fn start(
+    global: &GlobalArgs,
+    rules: &[MatchRule<'_>],
+    lookups: bool,
+) -> Result<(Connection, MessageStream, Option<Arc<Peers>>)> {
+    let peers = match lookups {
+        true => Some(Peers::new(global).await?),
+        false => None,
+    };
+    let connection = connect(global).await?;
+    let stream = MessageStream::from(&connection);
+    let proxy = MonitoringProxy::new(&connection).await?;
+    proxy.become_monitor(rules, 0).await.map_err(|e| match e {
+        zbus::fdo::Error::AccessDenied(_) => zbus::Error::Failure(format!(
+            "Not allowed to monitor the {} bus: {} (try --as-root)",
+            global.bus().name(),
+            e
+        )),
+        e => e.into(),
+    })?;
+    Ok((connection, stream, peers))
+}
+
+// Wait for what is being looked up about a message, if anything. A lookup
+// that failed to finish leaves it out.
+async fn wait(lookup: &mut Option<JoinHandle<Lookup>>) -> Option<Lookup> {
+    match lookup {
+        Some(lookup) => Some(lookup.await.unwrap_or(Lookup {
+            shown: false,
+            sender: None,
+        })),
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_span_between_marks() {
+        let mut session = Session::new(PathBuf::from("."));
+        assert!(session.span().is_none(), "Nothing is kept before a mark");
+
+        session.marks.push(0);
+        session.recorded = vec!["a".to_string(), "b".to_string()];
+        let (lines, between) = session.span().unwrap();
+        assert_eq!(lines, ["a", "b"]);
+        assert_eq!(between, "since mark 1");
+
+        session.marks.push(2);
+        session.recorded.push("c".to_string());
+        session.marks.push(3);
+        let (lines, between) = session.span().unwrap();
+        assert_eq!(lines, ["c"], "Only the last two marks should count");
+        assert_eq!(between, "between marks 2 and 3");
+    }
+}
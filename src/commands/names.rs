@@ -0,0 +1,150 @@
+use super::{connect_pool, format_list, format_table, print_json, print_output, wants_json};
+use crate::{Bus, GlobalArgs};
+use clap::Args;
+use std::collections::{BTreeSet, HashMap};
+use zbus::Result;
+use zbus::fdo::DBusProxy;
+use zbus::names::BusName;
+use zbusctl::activation::{load_service_files, service_dirs};
+use zbusctl::pool::Pool;
+
+#[derive(Args)]
+pub struct NamesArgs {
+    #[arg(long, help = "List names that can be started by activation")]
+    activatable: bool,
+
+    #[arg(
+        long,
+        requires = "activatable",
+        help = "Show the activation file and systemd unit behind each activatable name"
+    )]
+    with_units: bool,
+
+    #[arg(
+        long,
+        value_name = "NAME",
+        conflicts_with = "activatable",
+        help = "List the well-known names owned by the connection with this unique or well-known name"
+    )]
+    owner: Option<String>,
+}
+
+pub async fn run_names_command(global: &GlobalArgs, args: NamesArgs) -> Result<()> {
+    let connections = connect_pool(global).await?;
+    let proxies = global
+        .jobs()
+        .run_all(connections.iter().map(DBusProxy::new))
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>>>()?;
+    let proxies = Pool::new(proxies)?;
+    let proxy = proxies.first();
+
+    let running = proxy
+        .list_names()
+        .await?
+        .into_iter()
+        .map(|name| name.to_string())
+        .collect::<BTreeSet<_>>();
+
+    if let Some(ref owner) = args.owner {
+        let owner = BusName::try_from(owner.as_str())?;
+        let unique = proxy.get_name_owner(owner).await?;
+        let names = running
+            .iter()
+            .filter(|name| !name.starts_with(':'))
+            .map(|name| BusName::try_from(name.as_str()))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let progress = global.progress("names");
+        progress.expect(names.len());
+        let owners = global
+            .jobs()
+            .run_all(
+                names
+                    .iter()
+                    .map(|name| progress.track(proxies.get().get_name_owner(name.clone()))),
+            )
+            .await;
+        drop(progress);
+        // Names can be released between listing and asking.
+        let owned = names
+            .iter()
+            .zip(owners)
+            .filter(|(_, owner)| owner.as_ref().ok() == Some(&unique))
+            .map(|(name, _)| name.to_string())
+            .collect::<BTreeSet<_>>();
+        return print_names(global, &owned);
+    }
+
+    if !args.activatable {
+        return print_names(global, &running);
+    }
+
+    let activatable = proxy
+        .list_activatable_names()
+        .await?
+        .into_iter()
+        .map(|name| name.to_string())
+        .collect::<BTreeSet<_>>();
+
+    if !args.with_units {
+        return print_names(global, &activatable);
+    }
+
+    // Match each activatable name with the first service file declaring it;
+    // earlier directories take precedence, as they do for the bus itself.
+    let (services, errors) = load_service_files(&service_dirs(global.bus() == Bus::System));
+    for error in errors {
+        eprintln!("Warning: {}", error);
+    }
+    let mut by_name = HashMap::new();
+    for service in &services {
+        if let Some(ref name) = service.name {
+            by_name.entry(name.as_str()).or_insert(service);
+        }
+    }
+
+    if wants_json(global) {
+        let entries = activatable
+            .iter()
+            .map(|name| {
+                let service = by_name.get(name.as_str());
+                serde_json::json!({
+                    "name": name,
+                    "running": running.contains(name),
+                    "unit": service.and_then(|s| s.systemd_service.as_deref()),
+                    "exec": service.and_then(|s| s.exec.as_deref()),
+                    "file": service.map(|s| s.path.display().to_string()),
+                })
+            })
+            .collect::<Vec<_>>();
+        return print_json(global, &entries);
+    }
+
+    let rows = activatable
+        .iter()
+        .map(|name| {
+            let service = by_name.get(name.as_str());
+            [
+                name.as_str(),
+                if running.contains(name) { "yes" } else { "no" },
+                service
+                    .and_then(|s| s.systemd_service.as_deref())
+                    .unwrap_or("-"),
+                service.and_then(|s| s.exec.as_deref()).unwrap_or("-"),
+            ]
+        })
+        .collect::<Vec<_>>();
+    print_output(
+        global,
+        &format_table(&["NAME", "RUNNING", "UNIT", "EXEC"], &rows),
+    )
+}
+
+fn print_names(global: &GlobalArgs, names: &BTreeSet<String>) -> Result<()> {
+    if wants_json(global) {
+        print_json(global, names)
+    } else {
+        print_output(global, &format_list(names))
+    }
+}
@@ -0,0 +1,272 @@
+use super::{format_table, print_json, print_output, wants_json};
+use crate::GlobalArgs;
+use clap::{Args, Subcommand};
+use serde_json::json;
+use std::ffi::{CStr, CString};
+use std::path::{Path, PathBuf};
+use zbus::Result;
+use zbus::names::WellKnownName;
+use zbusctl::policy::{Action, Explanation, Identity, explain, load_config};
+
+#[derive(Args)]
+pub struct PolicyArgs {
+    #[command(subcommand)]
+    command: PolicyCommand,
+}
+
+#[derive(Subcommand)]
+enum PolicyCommand {
+    // Explain the rules about a name
+    #[command(about = "Explain which bus policy rules let a user own a name and call its methods")]
+    Explain(ExplainArgs),
+}
+
+#[derive(Args)]
+struct ExplainArgs {
+    #[arg(short, long, help = "D-Bus service name")]
+    service: String,
+
+    #[arg(
+        short,
+        long,
+        help = "D-Bus object path, to leave out rules about other objects"
+    )]
+    object: Option<String>,
+
+    #[arg(
+        short,
+        long,
+        help = "D-Bus interface name, to leave out rules about other interfaces"
+    )]
+    interface: Option<String>,
+
+    #[arg(
+        short,
+        long,
+        help = "D-Bus method name, to leave out rules about other methods"
+    )]
+    method: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "USER",
+        help = "Explain the rules for USER, by name or ID (default: the current user)"
+    )]
+    user: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Read the bus configuration from FILE (default: /usr/share/dbus-1/<bus>.conf)"
+    )]
+    config: Option<PathBuf>,
+}
+
+pub async fn run_policy_command(global: &GlobalArgs, args: PolicyArgs) -> Result<()> {
+    match args.command {
+        PolicyCommand::Explain(explain) => explain_policy(global, explain),
+    }
+}
+
+// Show the rules of the bus configuration that decide whether a user may own
+// a name and call methods on it, since an AccessDenied error names neither
+// the rule nor the file it is in. Only the rules the bus would apply are
+// shown, in the order it applies them.
+fn explain_policy(global: &GlobalArgs, args: ExplainArgs) -> Result<()> {
+    WellKnownName::try_from(args.service.as_str())?;
+    let config = match args.config {
+        Some(path) => path,
+        None => default_config(global.bus().name()),
+    };
+    let rules = load_config(&config)?;
+    let identity = identity(args.user.as_deref())?;
+
+    let own = explain(&rules, &identity, Action::Own(&args.service));
+    let call = explain(
+        &rules,
+        &identity,
+        Action::Call {
+            destination: &args.service,
+            interface: args.interface.as_deref(),
+            member: args.method.as_deref(),
+            path: args.object.as_deref(),
+        },
+    );
+
+    if wants_json(global) {
+        return print_json(
+            global,
+            &json!({
+                "user": identity.user,
+                "own": explanation_to_json(&own),
+                "call": explanation_to_json(&call),
+            }),
+        );
+    }
+
+    let mut out = String::new();
+    for (what, explanation) in [
+        (format!("Own {}", args.service), &own),
+        (
+            match (&args.interface, &args.method) {
+                (Some(interface), Some(method)) => format!("Call {}.{}", interface, method),
+                (None, Some(method)) => format!("Call {}", method),
+                _ => "Call methods".to_string(),
+            } + &format!(" of {}", args.service),
+            &call,
+        ),
+    ] {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out.push_str(&format!(
+            "{} as {}: {}\n",
+            what,
+            identity.user,
+            verdict(explanation)
+        ));
+        let rows = explanation
+            .steps
+            .iter()
+            .map(|step| {
+                // `*` marks the deciding rule, `?` rules that only apply to
+                // some of the methods.
+                let mark = match explanation.decided_by == Some(step.rule) {
+                    true => "*",
+                    false if !step.conditions.is_empty() => "?",
+                    false => "",
+                };
+                [
+                    mark.to_string(),
+                    format!("{}:{}", step.rule.file.display(), step.rule.line),
+                    step.rule.policy.to_string(),
+                    step.rule.to_string(),
+                ]
+            })
+            .collect::<Vec<_>>();
+        if !rows.is_empty() {
+            out.push_str(&format_table(&["", "FILE", "POLICY", "RULE"], &rows));
+        }
+    }
+    print_output(global, &out)
+}
+
+fn verdict(explanation: &Explanation) -> String {
+    let allowed = if explanation.allowed {
+        "allowed"
+    } else {
+        "denied"
+    };
+    match explanation.decided_by {
+        Some(rule) => format!("{} by {}:{}", allowed, rule.file.display(), rule.line),
+        None => format!("{}, as no rule is about it", allowed),
+    }
+}
+
+fn explanation_to_json(explanation: &Explanation) -> serde_json::Value {
+    let rules = explanation
+        .steps
+        .iter()
+        .map(|step| {
+            let mut rule = json!({
+                "file": step.rule.file,
+                "line": step.rule.line,
+                "policy": step.rule.policy.to_string(),
+                "rule": step.rule.to_string(),
+                "decides": explanation.decided_by == Some(step.rule),
+            });
+            if !step.conditions.is_empty() {
+                rule["conditions"] = json!(step.conditions);
+            }
+            rule
+        })
+        .collect::<Vec<_>>();
+    json!({"allowed": explanation.allowed, "rules": rules})
+}
+
+// The main configuration file of a bus, which includes the policy files of
+// the services on it. Older systems keep it in /etc.
+fn default_config(bus: &str) -> PathBuf {
+    let shared = Path::new("/usr/share/dbus-1").join(format!("{}.conf", bus));
+    if shared.exists() {
+        return shared;
+    }
+    Path::new("/etc/dbus-1").join(format!("{}.conf", bus))
+}
+
+// Look up a user, by name or ID, or the current user, with the groups it is
+// in.
+fn identity(user: Option<&str>) -> Result<Identity> {
+    let entry = match user {
+        Some(user) => match user.parse::<libc::uid_t>() {
+            Ok(uid) => unsafe { libc::getpwuid(uid) },
+            Err(_) => {
+                let name = CString::new(user)
+                    .map_err(|_| zbus::Error::Failure(format!("Invalid user name '{}'", user)))?;
+                unsafe { libc::getpwnam(name.as_ptr()) }
+            }
+        },
+        None => unsafe { libc::getpwuid(libc::geteuid()) },
+    };
+    if entry.is_null() {
+        return Err(zbus::Error::Failure(format!(
+            "No such user '{}'",
+            user.unwrap_or("(current user)")
+        )));
+    }
+    let (name, uid, gid) = unsafe {
+        (
+            CStr::from_ptr((*entry).pw_name).to_owned(),
+            (*entry).pw_uid,
+            (*entry).pw_gid,
+        )
+    };
+
+    // Ask again with more room if the user is in more groups than fit.
+    let mut gids = vec![0 as libc::gid_t; 64];
+    loop {
+        let mut count = gids.len() as libc::c_int;
+        let found =
+            unsafe { libc::getgrouplist(name.as_ptr(), gid, gids.as_mut_ptr(), &mut count) };
+        if found >= 0 {
+            gids.truncate(count as usize);
+            break;
+        }
+        gids.resize(count.max(gids.len() as libc::c_int * 2) as usize, 0);
+    }
+    let groups = gids
+        .into_iter()
+        .map(|gid| (group_name(gid).unwrap_or_else(|| gid.to_string()), gid))
+        .collect();
+    Ok(Identity {
+        user: name.to_string_lossy().into_owned(),
+        uid,
+        groups,
+    })
+}
+
+// The name of a user, if it has an entry.
+pub fn user_name(uid: libc::uid_t) -> Option<String> {
+    let entry = unsafe { libc::getpwuid(uid) };
+    match entry.is_null() {
+        true => None,
+        false => Some(
+            unsafe { CStr::from_ptr((*entry).pw_name) }
+                .to_string_lossy()
+                .into_owned(),
+        ),
+    }
+}
+
+// The name of a group, if it has an entry.
+pub fn group_name(gid: libc::gid_t) -> Option<String> {
+    let group = unsafe { libc::getgrgid(gid) };
+    match group.is_null() {
+        true => None,
+        false => Some(
+            unsafe { CStr::from_ptr((*group).gr_name) }
+                .to_string_lossy()
+                .into_owned(),
+        ),
+    }
+}
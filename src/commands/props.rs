@@ -0,0 +1,758 @@
+use super::introspect::introspect;
+use super::{connect, connect_pool, format_table, print_json, print_output, wants_json};
+use crate::GlobalArgs;
+use clap::{ArgGroup, Args};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use std::io::IsTerminal;
+use zbus::Result;
+use zbus::fdo::PropertiesProxy;
+use zbus::names::{BusName, InterfaceName};
+use zbus_xml::Property;
+use zbusctl::cache::Cache;
+use zbusctl::format::{merge_json, value_to_json};
+use zbusctl::introspect::parse_node;
+use zbusctl::pool::Pool;
+use zbusctl::snapshot::Snapshot;
+use zbusctl::{append_elements, build_body_with, coerce, remove_elements};
+use zvariant::{ObjectPath, OwnedValue};
+#[cfg(feature = "props")]
+use {
+    clap::Subcommand,
+    serde::Serialize,
+    std::fs,
+    std::path::{Path, PathBuf},
+    zbusctl::format::typed_json_to_value,
+    zbusctl::redact::REDACTED,
+    zbusctl::snapshot::{diff, load_snapshot},
+};
+
+#[cfg(feature = "props")]
+#[derive(Args)]
+pub struct PropsDiffArgs {
+    #[arg(
+        short,
+        long,
+        required_unless_present = "after",
+        help = "D-Bus service name"
+    )]
+    service: Option<String>,
+
+    #[arg(
+        short,
+        long,
+        required_unless_present = "after",
+        help = "D-Bus object path"
+    )]
+    object: Option<String>,
+
+    #[arg(
+        short,
+        long,
+        help = "Only compare properties of this interface (can be repeated)"
+    )]
+    interface: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        required_unless_present = "save",
+        help = "Snapshot to compare against"
+    )]
+    before: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "before",
+        help = "Compare with this snapshot instead of the live values"
+    )]
+    after: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with = "after",
+        help = "Save the live values as a snapshot to FILE"
+    )]
+    save: Option<PathBuf>,
+}
+
+#[derive(Args)]
+#[command(group = ArgGroup::new("edit").args(["append", "remove", "merge_json"]))]
+pub struct SetPropertyArgs {
+    #[arg(short, long, help = "D-Bus service name")]
+    service: String,
+
+    #[arg(short, long, help = "D-Bus object path")]
+    object: String,
+
+    #[arg(short, long, help = "D-Bus interface name")]
+    interface: String,
+
+    #[arg(short, long, help = "D-Bus property name")]
+    property: String,
+
+    #[arg(
+        value_name = "TYPE:VALUE",
+        required_unless_present = "merge_json",
+        conflicts_with = "merge_json",
+        help = "New value, in the same syntax as `call` arguments"
+    )]
+    value: Option<String>,
+
+    #[arg(
+        long,
+        help = "Add VALUE to the current array, or its entries to the current dictionary"
+    )]
+    append: bool,
+
+    #[arg(
+        long,
+        help = "Remove VALUE from the current array, or the entry with key VALUE from the current dictionary"
+    )]
+    remove: bool,
+
+    #[arg(
+        long,
+        value_name = "JSON",
+        help = "Apply a JSON merge patch to the current value"
+    )]
+    merge_json: Option<String>,
+
+    #[arg(
+        long,
+        requires = "edit",
+        help = "Read the value again before writing it back, and fail if it changed meanwhile"
+    )]
+    check_unchanged: bool,
+}
+
+#[derive(Args)]
+pub struct GetAllPropertiesArgs {
+    #[arg(short, long, help = "D-Bus service name")]
+    service: String,
+
+    #[arg(short, long, help = "D-Bus object path")]
+    object: String,
+
+    #[arg(
+        short,
+        long,
+        help = "D-Bus interface name (default: every interface of the object)"
+    )]
+    interface: Option<String>,
+}
+
+#[cfg(feature = "props")]
+#[derive(Args)]
+pub struct PropsArgs {
+    #[command(subcommand)]
+    command: PropsCommand,
+}
+
+#[cfg(feature = "props")]
+#[derive(Subcommand)]
+enum PropsCommand {
+    // Save writable properties
+    #[command(about = "Save the values of an object's writable properties to a file")]
+    Export(ExportArgs),
+
+    // Restore saved properties
+    #[command(about = "Set an object's properties to the values saved by `props export`")]
+    Import(ImportArgs),
+}
+
+// The object whose properties are exported or imported.
+#[cfg(feature = "props")]
+#[derive(Args)]
+struct ObjectArgs {
+    #[arg(short, long, help = "D-Bus service name")]
+    service: String,
+
+    #[arg(short, long, help = "D-Bus object path")]
+    object: String,
+
+    #[arg(
+        short,
+        long,
+        help = "Only include properties of this interface (can be repeated)"
+    )]
+    interface: Vec<String>,
+}
+
+#[cfg(feature = "props")]
+#[derive(Args)]
+struct ExportArgs {
+    #[command(flatten)]
+    target: ObjectArgs,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Write the properties to FILE instead of stdout"
+    )]
+    file: Option<PathBuf>,
+}
+
+#[cfg(feature = "props")]
+#[derive(Args)]
+struct ImportArgs {
+    #[command(flatten)]
+    target: ObjectArgs,
+
+    #[arg(value_name = "FILE", help = "Properties saved by `props export`")]
+    file: PathBuf,
+}
+
+// Exported properties by interface, each with its signature so it can be set
+// again with the right type.
+#[cfg(feature = "props")]
+type Export = BTreeMap<String, BTreeMap<String, serde_json::Value>>;
+
+// What importing one property did.
+#[cfg(feature = "props")]
+#[derive(Serialize)]
+struct Imported {
+    interface: String,
+    property: String,
+    result: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[cfg(feature = "props")]
+pub async fn run_props_command(global: &GlobalArgs, args: PropsArgs) -> Result<()> {
+    match args.command {
+        PropsCommand::Export(export) => export_properties(global, export).await,
+        PropsCommand::Import(import) => import_properties(global, import).await,
+    }
+}
+
+#[cfg(feature = "props")]
+async fn export_properties(global: &GlobalArgs, args: ExportArgs) -> Result<()> {
+    let target = &args.target;
+    let properties = read_properties(
+        global,
+        &target.service,
+        &target.object,
+        &target.interface,
+        true,
+    )
+    .await?;
+    // Hidden values keep their signature, so importing can tell them apart.
+    let redactor = global.redactor();
+    let export = properties
+        .iter()
+        .map(|(interface, values)| {
+            let values = values
+                .iter()
+                .map(|(name, value)| {
+                    let mut json = serde_json::to_value(&**value)?;
+                    if redactor.redacts_property(interface, name) {
+                        json["value"] = REDACTED.into();
+                    }
+                    Ok((name.clone(), json))
+                })
+                .collect::<serde_json::Result<_>>()?;
+            Ok((interface.clone(), values))
+        })
+        .collect::<serde_json::Result<Export>>()
+        .map_err(|e| {
+            zbus::Error::Failure(format!("Failed to convert properties to JSON: {}", e))
+        })?;
+
+    let Some(ref file) = args.file else {
+        return print_json(global, &export);
+    };
+    let json = serde_json::to_string_pretty(&export).map_err(|e| {
+        zbus::Error::Failure(format!("Failed to convert properties to JSON: {}", e))
+    })?;
+    fs::write(file, format!("{}\n", json))
+        .map_err(|e| zbus::Error::Failure(format!("Failed to write '{}': {}", file.display(), e)))
+}
+
+#[cfg(feature = "props")]
+fn load_export(path: &Path) -> Result<Export> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| zbus::Error::Failure(format!("Failed to read '{}': {}", path.display(), e)))?;
+    serde_json::from_str(&contents).map_err(|e| {
+        zbus::Error::Failure(format!(
+            "Invalid properties file '{}': {}",
+            path.display(),
+            e
+        ))
+    })
+}
+
+// Set every saved property whose value differs from the live one. A property
+// that cannot be set, say for lack of permission, does not stop the others;
+// nor does one whose value was redacted on export, which is never set.
+#[cfg(feature = "props")]
+async fn import_properties(global: &GlobalArgs, args: ImportArgs) -> Result<()> {
+    let target = &args.target;
+    let service = BusName::try_from(target.service.as_str())?;
+    ObjectPath::try_from(target.object.as_str())?;
+    for interface in &target.interface {
+        InterfaceName::try_from(interface.as_str())?;
+    }
+
+    // Check the whole file before setting anything.
+    let mut wanted = Vec::new();
+    for (interface, values) in load_export(&args.file)? {
+        if !(target.interface.is_empty() || target.interface.contains(&interface)) {
+            continue;
+        }
+        let name = InterfaceName::try_from(interface.clone())?;
+        for (property, json) in values {
+            // A value hidden on export is not the value, so it is not set.
+            if json["value"] == REDACTED {
+                wanted.push((name.clone(), property, None));
+                continue;
+            }
+            let value = typed_json_to_value(&json).map_err(|e| {
+                zbus::Error::Failure(format!(
+                    "Invalid value for {}.{}: {}",
+                    interface, property, e
+                ))
+            })?;
+            wanted.push((name.clone(), property, Some(value)));
+        }
+    }
+
+    let connection = connect(global).await?;
+    let proxy = PropertiesProxy::builder(&connection)
+        .destination(&service)?
+        .path(target.object.as_str())?
+        .build()
+        .await?;
+
+    let mut results = Vec::new();
+    for (interface, property, value) in wanted {
+        let Some(value) = value else {
+            results.push(Imported {
+                interface: interface.to_string(),
+                property,
+                result: "skipped",
+                error: Some("its value was redacted when it was exported".to_string()),
+            });
+            continue;
+        };
+        let current = proxy.get(interface.clone(), &property).await.ok();
+        let unchanged = current.is_some_and(|current| {
+            serde_json::to_value(&*current).ok() == serde_json::to_value(&value).ok()
+        });
+        let (result, error) = if unchanged {
+            ("unchanged", None)
+        } else {
+            match proxy.set(interface.clone(), &property, value).await {
+                Ok(()) => ("set", None),
+                Err(e) => ("failed", Some(e.to_string())),
+            }
+        };
+        results.push(Imported {
+            interface: interface.to_string(),
+            property,
+            result,
+            error,
+        });
+    }
+
+    if wants_json(global) {
+        print_json(global, &results)?;
+    } else {
+        let rows = results
+            .iter()
+            .map(|r| {
+                [
+                    format!("{}.{}", r.interface, r.property),
+                    match r.error {
+                        Some(ref error) => format!("{}: {}", r.result, error),
+                        None => r.result.to_string(),
+                    },
+                ]
+            })
+            .collect::<Vec<_>>();
+        print_output(global, &format_table(&["PROPERTY", "RESULT"], &rows))?;
+    }
+
+    let failed = results.iter().filter(|r| r.error.is_some()).count();
+    if failed > 0 {
+        return Err(zbus::Error::Failure(format!(
+            "Failed to set {} of {} properties",
+            failed,
+            results.len()
+        )));
+    }
+    Ok(())
+}
+
+// Set one property with `org.freedesktop.DBus.Properties.Set`. The value is
+// parsed like a `call` argument and sent in the variant `Set` expects, so
+// there is no need to spell out the wrapping. A value of another type than
+// the introspected one is converted where nothing is lost, such as `int32:5`
+// for a `u` property, and refused before anything is sent otherwise;
+// properties the object does not list are set unchecked, as some services
+// add them at run time. With `--append`, `--remove` or `--merge-json`, the
+// current value is read and edited instead, and nothing is sent if the edit
+// leaves it as it was. The cached `GetAll` reply for the interface is
+// dropped afterwards.
+pub async fn run_set_property_command(global: &GlobalArgs, args: SetPropertyArgs) -> Result<()> {
+    let service = BusName::try_from(args.service.as_str())?;
+    ObjectPath::try_from(args.object.as_str())?;
+    let interface = InterfaceName::try_from(args.interface.as_str())?;
+    let given = match &args.value {
+        Some(value) => Some(
+            build_body_with(vec![value.as_str()], &global.parse_options())?
+                .into_fields()
+                .remove(0),
+        ),
+        None => None,
+    };
+    let patch = match &args.merge_json {
+        Some(json) => Some(
+            serde_json::from_str::<serde_json::Value>(json)
+                .map_err(|e| zbus::Error::Failure(format!("Invalid JSON patch: {}", e)))?,
+        ),
+        None => None,
+    };
+
+    let connection = connect(global).await?;
+    let cache = global.cache();
+    let proxy = PropertiesProxy::builder(&connection)
+        .destination(&service)?
+        .path(args.object.as_str())?
+        .build()
+        .await?;
+    let read = || async {
+        proxy
+            .get(interface.clone(), &args.property)
+            .await
+            .map_err(|e| {
+                zbus::Error::Failure(format!(
+                    "Failed to read {}.{}: {}",
+                    args.interface, args.property, e
+                ))
+            })
+    };
+
+    let (mut value, before) = match (given, &patch) {
+        (_, Some(patch)) => {
+            let current = read().await?;
+            (merge_json(&current, patch)?, Some(current))
+        }
+        (Some(given), None) if args.append || args.remove => {
+            let current = read().await?;
+            let value = if args.append {
+                append_elements(&current, &given)?
+            } else {
+                remove_elements(&current, &given)?
+            };
+            (value, Some(current))
+        }
+        (Some(given), None) => (given, None),
+        (None, None) => unreachable!("clap requires a value or --merge-json"),
+    };
+    if before.as_deref() == Some(&value) {
+        return Ok(());
+    }
+
+    let xml = introspect(&connection, cache.as_ref(), &service, &args.object).await;
+    let node = xml.and_then(|xml| parse_node(&args.object, &xml));
+    let property = node.as_ref().ok().and_then(|node| {
+        node.interfaces()
+            .iter()
+            .find(|i| i.name() == interface.as_str())?
+            .properties()
+            .iter()
+            .find(|p| p.name() == args.property.as_str())
+    });
+    if let Some(property) = property {
+        let expected = property.ty().to_string();
+        let given = value.value_signature().to_string();
+        if given != expected {
+            value = coerce(&value, &expected).ok_or_else(|| {
+                zbus::Error::Failure(format!(
+                    "{}.{} has type '{}', but the value given is of type '{}' and cannot be converted",
+                    args.interface, args.property, expected, given
+                ))
+            })?;
+        }
+    }
+
+    // Checked as late as possible, though another client can still change
+    // the value between this read and the `Set`.
+    if args.check_unchanged && before != Some(read().await?) {
+        return Err(zbus::Error::Failure(format!(
+            "{}.{} changed since it was read; not setting it",
+            args.interface, args.property
+        )));
+    }
+    proxy
+        .set(interface.clone(), &args.property, value)
+        .await
+        .map_err(|e| {
+            zbus::Error::Failure(format!(
+                "Failed to set {}.{}: {}",
+                args.interface, args.property, e
+            ))
+        })?;
+    if let Some(cache) = cache {
+        cache.remove(&get_all_key(&service, &args.object, &interface))?;
+    }
+    Ok(())
+}
+
+// Print every property of an interface, or of every interface of an object,
+// as name → value. Scripts get a JSON object, keyed by interface first when
+// there are several; humans at a terminal get a table.
+pub async fn run_get_all_properties_command(
+    global: &GlobalArgs,
+    args: GetAllPropertiesArgs,
+) -> Result<()> {
+    let interfaces = args.interface.iter().cloned().collect::<Vec<_>>();
+    let mut properties = snapshot(global, &args.service, &args.object, &interfaces).await?;
+    global.redactor().redact_snapshot(&mut properties);
+    if let Some(ref interface) = args.interface
+        && !properties.contains_key(interface)
+    {
+        return Err(zbus::Error::Failure(format!(
+            "{} on '{}' has no properties in interface {}",
+            args.service, args.object, interface
+        )));
+    }
+
+    if wants_json(global) || !std::io::stdout().is_terminal() {
+        return match args.interface {
+            Some(ref interface) => print_json(global, &properties[interface]),
+            None => print_json(global, &properties),
+        };
+    }
+    let qualify = args.interface.is_none();
+    let rows = properties
+        .iter()
+        .flat_map(|(interface, values)| {
+            values.iter().map(move |(property, value)| {
+                [
+                    match qualify {
+                        false => property.clone(),
+                        true => format!("{}.{}", interface, property),
+                    },
+                    match value {
+                        serde_json::Value::String(s) => s.clone(),
+                        value => value.to_string(),
+                    },
+                ]
+            })
+        })
+        .collect::<Vec<_>>();
+    print_output(global, &format_table(&["PROPERTY", "VALUE"], &rows))
+}
+
+#[cfg(feature = "props")]
+pub async fn run_props_diff_command(global: &GlobalArgs, args: PropsDiffArgs) -> Result<()> {
+    let keep = |snapshot: Snapshot| -> Snapshot {
+        snapshot
+            .into_iter()
+            .filter(|(name, _)| args.interface.is_empty() || args.interface.contains(name))
+            .collect()
+    };
+
+    let after = match args.after {
+        Some(ref path) => load_snapshot(path)?,
+        None => {
+            // Both are required unless --after is given.
+            let service = args.service.as_deref().unwrap_or_default();
+            let object = args.object.as_deref().unwrap_or_default();
+            let live = snapshot(global, service, object, &args.interface).await?;
+            if let Some(ref file) = args.save {
+                let json = serde_json::to_string_pretty(&live).map_err(|e| {
+                    zbus::Error::Failure(format!("Failed to convert snapshot to JSON: {}", e))
+                })?;
+                fs::write(file, format!("{}\n", json)).map_err(|e| {
+                    zbus::Error::Failure(format!("Failed to write '{}': {}", file.display(), e))
+                })?;
+            }
+            live
+        }
+    };
+    let Some(ref before) = args.before else {
+        return Ok(());
+    };
+    let mut changes = diff(&keep(load_snapshot(before)?), &keep(after));
+    // Hidden values are replaced after comparing them, so a change to one
+    // still shows.
+    let redactor = global.redactor();
+    for change in changes
+        .iter_mut()
+        .filter(|change| redactor.redacts_property(&change.interface, &change.property))
+    {
+        for value in [&mut change.before, &mut change.after]
+            .into_iter()
+            .flatten()
+        {
+            *value = REDACTED.into();
+        }
+    }
+
+    if wants_json(global) {
+        return print_json(global, &changes);
+    }
+
+    let cell = |value: &Option<serde_json::Value>| match value {
+        None => "-".to_string(),
+        Some(serde_json::Value::String(s)) => s.clone(),
+        Some(value) => value.to_string(),
+    };
+    let rows = changes
+        .iter()
+        .map(|change| {
+            [
+                format!("{}.{}", change.interface, change.property),
+                cell(&change.before),
+                cell(&change.after),
+            ]
+        })
+        .collect::<Vec<_>>();
+    if rows.is_empty() {
+        return Ok(());
+    }
+    print_output(
+        global,
+        &format_table(&["PROPERTY", "BEFORE", "AFTER"], &rows),
+    )
+}
+
+// Read the current value of every readable property of an object, or only of
+// the given interfaces.
+async fn snapshot(
+    global: &GlobalArgs,
+    service: &str,
+    object: &str,
+    interfaces: &[String],
+) -> Result<Snapshot> {
+    let properties = read_properties(global, service, object, interfaces, false).await?;
+    properties
+        .into_iter()
+        .map(|(interface, values)| {
+            let values = values
+                .iter()
+                .map(|(property, value)| {
+                    Ok((property.clone(), value_to_json(value, global.numbers())?))
+                })
+                .collect::<Result<_>>()?;
+            Ok((interface, values))
+        })
+        .collect()
+}
+
+// Read all properties of one interface, from the cache if one is given and
+// holds them.
+async fn get_all(
+    proxy: &PropertiesProxy<'_>,
+    cache: Option<&Cache>,
+    interface: &str,
+) -> Result<HashMap<String, OwnedValue>> {
+    let fetch = async { Ok(proxy.get_all(InterfaceName::try_from(interface)?).await?) };
+    match cache {
+        Some(cache) => {
+            let key = get_all_key(
+                proxy.inner().destination(),
+                proxy.inner().path(),
+                &interface,
+            );
+            cache.get_or_fetch(&key, fetch).await
+        }
+        None => fetch.await,
+    }
+}
+
+// The cache key of the `GetAll` reply for one interface of an object.
+fn get_all_key(service: &impl Display, path: &impl Display, interface: &impl Display) -> String {
+    format!("{} {} GetAll {}", service, path, interface)
+}
+
+// Read the current values of an object's properties, by interface, for every
+// interface or only the given ones. Named interfaces are read with `GetAll`
+// alone, so services without introspection data and properties missing from
+// it are still covered; the object is introspected only to list its
+// interfaces. With `writable_only`, properties that the introspection data
+// does not declare writable are left out.
+async fn read_properties(
+    global: &GlobalArgs,
+    service: &str,
+    object: &str,
+    interfaces: &[String],
+    writable_only: bool,
+) -> Result<BTreeMap<String, BTreeMap<String, OwnedValue>>> {
+    ObjectPath::try_from(object)?;
+    for interface in interfaces {
+        InterfaceName::try_from(interface.as_str())?;
+    }
+
+    let connections = connect_pool(global).await?;
+    let connection = connections.first();
+    let service = BusName::try_from(service)?;
+    let cache = global.cache();
+    let proxies = global
+        .jobs()
+        .run_all(connections.iter().map(|connection| async {
+            PropertiesProxy::builder(connection)
+                .destination(&service)?
+                .path(object)?
+                .build()
+                .await
+        }))
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>>>()?;
+    let proxies = Pool::new(proxies)?;
+
+    // The names of the interfaces to read, with the writable properties of
+    // each when only those are wanted.
+    let selected: Vec<(String, Option<Vec<String>>)> = if interfaces.is_empty() || writable_only {
+        let xml = introspect(connection, cache.as_ref(), &service, object).await?;
+        let node = parse_node(object, &xml)?;
+        let wanted = |property: &Property| !writable_only || property.access().write();
+        node.interfaces()
+            .iter()
+            .filter(|interface| {
+                interface.properties().iter().any(wanted)
+                    && (interfaces.is_empty()
+                        || interfaces.iter().any(|i| i == interface.name().as_str()))
+            })
+            .map(|interface| {
+                let writable = writable_only.then(|| {
+                    interface
+                        .properties()
+                        .iter()
+                        .filter(|p| wanted(p))
+                        .map(|p| p.name().to_string())
+                        .collect()
+                });
+                (interface.name().to_string(), writable)
+            })
+            .collect()
+    } else {
+        interfaces.iter().map(|i| (i.clone(), None)).collect()
+    };
+    let progress = global.progress("interfaces");
+    progress.expect(selected.len());
+    let all_values = global
+        .jobs()
+        .run_all(selected.iter().map(|(interface, _)| {
+            progress.track(get_all(proxies.get(), cache.as_ref(), interface))
+        }))
+        .await;
+    drop(progress);
+
+    let mut properties = BTreeMap::new();
+    for ((interface, writable), values) in selected.into_iter().zip(all_values) {
+        let values = values?
+            .into_iter()
+            .filter(|(name, _)| writable.as_ref().is_none_or(|w| w.contains(name)))
+            .collect();
+        properties.insert(interface, values);
+    }
+    Ok(properties)
+}
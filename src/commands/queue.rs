@@ -0,0 +1,140 @@
+use super::{
+    connect_target, format_table, is_unknown_service, print_json, print_output, send_method_call,
+    state_dir, wants_json,
+};
+use crate::GlobalArgs;
+use clap::Args;
+use serde::Serialize;
+use std::collections::HashMap;
+use zbus::message::Message;
+use zbus::{Connection, Result};
+use zbusctl::queue::{BusTarget, Queue, QueuedCall};
+
+#[derive(Args)]
+pub struct FlushQueueArgs {}
+
+#[derive(Serialize)]
+struct Outcome {
+    call: String,
+    result: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+}
+
+// The queue of calls made with `call --queue-if-unreachable`.
+fn queue() -> Result<Queue> {
+    state_dir()
+        .map(|dir| Queue::new(dir.join("queue")))
+        .ok_or_else(|| {
+            zbus::Error::Failure(
+                "No place for the call queue: neither XDG_STATE_HOME nor HOME is set".to_string(),
+            )
+        })
+}
+
+// Put a call that could not reach its service on the queue instead of
+// failing, so a provisioning step can go on while the service is missing.
+// The bus is kept as it was reached, with its address and namespaces, so
+// the call goes to the same bus whatever environment the flush runs in.
+pub fn queue_call(global: &GlobalArgs, msg: &Message, reason: &zbus::Error) -> Result<()> {
+    // Queued calls are kept with the bus they are for, and a peer is not one.
+    if global.peer.is_some() {
+        return Err(zbus::Error::Failure(format!(
+            "Calls to a peer are not queued; the call failed: {}",
+            reason
+        )));
+    }
+    let call = QueuedCall::new(global.target(global.bus()), msg, &reason.to_string());
+    let path = queue()?.push(&call)?;
+    eprintln!(
+        "Note: queued the call to {} in '{}', as it could not be delivered: {}. Run `zbusctl flush-queue` to send it.",
+        call.describe(),
+        path.display(),
+        reason
+    );
+    Ok(())
+}
+
+// Try the queued calls again, oldest first. A call that is delivered leaves
+// the queue, even if the service answers with an error, since sending it
+// again would repeat it; one whose bus or service is still unreachable
+// stays for the next flush. Each call is claimed before it is sent, so
+// flushes running at the same time never send the same call twice. Replies
+// are not printed, only what became of each call.
+pub async fn run_flush_queue_command(global: &GlobalArgs, _args: FlushQueueArgs) -> Result<()> {
+    let queue = queue()?;
+    let mut connections = HashMap::<BusTarget, std::result::Result<Connection, String>>::new();
+    let mut outcomes = Vec::new();
+    for (path, call) in queue.entries()? {
+        let Some(claimed) = queue.claim(&path)? else {
+            continue;
+        };
+        let connection = match connections.get(&call.target) {
+            Some(connection) => connection,
+            None => {
+                let connection = connect_target(global, &call.target)
+                    .await
+                    .map_err(|e| e.to_string());
+                connections.entry(call.target.clone()).or_insert(connection)
+            }
+        };
+        let (result, detail) = match connection {
+            Ok(connection) => send(connection, &call).await,
+            Err(e) => ("queued", Some(e.clone())),
+        };
+        match result {
+            "queued" => queue.release(&claimed, &path)?,
+            _ => queue.remove(&claimed)?,
+        }
+        outcomes.push(Outcome {
+            call: call.describe(),
+            result,
+            detail,
+        });
+    }
+
+    if wants_json(global) {
+        print_json(global, &outcomes)?;
+    } else if !outcomes.is_empty() {
+        let rows = outcomes
+            .iter()
+            .map(|o| {
+                [
+                    o.call.clone(),
+                    o.result.to_string(),
+                    o.detail.clone().unwrap_or_default(),
+                ]
+            })
+            .collect::<Vec<_>>();
+        print_output(global, &format_table(&["CALL", "RESULT", "DETAIL"], &rows))?;
+    }
+
+    let count = |result: &str| outcomes.iter().filter(|o| o.result == result).count();
+    let mut problems = Vec::new();
+    match count("failed") {
+        0 => {}
+        1 => problems.push("1 queued call failed".to_string()),
+        n => problems.push(format!("{} queued calls failed", n)),
+    }
+    match count("queued") {
+        0 => {}
+        1 => problems.push("1 call is still queued".to_string()),
+        n => problems.push(format!("{} calls are still queued", n)),
+    }
+    if !problems.is_empty() {
+        return Err(zbus::Error::Failure(problems.join(", ")));
+    }
+    Ok(())
+}
+
+async fn send(connection: &Connection, call: &QueuedCall) -> (&'static str, Option<String>) {
+    let msg = match call.message() {
+        Ok(msg) => msg,
+        Err(e) => return ("failed", Some(e.to_string())),
+    };
+    match send_method_call(connection, &msg, None).await {
+        Ok(_) => ("sent", None),
+        Err(e) if is_unknown_service(&e) => ("queued", Some(e.to_string())),
+        Err(e) => ("failed", Some(e.to_string())),
+    }
+}
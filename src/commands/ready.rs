@@ -0,0 +1,227 @@
+use super::connect;
+use crate::GlobalArgs;
+use clap::Args;
+use futures_lite::StreamExt;
+use std::time::Duration;
+use zbus::fdo::{DBusProxy, PropertiesProxy};
+use zbus::names::{BusName, InterfaceName};
+use zbus::{Connection, Result};
+use zbusctl::format::display_value;
+use zbusctl::readiness::PropertyCondition;
+use zvariant::ObjectPath;
+
+#[derive(Args)]
+pub struct WaitUntilReadyArgs {
+    #[arg(short, long, help = "D-Bus service name")]
+    service: String,
+
+    #[arg(
+        short,
+        long,
+        default_value = "/",
+        help = "Object to ping and read properties from"
+    )]
+    object: String,
+
+    #[arg(
+        long = "property",
+        value_name = "INTERFACE.PROPERTY=VALUE",
+        help = "Also wait until the property has this value (can be repeated)"
+    )]
+    properties: Vec<PropertyCondition>,
+
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        help = "Give up after SECONDS (default: wait until stopped)"
+    )]
+    max_wait: Option<f64>,
+
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value_t = 0.2,
+        help = "How long to wait between checks"
+    )]
+    interval: f64,
+}
+
+#[derive(Args)]
+pub struct WaitForNameArgs {
+    #[arg(help = "Bus name to wait for")]
+    name: String,
+
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        help = "Give up after SECONDS (default: the global --timeout, or wait until stopped)"
+    )]
+    max_wait: Option<f64>,
+}
+
+impl WaitForNameArgs {
+    // How long to wait for an owner: --max-wait, or else the global
+    // --timeout, so `wait-for-name NAME --timeout 30` gives up after 30
+    // seconds as it reads.
+    pub fn max_wait(&self, global: &GlobalArgs) -> Result<Option<Duration>> {
+        match self.max_wait {
+            Some(secs) => Duration::try_from_secs_f64(secs)
+                .map(Some)
+                .map_err(|e| zbus::Error::Failure(format!("Invalid duration '{}': {}", secs, e))),
+            None => global.timeout(),
+        }
+    }
+}
+
+pub async fn run_wait_until_ready_command(
+    global: &GlobalArgs,
+    args: WaitUntilReadyArgs,
+) -> Result<()> {
+    let service = BusName::try_from(args.service.as_str())?;
+    ObjectPath::try_from(args.object.as_str())?;
+    for condition in &args.properties {
+        InterfaceName::try_from(condition.interface.as_str())?;
+    }
+    let seconds = |secs: f64| {
+        Duration::try_from_secs_f64(secs)
+            .map_err(|e| zbus::Error::Failure(format!("Invalid duration '{}': {}", secs, e)))
+    };
+    let interval = seconds(args.interval)?;
+    let max_wait = args.max_wait.map(seconds).transpose()?;
+
+    let connection = connect(global).await?;
+    let mut waiting_for = String::from("the first check");
+    let wait = async {
+        loop {
+            match check(&connection, &service, &args.object, &args.properties).await {
+                Ok(()) => return,
+                Err(reason) => waiting_for = reason,
+            }
+            tokio::time::sleep(interval).await;
+        }
+    };
+
+    match max_wait {
+        Some(max_wait) => tokio::time::timeout(max_wait, wait).await.map_err(|_| {
+            zbus::Error::Failure(format!(
+                "{} is not ready after {} seconds: {}",
+                service,
+                max_wait.as_secs_f64(),
+                waiting_for
+            ))
+        }),
+        None => {
+            wait.await;
+            Ok(())
+        }
+    }
+}
+
+// Check each readiness condition in turn, returning what is still missing.
+// The name is checked before anything is sent to it, so waiting doesn't
+// trigger activation of the service.
+async fn check(
+    connection: &Connection,
+    service: &BusName<'_>,
+    object: &str,
+    properties: &[PropertyCondition],
+) -> std::result::Result<(), String> {
+    let dbus = DBusProxy::new(connection)
+        .await
+        .map_err(|e| e.to_string())?;
+    if !dbus
+        .name_has_owner(service.as_ref())
+        .await
+        .map_err(|e| e.to_string())?
+    {
+        return Err(format!("the name {} has no owner", service));
+    }
+
+    connection
+        .call_method(
+            Some(service),
+            object,
+            Some("org.freedesktop.DBus.Peer"),
+            "Ping",
+            &(),
+        )
+        .await
+        .map_err(|e| format!("Ping failed: {}", e))?;
+
+    if properties.is_empty() {
+        return Ok(());
+    }
+    let proxy = PropertiesProxy::builder(connection)
+        .destination(service)
+        .and_then(|builder| builder.path(object))
+        .map_err(|e| e.to_string())?
+        .build()
+        .await
+        .map_err(|e| e.to_string())?;
+    for condition in properties {
+        let interface =
+            InterfaceName::try_from(condition.interface.as_str()).map_err(|e| e.to_string())?;
+        let value = proxy
+            .get(interface, &condition.property)
+            .await
+            .map_err(|e| {
+                format!(
+                    "failed to read {}.{}: {}",
+                    condition.interface, condition.property, e
+                )
+            })?;
+        if !condition.matches(&value) {
+            return Err(format!(
+                "{}.{} is '{}', not '{}'",
+                condition.interface,
+                condition.property,
+                display_value(&value),
+                condition.value
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Wait until a name has an owner on the bus, for startup scripts of services
+// that need another one. The bus announces new owners with NameOwnerChanged,
+// which is subscribed to before asking whether the name is already owned so
+// an owner arriving in between is not missed. Only --max-wait or --timeout
+// ends the wait without one.
+pub async fn run_wait_for_name_command(global: &GlobalArgs, args: WaitForNameArgs) -> Result<()> {
+    let name = BusName::try_from(args.name.as_str())?;
+    let max_wait = args.max_wait(global)?;
+    let connection = connect(global).await?;
+    let dbus = DBusProxy::new(&connection).await?;
+    let mut changes = dbus
+        .receive_name_owner_changed_with_args(&[(0, name.as_str())])
+        .await?;
+
+    let wait = async {
+        if dbus.name_has_owner(name.as_ref()).await? {
+            return Ok(());
+        }
+        while let Some(change) = changes.next().await {
+            if change.args()?.new_owner().is_some() {
+                return Ok(());
+            }
+        }
+        Err(zbus::Error::Failure(format!(
+            "The {} bus went away while waiting for {}",
+            global.bus().name(),
+            name
+        )))
+    };
+
+    match max_wait {
+        Some(max_wait) => tokio::time::timeout(max_wait, wait).await.map_err(|_| {
+            zbus::Error::Failure(format!(
+                "{} did not appear on the {} bus within {} seconds",
+                name,
+                global.bus().name(),
+                max_wait.as_secs_f64()
+            ))
+        })?,
+        None => wait.await,
+    }
+}
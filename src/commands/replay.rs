@@ -0,0 +1,164 @@
+use super::{connect, print_warning, serve_until_interrupted};
+use crate::GlobalArgs;
+use clap::Args;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use zbus::Result;
+use zbus::names::{InterfaceName, MemberName, WellKnownName};
+use zbusctl::serve::{Interface, Method, Pacing, Replay, serve_object_with};
+
+#[derive(Args)]
+pub struct ReplayArgs {
+    #[arg(
+        value_name = "FILE",
+        help = "Capture written by `monitor` or `call --debug-log`"
+    )]
+    capture: PathBuf,
+
+    #[arg(
+        long,
+        value_name = "FACTOR",
+        default_value = "1x",
+        help = "Replay FACTOR times as fast as captured, such as 2x or 0.5x"
+    )]
+    speed: Pacing,
+
+    #[arg(
+        long,
+        conflicts_with = "speed",
+        help = "Emit the signals one after another without waiting"
+    )]
+    no_delay: bool,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["speed", "no_delay"],
+        help = "Emit one signal each time Enter is pressed"
+    )]
+    step: bool,
+
+    #[arg(
+        long,
+        value_name = "INTERFACE.METHOD",
+        conflicts_with = "step",
+        help = "Serve METHOD of INTERFACE and replay the capture each time it is called, until interrupted"
+    )]
+    trigger: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        default_value = "/",
+        requires = "trigger",
+        help = "Object path to serve the trigger method at"
+    )]
+    trigger_object: String,
+
+    #[arg(
+        long,
+        value_name = "NAME",
+        requires = "trigger",
+        help = "Well-known name to request while serving the trigger method"
+    )]
+    name: Option<String>,
+}
+
+// Emit the signals of a capture from our own connection, in their recorded
+// order and at their recorded intervals, or at another pace. With `--step`,
+// each signal is described on stderr and sent when a line is read from
+// stdin, so timing-dependent bugs can be walked through one signal at a
+// time; the end of input stops the replay. That needs someone at a terminal,
+// so `--step` is refused without one. With `--trigger`, nothing is sent
+// until a client calls the trigger method, which replays the capture each
+// time, so a client's reaction to it can be tested on demand.
+pub async fn run_replay_command(global: &GlobalArgs, args: ReplayArgs) -> Result<()> {
+    if args.step && !global.interactive() {
+        return Err(zbus::Error::Failure(
+            "--step waits for Enter before each signal, which cannot be pressed with --non-interactive or without a terminal on stdin"
+                .to_string(),
+        ));
+    }
+    let pacing = if args.no_delay {
+        Pacing::NoDelay
+    } else {
+        args.speed
+    };
+    let replay = Replay::load(&args.capture)?.with_pacing(pacing);
+    let connection = connect(global).await?;
+    if let Some(trigger) = &args.trigger {
+        let (interface, method) = trigger
+            .rsplit_once('.')
+            .and_then(|(interface, method)| {
+                InterfaceName::try_from(interface).ok()?;
+                MemberName::try_from(method).ok()?;
+                Some((interface, method))
+            })
+            .ok_or_else(|| {
+                zbus::Error::Failure(format!(
+                    "Invalid trigger '{}': expected INTERFACE.METHOD, such as org.example.Debug.Replay",
+                    trigger
+                ))
+            })?;
+        let total = replay.signals().len();
+        let methods = HashMap::from([(
+            method,
+            Method::any(Arc::new(replay).trigger(&connection, Some(print_warning))),
+        )]);
+        let interfaces = vec![Interface::new(interface, methods)?];
+        let mut served = serve_object_with(
+            &connection,
+            &args.trigger_object,
+            interfaces,
+            Some(print_warning),
+        )
+        .await?;
+        if let Some(name) = &args.name {
+            connection
+                .request_name(WellKnownName::try_from(name.as_str())?)
+                .await?;
+        }
+        eprintln!(
+            "Serving {} at {} on {}; each call replays {} signals; press Ctrl-C to stop",
+            trigger,
+            args.trigger_object,
+            args.name
+                .as_deref()
+                .or(connection.unique_name().map(|n| n.as_str()))
+                .unwrap_or("the bus"),
+            total
+        );
+        return serve_until_interrupted(&mut served).await;
+    }
+    if !args.step {
+        return replay.emit(&connection).await;
+    }
+
+    let total = replay.signals().len();
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    for (i, signal) in replay.signals().iter().enumerate() {
+        eprint!(
+            "[{}/{}] {} {}.{} (Enter to send) ",
+            i + 1,
+            total,
+            signal.path,
+            signal.interface,
+            signal.member
+        );
+        std::io::stderr()
+            .flush()
+            .map_err(|e| zbus::Error::Failure(format!("Failed to write prompt: {}", e)))?;
+        let line = lines
+            .next_line()
+            .await
+            .map_err(|e| zbus::Error::Failure(format!("Failed to read input: {}", e)))?;
+        if line.is_none() {
+            eprintln!();
+            return Ok(());
+        }
+        connection.send(&signal.message()?).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,82 @@
+use crate::GlobalArgs;
+use clap::Args;
+use std::fs;
+use std::path::PathBuf;
+use zbus::Result;
+use zbusctl::events::{parse_event, parse_gap, parse_note};
+use zbusctl::scrub::Scrubber;
+
+#[derive(Args)]
+pub struct CaptureScrubArgs {
+    #[arg(
+        value_name = "IN",
+        help = "Capture written by `monitor` or `call --debug-log`"
+    )]
+    capture: PathBuf,
+
+    #[arg(value_name = "OUT", help = "Where to write the scrubbed capture")]
+    scrubbed: PathBuf,
+
+    #[arg(
+        long = "drop-interface",
+        value_name = "INTERFACE",
+        help = "Leave out messages on INTERFACE, which may contain `*` (can be repeated)"
+    )]
+    drop_interfaces: Vec<String>,
+
+    #[arg(
+        long,
+        help = "Replace strings, object paths and byte arrays in message bodies, dictionary keys included, with hashes of them"
+    )]
+    hash_strings: bool,
+}
+
+// Write a copy of a capture that is safe to attach to a bug report: the
+// project's redaction rules are applied, and messages are dropped or their
+// strings hashed as asked. Gap markers are kept as they are. The whole
+// capture is read before anything is written, so IN and OUT may be the same
+// file.
+pub async fn run_capture_scrub_command(global: &GlobalArgs, args: CaptureScrubArgs) -> Result<()> {
+    let contents = fs::read_to_string(&args.capture).map_err(|e| {
+        zbus::Error::Failure(format!(
+            "Failed to read '{}': {}",
+            args.capture.display(),
+            e
+        ))
+    })?;
+    let redactor = global.redactor();
+    let mut scrubber = Scrubber::new(args.drop_interfaces, args.hash_strings);
+
+    let mut scrubbed = String::new();
+    for (i, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if parse_gap(line).is_some() || parse_note(line).is_some() {
+            scrubbed.push_str(line);
+            scrubbed.push('\n');
+            continue;
+        }
+        let mut event = parse_event(line).map_err(|e| {
+            zbus::Error::Failure(format!(
+                "'{}' line {}: {}",
+                args.capture.display(),
+                i + 1,
+                e
+            ))
+        })?;
+        redactor.redact_event(&mut event);
+        if let Some(event) = scrubber.scrub(event) {
+            scrubbed.push_str(&event.to_line()?);
+            scrubbed.push('\n');
+        }
+    }
+
+    fs::write(&args.scrubbed, scrubbed).map_err(|e| {
+        zbus::Error::Failure(format!(
+            "Failed to write '{}': {}",
+            args.scrubbed.display(),
+            e
+        ))
+    })
+}
@@ -0,0 +1,90 @@
+use super::{connect, print_warning, serve_until_interrupted};
+use crate::GlobalArgs;
+use clap::{ArgGroup, Args};
+use std::path::PathBuf;
+use std::sync::Arc;
+use zbus::Result;
+use zbus::names::{InterfaceName, WellKnownName};
+use zbusctl::serve::serve_object_with;
+use zbusctl::store::{Backend, Store};
+
+#[derive(Args)]
+#[command(group = ArgGroup::new("backend").args(["file", "env"]).required(true))]
+pub struct ServePropertiesArgs {
+    #[arg(
+        long,
+        value_name = "PATH",
+        default_value = "/",
+        help = "Object path to serve the properties at"
+    )]
+    object: String,
+
+    #[arg(
+        long,
+        value_name = "NAME",
+        help = "Well-known name to request while serving"
+    )]
+    name: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Serve the properties in FILE, as `props export` writes it (TOML when it ends in .toml), writing changes back to it"
+    )]
+    file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "PREFIX",
+        requires = "interface",
+        help = "Serve the environment variables starting with PREFIX as string properties named by the rest"
+    )]
+    env: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "INTERFACE",
+        requires = "env",
+        help = "Interface to serve the environment variables on"
+    )]
+    interface: Option<String>,
+}
+
+// Serve properties kept in a file or the environment until interrupted, so
+// other processes can read them over the bus. Clients setting one write it
+// back to the file, and every change is announced with PropertiesChanged.
+pub async fn run_serve_properties_command(
+    global: &GlobalArgs,
+    args: ServePropertiesArgs,
+) -> Result<()> {
+    let backend = match (args.file, args.env, args.interface) {
+        (Some(file), _, _) => Backend::File(file),
+        (None, Some(prefix), Some(interface)) => {
+            InterfaceName::try_from(interface.as_str())?;
+            Backend::Env { prefix, interface }
+        }
+        _ => unreachable!("clap requires a backend"),
+    };
+    let store = Arc::new(Store::load(backend)?);
+    let connection = connect(global).await?;
+    let interfaces = store.serve(&connection, &args.object)?;
+    let mut served =
+        serve_object_with(&connection, &args.object, interfaces, Some(print_warning)).await?;
+    if let Some(name) = &args.name {
+        connection
+            .request_name(WellKnownName::try_from(name.as_str())?)
+            .await?;
+    }
+
+    let count = store.properties().values().map(Vec::len).sum::<usize>();
+    eprintln!(
+        "Serving {} properties at {} on {}; press Ctrl-C to stop",
+        count,
+        args.object,
+        args.name
+            .as_deref()
+            .or(connection.unique_name().map(|n| n.as_str()))
+            .unwrap_or("the bus")
+    );
+    serve_until_interrupted(&mut served).await
+}
@@ -0,0 +1,81 @@
+use super::{connect, format_table, print_json, print_output, wants_json};
+use crate::GlobalArgs;
+use clap::Args;
+use std::collections::{BTreeMap, HashMap};
+use zbus::Result;
+use zbus::names::BusName;
+use zbusctl::format::display_value;
+use zvariant::OwnedValue;
+
+#[derive(Args)]
+pub struct StatsArgs {
+    #[arg(help = "Show statistics for this connection instead of the whole bus")]
+    name: Option<String>,
+}
+
+pub async fn run_stats_command(global: &GlobalArgs, args: StatsArgs) -> Result<()> {
+    let name = args.name.as_deref().map(BusName::try_from).transpose()?;
+
+    let connection = connect(global).await?;
+
+    let result = match name {
+        Some(ref name) => {
+            connection
+                .call_method(
+                    Some("org.freedesktop.DBus"),
+                    "/org/freedesktop/DBus",
+                    Some("org.freedesktop.DBus.Debug.Stats"),
+                    "GetConnectionStats",
+                    name,
+                )
+                .await
+        }
+        None => {
+            connection
+                .call_method(
+                    Some("org.freedesktop.DBus"),
+                    "/org/freedesktop/DBus",
+                    Some("org.freedesktop.DBus.Debug.Stats"),
+                    "GetStats",
+                    &(),
+                )
+                .await
+        }
+    };
+
+    // The Stats interface is optional and disabled on many distributions, so
+    // explain what is going on instead of surfacing a bare UnknownMethod error.
+    let reply = match result {
+        Err(zbus::Error::MethodError(ref error_name, _, _))
+            if error_name.as_str() == "org.freedesktop.DBus.Error.UnknownInterface"
+                || error_name.as_str() == "org.freedesktop.DBus.Error.UnknownMethod" =>
+        {
+            return Err(zbus::Error::Failure(
+                "This bus does not enable the org.freedesktop.DBus.Debug.Stats interface"
+                    .to_string(),
+            ));
+        }
+        result => result?,
+    };
+
+    let stats = reply
+        .body()
+        .deserialize::<HashMap<String, OwnedValue>>()?
+        .into_iter()
+        .collect::<BTreeMap<_, _>>();
+
+    if wants_json(global) {
+        return print_json(global, &stats);
+    }
+
+    let values = stats
+        .values()
+        .map(|value| display_value(value))
+        .collect::<Vec<_>>();
+    let rows = stats
+        .keys()
+        .zip(&values)
+        .map(|(name, value)| [name.as_str(), value.as_str()])
+        .collect::<Vec<_>>();
+    print_output(global, &format_table(&["STATISTIC", "VALUE"], &rows))
+}
@@ -0,0 +1,164 @@
+use super::policy::{group_name, user_name};
+use super::{connect, format_table, print_json, print_output, wants_json};
+use crate::GlobalArgs;
+use clap::Args;
+use serde::Serialize;
+use zbus::Result;
+use zbus::fdo::DBusProxy;
+use zbus::names::BusName;
+use zbusctl::credentials::SecurityLabel;
+
+#[derive(Args)]
+pub struct StatusArgs {
+    #[arg(help = "Bus name to show the owner of, unique or well-known")]
+    name: String,
+}
+
+#[derive(Serialize)]
+struct Status {
+    name: String,
+    unique_name: String,
+    pid: Option<u32>,
+    uid: Option<u32>,
+    user: Option<String>,
+    groups: Option<Vec<Group>>,
+    command_line: Option<String>,
+    security_label: Option<String>,
+    security_context: Option<SecurityLabel>,
+}
+
+#[derive(Serialize)]
+struct Group {
+    gid: u32,
+    name: Option<String>,
+}
+
+// Show who owns a name: the connection's unique name and what the bus knows
+// about the process behind it. Credentials the bus does not report, or a
+// command line that cannot be read, are left empty rather than failing.
+pub async fn run_status_command(global: &GlobalArgs, args: StatusArgs) -> Result<()> {
+    let name = BusName::try_from(args.name.as_str())?;
+
+    let connection = connect(global).await?;
+    let proxy = DBusProxy::new(&connection).await?;
+
+    let unique_name = match proxy.get_name_owner(name.clone()).await {
+        Err(zbus::fdo::Error::NameHasNoOwner(_)) => {
+            return Err(zbus::Error::Failure(format!(
+                "The name '{}' has no owner",
+                name
+            )));
+        }
+        result => result?.to_string(),
+    };
+    // Ask by the unique name, so the answers are about the same connection
+    // even if the well-known name changes hands meanwhile.
+    let owner = BusName::try_from(unique_name.as_str())?;
+
+    let (pid, uid, gids, security_label) =
+        match proxy.get_connection_credentials(owner.clone()).await {
+            Ok(credentials) => (
+                credentials.process_id(),
+                credentials.unix_user_id(),
+                credentials.unix_group_ids().cloned(),
+                credentials
+                    .linux_security_label()
+                    .map(|label| SecurityLabel::parse(label)),
+            ),
+            // Buses older than GetConnectionCredentials still have the
+            // single-value methods.
+            Err(zbus::fdo::Error::UnknownMethod(_)) => (
+                proxy
+                    .get_connection_unix_process_id(owner.clone())
+                    .await
+                    .ok(),
+                proxy.get_connection_unix_user(owner).await.ok(),
+                None,
+                None,
+            ),
+            Err(e) => return Err(e.into()),
+        };
+
+    let status = Status {
+        name: name.to_string(),
+        unique_name,
+        pid,
+        uid,
+        user: uid.and_then(user_name),
+        groups: gids.map(|gids| {
+            gids.into_iter()
+                .map(|gid| Group {
+                    gid,
+                    name: group_name(gid),
+                })
+                .collect()
+        }),
+        command_line: pid.and_then(command_line),
+        security_label: security_label
+            .as_ref()
+            .map(|label| label.label().to_string()),
+        security_context: security_label,
+    };
+
+    if wants_json(global) {
+        return print_json(global, &status);
+    }
+
+    let or_dash = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+    let with_name = |id: u32, name: &Option<String>| match name {
+        Some(name) => format!("{} ({})", id, name),
+        None => id.to_string(),
+    };
+    let rows = [
+        ["NAME".to_string(), status.name.clone()],
+        ["UNIQUE NAME".to_string(), status.unique_name.clone()],
+        [
+            "PID".to_string(),
+            or_dash(status.pid.map(|pid| pid.to_string())),
+        ],
+        [
+            "UID".to_string(),
+            or_dash(status.uid.map(|uid| with_name(uid, &status.user))),
+        ],
+        [
+            "GROUPS".to_string(),
+            or_dash(status.groups.as_ref().map(|groups| {
+                groups
+                    .iter()
+                    .map(|group| with_name(group.gid, &group.name))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            })),
+        ],
+        [
+            "COMMAND LINE".to_string(),
+            or_dash(status.command_line.clone()),
+        ],
+        [
+            "SECURITY LABEL".to_string(),
+            or_dash(
+                status
+                    .security_context
+                    .as_ref()
+                    .map(|context| match context {
+                        SecurityLabel::Other { label } => label.clone(),
+                        context => format!("{} ({})", context.label(), context),
+                    }),
+            ),
+        ],
+    ];
+    print_output(global, &format_table(&["FIELD", "VALUE"], &rows))
+}
+
+// The command line of a process, with its arguments separated by spaces, if
+// it can be read. Processes in other PID namespaces, or owned by other users
+// where /proc is mounted with hidepid, cannot.
+fn command_line(pid: u32) -> Option<String> {
+    let contents = std::fs::read(format!("/proc/{}/cmdline", pid)).ok()?;
+    let arguments = contents
+        .split(|&byte| byte == 0)
+        .filter(|argument| !argument.is_empty())
+        .map(String::from_utf8_lossy)
+        .collect::<Vec<_>>();
+    (!arguments.is_empty()).then(|| arguments.join(" "))
+}
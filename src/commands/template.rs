@@ -0,0 +1,217 @@
+use super::{config_dir, format_table, print_json, print_output, wants_json};
+use crate::{Commands, GlobalArgs};
+use clap::{Args, Subcommand};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use zbus::Result;
+use zbusctl::project::{PROJECT_FILE, ProjectConfig};
+use zbusctl::template::Template;
+
+#[derive(Args)]
+pub struct TemplateArgs {
+    #[command(subcommand)]
+    command: TemplateCommand,
+}
+
+#[derive(Subcommand)]
+enum TemplateCommand {
+    // Save a template
+    #[command(about = "Save a zbusctl command line, with {name} placeholders, as a template")]
+    Save(SaveArgs),
+
+    // Run a template
+    #[command(about = "Run a saved template, filling in its placeholders")]
+    Apply(ApplyArgs),
+
+    // List templates
+    #[command(about = "List the saved templates")]
+    List,
+}
+
+#[derive(Args)]
+struct SaveArgs {
+    #[arg(help = "Template name")]
+    name: String,
+
+    #[arg(long, help = "What the template is for")]
+    description: Option<String>,
+
+    #[arg(long, help = "Replace an existing template with the same name")]
+    force: bool,
+
+    #[arg(
+        last = true,
+        required = true,
+        value_name = "COMMAND",
+        help = "The zbusctl command line to save, after --"
+    )]
+    args: Vec<String>,
+}
+
+#[derive(Args)]
+struct ApplyArgs {
+    #[arg(help = "Template name")]
+    name: String,
+
+    #[arg(
+        long = "var",
+        value_name = "NAME=VALUE",
+        value_parser = parse_var,
+        help = "Value for a placeholder (can be repeated)"
+    )]
+    vars: Vec<(String, String)>,
+}
+
+fn parse_var(s: &str) -> std::result::Result<(String, String), String> {
+    s.split_once('=')
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected NAME=VALUE, got '{}'", s))
+}
+
+// Templates are shared through the configuration directory, one JSON file
+// each, so they can be copied between machines or kept in dotfiles.
+fn templates_dir() -> Result<PathBuf> {
+    config_dir()
+        .map(|dir| dir.join("templates"))
+        .ok_or_else(|| {
+            zbus::Error::Failure(
+                "Cannot find the configuration directory: neither XDG_CONFIG_HOME nor HOME is set"
+                    .to_string(),
+            )
+        })
+}
+
+fn template_file(name: &str) -> Result<PathBuf> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    if !valid {
+        return Err(zbus::Error::Failure(format!(
+            "Invalid template name '{}': use letters, digits, '_', '-' and '.'",
+            name
+        )));
+    }
+    Ok(templates_dir()?.join(format!("{}.json", name)))
+}
+
+pub async fn run_template_command(global: &GlobalArgs, args: TemplateArgs) -> Result<()> {
+    match args.command {
+        TemplateCommand::Save(save) => save_template(save),
+        TemplateCommand::Apply(apply) => apply_template(global, apply).await,
+        TemplateCommand::List => list_templates(global),
+    }
+}
+
+fn save_template(args: SaveArgs) -> Result<()> {
+    let file = template_file(&args.name)?;
+    if file.exists() && !args.force {
+        return Err(zbus::Error::Failure(format!(
+            "Template '{}' already exists; pass --force to replace it",
+            args.name
+        )));
+    }
+
+    let template = Template {
+        description: args.description,
+        args: args.args,
+    };
+    // Catch malformed placeholders now rather than when the template is used.
+    template.placeholders()?;
+
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir).map_err(|e| {
+            zbus::Error::Failure(format!("Failed to create '{}': {}", dir.display(), e))
+        })?;
+    }
+    let json = serde_json::to_string_pretty(&template)
+        .map_err(|e| zbus::Error::Failure(format!("Failed to convert template to JSON: {}", e)))?;
+    fs::write(&file, format!("{}\n", json))
+        .map_err(|e| zbus::Error::Failure(format!("Failed to write '{}': {}", file.display(), e)))
+}
+
+async fn apply_template(global: &GlobalArgs, args: ApplyArgs) -> Result<()> {
+    // The project's templates come first, so a repository can rely on its
+    // own definitions whatever its users have saved.
+    let project = ProjectConfig::discover()?
+        .map(|(_, config)| config)
+        .unwrap_or_default();
+    let template = match project.templates.get(&args.name) {
+        Some(template) => template.clone(),
+        None => {
+            let file = template_file(&args.name)?;
+            if !file.exists() {
+                return Err(zbus::Error::Failure(format!(
+                    "No template named '{}' in {} or {}",
+                    args.name,
+                    PROJECT_FILE,
+                    templates_dir()?.display()
+                )));
+            }
+            Template::load(&file)?
+        }
+    };
+    let vars = args.vars.into_iter().collect::<BTreeMap<_, _>>();
+    let words = template.apply(&vars)?;
+
+    let parsed = crate::parse_template_args(words, global, &project).map_err(|e| {
+        let message = e.to_string();
+        zbus::Error::Failure(format!(
+            "Template '{}' is not a valid command line: {}",
+            args.name,
+            message.lines().next().unwrap_or_default()
+        ))
+    })?;
+    if matches!(parsed.command, Commands::Template(_)) {
+        return Err(zbus::Error::Failure(format!(
+            "Template '{}' runs another template command, which is not allowed",
+            args.name
+        )));
+    }
+    Box::pin(crate::run(parsed)).await
+}
+
+fn list_templates(global: &GlobalArgs) -> Result<()> {
+    let dir = templates_dir()?;
+    let mut templates = BTreeMap::new();
+    let project = ProjectConfig::discover()?;
+    if dir.exists() {
+        let entries = fs::read_dir(&dir).map_err(|e| {
+            zbus::Error::Failure(format!("Failed to read '{}': {}", dir.display(), e))
+        })?;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "json")
+                && let Some(name) = path.file_stem()
+            {
+                templates.insert(name.to_string_lossy().into_owned(), Template::load(&path)?);
+            }
+        }
+    }
+    if let Some((_, config)) = project {
+        templates.extend(config.templates);
+    }
+
+    if wants_json(global) {
+        return print_json(global, &templates);
+    }
+    let rows = templates
+        .iter()
+        .map(|(name, template)| {
+            [
+                name.clone(),
+                template
+                    .description
+                    .clone()
+                    .unwrap_or_else(|| "-".to_string()),
+                template.args.join(" "),
+            ]
+        })
+        .collect::<Vec<_>>();
+    print_output(
+        global,
+        &format_table(&["NAME", "DESCRIPTION", "COMMAND"], &rows),
+    )
+}
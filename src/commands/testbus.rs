@@ -0,0 +1,92 @@
+use crate::GlobalArgs;
+use clap::Args;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use zbus::Result;
+
+#[derive(Args)]
+pub struct TestbusArgs {
+    #[arg(
+        long,
+        default_value = "dbus-daemon",
+        help = "Bus daemon to run; it must accept dbus-daemon's options"
+    )]
+    daemon: String,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Bus configuration file to use instead of the standard session bus one"
+    )]
+    config: Option<PathBuf>,
+
+    #[arg(
+        last = true,
+        required = true,
+        value_name = "COMMAND",
+        help = "Command to run on the private bus, after --"
+    )]
+    command: Vec<String>,
+}
+
+// Stops the bus when dropped, so it goes away however the command ends.
+struct Daemon(Child);
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+// Start a private bus and read back the address it listens on.
+fn start_daemon(args: &TestbusArgs) -> Result<(Daemon, String)> {
+    let mut command = Command::new(&args.daemon);
+    match args.config {
+        Some(ref config) => command.arg(format!("--config-file={}", config.display())),
+        None => command.arg("--session"),
+    };
+    command
+        .args(["--nofork", "--print-address=1"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped());
+    let mut daemon =
+        Daemon(command.spawn().map_err(|e| {
+            zbus::Error::Failure(format!("Failed to start '{}': {}", args.daemon, e))
+        })?);
+
+    let mut address = String::new();
+    if let Some(stdout) = daemon.0.stdout.take() {
+        BufReader::new(stdout)
+            .read_line(&mut address)
+            .map_err(|e| zbus::Error::Failure(format!("Failed to read the bus address: {}", e)))?;
+    }
+    let address = address.trim().to_string();
+    if address.is_empty() {
+        return Err(zbus::Error::Failure(format!(
+            "'{}' exited without printing a bus address",
+            args.daemon
+        )));
+    }
+    Ok((daemon, address))
+}
+
+// Run a command on a session bus of its own, which is torn down when the
+// command exits, so tests never see or disturb the developer's real bus.
+pub async fn run_testbus_command(_global: &GlobalArgs, args: TestbusArgs) -> Result<()> {
+    let (daemon, address) = start_daemon(&args)?;
+    let status = Command::new(&args.command[0])
+        .args(&args.command[1..])
+        .env("DBUS_SESSION_BUS_ADDRESS", &address)
+        .env_remove("DBUS_STARTER_ADDRESS")
+        .env_remove("DBUS_STARTER_BUS_TYPE")
+        .status()
+        .map_err(|e| zbus::Error::Failure(format!("Failed to run '{}': {}", args.command[0], e)))?;
+    drop(daemon);
+
+    if !status.success() {
+        std::process::exit(status.code().unwrap_or(1));
+    }
+    Ok(())
+}
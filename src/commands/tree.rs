@@ -0,0 +1,88 @@
+use super::introspect::{Source, WalkArgs, walk, walk_each};
+use super::{connect_pool, print_event, print_json, print_output, wants_json};
+use crate::GlobalArgs;
+use clap::Args;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use zbus::Result;
+use zbus::names::BusName;
+use zbusctl::format::to_json;
+use zbusctl::introspect::format_tree;
+use zvariant::ObjectPath;
+
+#[derive(Args)]
+pub struct TreeArgs {
+    #[arg(short, long, help = "D-Bus service name")]
+    service: String,
+
+    #[arg(
+        short,
+        long,
+        default_value = "/",
+        help = "D-Bus object path to start from"
+    )]
+    object: String,
+
+    #[command(flatten)]
+    walk: WalkArgs,
+
+    #[arg(
+        long,
+        help = "Print each object and its children as a line of JSON as soon as it is introspected, instead of the whole tree at the end"
+    )]
+    stream: bool,
+}
+
+// An object as printed with `--stream`.
+#[derive(Serialize)]
+struct Streamed<'a> {
+    path: &'a str,
+    children: &'a [String],
+}
+
+// Print the objects of a service as a tree, as `busctl tree` does: humans
+// get one object per line below its parent, scripts a list of paths. An
+// object below the starting one that cannot be introspected is shown
+// without children, with a warning, rather than failing the whole walk.
+// With `--stream`, each object is printed with its children as soon as it
+// is found, for trees too big to wait for or to hold in memory.
+pub async fn run_tree_command(global: &GlobalArgs, args: TreeArgs) -> Result<()> {
+    let service = BusName::try_from(args.service.as_str())?;
+    ObjectPath::try_from(args.object.as_str())?;
+
+    let source = Source::Bus {
+        connections: connect_pool(global).await?,
+        cache: global.cache(),
+        service: service.into_owned(),
+    };
+    if args.stream {
+        return walk_each(global, &source, &args.object, args.walk, true, |object| {
+            let streamed = Streamed {
+                path: &object.path,
+                children: &object.children,
+            };
+            print_event(
+                &to_json(&streamed, false, global.float_format).map_err(|e| {
+                    zbus::Error::Failure(format!("Failed to convert object to JSON: {}", e))
+                })?,
+            )
+        })
+        .await;
+    }
+    let children = walk(global, &source, &args.object, args.walk, true)
+        .await?
+        .into_iter()
+        .map(|object| (object.path, object.children))
+        .collect::<BTreeMap<_, _>>();
+
+    if wants_json(global) {
+        let mut paths = children
+            .values()
+            .flatten()
+            .chain([&args.object])
+            .collect::<Vec<_>>();
+        paths.sort();
+        return print_json(global, &paths);
+    }
+    print_output(global, &format_tree(&args.object, &children))
+}
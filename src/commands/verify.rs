@@ -0,0 +1,82 @@
+use super::introspect::{Source, SourceArgs};
+use crate::GlobalArgs;
+use clap::Args;
+use zbus::Result;
+use zbus::names::{BusName, InterfaceName, MemberName};
+use zbusctl::introspect::{parse_node, verify_call};
+use zbusctl::{ParseOptions, build_body_with};
+use zvariant::ObjectPath;
+
+#[derive(Args)]
+pub struct VerifyArgs {
+    #[arg(short, long, help = "D-Bus service name")]
+    service: String,
+
+    #[arg(short, long, help = "D-Bus object path")]
+    object: String,
+
+    #[arg(short, long, help = "D-Bus interface name")]
+    interface: String,
+
+    #[arg(short, long, help = "D-Bus method name")]
+    method: String,
+
+    #[command(flatten)]
+    source: SourceArgs,
+
+    #[arg(help = "D-Bus method arguments")]
+    args: Option<Vec<String>>,
+}
+
+pub async fn run_verify_command(global: &GlobalArgs, args: VerifyArgs) -> Result<()> {
+    BusName::try_from(args.service.as_str())?;
+    let source = Source::open(global, &args.source, Some(&args.service)).await?;
+    let signature = verify(
+        &source,
+        &args.service,
+        &args.object,
+        &args.interface,
+        &args.method,
+        args.args.as_deref(),
+        &global.parse_options(),
+    )
+    .await?;
+
+    println!(
+        "{}.{}({}) on {} {}: OK",
+        args.interface, args.method, signature, args.service, args.object
+    );
+    Ok(())
+}
+
+// Check a planned call against introspection data from `source`, returning the
+// signature of its arguments if it matches.
+pub async fn verify(
+    source: &Source,
+    service: &str,
+    object: &str,
+    interface: &str,
+    method: &str,
+    args: Option<&[String]>,
+    options: &ParseOptions,
+) -> Result<String> {
+    ObjectPath::try_from(object)?;
+    InterfaceName::try_from(interface)?;
+    MemberName::try_from(method)?;
+
+    let signature = match args {
+        Some(args) => build_body_with(args.iter().map(|s| s.as_str()).collect(), options)?
+            .fields()
+            .iter()
+            .map(|field| field.value_signature().to_string())
+            .collect(),
+        None => String::new(),
+    };
+
+    let xml = source.xml(object).await?;
+    let node = parse_node(object, &xml)?;
+    verify_call(&node, interface, method, &signature).map_err(|reason| {
+        zbus::Error::Failure(format!("{} on '{}': {}", service, object, reason))
+    })?;
+    Ok(signature)
+}
@@ -0,0 +1,251 @@
+use super::{connect, print_event, to_json_string, wants_json};
+use crate::GlobalArgs;
+use clap::Args;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::mpsc::unbounded_channel;
+use zbus::Result;
+use zbus::fdo::PropertiesProxy;
+use zbus::names::{BusName, InterfaceName};
+use zbusctl::expression::Expression;
+use zbusctl::format::{to_json, value_to_json};
+use zbusctl::redact::{REDACTED, Redactor};
+use zbusctl::watcher::{PropertyChange, PropertyWatcher};
+use zvariant::{ObjectPath, OwnedValue};
+
+#[derive(Args)]
+pub struct WatchArgs {
+    #[arg(short, long, help = "D-Bus service name")]
+    service: String,
+
+    #[arg(
+        short,
+        long,
+        default_value = "/",
+        help = "Object to watch the properties of"
+    )]
+    object: String,
+
+    #[arg(short, long, help = "Interface the properties belong to")]
+    interface: String,
+
+    #[arg(
+        long = "property",
+        value_name = "NAME",
+        help = "Watch the property NAME (can be repeated; default: every property the interface has)"
+    )]
+    properties: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "EXPR",
+        help = "Exit once EXPR holds, such as 'props[\"State\"] == \"activated\"', instead of printing changes"
+    )]
+    until: Option<Expression>,
+
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        help = "Give up after SECONDS (default: wait until stopped)"
+    )]
+    max_wait: Option<f64>,
+}
+
+// A change as printed with `--output json`.
+#[derive(Serialize)]
+struct Change<'a> {
+    property: &'a str,
+    value: Option<serde_json::Value>,
+}
+
+// Watch the properties of an interface through `PropertiesChanged`, printing
+// each change, or with `--until` silently until an expression over them
+// holds, for provisioning scripts that would otherwise poll. The signals are
+// subscribed to before the current values are read, so a change in between
+// is not missed. Properties whose change is announced without a value are
+// read again.
+pub async fn run_watch_command(global: &GlobalArgs, args: WatchArgs) -> Result<()> {
+    let service = BusName::try_from(args.service.as_str())?;
+    ObjectPath::try_from(args.object.as_str())?;
+    let interface = InterfaceName::try_from(args.interface.as_str())?;
+    let max_wait = args
+        .max_wait
+        .map(|secs| {
+            Duration::try_from_secs_f64(secs)
+                .map_err(|e| zbus::Error::Failure(format!("Invalid duration '{}': {}", secs, e)))
+        })
+        .transpose()?;
+
+    let connection = connect(global).await?;
+    let proxy = PropertiesProxy::builder(&connection)
+        .destination(&service)?
+        .path(args.object.as_str())?
+        .build()
+        .await?;
+    let mut names = args.properties.clone();
+    for name in args.until.iter().flat_map(Expression::properties) {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    let watch_all = names.is_empty();
+
+    // Every watched property's changes, through one channel.
+    let watcher = PropertyWatcher::new(&connection);
+    let (sender, mut changes) = unbounded_channel();
+    let subscribe = async |name: String| {
+        let mut subscription = watcher
+            .subscribe(&args.object, &args.interface, &name)
+            .await?;
+        let sender = sender.clone();
+        tokio::spawn(async move {
+            while let Some(change) = subscription.next().await {
+                if sender.send((name.clone(), change)).is_err() {
+                    return;
+                }
+            }
+        });
+        Ok::<_, zbus::Error>(())
+    };
+    for name in &names {
+        subscribe(name.clone()).await?;
+    }
+    let mut values: HashMap<String, OwnedValue> = proxy.get_all(interface.as_ref()).await?;
+    if watch_all {
+        for name in values.keys() {
+            subscribe(name.clone()).await?;
+        }
+    }
+    drop(sender);
+
+    let until = args.until.as_ref();
+    let redactor = global.redactor();
+    let watch = async {
+        if until.is_some_and(|until| until.eval(&values)) {
+            return Ok(());
+        }
+        while let Some((name, change)) = changes.recv().await {
+            let value = match change {
+                PropertyChange::Value(value) => Some(value),
+                PropertyChange::Invalidated => proxy.get(interface.as_ref(), &name).await.ok(),
+            };
+            match &value {
+                Some(value) => values.insert(name.clone(), value.try_clone()?),
+                None => values.remove(&name),
+            };
+            match until {
+                Some(until) if until.eval(&values) => return Ok(()),
+                Some(_) => {}
+                None => print_event(&format_change(
+                    global,
+                    &redactor,
+                    &args.interface,
+                    &name,
+                    value.as_ref(),
+                )?)?,
+            }
+        }
+        Err(zbus::Error::Failure(
+            "The connection to the bus was lost".to_string(),
+        ))
+    };
+
+    let Some(max_wait) = max_wait else {
+        return watch.await;
+    };
+    match tokio::time::timeout(max_wait, watch).await {
+        Ok(result) => result,
+        // Without a condition, watching for a while is done when the time is up.
+        Err(_) if until.is_none() => Ok(()),
+        Err(_) => Err(zbus::Error::Failure(format!(
+            "The condition did not hold within {} seconds",
+            max_wait.as_secs_f64()
+        ))),
+    }
+}
+
+// A change as it is printed when it arrives, as `NAME = VALUE`, or as a JSON
+// line with `--output json`. Values are shown as `get-all-properties` shows
+// them, redacted and with floats in the chosen format: strings bare, and
+// anything else as JSON. A property that could not be read again is shown
+// as `-`.
+fn format_change(
+    global: &GlobalArgs,
+    redactor: &Redactor,
+    interface: &str,
+    name: &str,
+    value: Option<&OwnedValue>,
+) -> Result<String> {
+    let json = match value {
+        Some(_) if redactor.redacts_property(interface, name) => Some(REDACTED.into()),
+        Some(value) => Some(value_to_json(value, global.numbers())?),
+        None => None,
+    };
+    if wants_json(global) {
+        return to_json_string(
+            global,
+            &Change {
+                property: name,
+                value: json,
+            },
+        );
+    }
+    let shown = match json {
+        Some(serde_json::Value::String(s)) => s,
+        Some(json) => to_json(&json, false, global.float_format)
+            .map_err(|e| zbus::Error::Failure(format!("Failed to convert value to JSON: {}", e)))?,
+        None => "-".to_string(),
+    };
+    Ok(format!("{} = {}", name, shown))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ZBusCtl;
+    use clap::Parser;
+    use zbusctl::redact::RedactRule;
+
+    #[test]
+    fn test_redacted_change() {
+        let parse = |output: &[&str]| {
+            let words = [&["zbusctl", "--float-format", "fixed:1"], output]
+                .concat()
+                .into_iter()
+                .chain(["watch", "-s", "org.example.Wifi", "-i", "org.example.Wifi"]);
+            ZBusCtl::try_parse_from(words).unwrap().global
+        };
+        let redactor = Redactor::new(vec![RedactRule {
+            interface: Some("org.example.Wifi".to_string()),
+            property: Some("Psk".to_string()),
+            ..RedactRule::default()
+        }]);
+        let psk = OwnedValue::try_from(zvariant::Value::from("hunter2")).unwrap();
+        let levels = OwnedValue::try_from(zvariant::Value::from(vec![21.46_f64, 2.0])).unwrap();
+
+        let text = parse(&[]);
+        let line = format_change(&text, &redactor, "org.example.Wifi", "Psk", Some(&psk)).unwrap();
+        assert_eq!(line, format!("Psk = {}", REDACTED));
+        let line = format_change(
+            &text,
+            &redactor,
+            "org.example.Wifi",
+            "Levels",
+            Some(&levels),
+        )
+        .unwrap();
+        assert_eq!(line, "Levels = [21.5,2.0]", "Floats should be formatted");
+        let line = format_change(&text, &redactor, "org.example.Wifi", "Ssid", None).unwrap();
+        assert_eq!(line, "Ssid = -");
+
+        let json = parse(&["--output", "json"]);
+        let line = format_change(&json, &redactor, "org.example.Wifi", "Psk", Some(&psk)).unwrap();
+        let line: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(line["value"], REDACTED);
+        assert!(
+            !line.to_string().contains("hunter2"),
+            "The redacted value should not be printed"
+        );
+    }
+}
@@ -0,0 +1,443 @@
+use super::{connect, print_warning, state_dir};
+use crate::GlobalArgs;
+use clap::Args;
+use futures_lite::StreamExt;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::sync::Notify;
+use zbus::message::Type;
+use zbus::{MatchRule, MessageStream, Result};
+use zbusctl::events::MessageEvent;
+use zbusctl::format::to_json;
+use zbusctl::outbox::Outbox;
+
+#[derive(Args)]
+pub struct WebhookArgs {
+    #[arg(
+        long,
+        value_name = "URL",
+        help = "HTTP or HTTPS endpoint to POST each signal to"
+    )]
+    url: String,
+
+    #[arg(short, long, help = "Only forward signals sent by this service")]
+    service: Option<String>,
+
+    #[arg(short, long, help = "Only forward signals from this object")]
+    object: Option<String>,
+
+    #[arg(short, long, help = "Only forward signals of this interface")]
+    interface: Option<String>,
+
+    #[arg(short, long, help = "Only forward signals with this name")]
+    member: Option<String>,
+
+    #[arg(
+        long = "header",
+        value_name = "NAME: VALUE",
+        help = "Send a header with every request, such as an Authorization token (can be repeated)"
+    )]
+    headers: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "Keep the signals waiting to be delivered in DIR (default: one for the URL under $XDG_STATE_HOME/zbusctl/webhook)"
+    )]
+    queue_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 1000,
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "Keep at most N signals waiting to be delivered, dropping the oldest to make room"
+    )]
+    queue_size: u32,
+
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value = "1",
+        value_parser = parse_retry_delay,
+        help = "How long to wait before trying a failed delivery again, at least 0.1, doubled after each failed attempt up to 5 minutes"
+    )]
+    retry_delay: Duration,
+
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Drop a signal that still fails after N retries (default: keep trying)"
+    )]
+    max_retries: Option<u32>,
+}
+
+// The shortest and longest waits between attempts to deliver a signal. An
+// endpoint that is down is not tried again at once, which would only keep
+// zbusctl and it busy.
+const MIN_RETRY_DELAY: Duration = Duration::from_millis(100);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(300);
+
+// How long one attempt to deliver a signal may take.
+const POST_TIMEOUT: Duration = Duration::from_secs(30);
+
+// How an attempt to deliver a signal went.
+#[derive(Debug, PartialEq)]
+enum Delivery {
+    Delivered,
+    // The endpoint refused it, and would again.
+    Refused(String),
+    // The endpoint could not be reached or failed, and may not next time.
+    Failed(String),
+}
+
+// Post every signal matching the filters to an HTTP endpoint as JSON, as a
+// `MessageEvent` like `monitor` writes, until interrupted, for cloud
+// automation that should not need a daemon of its own. Signals go through a
+// spool on disk, so a slow or unreachable endpoint holds up neither reading
+// the bus nor the signals after it, and those not delivered when zbusctl
+// stops are delivered, oldest first, by the next webhook for the same URL.
+// A failed delivery is tried again after a delay that doubles each time;
+// one the endpoint refuses with a status other than 408, 429 or 5xx is
+// dropped with a warning, as trying again would not help. Requests are made
+// with `curl`, so zbusctl needs no HTTP or TLS stack of its own.
+pub async fn run_webhook_command(global: &GlobalArgs, args: WebhookArgs) -> Result<()> {
+    if !["http://", "https://"]
+        .iter()
+        .any(|scheme| args.url.starts_with(scheme))
+        || args.url.contains(char::is_whitespace)
+    {
+        return Err(zbus::Error::Failure(format!(
+            "Invalid URL '{}': expected an http:// or https:// URL",
+            args.url
+        )));
+    }
+    for header in &args.headers {
+        if !header.contains(':') || header.contains(['\r', '\n']) {
+            return Err(zbus::Error::Failure(format!(
+                "Invalid header '{}': expected 'NAME: VALUE' on one line",
+                header
+            )));
+        }
+    }
+    let queue_dir = match args.queue_dir {
+        Some(ref dir) => dir.clone(),
+        None => default_queue_dir(&args.url)?,
+    };
+    let outbox = Outbox::new(queue_dir, args.queue_size as usize);
+
+    let mut rule = MatchRule::builder().msg_type(Type::Signal);
+    if let Some(ref service) = args.service {
+        rule = rule.sender(service.as_str())?;
+    }
+    if let Some(ref object) = args.object {
+        rule = rule.path(object.as_str())?;
+    }
+    if let Some(ref interface) = args.interface {
+        rule = rule.interface(interface.as_str())?;
+    }
+    if let Some(ref member) = args.member {
+        rule = rule.member(member.as_str())?;
+    }
+    let connection = connect(global).await?;
+    let mut signals = MessageStream::for_match_rule(rule.build(), &connection, None).await?;
+
+    let redactor = global.redactor();
+    let added = Notify::new();
+    let receive = async {
+        while let Some(msg) = signals.next().await {
+            let mut event = MessageEvent::new(&msg?);
+            redactor.redact_event(&mut event);
+            global.numbers().apply_to_body(&mut event.body)?;
+            let payload = to_json(&event, false, global.float_format).map_err(|e| {
+                zbus::Error::Failure(format!("Failed to convert signal to JSON: {}", e))
+            })?;
+            let dropped = outbox.push(&payload)?;
+            if dropped > 0 {
+                print_warning(&format!(
+                    "Dropped the {} oldest signals waiting to be delivered, to keep at most {}",
+                    dropped, args.queue_size
+                ));
+            }
+            added.notify_one();
+        }
+        Err::<(), _>(zbus::Error::Failure(
+            "The connection to the bus was lost".to_string(),
+        ))
+    };
+    tokio::try_join!(receive, deliver(&args, &outbox, &added)).map(|_| ())
+}
+
+// Deliver the signals in the spool, oldest first, waiting for more when it
+// is empty, until something goes wrong with the spool itself.
+async fn deliver(args: &WebhookArgs, outbox: &Outbox, added: &Notify) -> Result<()> {
+    let mut retries = Retries::new(args.retry_delay, args.max_retries);
+    loop {
+        let Some(path) = outbox.oldest()? else {
+            added.notified().await;
+            continue;
+        };
+        match post(args, &path).await? {
+            Delivery::Delivered => {}
+            Delivery::Refused(reason) => print_warning(&format!(
+                "The endpoint refused a signal, which is dropped: {}",
+                reason
+            )),
+            Delivery::Failed(reason) => match retries.failed(&path) {
+                (_, Some(delay)) => {
+                    print_warning(&format!(
+                        "Failed to deliver a signal, trying again in {:.1} seconds: {}",
+                        delay.as_secs_f64(),
+                        reason
+                    ));
+                    tokio::time::sleep(delay).await;
+                    continue;
+                }
+                (failures, None) => print_warning(&format!(
+                    "Dropped a signal after {} failed attempts to deliver it: {}",
+                    failures, reason
+                )),
+            },
+        }
+        outbox.remove(&path)?;
+        retries.settled();
+    }
+}
+
+// When to try again to deliver the oldest signal in the spool, which is the
+// one being delivered, after it failed: after a delay that doubles with each
+// failure, until it has been retried `max_retries` times, if there is a
+// limit.
+struct Retries {
+    first_delay: Duration,
+    max_retries: Option<u32>,
+    delay: Duration,
+    // The signal that failed to be delivered last, and how many times.
+    failing: Option<(PathBuf, u32)>,
+}
+
+impl Retries {
+    fn new(first_delay: Duration, max_retries: Option<u32>) -> Self {
+        Self {
+            first_delay,
+            max_retries,
+            delay: first_delay,
+            failing: None,
+        }
+    }
+
+    // Count a failed attempt to deliver the signal at `path`, returning how
+    // many attempts to deliver it have failed, and how long to wait before
+    // the next one, or `None` if it is to be dropped.
+    fn failed(&mut self, path: &Path) -> (u32, Option<Duration>) {
+        let failures = match self.failing.take() {
+            Some((failed, failures)) if failed == path => failures + 1,
+            _ => 1,
+        };
+        if self.max_retries.is_some_and(|max| failures > max) {
+            return (failures, None);
+        }
+        self.failing = Some((path.to_path_buf(), failures));
+        let delay = self.delay;
+        self.delay = self.delay.saturating_mul(2).min(MAX_RETRY_DELAY);
+        (failures, Some(delay))
+    }
+
+    // The signal being delivered was delivered or dropped; the next one
+    // starts with the first delay again.
+    fn settled(&mut self) {
+        self.delay = self.first_delay;
+        self.failing = None;
+    }
+}
+
+fn parse_retry_delay(value: &str) -> std::result::Result<Duration, String> {
+    let delay = value
+        .parse::<f64>()
+        .map_err(|e| e.to_string())
+        .and_then(|secs| Duration::try_from_secs_f64(secs).map_err(|e| e.to_string()))?;
+    if delay < MIN_RETRY_DELAY {
+        return Err(format!(
+            "must be at least {} seconds",
+            MIN_RETRY_DELAY.as_secs_f64()
+        ));
+    }
+    Ok(delay)
+}
+
+// The spool of a URL, unless another is given: each URL has its own, so
+// webhooks to different endpoints can run side by side.
+fn default_queue_dir(url: &str) -> Result<PathBuf> {
+    let name = url
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' => c,
+            _ => '_',
+        })
+        .take(200)
+        .collect::<String>();
+    state_dir()
+        .map(|dir| dir.join("webhook").join(name))
+        .ok_or_else(|| {
+            zbus::Error::Failure(
+                "No place for the webhook queue: neither XDG_STATE_HOME nor HOME is set; use --queue-dir"
+                    .to_string(),
+            )
+        })
+}
+
+// POST the payload in the file at `path` with curl. Its configuration,
+// headers included, is given on stdin rather than the command line, where
+// other users could read tokens from the process list.
+async fn post(args: &WebhookArgs, path: &Path) -> Result<Delivery> {
+    let mut config = format!("url = {}\n", quote(&args.url));
+    config.push_str("header = \"Content-Type: application/json\"\n");
+    for header in &args.headers {
+        config.push_str(&format!("header = {}\n", quote(header)));
+    }
+    config.push_str(&format!(
+        "data-binary = {}\n",
+        quote(&format!("@{}", path.display()))
+    ));
+
+    let mut child = Command::new("curl")
+        .args(["--config", "-", "--silent", "--show-error"])
+        .args(["--proto", "=http,https", "--request", "POST"])
+        .args(["--max-time", &POST_TIMEOUT.as_secs().to_string()])
+        .args(["--output", "/dev/null", "--write-out", "%{http_code}"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| zbus::Error::Failure(format!("Failed to run curl: {}", e)))?;
+    let mut stdin = child.stdin.take().expect("curl's stdin is piped");
+    stdin
+        .write_all(config.as_bytes())
+        .await
+        .map_err(|e| zbus::Error::Failure(format!("Failed to configure curl: {}", e)))?;
+    drop(stdin);
+    let output = child
+        .wait_with_output()
+        .await
+        .map_err(|e| zbus::Error::Failure(format!("Failed to run curl: {}", e)))?;
+
+    if !output.status.success() {
+        return Ok(Delivery::Failed(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    let status = String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse::<u16>()
+        .unwrap_or_default();
+    Ok(delivery(status))
+}
+
+// How a delivery went by the HTTP status the endpoint answered with. Those
+// saying it is busy or failing may do better next time; other errors would
+// come back the same.
+fn delivery(status: u16) -> Delivery {
+    match status {
+        200..=299 => Delivery::Delivered,
+        408 | 429 | 500..=599 => Delivery::Failed(format!("HTTP status {}", status)),
+        _ => Delivery::Refused(format!("HTTP status {}", status)),
+    }
+}
+
+// A value as a double-quoted string of curl's configuration syntax.
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_retry_delay() {
+        assert_eq!(parse_retry_delay("1.5"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse_retry_delay("0.1"), Ok(MIN_RETRY_DELAY));
+        for bad in ["0", "0.01", "-1", "soon", "inf"] {
+            assert!(
+                parse_retry_delay(bad).is_err(),
+                "{} should be rejected",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn test_delivery_by_status() {
+        assert_eq!(delivery(204), Delivery::Delivered);
+        for status in [408, 429, 503] {
+            assert!(
+                matches!(delivery(status), Delivery::Failed(_)),
+                "{} should be retried",
+                status
+            );
+        }
+        for status in [0, 301, 400, 404] {
+            assert!(
+                matches!(delivery(status), Delivery::Refused(_)),
+                "{} should be dropped",
+                status
+            );
+        }
+    }
+
+    #[test]
+    fn test_retries_back_off() {
+        let mut retries = Retries::new(Duration::from_secs(100), None);
+        let path = Path::new("a.json");
+        let delays = (0..4)
+            .map(|_| retries.failed(path).1.unwrap().as_secs())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            delays,
+            vec![100, 200, 300, 300],
+            "Delays should double up to the longest"
+        );
+        retries.settled();
+        assert_eq!(
+            retries.failed(path),
+            (1, Some(Duration::from_secs(100))),
+            "The next signal should start with the first delay again"
+        );
+    }
+
+    #[test]
+    fn test_outbox_retry_and_drop() {
+        let dir = std::env::temp_dir().join(format!("zbusctl-webhook-{}", std::process::id()));
+        let outbox = Outbox::new(&dir, 10);
+        outbox.push("first").unwrap();
+        outbox.push("second").unwrap();
+        let mut retries = Retries::new(MIN_RETRY_DELAY, Some(2));
+
+        // The oldest signal is retried twice, then dropped.
+        let first = outbox.oldest().unwrap().unwrap();
+        assert_eq!(retries.failed(&first), (1, Some(MIN_RETRY_DELAY)));
+        assert_eq!(
+            outbox.oldest().unwrap().as_ref(),
+            Some(&first),
+            "A signal being retried should stay first in line"
+        );
+        assert_eq!(retries.failed(&first), (2, Some(MIN_RETRY_DELAY * 2)));
+        assert_eq!(retries.failed(&first), (3, None));
+        outbox.remove(&first).unwrap();
+        retries.settled();
+
+        let second = outbox.oldest().unwrap().unwrap();
+        assert_eq!(fs::read_to_string(&second).unwrap(), "second");
+        assert_eq!(
+            retries.failed(&second),
+            (1, Some(MIN_RETRY_DELAY)),
+            "The failures of a dropped signal should not count against the next"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,210 @@
+use serde::Serialize;
+use std::fmt;
+use zbus::fdo::ConnectionCredentials;
+
+/// A Linux security label, as the bus reports it in the `LinuxSecurityLabel`
+/// credential, decoded for the security modules whose labels have a known
+/// layout. Labels in any other format, such as Smack's, are kept as they are.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "lsm", rename_all = "lowercase")]
+pub enum SecurityLabel {
+    /// An AppArmor profile and the mode it is in, such as
+    /// `/usr/sbin/cupsd (enforce)`, or `unconfined`.
+    AppArmor {
+        label: String,
+        profile: String,
+        mode: Option<String>,
+    },
+    /// An SELinux context, `user:role:type` with an optional MLS/MCS level.
+    SELinux {
+        label: String,
+        user: String,
+        role: String,
+        #[serde(rename = "type")]
+        type_: String,
+        level: Option<String>,
+    },
+    Other {
+        label: String,
+    },
+}
+
+// The modes AppArmor reports after a profile name.
+const APPARMOR_MODES: &[&str] = &["enforce", "complain", "kill", "unconfined", "mixed", "user"];
+
+impl SecurityLabel {
+    // Decode a label as the bus sends it, without the NUL terminator the
+    // kernel includes.
+    pub fn parse(bytes: &[u8]) -> Self {
+        let bytes = bytes.strip_suffix(&[0]).unwrap_or(bytes);
+        let label = String::from_utf8_lossy(bytes).into_owned();
+
+        if label == "unconfined" {
+            return Self::AppArmor {
+                profile: label.clone(),
+                label,
+                mode: None,
+            };
+        }
+        if let Some((profile, mode)) = label
+            .strip_suffix(')')
+            .and_then(|rest| rest.rsplit_once(" ("))
+            .filter(|(profile, mode)| !profile.is_empty() && APPARMOR_MODES.contains(mode))
+        {
+            return Self::AppArmor {
+                profile: profile.to_string(),
+                mode: Some(mode.to_string()),
+                label,
+            };
+        }
+        let parts = label.splitn(4, ':').collect::<Vec<_>>();
+        if parts.len() >= 3 && parts[..3].iter().all(|part| is_selinux_name(part)) {
+            return Self::SELinux {
+                user: parts[0].to_string(),
+                role: parts[1].to_string(),
+                type_: parts[2].to_string(),
+                level: parts.get(3).map(|level| level.to_string()),
+                label,
+            };
+        }
+        Self::Other { label }
+    }
+
+    // The label as the kernel gave it.
+    pub fn label(&self) -> &str {
+        match self {
+            Self::AppArmor { label, .. } | Self::SELinux { label, .. } | Self::Other { label } => {
+                label
+            }
+        }
+    }
+}
+
+fn is_selinux_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+impl fmt::Display for SecurityLabel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AppArmor {
+                profile,
+                mode: Some(mode),
+                ..
+            } => write!(f, "AppArmor profile {} ({})", profile, mode),
+            Self::AppArmor { profile, .. } => write!(f, "AppArmor {}", profile),
+            Self::SELinux {
+                user,
+                role,
+                type_,
+                level,
+                ..
+            } => {
+                write!(f, "SELinux user {}, role {}, type {}", user, role, type_)?;
+                match level {
+                    Some(level) => write!(f, ", level {}", level),
+                    None => Ok(()),
+                }
+            }
+            Self::Other { label } => f.write_str(label),
+        }
+    }
+}
+
+/// What the bus says about the process behind a connection. Buses leave out
+/// what they do not know; older ones only report a process and user ID.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Credentials {
+    pub pid: Option<u32>,
+    pub uid: Option<u32>,
+    pub security_label: Option<SecurityLabel>,
+}
+
+impl From<&ConnectionCredentials> for Credentials {
+    fn from(credentials: &ConnectionCredentials) -> Self {
+        Self {
+            pid: credentials.process_id(),
+            uid: credentials.unix_user_id(),
+            security_label: credentials
+                .linux_security_label()
+                .map(|label| SecurityLabel::parse(label)),
+        }
+    }
+}
+
+impl fmt::Display for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let or_dash = |id: Option<u32>| id.map_or_else(|| "-".to_string(), |id| id.to_string());
+        write!(f, "pid {}, uid {}", or_dash(self.pid), or_dash(self.uid))?;
+        match &self.security_label {
+            Some(label) => write!(f, ", {}", label),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apparmor_labels() {
+        let label = SecurityLabel::parse(b"/usr/sbin/cupsd (enforce)\0");
+        assert_eq!(
+            label,
+            SecurityLabel::AppArmor {
+                label: "/usr/sbin/cupsd (enforce)".to_string(),
+                profile: "/usr/sbin/cupsd".to_string(),
+                mode: Some("enforce".to_string()),
+            }
+        );
+        assert_eq!(
+            label.to_string(),
+            "AppArmor profile /usr/sbin/cupsd (enforce)"
+        );
+        assert_eq!(label.label(), "/usr/sbin/cupsd (enforce)");
+
+        let label = SecurityLabel::parse(b"unconfined");
+        assert_eq!(label.to_string(), "AppArmor unconfined");
+    }
+
+    #[test]
+    fn test_selinux_labels() {
+        let label = SecurityLabel::parse(b"system_u:system_r:NetworkManager_t:s0\0");
+        let SecurityLabel::SELinux {
+            user, type_, level, ..
+        } = &label
+        else {
+            panic!("Expected an SELinux context: {:?}", label);
+        };
+        assert_eq!(user, "system_u");
+        assert_eq!(type_, "NetworkManager_t");
+        assert_eq!(level.as_deref(), Some("s0"));
+
+        let label = SecurityLabel::parse(b"unconfined_u:unconfined_r:unconfined_t:s0-s0:c0.c1023");
+        let SecurityLabel::SELinux { level, .. } = &label else {
+            panic!("Expected an SELinux context: {:?}", label);
+        };
+        assert_eq!(
+            level.as_deref(),
+            Some("s0-s0:c0.c1023"),
+            "Levels may contain colons"
+        );
+    }
+
+    #[test]
+    fn test_other_labels() {
+        assert_eq!(
+            SecurityLabel::parse(b"System\0"),
+            SecurityLabel::Other {
+                label: "System".to_string()
+            }
+        );
+        let json = serde_json::to_value(SecurityLabel::parse(b"/bin/foo (complain)")).unwrap();
+        assert_eq!(json["lsm"], "apparmor");
+        assert_eq!(json["profile"], "/bin/foo");
+    }
+}
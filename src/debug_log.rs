@@ -0,0 +1,68 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use zbus::Result;
+use zbus::message::Message;
+
+pub use crate::events::Direction;
+use crate::events::MessageEvent;
+use crate::redact::Redactor;
+
+/// Appends every message passing through a connection to a JSON Lines file,
+/// one `MessageEvent` per message.
+pub struct DebugLog {
+    file: Mutex<File>,
+    correlation_id: Option<String>,
+    redactor: Redactor,
+}
+
+impl DebugLog {
+    // Open the log file for appending, creating it if needed.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| {
+                zbus::Error::Failure(format!(
+                    "Failed to open debug log '{}': {}",
+                    path.display(),
+                    e
+                ))
+            })?;
+        Ok(Self {
+            file: Mutex::new(file),
+            correlation_id: None,
+            redactor: Redactor::default(),
+        })
+    }
+
+    // Tag every entry with a correlation ID, so the messages of one
+    // invocation can be picked out of a log shared by many.
+    pub fn with_correlation_id(mut self, id: Option<String>) -> Self {
+        self.correlation_id = id;
+        self
+    }
+
+    // Hide the values the redaction rules say to before writing entries.
+    pub fn with_redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = redactor;
+        self
+    }
+
+    // Append a single message to the log.
+    pub fn log(&self, direction: Direction, msg: &Message) -> Result<()> {
+        let mut event = MessageEvent::new(msg);
+        event.direction = Some(direction);
+        event.correlation_id = self.correlation_id.clone();
+        self.redactor.redact_event(&mut event);
+        let mut line = event.to_line()?;
+        line.push('\n');
+
+        let mut file = self.file.lock().unwrap();
+        file.write_all(line.as_bytes())
+            .map_err(|e| zbus::Error::Failure(format!("Failed to write debug log: {}", e)))
+    }
+}
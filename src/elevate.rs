@@ -0,0 +1,170 @@
+use std::env;
+use std::ffi::{CString, OsString};
+use std::io;
+use std::process::{Command, ExitStatus};
+
+// Look up the user ID of a user name, or take a numeric ID as it is.
+fn uid(user: &str) -> io::Result<libc::uid_t> {
+    if let Ok(uid) = user.parse() {
+        return Ok(uid);
+    }
+    let name = CString::new(user)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid user name"))?;
+    let entry = unsafe { libc::getpwnam(name.as_ptr()) };
+    if entry.is_null() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no such user '{}'", user),
+        ));
+    }
+    Ok(unsafe { (*entry).pw_uid })
+}
+
+// Whether zbusctl already runs as `user`, so there is nothing to wrap.
+pub fn is_user(user: &str) -> io::Result<bool> {
+    Ok(uid(user)? == unsafe { libc::geteuid() })
+}
+
+// The command line to run again, without the options that asked for it so
+// the inner zbusctl runs the command itself.
+fn inner_args(args: impl IntoIterator<Item = OsString>) -> Vec<OsString> {
+    let mut inner = Vec::new();
+    let mut args = args.into_iter().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.to_string_lossy().as_ref() {
+            "--" => {
+                inner.push(arg);
+                inner.extend(args.by_ref());
+            }
+            "--as-root" => {}
+            "--run-as" => {
+                args.next();
+            }
+            s if s.starts_with("--run-as=") => {}
+            _ => inner.push(arg),
+        }
+    }
+    inner
+}
+
+// The systemd-run options that run a command as `user` in the foreground.
+// Without a user to interact with there is no terminal to give the command,
+// and polkit is told not to ask for a password, so a script fails instead of
+// waiting on a prompt nobody answers.
+fn systemd_run_options(user: &str, interactive: bool) -> Vec<String> {
+    let mut options = vec![format!("--uid={}", user)];
+    match interactive {
+        true => options.push("--pty".to_string()),
+        false => options.push("--no-ask-password".to_string()),
+    }
+    options.extend(["--pipe", "--wait", "--quiet", "--collect", "--same-dir"].map(String::from));
+    options
+}
+
+// Run this zbusctl invocation again as another user, through systemd-run or,
+// where that is not available, pkexec. Output goes straight to our own, and
+// the inner exit status is returned so it can be passed on. Settings made
+// through ZBUSCTL_* variables are carried over; systemd-run would drop them.
+// pkexec always asks for a password, so it is only used when `interactive`.
+pub fn run_as(user: &str, interactive: bool) -> io::Result<ExitStatus> {
+    let exe = env::current_exe()?;
+    let args = inner_args(env::args_os());
+    let settings = env::vars_os()
+        .filter(|(name, _)| name.to_string_lossy().starts_with("ZBUSCTL_"))
+        .collect::<Vec<_>>();
+
+    let mut systemd_run = Command::new("systemd-run");
+    systemd_run.args(systemd_run_options(user, interactive));
+    for (name, value) in &settings {
+        let mut setenv = OsString::from("--setenv=");
+        setenv.push(name);
+        setenv.push("=");
+        setenv.push(value);
+        systemd_run.arg(setenv);
+    }
+    match systemd_run.arg("--").arg(&exe).args(&args).status() {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        result => return result,
+    }
+    if !interactive {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "systemd-run is not available, and pkexec would ask for a password, which cannot be answered with --non-interactive or without a terminal",
+        ));
+    }
+
+    Command::new("pkexec")
+        .args(["--user", user, "env"])
+        .args(settings.iter().map(|(name, value)| {
+            let mut setting = name.clone();
+            setting.push("=");
+            setting.push(value);
+            setting
+        }))
+        .arg(&exe)
+        .args(&args)
+        .status()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(words: &[&str]) -> Vec<OsString> {
+        words.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn test_inner_args() {
+        assert_eq!(
+            inner_args(words(&[
+                "zbusctl",
+                "--as-root",
+                "--output",
+                "json",
+                "names"
+            ])),
+            words(&["--output", "json", "names"])
+        );
+        assert_eq!(
+            inner_args(words(&[
+                "zbusctl",
+                "--run-as",
+                "alice",
+                "call",
+                "--run-as=bob"
+            ])),
+            words(&["call"]),
+            "Both forms of --run-as should be dropped"
+        );
+        assert_eq!(
+            inner_args(words(&[
+                "zbusctl",
+                "--as-root",
+                "testbus",
+                "--",
+                "zbusctl",
+                "--as-root",
+                "--run-as",
+                "x"
+            ])),
+            words(&["testbus", "--", "zbusctl", "--as-root", "--run-as", "x"]),
+            "Arguments after -- belong to another command line"
+        );
+    }
+
+    #[test]
+    fn test_systemd_run_options() {
+        let interactive = systemd_run_options("root", true);
+        assert!(interactive.contains(&"--pty".to_string()));
+        assert!(!interactive.contains(&"--no-ask-password".to_string()));
+
+        let script = systemd_run_options("root", false);
+        assert_eq!(script[0], "--uid=root");
+        assert!(
+            script.contains(&"--no-ask-password".to_string()),
+            "Scripts should never be asked for a password"
+        );
+        assert!(!script.contains(&"--pty".to_string()));
+    }
+}
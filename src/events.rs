@@ -0,0 +1,548 @@
+use crate::format::typed_json_to_value;
+use crate::numbers::NonFinite;
+use crate::redact::REDACTED;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use zbus::Result;
+use zbus::message::{Message, Type};
+use zvariant::{Signature, Value};
+
+/// The version of the event schema, written to every event as
+/// `schema_version`. Fields are only ever added within a version, so parsers
+/// should ignore fields they do not know; the version is bumped when a field
+/// is removed, renamed or changes meaning.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Direction of a logged message, relative to zbusctl.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+/// The type of a message, as named in events.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageType {
+    MethodCall,
+    MethodReturn,
+    Error,
+    Signal,
+}
+
+impl From<Type> for MessageType {
+    fn from(message_type: Type) -> Self {
+        match message_type {
+            Type::MethodCall => Self::MethodCall,
+            Type::MethodReturn => Self::MethodReturn,
+            Type::Error => Self::Error,
+            Type::Signal => Self::Signal,
+        }
+    }
+}
+
+/// A message seen by zbusctl, as written one per line by `monitor` and
+/// `call --debug-log`: when it was seen, its header fields and its decoded
+/// body. Header fields a message does not have are written as `null`, so
+/// every event of a type has the same fields.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MessageEvent {
+    /// Logs written before the field existed are version 1.
+    #[serde(default = "first_version")]
+    pub schema_version: u32,
+    /// Seconds since the Unix epoch.
+    pub timestamp: f64,
+    #[serde(rename = "type")]
+    pub message_type: MessageType,
+    pub serial: u32,
+    pub reply_serial: Option<u32>,
+    pub sender: Option<String>,
+    pub destination: Option<String>,
+    pub path: Option<String>,
+    pub interface: Option<String>,
+    pub member: Option<String>,
+    pub error_name: Option<String>,
+    pub signature: String,
+    /// The body's fields, each as `format::body_to_json` writes them.
+    pub body: serde_json::Value,
+    /// Only in debug logs, which see both directions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub direction: Option<Direction>,
+    /// Only in debug logs of calls made with `--correlate`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+}
+
+/// A marker `monitor --reconnect` writes between the messages it saw before
+/// losing its connection to the bus and those it sees after connecting
+/// again, as any sent in between were missed. Its `type` is always `gap`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GapEvent {
+    #[serde(default = "first_version")]
+    pub schema_version: u32,
+    /// When monitoring resumed, in seconds since the Unix epoch.
+    pub timestamp: f64,
+    #[serde(rename = "type")]
+    pub kind: GapType,
+    /// When the connection was lost.
+    pub since: f64,
+    /// Why the connection was lost.
+    pub reason: String,
+}
+
+/// The `type` of a `GapEvent`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GapType {
+    Gap,
+}
+
+impl GapEvent {
+    // The marker for a gap from `since` until now.
+    pub fn new(since: f64, reason: String) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            timestamp: now(),
+            kind: GapType::Gap,
+            since,
+            reason,
+        }
+    }
+
+    // The marker as a line of text for people, in any of the text layouts.
+    pub fn summary(&self) -> String {
+        format!(
+            "--- connection lost ({}); messages from time={:.6} to time={:.6} are missing ---",
+            self.reason, self.since, self.timestamp
+        )
+    }
+}
+
+// Parse a line as a gap marker, if it is one.
+pub fn parse_gap(line: &str) -> Option<GapEvent> {
+    serde_json::from_str(line).ok()
+}
+
+/// A note typed into an interactive `monitor` about the messages around it,
+/// kept with them when they are exported. Its `type` is always `note`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NoteEvent {
+    #[serde(default = "first_version")]
+    pub schema_version: u32,
+    /// When the note was written, in seconds since the Unix epoch.
+    pub timestamp: f64,
+    #[serde(rename = "type")]
+    pub kind: NoteType,
+    pub text: String,
+}
+
+/// The `type` of a `NoteEvent`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoteType {
+    Note,
+}
+
+impl NoteEvent {
+    // A note written now.
+    pub fn new(text: String) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            timestamp: now(),
+            kind: NoteType::Note,
+            text,
+        }
+    }
+
+    // The note as a line of text for people.
+    pub fn summary(&self) -> String {
+        format!("--- note at time={:.6}: {} ---", self.timestamp, self.text)
+    }
+}
+
+// Parse a line as a note, if it is one.
+pub fn parse_note(line: &str) -> Option<NoteEvent> {
+    serde_json::from_str(line).ok()
+}
+
+// Seconds since the Unix epoch.
+pub fn now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or_default()
+}
+
+fn first_version() -> u32 {
+    1
+}
+
+impl MessageEvent {
+    // The event for a message seen now.
+    pub fn new(msg: &Message) -> Self {
+        let header = msg.header();
+
+        Self {
+            schema_version: SCHEMA_VERSION,
+            timestamp: now(),
+            message_type: header.message_type().into(),
+            serial: header.primary().serial_num().get(),
+            reply_serial: header.reply_serial().map(|s| s.get()),
+            sender: header.sender().map(|s| s.to_string()),
+            destination: header.destination().map(|s| s.to_string()),
+            path: header.path().map(|s| s.to_string()),
+            interface: header.interface().map(|s| s.to_string()),
+            member: header.member().map(|s| s.to_string()),
+            error_name: header.error_name().map(|s| s.to_string()),
+            signature: header.signature().to_string_no_parens(),
+            body: crate::format::body_to_json(&msg.body()),
+            direction: None,
+            correlation_id: None,
+        }
+    }
+
+    // The values of the body, with the types they were seen with.
+    pub fn body_values(&self) -> Result<Vec<Value<'static>>> {
+        match self.body.as_array() {
+            Some(fields) => fields.iter().map(typed_json_to_value).collect(),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    // Encode the event as a single line of JSON, without the newline.
+    pub fn to_line(&self) -> Result<String> {
+        serde_json::to_string(self)
+            .map_err(|e| zbus::Error::Failure(format!("Failed to encode event: {}", e)))
+    }
+
+    // Write the event in the text layout of `dbus-monitor`: a line of header
+    // fields, then a line per value of the body, with the values inside
+    // containers indented below them. Values hidden by redaction are written
+    // as `<redacted>` after their type.
+    pub fn to_dbus_monitor(&self) -> String {
+        let or = |field: &Option<String>, missing: &str| {
+            field.clone().unwrap_or_else(|| missing.to_string())
+        };
+        let kind = match self.message_type {
+            MessageType::MethodCall => "method call",
+            MessageType::MethodReturn => "method return",
+            MessageType::Error => "error",
+            MessageType::Signal => "signal",
+        };
+        let mut out = format!(
+            "{} time={:.6} sender={} -> destination={}",
+            kind,
+            self.timestamp,
+            or(&self.sender, "(null sender)"),
+            or(&self.destination, "(null destination)")
+        );
+        let reply_serial = self.reply_serial.unwrap_or_default();
+        out.push_str(&match self.message_type {
+            MessageType::MethodCall | MessageType::Signal => format!(
+                " serial={} path={}; interface={}; member={}\n",
+                self.serial,
+                or(&self.path, "(null path)"),
+                or(&self.interface, "(null interface)"),
+                or(&self.member, "(null member)")
+            ),
+            MessageType::MethodReturn => {
+                format!(" serial={} reply_serial={}\n", self.serial, reply_serial)
+            }
+            MessageType::Error => format!(
+                " error_name={} reply_serial={}\n",
+                or(&self.error_name, "(no error name)"),
+                reply_serial
+            ),
+        });
+        for field in self.body.as_array().into_iter().flatten() {
+            if let Some(signature) = field["signature"]
+                .as_str()
+                .and_then(|s| Signature::from_str(s).ok())
+            {
+                write_dbus_monitor(&mut out, &signature, &field["value"], 1);
+            }
+        }
+        out
+    }
+}
+
+// Write a value of an event body as `dbus-monitor` does, three spaces of
+// indentation per level. A value that does not have the shape its signature
+// calls for, such as one replaced by redaction, is written as its JSON text.
+fn write_dbus_monitor(
+    out: &mut String,
+    signature: &Signature,
+    json: &serde_json::Value,
+    depth: usize,
+) {
+    let indent = "   ".repeat(depth);
+    let name = match signature {
+        Signature::U8 => "byte",
+        Signature::Bool => "boolean",
+        Signature::I16 => "int16",
+        Signature::U16 => "uint16",
+        Signature::I32 => "int32",
+        Signature::U32 => "uint32",
+        Signature::I64 => "int64",
+        Signature::U64 => "uint64",
+        Signature::F64 => "double",
+        Signature::Str => "string",
+        Signature::ObjectPath => "object path",
+        Signature::Signature => "signature",
+        Signature::Fd => "unix fd",
+        Signature::Variant => "variant",
+        Signature::Array(_) | Signature::Dict { .. } => "array",
+        Signature::Structure(_) => "struct",
+        Signature::Unit | Signature::Maybe(_) => "unknown",
+    };
+    let redacted = json.as_str() == Some(REDACTED);
+    match (signature, json) {
+        (
+            Signature::Str | Signature::ObjectPath | Signature::Signature,
+            serde_json::Value::String(s),
+        ) => {
+            out.push_str(&format!("{}{} \"{}\"\n", indent, name, s));
+        }
+        (Signature::F64, serde_json::Value::Null) => {
+            out.push_str(&format!("{}double nan\n", indent))
+        }
+        // Doubles JSON cannot represent are held by name.
+        (Signature::F64, serde_json::Value::String(s)) if !redacted => {
+            let value = NonFinite::parse(s).unwrap_or(f64::NAN);
+            out.push_str(&format!(
+                "{}double {}\n",
+                indent,
+                value.to_string().to_lowercase()
+            ))
+        }
+        (Signature::Variant, _) if !redacted => {
+            match json["signature"]
+                .as_str()
+                .and_then(|s| Signature::from_str(s).ok())
+            {
+                Some(inner) => {
+                    out.push_str(&format!("{}variant ", indent));
+                    write_dbus_monitor(out, &inner, &json["value"], depth + 1);
+                }
+                None => out.push_str(&format!("{}variant {}\n", indent, json)),
+            }
+        }
+        (Signature::Array(element), serde_json::Value::Array(items))
+            if **element == Signature::U8 =>
+        {
+            let bytes = items
+                .iter()
+                .filter_map(|item| item.as_u64())
+                .map(|byte| byte as u8)
+                .collect::<Vec<_>>();
+            if !bytes.is_empty() && bytes.iter().all(|b| b.is_ascii_graphic() || *b == b' ') {
+                out.push_str(&format!(
+                    "{}array of bytes \"{}\"\n",
+                    indent,
+                    String::from_utf8_lossy(&bytes)
+                ));
+            } else {
+                out.push_str(&format!("{}array of bytes [\n", indent));
+                for chunk in bytes.chunks(16) {
+                    let hex = chunk
+                        .iter()
+                        .map(|b| format!("{:02x}", b))
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    out.push_str(&format!("{}   {}\n", indent, hex));
+                }
+                out.push_str(&format!("{}]\n", indent));
+            }
+        }
+        (Signature::Array(element), serde_json::Value::Array(items)) => {
+            out.push_str(&format!("{}array [\n", indent));
+            for item in items {
+                write_dbus_monitor(out, element, item, depth + 1);
+            }
+            out.push_str(&format!("{}]\n", indent));
+        }
+        (Signature::Dict { key, value }, serde_json::Value::Object(entries)) => {
+            out.push_str(&format!("{}array [\n", indent));
+            for (k, v) in entries {
+                // Keys are written as JSON object keys, which are strings.
+                let k = match **key {
+                    Signature::Str | Signature::ObjectPath | Signature::Signature => {
+                        serde_json::Value::from(k.as_str())
+                    }
+                    _ => serde_json::from_str(k)
+                        .unwrap_or_else(|_| serde_json::Value::from(k.as_str())),
+                };
+                out.push_str(&format!("{}   dict entry(\n", indent));
+                write_dbus_monitor(out, key, &k, depth + 2);
+                write_dbus_monitor(out, value, v, depth + 2);
+                out.push_str(&format!("{}   )\n", indent));
+            }
+            out.push_str(&format!("{}]\n", indent));
+        }
+        (Signature::Structure(fields), serde_json::Value::Array(items)) => {
+            out.push_str(&format!("{}struct {{\n", indent));
+            for (field, item) in fields.iter().zip(items) {
+                write_dbus_monitor(out, field, item, depth + 1);
+            }
+            out.push_str(&format!("{}}}\n", indent));
+        }
+        _ if redacted => out.push_str(&format!("{}{} {}\n", indent, name, REDACTED)),
+        _ => out.push_str(&format!("{}{} {}\n", indent, name, json)),
+    }
+}
+
+// Parse a line written by `monitor` or `call --debug-log`. Fields added since
+// are ignored, but an event of a later schema version is refused, as its
+// fields may no longer mean what this version reads them as.
+pub fn parse_event(line: &str) -> Result<MessageEvent> {
+    let event = serde_json::from_str::<MessageEvent>(line)
+        .map_err(|e| zbus::Error::Failure(format!("Invalid event: {}", e)))?;
+    if event.schema_version > SCHEMA_VERSION {
+        return Err(zbus::Error::Failure(format!(
+            "Unsupported event schema version {} (this zbusctl reads up to {})",
+            event.schema_version, SCHEMA_VERSION
+        )));
+    }
+    Ok(event)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn echo() -> Message {
+        Message::method_call("/org/example/Test", "Echo")
+            .unwrap()
+            .destination("org.example.Test")
+            .unwrap()
+            .interface("org.example.Test")
+            .unwrap()
+            .build(&("hi",))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_event_fields() {
+        let mut event = MessageEvent::new(&echo());
+        // serde_json parses some doubles one ulp off, so a clock reading
+        // might not survive the round trip below.
+        event.timestamp = 1_700_000_000.25;
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["schema_version"], SCHEMA_VERSION);
+        assert_eq!(json["type"], "method_call");
+        assert_eq!(json["member"], "Echo");
+        assert_eq!(json["signature"], "s");
+        assert!(
+            json["sender"].is_null() && json.get("sender").is_some(),
+            "Missing header fields should be written as null"
+        );
+        assert!(
+            json.get("direction").is_none(),
+            "Debug log fields should be left out when unset"
+        );
+
+        let line = event.to_line().unwrap();
+        assert!(!line.contains('\n'), "An event should fit on one line");
+        assert_eq!(parse_event(&line).unwrap(), event);
+    }
+
+    #[test]
+    fn test_parse_event_versions() {
+        let mut json = serde_json::to_value(MessageEvent::new(&echo())).unwrap();
+        json["added_later"] = "x".into();
+        json.as_object_mut().unwrap().remove("schema_version");
+        let event = parse_event(&json.to_string()).unwrap();
+        assert_eq!(
+            event.schema_version, 1,
+            "Unknown fields should be ignored and a missing version read as 1"
+        );
+
+        json["schema_version"] = (SCHEMA_VERSION + 1).into();
+        let error = parse_event(&json.to_string()).unwrap_err().to_string();
+        assert!(
+            error.contains("Unsupported event schema version"),
+            "Later versions should be refused: {}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_gap_lines() {
+        let gap = GapEvent::new(1.0, "the bus went away".to_string());
+        let line = serde_json::to_string(&gap).unwrap();
+        assert!(line.contains(r#""type":"gap""#), "{}", line);
+        assert_eq!(parse_gap(&line), Some(gap));
+        assert!(parse_event(&line).is_err());
+
+        let line = MessageEvent::new(&echo()).to_line().unwrap();
+        assert_eq!(parse_gap(&line), None, "Messages should not read as gaps");
+    }
+
+    #[test]
+    fn test_note_lines() {
+        let note = NoteEvent::new("the device is unplugged here".to_string());
+        let line = serde_json::to_string(&note).unwrap();
+        assert!(line.contains(r#""type":"note""#), "{}", line);
+        assert_eq!(parse_note(&line), Some(note));
+        assert!(parse_event(&line).is_err());
+
+        let gap = serde_json::to_string(&GapEvent::new(1.0, "gone".to_string())).unwrap();
+        assert_eq!(parse_note(&gap), None, "Gaps should not read as notes");
+        assert_eq!(parse_gap(&line), None, "Notes should not read as gaps");
+    }
+
+    #[test]
+    fn test_dbus_monitor_layout() {
+        let mut event = MessageEvent::new(&echo());
+        event.timestamp = 1_700_000_000.25;
+        // Serials come from a counter shared by every test building messages.
+        event.serial = 1;
+        assert_eq!(
+            event.to_dbus_monitor(),
+            "method call time=1700000000.250000 sender=(null sender) -> destination=org.example.Test serial=1 path=/org/example/Test; interface=org.example.Test; member=Echo\n   string \"hi\"\n"
+        );
+
+        let changed = Message::signal("/org/example/Test", "org.example.Test", "Changed")
+            .unwrap()
+            .build(&(
+                std::collections::HashMap::from([("Count", Value::from(5u32))]),
+                vec![0u8, 255],
+                (true, Value::from("x")),
+            ))
+            .unwrap();
+        let mut event = MessageEvent::new(&changed);
+        event.timestamp = 1.5;
+        let body = event
+            .to_dbus_monitor()
+            .split_once('\n')
+            .unwrap()
+            .1
+            .to_string();
+        assert_eq!(
+            body,
+            concat!(
+                "   array [\n",
+                "      dict entry(\n",
+                "         string \"Count\"\n",
+                "         variant             uint32 5\n",
+                "      )\n",
+                "   ]\n",
+                "   array of bytes [\n",
+                "      00 ff\n",
+                "   ]\n",
+                "   struct {\n",
+                "      boolean true\n",
+                "      variant          string \"x\"\n",
+                "   }\n",
+            )
+        );
+
+        event.body[0]["value"] = REDACTED.into();
+        assert!(
+            event.to_dbus_monitor().contains("\n   array <redacted>\n"),
+            "Redacted values should keep their type"
+        );
+    }
+}
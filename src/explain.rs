@@ -0,0 +1,238 @@
+use serde::Serialize;
+use std::str::FromStr;
+use zbus::Result;
+use zvariant::Signature;
+
+/// A human-readable description of one type in a signature, with the types
+/// it contains and, where zbusctl's argument syntax can express it, an
+/// example argument of that type.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct TypeDescription {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    pub signature: String,
+    pub description: String,
+    pub example: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub members: Vec<TypeDescription>,
+}
+
+impl TypeDescription {
+    // An indented outline of the type, one line per type it contains.
+    pub fn outline(&self) -> String {
+        let mut out = String::new();
+        self.write_outline(&mut out, 0);
+        out
+    }
+
+    fn write_outline(&self, out: &mut String, depth: usize) {
+        out.push_str(&"  ".repeat(depth));
+        if let Some(ref role) = self.role {
+            out.push_str(&format!("{}: ", role));
+        }
+        out.push_str(&format!("{} ({})\n", self.description, self.signature));
+        for member in &self.members {
+            member.write_outline(out, depth + 1);
+        }
+    }
+}
+
+// Split a signature into its complete types, such as the arguments of a
+// method: `sa{sv}` is a string followed by a dictionary, while `(sa{sv})` is
+// a single structure.
+pub fn split_signature(signature: &str) -> Result<Vec<Signature>> {
+    let parsed = Signature::from_str(signature)
+        .map_err(|e| zbus::Error::Failure(format!("Invalid signature '{}': {}", signature, e)))?;
+    match parsed {
+        Signature::Unit => Ok(Vec::new()),
+        // A list of several types parses as a structure without parentheses.
+        Signature::Structure(ref fields) if parsed.to_string() != signature => {
+            Ok(fields.iter().cloned().collect())
+        }
+        parsed => Ok(vec![parsed]),
+    }
+}
+
+fn basic_name(signature: &Signature) -> Option<&'static str> {
+    Some(match signature {
+        Signature::U8 => "byte",
+        Signature::Bool => "boolean",
+        Signature::I16 => "int16",
+        Signature::U16 => "uint16",
+        Signature::I32 => "int32",
+        Signature::U32 => "uint32",
+        Signature::I64 => "int64",
+        Signature::U64 => "uint64",
+        Signature::F64 => "double",
+        Signature::Str => "string",
+        Signature::ObjectPath => "objpath",
+        Signature::Signature => "signature",
+        _ => return None,
+    })
+}
+
+// Values to show in examples, a few per type so arrays and dictionaries look
+// like themselves.
+fn example_values(signature: &Signature) -> Option<[&'static str; 2]> {
+    Some(match signature {
+        Signature::U8 => ["1", "255"],
+        Signature::Bool => ["true", "false"],
+        Signature::I16 | Signature::I32 | Signature::I64 => ["-42", "7"],
+        Signature::U16 | Signature::U32 | Signature::U64 => ["42", "7"],
+        Signature::F64 => ["3.14", "0.5"],
+        Signature::Str => ["hello", "world"],
+        Signature::ObjectPath => ["/org/example/First", "/org/example/Second"],
+        Signature::Signature => ["s", "a{sv}"],
+        _ => return None,
+    })
+}
+
+// An argument of this type in zbusctl's `type:value` syntax, if it can be
+// written at all. Arrays of basic types and dictionaries from strings to
+// basic types other than paths and signatures are supported.
+pub fn example_argument(signature: &Signature) -> Option<String> {
+    match signature {
+        Signature::Array(element) => {
+            let name = basic_name(element)?;
+            let values = example_values(element)?;
+            Some(format!("array:{}:{}", name, values.join(",")))
+        }
+        Signature::Dict { key, value } => {
+            if **key != Signature::Str
+                || matches!(**value, Signature::ObjectPath | Signature::Signature)
+            {
+                return None;
+            }
+            let name = basic_name(value)?;
+            let [first, second] = example_values(value)?;
+            Some(format!("dict:string:{}:one,{},two,{}", name, first, second))
+        }
+        basic => Some(format!(
+            "{}:{}",
+            basic_name(basic)?,
+            example_values(basic)?[0]
+        )),
+    }
+}
+
+// Describe a type and everything inside it.
+pub fn describe(signature: &Signature) -> TypeDescription {
+    let member = |role: &str, signature: &Signature| TypeDescription {
+        role: Some(role.to_string()),
+        ..describe(signature)
+    };
+    let (description, members) = match signature {
+        Signature::Unit => ("nothing".to_string(), Vec::new()),
+        Signature::U8 => ("byte, an unsigned 8-bit integer".to_string(), Vec::new()),
+        Signature::Bool => ("boolean".to_string(), Vec::new()),
+        Signature::I16 => ("signed 16-bit integer".to_string(), Vec::new()),
+        Signature::U16 => ("unsigned 16-bit integer".to_string(), Vec::new()),
+        Signature::I32 => ("signed 32-bit integer".to_string(), Vec::new()),
+        Signature::U32 => ("unsigned 32-bit integer".to_string(), Vec::new()),
+        Signature::I64 => ("signed 64-bit integer".to_string(), Vec::new()),
+        Signature::U64 => ("unsigned 64-bit integer".to_string(), Vec::new()),
+        Signature::F64 => (
+            "double-precision floating point number".to_string(),
+            Vec::new(),
+        ),
+        Signature::Str => ("string".to_string(), Vec::new()),
+        Signature::ObjectPath => ("object path".to_string(), Vec::new()),
+        Signature::Signature => ("type signature".to_string(), Vec::new()),
+        Signature::Variant => (
+            "variant, a value of any type that carries its own signature".to_string(),
+            Vec::new(),
+        ),
+        Signature::Fd => ("Unix file descriptor".to_string(), Vec::new()),
+        Signature::Array(element) => ("array".to_string(), vec![member("element", element)]),
+        Signature::Dict { key, value } => (
+            "dictionary".to_string(),
+            vec![member("key", key), member("value", value)],
+        ),
+        Signature::Structure(fields) => (
+            format!("structure of {} fields", fields.len()),
+            fields
+                .iter()
+                .enumerate()
+                .map(|(i, field)| member(&format!("field {}", i + 1), field))
+                .collect(),
+        ),
+        Signature::Maybe(child) => (
+            "maybe, a GVariant value that may be absent".to_string(),
+            vec![member("value", child)],
+        ),
+    };
+
+    TypeDescription {
+        role: None,
+        signature: signature.to_string(),
+        description,
+        example: example_argument(signature),
+        members,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_body;
+
+    #[test]
+    fn test_split_signature() {
+        let split = |s: &str| {
+            split_signature(s)
+                .unwrap()
+                .iter()
+                .map(|s| s.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(split("sa{sv}"), vec!["s", "a{sv}"]);
+        assert_eq!(split("(sa{sv})"), vec!["(sa{sv})"]);
+        assert_eq!(split("(si)(ii)"), vec!["(si)", "(ii)"]);
+        assert_eq!(split("a{sa{sv}}"), vec!["a{sa{sv}}"]);
+        assert!(split("").is_empty());
+        assert!(split_signature("a{").is_err());
+    }
+
+    #[test]
+    fn test_outline() {
+        let signature = Signature::from_str("a{sa{sv}}").unwrap();
+        assert_eq!(
+            describe(&signature).outline(),
+            "dictionary (a{sa{sv}})\n\
+             \x20 key: string (s)\n\
+             \x20 value: dictionary (a{sv})\n\
+             \x20   key: string (s)\n\
+             \x20   value: variant, a value of any type that carries its own signature (v)\n"
+        );
+    }
+
+    #[test]
+    fn test_examples_parse_back() {
+        for signature in [
+            "y", "b", "n", "q", "i", "u", "x", "t", "d", "s", "o", "g", "ay", "ab", "ai", "at",
+            "ad", "as", "ao", "ag", "a{sy}", "a{sb}", "a{si}", "a{st}", "a{sd}", "a{ss}",
+        ] {
+            let parsed = Signature::from_str(signature).unwrap();
+            let example = example_argument(&parsed)
+                .unwrap_or_else(|| panic!("No example for '{}'", signature));
+            let body = build_body(vec![example.as_str()])
+                .unwrap_or_else(|e| panic!("Example '{}' does not parse: {}", example, e));
+            assert_eq!(
+                body.fields()[0].value_signature().to_string(),
+                signature,
+                "Example '{}' has the wrong type",
+                example
+            );
+        }
+
+        for signature in ["v", "h", "a{sv}", "a{is}", "a{so}", "(si)", "aas"] {
+            let parsed = Signature::from_str(signature).unwrap();
+            assert_eq!(
+                example_argument(&parsed),
+                None,
+                "'{}' has no argument syntax",
+                signature
+            );
+        }
+    }
+}
@@ -0,0 +1,444 @@
+use crate::format::display_value;
+use std::collections::HashMap;
+use std::str::FromStr;
+use zvariant::{OwnedValue, Value};
+
+/// A condition over the properties of an interface, as `watch --until`
+/// takes it, such as `props["State"] == "activated" && props.Progress >= 100`.
+///
+/// Properties are written `props["Name"]` or `props.Name` and compared with
+/// `==`, `!=`, `<`, `<=`, `>` and `>=` to strings in double quotes, numbers,
+/// `true`, `false` or each other; conditions combine with `&&`, `||`, `!`
+/// and parentheses. Numbers compare as numbers and anything else as the
+/// value is shown in tables, so `props.State == "70"` holds for a `u` of 70.
+/// A property on its own holds when it is true, non-zero or non-empty. A
+/// property that is unknown, or of a type that cannot be put in order,
+/// makes every comparison it is in false.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expression {
+    Property(String),
+    Literal(Literal),
+    Compare(Box<Expression>, Operator, Box<Expression>),
+    Not(Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+}
+
+/// A constant in an [`Expression`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Literal {
+    String(String),
+    Number(f64),
+    Bool(bool),
+}
+
+/// How an [`Expression`] compares two values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operator {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Props,
+    Name(String),
+    String(String),
+    Number(f64),
+    Dot,
+    Open,
+    Close,
+    OpenBracket,
+    CloseBracket,
+    Not,
+    And,
+    Or,
+    Operator(Operator),
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = s.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let mut next_is = |expected: char| chars.next_if(|(_, c)| *c == expected).is_some();
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '.' => Token::Dot,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '[' => Token::OpenBracket,
+            ']' => Token::CloseBracket,
+            '&' if next_is('&') => Token::And,
+            '|' if next_is('|') => Token::Or,
+            '=' if next_is('=') => Token::Operator(Operator::Eq),
+            '!' if next_is('=') => Token::Operator(Operator::Ne),
+            '!' => Token::Not,
+            '<' if next_is('=') => Token::Operator(Operator::Le),
+            '<' => Token::Operator(Operator::Lt),
+            '>' if next_is('=') => Token::Operator(Operator::Ge),
+            '>' => Token::Operator(Operator::Gt),
+            '"' => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, c)) => string.push(c),
+                            None => return Err("unterminated string".to_string()),
+                        },
+                        Some((_, c)) => string.push(c),
+                        None => return Err("unterminated string".to_string()),
+                    }
+                }
+                Token::String(string)
+            }
+            c if c.is_ascii_digit() || c == '-' => {
+                let mut number = c.to_string();
+                while let Some((_, c)) = chars.next_if(|(_, c)| c.is_ascii_digit() || *c == '.') {
+                    number.push(c);
+                }
+                Token::Number(
+                    number
+                        .parse()
+                        .map_err(|_| format!("invalid number '{}'", number))?,
+                )
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let mut name = c.to_string();
+                while let Some((_, c)) =
+                    chars.next_if(|(_, c)| c.is_ascii_alphanumeric() || *c == '_')
+                {
+                    name.push(c);
+                }
+                match name.as_str() {
+                    "props" => Token::Props,
+                    _ => Token::Name(name),
+                }
+            }
+            c => return Err(format!("unexpected '{}' at offset {}", c, i)),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+// A recursive-descent parser over the tokens, from the loosest-binding
+// operator down.
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token, what: &str) -> Result<(), String> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            _ => Err(format!("expected {}", what)),
+        }
+    }
+
+    fn or(&mut self) -> Result<Expression, String> {
+        let mut left = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            left = Expression::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expression, String> {
+        let mut left = self.not()?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            left = Expression::And(Box::new(left), Box::new(self.not()?));
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<Expression, String> {
+        if self.peek() == Some(&Token::Not) {
+            self.next();
+            return Ok(Expression::Not(Box::new(self.not()?)));
+        }
+        self.compare()
+    }
+
+    fn compare(&mut self) -> Result<Expression, String> {
+        let left = self.operand()?;
+        match self.peek() {
+            Some(&Token::Operator(operator)) => {
+                self.next();
+                Ok(Expression::Compare(
+                    Box::new(left),
+                    operator,
+                    Box::new(self.operand()?),
+                ))
+            }
+            _ => Ok(left),
+        }
+    }
+
+    fn operand(&mut self) -> Result<Expression, String> {
+        match self.next() {
+            Some(Token::Props) => match self.next() {
+                Some(Token::Dot) => match self.next() {
+                    Some(Token::Name(name)) => Ok(Expression::Property(name)),
+                    _ => Err("expected a property name after 'props.'".to_string()),
+                },
+                Some(Token::OpenBracket) => match self.next() {
+                    Some(Token::String(name)) => {
+                        self.expect(Token::CloseBracket, "']'")?;
+                        Ok(Expression::Property(name))
+                    }
+                    _ => Err("expected a quoted property name after 'props['".to_string()),
+                },
+                _ => Err("expected '.' or '[' after 'props'".to_string()),
+            },
+            Some(Token::String(s)) => Ok(Expression::Literal(Literal::String(s))),
+            Some(Token::Number(n)) => Ok(Expression::Literal(Literal::Number(n))),
+            Some(Token::Name(name)) if name == "true" || name == "false" => {
+                Ok(Expression::Literal(Literal::Bool(name == "true")))
+            }
+            Some(Token::Name(name)) => Err(format!(
+                "unknown name '{}'; properties are written props.{}",
+                name, name
+            )),
+            Some(Token::Open) => {
+                let expression = self.or()?;
+                self.expect(Token::Close, "')'")?;
+                Ok(expression)
+            }
+            Some(_) => Err("expected a property, a value or '('".to_string()),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+}
+
+impl FromStr for Expression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |e: String| format!("Invalid expression '{}': {}", s, e);
+        let mut parser = Parser {
+            tokens: tokenize(s).map_err(invalid)?,
+            position: 0,
+        };
+        let expression = parser.or().map_err(invalid)?;
+        if parser.peek().is_some() {
+            return Err(invalid("unexpected text after the end".to_string()));
+        }
+        Ok(expression)
+    }
+}
+
+// A value as the expression compares it.
+#[derive(Debug, PartialEq)]
+enum Operand {
+    Number(f64),
+    Bool(bool),
+    Text(String),
+    Unknown,
+}
+
+impl Operand {
+    fn of(value: &Value) -> Self {
+        match value {
+            Value::Value(value) => Self::of(value),
+            Value::U8(n) => Self::Number(f64::from(*n)),
+            Value::I16(n) => Self::Number(f64::from(*n)),
+            Value::U16(n) => Self::Number(f64::from(*n)),
+            Value::I32(n) => Self::Number(f64::from(*n)),
+            Value::U32(n) => Self::Number(f64::from(*n)),
+            Value::I64(n) => Self::Number(*n as f64),
+            Value::U64(n) => Self::Number(*n as f64),
+            Value::F64(n) => Self::Number(*n),
+            Value::Bool(b) => Self::Bool(*b),
+            value => Self::Text(display_value(value)),
+        }
+    }
+
+    fn text(&self) -> Option<String> {
+        match self {
+            Self::Number(n) => Some(n.to_string()),
+            Self::Bool(b) => Some(b.to_string()),
+            Self::Text(s) => Some(s.clone()),
+            Self::Unknown => None,
+        }
+    }
+
+    fn truthy(&self) -> bool {
+        match self {
+            Self::Number(n) => *n != 0.0,
+            Self::Bool(b) => *b,
+            Self::Text(s) => !s.is_empty(),
+            Self::Unknown => false,
+        }
+    }
+}
+
+impl Expression {
+    // The properties the expression reads, each once, in the order they
+    // first appear.
+    pub fn properties(&self) -> Vec<String> {
+        let mut properties = Vec::new();
+        self.collect_properties(&mut properties);
+        properties
+    }
+
+    fn collect_properties(&self, properties: &mut Vec<String>) {
+        match self {
+            Self::Property(name) if !properties.contains(name) => properties.push(name.clone()),
+            Self::Property(_) | Self::Literal(_) => {}
+            Self::Not(inner) => inner.collect_properties(properties),
+            Self::Compare(left, _, right) | Self::And(left, right) | Self::Or(left, right) => {
+                left.collect_properties(properties);
+                right.collect_properties(properties);
+            }
+        }
+    }
+
+    // Whether the condition holds for the properties' current values.
+    pub fn eval(&self, properties: &HashMap<String, OwnedValue>) -> bool {
+        match self {
+            Self::Compare(left, operator, right) => compare(
+                left.operand(properties),
+                *operator,
+                right.operand(properties),
+            ),
+            Self::Not(inner) => !inner.eval(properties),
+            Self::And(left, right) => left.eval(properties) && right.eval(properties),
+            Self::Or(left, right) => left.eval(properties) || right.eval(properties),
+            operand => operand.operand(properties).truthy(),
+        }
+    }
+
+    fn operand(&self, properties: &HashMap<String, OwnedValue>) -> Operand {
+        match self {
+            Self::Property(name) => properties
+                .get(name)
+                .map_or(Operand::Unknown, |value| Operand::of(value)),
+            Self::Literal(Literal::String(s)) => Operand::Text(s.clone()),
+            Self::Literal(Literal::Number(n)) => Operand::Number(*n),
+            Self::Literal(Literal::Bool(b)) => Operand::Bool(*b),
+            condition => Operand::Bool(condition.eval(properties)),
+        }
+    }
+}
+
+fn compare(left: Operand, operator: Operator, right: Operand) -> bool {
+    let ordering = match (&left, &right) {
+        (Operand::Number(a), Operand::Number(b)) => a.partial_cmp(b),
+        (Operand::Text(a), Operand::Text(b)) => Some(a.cmp(b)),
+        _ => None,
+    };
+    match operator {
+        Operator::Eq | Operator::Ne => {
+            let equal = match (&left, &right) {
+                (Operand::Number(a), Operand::Number(b)) => Some(a == b),
+                _ => left.text().zip(right.text()).map(|(a, b)| a == b),
+            };
+            equal.is_some_and(|equal| equal == (operator == Operator::Eq))
+        }
+        Operator::Lt => ordering.is_some_and(|o| o.is_lt()),
+        Operator::Le => ordering.is_some_and(|o| o.is_le()),
+        Operator::Gt => ordering.is_some_and(|o| o.is_gt()),
+        Operator::Ge => ordering.is_some_and(|o| o.is_ge()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn properties() -> HashMap<String, OwnedValue> {
+        HashMap::from([
+            ("State".to_string(), OwnedValue::from(70u32)),
+            (
+                "Name".to_string(),
+                Value::from("activated").try_into().unwrap(),
+            ),
+            ("Enabled".to_string(), OwnedValue::from(true)),
+        ])
+    }
+
+    fn holds(expression: &str) -> bool {
+        expression
+            .parse::<Expression>()
+            .unwrap()
+            .eval(&properties())
+    }
+
+    #[test]
+    fn test_parse_expression() {
+        let expression: Expression = r#"props["State"] == 70 && !props.Enabled"#.parse().unwrap();
+        assert_eq!(
+            expression,
+            Expression::And(
+                Box::new(Expression::Compare(
+                    Box::new(Expression::Property("State".to_string())),
+                    Operator::Eq,
+                    Box::new(Expression::Literal(Literal::Number(70.0))),
+                )),
+                Box::new(Expression::Not(Box::new(Expression::Property(
+                    "Enabled".to_string()
+                )))),
+            )
+        );
+        assert_eq!(expression.properties(), vec!["State", "Enabled"]);
+
+        for bad in [
+            "",
+            "State == 1",
+            "props[State]",
+            "props.State ==",
+            "(props.State",
+            "props.Name == \"x",
+            "props.State = 1",
+            "props.State 1",
+        ] {
+            assert!(
+                bad.parse::<Expression>().is_err(),
+                "{} should be rejected",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn test_eval_expression() {
+        assert!(holds(r#"props["Name"] == "activated""#));
+        assert!(holds(
+            "props.State == 70 && props.State >= 70 && props.State < 71"
+        ));
+        assert!(
+            holds(r#"props.State == "70""#),
+            "Numbers should compare with strings as they are shown"
+        );
+        assert!(holds("props.Enabled && props.Enabled == true"));
+        assert!(holds("!(props.State > 70) || false"));
+        assert!(holds(r#"props.Name > "a""#));
+        assert!(!holds(r#"props.Name == "connecting""#));
+
+        assert!(!holds("props.Missing == 1"));
+        assert!(
+            !holds("props.Missing != 1"),
+            "Unknown properties should make every comparison false"
+        );
+        assert!(!holds("props.Missing"));
+        assert!(!holds(r#"props.State > "a""#));
+    }
+}
@@ -0,0 +1,236 @@
+use serde::{Deserialize, Serialize};
+use std::ffi::{CStr, CString, c_char};
+use std::sync::OnceLock;
+use std::time::Duration;
+use zbus::Result;
+use zbus::message::Message;
+
+use crate::events::MessageEvent;
+use crate::format::{FloatFormat, body_to_json, to_json};
+use crate::numbers::Numbers;
+use crate::project::ProjectConfig;
+use crate::redact::Redactor;
+use crate::{ParseOptions, build_body_with};
+
+// A method call request, as passed to `zbusctl_call`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Request {
+    #[serde(default)]
+    system: bool,
+    service: String,
+    object: String,
+    interface: String,
+    method: String,
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default)]
+    timeout: Option<f64>,
+    #[serde(default)]
+    no_redact: bool,
+}
+
+// The answer to a request: the reply's fields, or why there is no reply.
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Response {
+    Reply(serde_json::Value),
+    Error(String),
+}
+
+// Calls from C run on one shared runtime, created on first use, since the
+// caller has none.
+fn runtime() -> &'static tokio::runtime::Runtime {
+    static RUNTIME: OnceLock<tokio::runtime::Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .expect("failed to start the tokio runtime")
+    })
+}
+
+async fn call(request: Request) -> Result<serde_json::Value> {
+    // Parse the arguments first, so bad ones fail without a connection.
+    let body = if request.args.is_empty() {
+        None
+    } else {
+        Some(build_body_with(
+            request.args.iter().map(|s| s.as_str()).collect(),
+            &ParseOptions::default(),
+        )?)
+    };
+
+    let mut builder = if request.system {
+        zbus::connection::Builder::system()?
+    } else {
+        zbus::connection::Builder::session()?
+    };
+    if let Some(secs) = request.timeout {
+        let timeout = Duration::try_from_secs_f64(secs)
+            .map_err(|e| zbus::Error::Failure(format!("Invalid timeout '{}': {}", secs, e)))?;
+        builder = builder.method_timeout(timeout);
+    }
+    // The rules of the project the caller runs in apply, as they do to the
+    // command line.
+    let redactor = match request.no_redact {
+        true => Redactor::default(),
+        false => Redactor::new(
+            ProjectConfig::discover()?
+                .map(|(_, config)| config.redact)
+                .unwrap_or_default(),
+        ),
+    };
+    let connection = builder.build().await?;
+
+    let (service, object) = (request.service.as_str(), request.object.as_str());
+    let (interface, method) = (request.interface.as_str(), request.method.as_str());
+    let reply = match body {
+        Some(ref body) => {
+            connection
+                .call_method(Some(service), object, Some(interface), method, body)
+                .await?
+        }
+        None => {
+            connection
+                .call_method(Some(service), object, Some(interface), method, &())
+                .await?
+        }
+    };
+
+    let mut json = body_to_json(&reply.body());
+    Numbers::default().apply_to_body(&mut json)?;
+    if !redactor.is_empty() {
+        // What to hide is judged by the call, as the reply does not say.
+        let call = Message::method_call(object, method)?.interface(interface)?;
+        let call = match body {
+            Some(body) => call.build(&body)?,
+            None => call.build(&())?,
+        };
+        if let Some(redaction) = redactor.reply_redaction(&MessageEvent::new(&call)) {
+            redactor.redact_reply(&redaction, &mut json);
+        }
+    }
+    Ok(json)
+}
+
+// Handle a JSON request, returning a JSON response. This is what
+// `zbusctl_call` does, for callers in Rust. It blocks until the reply comes,
+// so it must not be called from a task of a Tokio runtime, where it answers
+// with an error; async callers should run it with `spawn_blocking`.
+pub fn call_json(request: &str) -> String {
+    let response = match serde_json::from_str::<Request>(request) {
+        Ok(_) if tokio::runtime::Handle::try_current().is_ok() => Response::Error(
+            "Cannot block on a call from within a Tokio runtime; call it from a blocking thread, such as with spawn_blocking"
+                .to_string(),
+        ),
+        Ok(request) => match runtime().block_on(call(request)) {
+            Ok(reply) => Response::Reply(reply),
+            Err(e) => Response::Error(e.to_string()),
+        },
+        Err(e) => Response::Error(format!("Invalid request: {}", e)),
+    };
+    to_json(&response, false, FloatFormat::Shortest)
+        .unwrap_or_else(|e| error_json(&format!("Failed to convert reply to JSON: {}", e)))
+}
+
+// The response for an error met before there is a `Response` to convert,
+// escaped as JSON like any other, whatever the message holds.
+fn error_json(message: &str) -> String {
+    serde_json::json!({ "error": message }).to_string()
+}
+
+/// Make a D-Bus method call described by a JSON request and return a JSON
+/// response, which must be released with [`zbusctl_free`]. See
+/// `include/zbusctl.h` for the request format.
+///
+/// # Safety
+///
+/// `request` must be a valid, NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn zbusctl_call(request: *const c_char) -> *mut c_char {
+    let response = if request.is_null() {
+        error_json("Invalid request: null pointer")
+    } else {
+        match unsafe { CStr::from_ptr(request) }.to_str() {
+            Ok(request) => call_json(request),
+            Err(e) => error_json(&format!("Invalid request: {}", e)),
+        }
+    };
+    // JSON escapes control characters, so the response has no NUL bytes.
+    CString::new(response).unwrap_or_default().into_raw()
+}
+
+/// Release a response returned by [`zbusctl_call`].
+///
+/// # Safety
+///
+/// `response` must come from `zbusctl_call` and not have been freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn zbusctl_free(response: *mut c_char) {
+    if !response.is_null() {
+        drop(unsafe { CString::from_raw(response) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_requests() {
+        let response = call_json("{");
+        assert!(
+            response.starts_with("{\"error\":\"Invalid request:"),
+            "Unexpected response: {}",
+            response
+        );
+
+        let response = call_json(
+            r#"{"service": "org.example.Test", "object": "/", "interface": "org.example.Test",
+                "method": "Echo", "args": ["bad"]}"#,
+        );
+        assert!(
+            response.contains("expected format: <type>:<value>"),
+            "Arguments should be checked before connecting: {}",
+            response
+        );
+    }
+
+    #[test]
+    fn test_error_json() {
+        let json = error_json(r#"Unknown "method" at C:\path"#);
+        let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed["error"], r#"Unknown "method" at C:\path"#);
+    }
+
+    #[tokio::test]
+    async fn test_inside_runtime() {
+        let response = call_json(
+            r#"{"service": "org.example.Test", "object": "/", "interface": "org.example.Test",
+                "method": "Ping"}"#,
+        );
+        assert!(
+            response.contains("within a Tokio runtime"),
+            "Calls from a runtime should fail rather than panic: {}",
+            response
+        );
+    }
+
+    #[test]
+    fn test_c_api() {
+        let request = CString::new(r#"{"service": "x"}"#).unwrap();
+        let response = unsafe { zbusctl_call(request.as_ptr()) };
+        let text = unsafe { CStr::from_ptr(response) }
+            .to_str()
+            .unwrap()
+            .to_string();
+        unsafe { zbusctl_free(response) };
+        assert!(
+            text.contains("missing field"),
+            "Unexpected response: {}",
+            text
+        );
+    }
+}
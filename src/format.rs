@@ -0,0 +1,702 @@
+use serde_json::ser::{CompactFormatter, Formatter, PrettyFormatter};
+use std::io::{self, Write};
+use std::str::FromStr;
+use zbus::Result;
+use zbus::message::Body;
+
+use crate::numbers::{NonFinite, Numbers, WithNumbers};
+use zvariant::{Array, Dict, ObjectPath, Signature, Structure, StructureBuilder, Value};
+
+/// How byte array (`ay`) replies are printed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum BytesFormat {
+    /// Render as a JSON array of numbers, like any other reply.
+    #[default]
+    Json,
+    /// Lowercase hexadecimal digits.
+    Hex,
+    /// Standard base64 with padding.
+    Base64,
+    /// The bytes themselves, unmodified.
+    Raw,
+}
+
+/// Output format for command results.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Compact JSON on a single line.
+    Json,
+    /// Indented JSON.
+    JsonPretty,
+    /// YAML, with repeated sub-structures written once and aliased.
+    #[cfg(feature = "yaml")]
+    Yaml,
+    /// The text layout of `dbus-monitor`, which only `monitor` writes.
+    #[cfg(feature = "capture")]
+    DbusMonitor,
+}
+
+/// How floating point (`d`) values are written in JSON output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FloatFormat {
+    /// The shortest representation that reads back as the same value.
+    #[default]
+    Shortest,
+    /// A fixed number of digits after the decimal point.
+    Fixed(usize),
+    /// Scientific notation, such as `1.5e3`.
+    Scientific,
+}
+
+impl FromStr for FloatFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "shortest" => Ok(Self::Shortest),
+            "scientific" => Ok(Self::Scientific),
+            _ => match s.strip_prefix("fixed:") {
+                Some(digits) => digits
+                    .parse()
+                    .map(Self::Fixed)
+                    .map_err(|e| format!("invalid number of digits '{}': {}", digits, e)),
+                None => Err(format!(
+                    "expected shortest, fixed:N or scientific, got '{}'",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+// Format a finite float. The result is always a valid JSON number.
+pub fn format_float(value: f64, format: FloatFormat) -> String {
+    match format {
+        FloatFormat::Shortest => serde_json::Number::from_f64(value)
+            .map(|n| n.to_string())
+            .unwrap_or_else(|| value.to_string()),
+        FloatFormat::Fixed(digits) => format!("{:.*}", digits, value),
+        FloatFormat::Scientific => format!("{:e}", value),
+    }
+}
+
+// A JSON formatter that writes floats in a chosen format and leaves everything
+// else to the compact or pretty formatter it wraps.
+struct FloatFormatter<F> {
+    inner: F,
+    floats: FloatFormat,
+}
+
+impl<F: Formatter> Formatter for FloatFormatter<F> {
+    fn write_f64<W: ?Sized + Write>(&mut self, writer: &mut W, value: f64) -> io::Result<()> {
+        writer.write_all(format_float(value, self.floats).as_bytes())
+    }
+
+    fn begin_array<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.begin_array(writer)
+    }
+
+    fn end_array<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.end_array(writer)
+    }
+
+    fn begin_array_value<W: ?Sized + Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> io::Result<()> {
+        self.inner.begin_array_value(writer, first)
+    }
+
+    fn end_array_value<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.end_array_value(writer)
+    }
+
+    fn begin_object<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.begin_object(writer)
+    }
+
+    fn end_object<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.end_object(writer)
+    }
+
+    fn begin_object_key<W: ?Sized + Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> io::Result<()> {
+        self.inner.begin_object_key(writer, first)
+    }
+
+    fn begin_object_value<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.begin_object_value(writer)
+    }
+
+    fn end_object_value<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.end_object_value(writer)
+    }
+}
+
+// Serialize a value as JSON, compact or indented, with floats in the given
+// format.
+pub fn to_json<T: serde::Serialize + ?Sized>(
+    value: &T,
+    pretty: bool,
+    floats: FloatFormat,
+) -> serde_json::Result<String> {
+    let mut out = Vec::new();
+    if pretty {
+        let formatter = FloatFormatter {
+            inner: PrettyFormatter::new(),
+            floats,
+        };
+        value.serialize(&mut serde_json::Serializer::with_formatter(
+            &mut out, formatter,
+        ))?;
+    } else {
+        let formatter = FloatFormatter {
+            inner: CompactFormatter,
+            floats,
+        };
+        value.serialize(&mut serde_json::Serializer::with_formatter(
+            &mut out, formatter,
+        ))?;
+    }
+    // The serializer only ever writes valid UTF-8.
+    Ok(String::from_utf8(out).expect("JSON output is UTF-8"))
+}
+
+// Size of the chunks bytes are encoded in before being handed to the writer.
+// Encoding in fixed chunks means multi-megabyte payloads never need an
+// intermediate string the size of the whole encoded output.
+const CHUNK_SIZE: usize = 3 * 1024;
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// Stream a byte array to the writer in the given format. Hex and base64 output
+// is terminated by a newline; raw output is written as-is.
+pub fn write_bytes<W: Write>(out: &mut W, bytes: &[u8], format: BytesFormat) -> io::Result<()> {
+    match format {
+        BytesFormat::Json => {
+            serde_json::to_writer(&mut *out, bytes)?;
+            out.write_all(b"\n")
+        }
+        BytesFormat::Hex => {
+            let mut buf = [0u8; CHUNK_SIZE * 2];
+            for chunk in bytes.chunks(CHUNK_SIZE) {
+                for (i, b) in chunk.iter().enumerate() {
+                    buf[i * 2] = HEX_DIGITS[(b >> 4) as usize];
+                    buf[i * 2 + 1] = HEX_DIGITS[(b & 0x0f) as usize];
+                }
+                out.write_all(&buf[..chunk.len() * 2])?;
+            }
+            out.write_all(b"\n")
+        }
+        BytesFormat::Base64 => {
+            // CHUNK_SIZE is a multiple of 3, so only the final chunk can need padding.
+            let mut buf = [0u8; CHUNK_SIZE / 3 * 4];
+            for chunk in bytes.chunks(CHUNK_SIZE) {
+                let mut len = 0;
+                for group in chunk.chunks(3) {
+                    let n = (group[0] as u32) << 16
+                        | (*group.get(1).unwrap_or(&0) as u32) << 8
+                        | *group.get(2).unwrap_or(&0) as u32;
+                    buf[len] = BASE64_ALPHABET[(n >> 18 & 0x3f) as usize];
+                    buf[len + 1] = BASE64_ALPHABET[(n >> 12 & 0x3f) as usize];
+                    buf[len + 2] = if group.len() > 1 {
+                        BASE64_ALPHABET[(n >> 6 & 0x3f) as usize]
+                    } else {
+                        b'='
+                    };
+                    buf[len + 3] = if group.len() > 2 {
+                        BASE64_ALPHABET[(n & 0x3f) as usize]
+                    } else {
+                        b'='
+                    };
+                    len += 4;
+                }
+                out.write_all(&buf[..len])?;
+            }
+            out.write_all(b"\n")
+        }
+        BytesFormat::Raw => out.write_all(bytes),
+    }
+}
+
+// Convert a message body to a JSON array holding one entry per field. Doubles
+// JSON cannot represent are written by name (`"NaN"`), which their signature
+// tells from strings, until `Numbers::apply_to_body` writes them as asked.
+// Bodies that cannot be decoded are represented by an object describing the
+// error.
+pub fn body_to_json(body: &Body) -> serde_json::Value {
+    if *body.signature() == Signature::Unit {
+        return serde_json::Value::Array(Vec::new());
+    }
+
+    let by_name = Numbers {
+        non_finite: NonFinite::String,
+        ..Numbers::default()
+    };
+    match body.deserialize::<Structure>() {
+        Ok(structure) => serde_json::to_value(WithNumbers {
+            value: structure.fields(),
+            policy: by_name,
+        })
+        .unwrap_or_else(|e| serde_json::json!({ "error": e.to_string() })),
+        Err(e) => serde_json::json!({ "error": e.to_string() }),
+    }
+}
+
+// Format bytes as a classic hexdump: offset, sixteen hex bytes and their
+// printable ASCII representation per line.
+pub fn hexdump(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for (i, line) in bytes.chunks(16).enumerate() {
+        let hex = line
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let ascii = line
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect::<String>();
+        out.push_str(&format!("  {:08x}  {:<47}  |{}|\n", i * 16, hex, ascii));
+    }
+    out
+}
+
+// Convert a value to plain JSON, without the signature annotations the serde
+// representation of `Value` carries, with 64-bit integers and doubles that
+// JSON cannot represent (NaN, infinities) written as `numbers` says.
+// Dictionary keys become strings.
+pub fn value_to_json(value: &Value, numbers: Numbers) -> Result<serde_json::Value> {
+    use serde_json::Value as Json;
+
+    let convert = |value: &Value| value_to_json(value, numbers);
+    Ok(match value {
+        Value::U8(v) => Json::from(*v),
+        Value::Bool(v) => Json::from(*v),
+        Value::I16(v) => Json::from(*v),
+        Value::U16(v) => Json::from(*v),
+        Value::I32(v) => Json::from(*v),
+        Value::U32(v) => Json::from(*v),
+        Value::I64(v) if numbers.int64_as_string => Json::from(v.to_string()),
+        Value::I64(v) => Json::from(*v),
+        Value::U64(v) if numbers.int64_as_string => Json::from(v.to_string()),
+        Value::U64(v) => Json::from(*v),
+        Value::F64(v) => numbers.double(*v)?,
+        Value::Str(v) => Json::from(v.as_str()),
+        Value::Signature(v) => Json::from(v.to_string()),
+        Value::ObjectPath(v) => Json::from(v.as_str()),
+        Value::Value(v) => convert(v)?,
+        Value::Array(array) => {
+            Json::Array(array.inner().iter().map(convert).collect::<Result<_>>()?)
+        }
+        Value::Dict(dict) => Json::Object(
+            dict.iter()
+                .map(|(k, v)| Ok((display_value(k), convert(v)?)))
+                .collect::<Result<_>>()?,
+        ),
+        Value::Structure(structure) => Json::Array(
+            structure
+                .fields()
+                .iter()
+                .map(convert)
+                .collect::<Result<_>>()?,
+        ),
+        _ => serde_json::to_value(value).unwrap_or(Json::Null),
+    })
+}
+
+// Convert JSON in the form values serialize to, `{"signature": ..., "value":
+// ...}` with nested variants written the same way, back into the value. Unlike
+// `value_to_json`, that form keeps every type, so values survive the round
+// trip through a file.
+pub fn typed_json_to_value(json: &serde_json::Value) -> Result<Value<'static>> {
+    let invalid = || {
+        zbus::Error::Failure(format!(
+            "Expected {{\"signature\": ..., \"value\": ...}}, got {}",
+            json
+        ))
+    };
+    let signature = json
+        .get("signature")
+        .and_then(|s| s.as_str())
+        .ok_or_else(invalid)?;
+    let value = json.get("value").ok_or_else(invalid)?;
+    let signature = Signature::from_str(signature)
+        .map_err(|e| zbus::Error::Failure(format!("Invalid signature '{}': {}", signature, e)))?;
+    json_to_value(value, &signature)
+}
+
+fn json_to_value(json: &serde_json::Value, signature: &Signature) -> Result<Value<'static>> {
+    use serde_json::Value as Json;
+
+    let mismatch = || {
+        zbus::Error::Failure(format!(
+            "Cannot convert {} to a value of type '{}'",
+            json, signature
+        ))
+    };
+    let string = || json.as_str().ok_or_else(mismatch);
+    fn integer<T: TryFrom<u64> + TryFrom<i64>>(json: &Json) -> Option<T> {
+        json.as_u64()
+            .and_then(|v| T::try_from(v).ok())
+            .or_else(|| json.as_i64().and_then(|v| T::try_from(v).ok()))
+    }
+    // 64-bit integers may have been written as strings, with
+    // `--int64-as-string`.
+    fn int64<T: FromStr + TryFrom<u64> + TryFrom<i64>>(json: &Json) -> Option<T> {
+        match json {
+            Json::String(s) => s.parse().ok(),
+            json => integer(json),
+        }
+    }
+
+    Ok(match signature {
+        Signature::U8 => Value::U8(integer(json).ok_or_else(mismatch)?),
+        Signature::Bool => Value::Bool(json.as_bool().ok_or_else(mismatch)?),
+        Signature::I16 => Value::I16(integer(json).ok_or_else(mismatch)?),
+        Signature::U16 => Value::U16(integer(json).ok_or_else(mismatch)?),
+        Signature::I32 => Value::I32(integer(json).ok_or_else(mismatch)?),
+        Signature::U32 => Value::U32(integer(json).ok_or_else(mismatch)?),
+        Signature::I64 => Value::I64(int64(json).ok_or_else(mismatch)?),
+        Signature::U64 => Value::U64(int64(json).ok_or_else(mismatch)?),
+        // JSON has no NaN or infinities, so they are written as null or by
+        // name.
+        Signature::F64 if json.is_null() => Value::F64(f64::NAN),
+        Signature::F64 if json.is_string() => Value::F64(
+            json.as_str()
+                .and_then(NonFinite::parse)
+                .ok_or_else(mismatch)?,
+        ),
+        Signature::F64 => Value::F64(json.as_f64().ok_or_else(mismatch)?),
+        Signature::Str => Value::from(string()?.to_string()),
+        Signature::ObjectPath => Value::ObjectPath(ObjectPath::try_from(string()?.to_string())?),
+        Signature::Signature => Value::Signature(
+            Signature::from_str(string()?)
+                .map_err(|e| zbus::Error::Failure(format!("Invalid signature {}: {}", json, e)))?,
+        ),
+        Signature::Variant => Value::Value(Box::new(typed_json_to_value(json)?)),
+        Signature::Array(element) => {
+            let mut array = Array::new(element);
+            for item in json.as_array().ok_or_else(mismatch)? {
+                array.append(json_to_value(item, element)?)?;
+            }
+            Value::Array(array)
+        }
+        Signature::Dict { key, value } => {
+            let mut dict = Dict::new(key, value);
+            for (k, v) in json.as_object().ok_or_else(mismatch)? {
+                dict.append(json_key(k, key)?, json_to_value(v, value)?)?;
+            }
+            Value::Dict(dict)
+        }
+        Signature::Structure(fields) => {
+            let items = json.as_array().ok_or_else(mismatch)?;
+            if items.len() != fields.len() {
+                return Err(mismatch());
+            }
+            let mut builder = StructureBuilder::new();
+            for (item, field) in items.iter().zip(fields.iter()) {
+                builder = builder.append_field(json_to_value(item, field)?);
+            }
+            Value::Structure(builder.build()?)
+        }
+        // D-Bus has no maybe type, and file descriptors cannot be written down.
+        Signature::Unit | Signature::Fd | Signature::Maybe(_) => return Err(mismatch()),
+    })
+}
+
+// A JSON object key as a dictionary key of the given type. JSON object keys
+// are always strings, so other key types are written as their JSON text.
+fn json_key(key: &str, signature: &Signature) -> Result<Value<'static>> {
+    let json = match signature {
+        Signature::Str | Signature::ObjectPath | Signature::Signature => {
+            serde_json::Value::from(key)
+        }
+        _ => serde_json::from_str(key).map_err(|_| {
+            zbus::Error::Failure(format!(
+                "Cannot convert key '{}' to a value of type '{}'",
+                key, signature
+            ))
+        })?,
+    };
+    json_to_value(&json, signature)
+}
+
+// Apply a JSON merge patch (RFC 7386) to a value. The members of a JSON object
+// replace the entries with the same key in a dictionary, merged in turn where
+// both are objects, and a `null` member removes its entry. Any other patch
+// replaces the value, converted to its type. A variant keeps the type it has
+// unless the patch is in the `{"signature": ..., "value": ...}` form, which
+// entries added to a dictionary of variants need.
+pub fn merge_json(value: &Value, patch: &serde_json::Value) -> Result<Value<'static>> {
+    use serde_json::Value as Json;
+
+    let typed = |patch: &Json| {
+        patch
+            .as_object()
+            .is_some_and(|o| o.len() == 2 && o.contains_key("signature") && o.contains_key("value"))
+    };
+    match (value, patch) {
+        (Value::Value(_), patch) if typed(patch) => {
+            Ok(Value::Value(Box::new(typed_json_to_value(patch)?)))
+        }
+        (Value::Value(inner), patch) => Ok(Value::Value(Box::new(merge_json(inner, patch)?))),
+        (Value::Dict(dict), Json::Object(members)) => {
+            let Signature::Dict {
+                key: key_signature,
+                value: value_signature,
+            } = dict.signature()
+            else {
+                unreachable!("Incorrect `Dict` signature")
+            };
+            let members = members
+                .iter()
+                .map(|(k, v)| Ok((json_key(k, key_signature)?, v)))
+                .collect::<Result<Vec<_>>>()?;
+            let mut merged = Dict::new(key_signature, value_signature);
+            for (k, v) in dict.iter() {
+                let k = Value::from(k.try_to_owned()?);
+                match members.iter().find(|(key, _)| *key == k) {
+                    Some((_, Json::Null)) => {}
+                    Some((_, patch)) => merged.append(k, merge_json(v, patch)?)?,
+                    None => merged.append(k, Value::from(v.try_to_owned()?))?,
+                }
+            }
+            for (k, patch) in members {
+                if !patch.is_null() && !dict.iter().any(|(key, _)| *key == k) {
+                    merged.append(k, json_to_value(patch, value_signature)?)?;
+                }
+            }
+            Ok(Value::Dict(merged))
+        }
+        (value, patch) => json_to_value(patch, value.value_signature()),
+    }
+}
+
+// Render a value for human-readable, tabular output. Basic values are printed
+// bare; containers fall back to compact JSON.
+pub fn display_value(value: &Value) -> String {
+    match value {
+        Value::U8(v) => v.to_string(),
+        Value::Bool(v) => v.to_string(),
+        Value::I16(v) => v.to_string(),
+        Value::U16(v) => v.to_string(),
+        Value::I32(v) => v.to_string(),
+        Value::U32(v) => v.to_string(),
+        Value::I64(v) => v.to_string(),
+        Value::U64(v) => v.to_string(),
+        Value::F64(v) => v.to_string(),
+        Value::Str(v) => v.to_string(),
+        Value::Signature(v) => v.to_string(),
+        Value::ObjectPath(v) => v.to_string(),
+        Value::Value(v) => display_value(v),
+        _ => serde_json::to_string(value).unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(bytes: &[u8], format: BytesFormat) -> String {
+        let mut out = Vec::new();
+        write_bytes(&mut out, bytes, format).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_float_format() {
+        assert_eq!("fixed:2".parse::<FloatFormat>(), Ok(FloatFormat::Fixed(2)));
+        assert!("fixed:x".parse::<FloatFormat>().is_err());
+        assert!("round".parse::<FloatFormat>().is_err());
+
+        let value = serde_json::json!({"reading": [21.456789, 1500.0], "count": 3});
+        assert_eq!(
+            to_json(&value, false, FloatFormat::Shortest).unwrap(),
+            r#"{"count":3,"reading":[21.456789,1500.0]}"#
+        );
+        assert_eq!(
+            to_json(&value, false, FloatFormat::Fixed(2)).unwrap(),
+            r#"{"count":3,"reading":[21.46,1500.00]}"#
+        );
+        assert_eq!(
+            to_json(&value, false, FloatFormat::Scientific).unwrap(),
+            r#"{"count":3,"reading":[2.1456789e1,1.5e3]}"#
+        );
+        assert_eq!(
+            to_json(&value, true, FloatFormat::Fixed(1)).unwrap(),
+            "{\n  \"count\": 3,\n  \"reading\": [\n    21.5,\n    1500.0\n  ]\n}"
+        );
+    }
+
+    #[test]
+    fn test_display_value() {
+        assert_eq!(display_value(&Value::U32(42)), "42");
+        assert_eq!(display_value(&Value::from("name")), "name");
+        assert_eq!(
+            display_value(&Value::Value(Box::new(Value::from(true)))),
+            "true"
+        );
+    }
+
+    #[test]
+    fn test_value_to_json() {
+        let body = crate::build_body(vec![
+            "array:string:a,b",
+            "dict:string:int32:one,1",
+            "objpath:/org/example",
+        ])
+        .unwrap();
+        let json = body
+            .fields()
+            .iter()
+            .map(|value| value_to_json(value, Numbers::default()).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            json,
+            vec![
+                serde_json::json!(["a", "b"]),
+                serde_json::json!({ "one": 1 }),
+                serde_json::json!("/org/example"),
+            ]
+        );
+        assert_eq!(
+            value_to_json(&Value::F64(f64::NAN), Numbers::default()).unwrap(),
+            serde_json::Value::Null
+        );
+    }
+
+    #[test]
+    fn test_typed_json_round_trip() {
+        let body = crate::build_body(vec![
+            "array:uint64:1,18446744073709551615",
+            "dict:string:int32:one,1",
+            "objpath:/org/example",
+            "signature:a{sv}",
+            "double:-0.5",
+        ])
+        .unwrap();
+        let dict = Value::from(std::collections::HashMap::from([
+            (1u32, Value::from("one")),
+            (2u32, Value::from(true)),
+        ]));
+        let nested = Value::from((7u8, Value::new(Value::from(-3i16)), vec!["x"]));
+        for value in body.fields().iter().chain([&dict, &nested]) {
+            let json = serde_json::to_value(value).unwrap();
+            let parsed = typed_json_to_value(&json).unwrap();
+            assert_eq!(
+                serde_json::to_value(&parsed).unwrap(),
+                json,
+                "Round trip changed {}",
+                json
+            );
+            assert_eq!(parsed.value_signature(), value.value_signature());
+        }
+
+        for bad in [
+            serde_json::json!({ "signature": "y", "value": 256 }),
+            serde_json::json!({ "signature": "(si)", "value": ["a"] }),
+            serde_json::json!({ "signature": "a{is}", "value": { "x": "y" } }),
+            serde_json::json!({ "signature": "v", "value": 1 }),
+            serde_json::json!("s"),
+        ] {
+            assert!(
+                typed_json_to_value(&bad).is_err(),
+                "{} should be rejected",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn test_merge_json() {
+        use std::collections::HashMap;
+
+        let settings = Value::from(HashMap::from([
+            ("name", Value::from("a")),
+            ("size", Value::from(3u32)),
+            ("old", Value::from(true)),
+        ]));
+        let merged = merge_json(
+            &settings,
+            &serde_json::json!({
+                "size": 4,
+                "old": null,
+                "mode": { "signature": "s", "value": "fast" }
+            }),
+        )
+        .unwrap();
+        assert_eq!(
+            merged,
+            Value::from(HashMap::from([
+                ("name", Value::from("a")),
+                ("size", Value::from(4u32)),
+                ("mode", Value::from("fast")),
+            ])),
+            "Variants should keep their type, and null should remove the entry"
+        );
+
+        let limits = Value::from(HashMap::from([(1u32, 10i64)]));
+        assert_eq!(
+            merge_json(&limits, &serde_json::json!({ "2": 20 })).unwrap(),
+            Value::from(HashMap::from([(1u32, 10i64), (2, 20)]))
+        );
+
+        for bad in [
+            serde_json::json!({ "size": "big" }),
+            serde_json::json!({ "new": 1 }),
+        ] {
+            assert!(
+                merge_json(&settings, &bad).is_err(),
+                "{} should be rejected",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn test_hexdump() {
+        let dump = hexdump(b"\x05\x00\x00\x00hello world, D-Bus");
+        let lines = dump.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0],
+            "  00000000  05 00 00 00 68 65 6c 6c 6f 20 77 6f 72 6c 64 2c  |....hello world,|"
+        );
+        assert!(lines[1].starts_with("  00000010  20 44 2d 42 75 73"));
+        assert!(lines[1].ends_with("| D-Bus|"));
+    }
+
+    #[test]
+    fn test_write_bytes_hex() {
+        assert_eq!(render(&[0x00, 0xab, 0x7f], BytesFormat::Hex), "00ab7f\n");
+    }
+
+    #[test]
+    fn test_write_bytes_base64_padding() {
+        assert_eq!(render(b"foobar", BytesFormat::Base64), "Zm9vYmFy\n");
+        assert_eq!(render(b"fooba", BytesFormat::Base64), "Zm9vYmE=\n");
+        assert_eq!(render(b"foob", BytesFormat::Base64), "Zm9vYg==\n");
+    }
+
+    #[test]
+    fn test_write_bytes_across_chunks() {
+        let bytes = vec![0xffu8; CHUNK_SIZE + 1];
+        let hex = render(&bytes, BytesFormat::Hex);
+        assert_eq!(hex.len(), (CHUNK_SIZE + 1) * 2 + 1);
+        let base64 = render(&bytes, BytesFormat::Base64);
+        assert!(base64.ends_with("/w==\n"), "{}", base64);
+    }
+}
@@ -0,0 +1,150 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use zbus::Result;
+
+/// A place where a reply differs from its golden file. `path` locates the
+/// value in the reply, such as `$.Devices[2].Name`; a missing `expected` or
+/// `actual` means the value was added or removed.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Difference {
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expected: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actual: Option<Value>,
+}
+
+// Read a golden file written with `call --update-golden`, or by hand.
+pub fn load_golden(path: &Path) -> Result<Value> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| zbus::Error::Failure(format!("Failed to read '{}': {}", path.display(), e)))?;
+    serde_json::from_str(&contents).map_err(|e| {
+        zbus::Error::Failure(format!("Invalid golden file '{}': {}", path.display(), e))
+    })
+}
+
+// Write a golden file the way it is easiest to review in version control:
+// pretty-printed, with object keys sorted.
+pub fn save_golden(path: &Path, value: &Value) -> Result<()> {
+    let failed =
+        |e: String| zbus::Error::Failure(format!("Failed to write '{}': {}", path.display(), e));
+    let json = serde_json::to_string_pretty(value).map_err(|e| failed(e.to_string()))?;
+    fs::write(path, format!("{}\n", json)).map_err(|e| failed(e.to_string()))
+}
+
+// The places where `actual` differs from `expected`, ordered by key and index.
+// Objects are compared key by key, whatever order the keys are written in, and
+// arrays element by element, so one changed field is reported on its own
+// instead of as a whole new reply.
+pub fn compare(expected: &Value, actual: &Value) -> Vec<Difference> {
+    let mut differences = Vec::new();
+    compare_at(
+        "$".to_string(),
+        Some(expected),
+        Some(actual),
+        &mut differences,
+    );
+    differences
+}
+
+fn compare_at(
+    path: String,
+    expected: Option<&Value>,
+    actual: Option<&Value>,
+    differences: &mut Vec<Difference>,
+) {
+    match (expected, actual) {
+        (Some(Value::Object(expected)), Some(Value::Object(actual))) => {
+            let mut keys = expected.keys().chain(actual.keys()).collect::<Vec<_>>();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                compare_at(
+                    member(&path, key),
+                    expected.get(key),
+                    actual.get(key),
+                    differences,
+                );
+            }
+        }
+        (Some(Value::Array(expected)), Some(Value::Array(actual))) => {
+            for i in 0..expected.len().max(actual.len()) {
+                compare_at(
+                    format!("{}[{}]", path, i),
+                    expected.get(i),
+                    actual.get(i),
+                    differences,
+                );
+            }
+        }
+        (expected, actual) if expected != actual => differences.push(Difference {
+            path,
+            expected: expected.cloned(),
+            actual: actual.cloned(),
+        }),
+        _ => {}
+    }
+}
+
+// The path to a member of an object: `.Key` for keys that read as
+// identifiers, `["some key"]` for the rest.
+fn member(path: &str, key: &str) -> String {
+    let identifier = key
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if identifier {
+        format!("{}.{}", path, key)
+    } else {
+        format!("{}[{}]", path, Value::from(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_compare() {
+        let expected = json!({"Name": "n", "Tags": ["a", "b"], "Old": 1, "some key": true});
+        let actual = json!({"some key": false, "Tags": ["a", "c", "d"], "Name": "n", "New": 2});
+
+        let differences = compare(&expected, &actual)
+            .into_iter()
+            .map(|d| (d.path, d.expected, d.actual))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            differences,
+            vec![
+                ("$.New".to_string(), None, Some(json!(2))),
+                ("$.Old".to_string(), Some(json!(1)), None),
+                ("$.Tags[1]".to_string(), Some(json!("b")), Some(json!("c"))),
+                ("$.Tags[2]".to_string(), None, Some(json!("d"))),
+                (
+                    "$[\"some key\"]".to_string(),
+                    Some(json!(true)),
+                    Some(json!(false))
+                ),
+            ],
+            "Equal values should be left out, whatever the key order"
+        );
+    }
+
+    #[test]
+    fn test_compare_types() {
+        assert_eq!(
+            compare(&json!([1]), &json!({"0": 1})),
+            vec![Difference {
+                path: "$".to_string(),
+                expected: Some(json!([1])),
+                actual: Some(json!({"0": 1})),
+            }],
+            "A value of another type should differ as a whole"
+        );
+        assert!(compare(&json!(null), &json!(null)).is_empty());
+    }
+}
@@ -0,0 +1,96 @@
+use clap::{Arg, ArgAction, Command};
+use serde_json::{Value as Json, json};
+use std::ffi::OsString;
+
+// The option that prints the command-line interface as JSON.
+pub const FLAG: &str = "help-json";
+
+// Add the --help-json option to a command, so it shows up in --help and in
+// its own output.
+pub fn with_flag(command: Command) -> Command {
+    command.arg(
+        Arg::new(FLAG)
+            .long(FLAG)
+            .global(true)
+            .action(ArgAction::SetTrue)
+            .help("Print every command and option as JSON and exit"),
+    )
+}
+
+// Whether --help-json is among the arguments, before any `--` that starts a
+// command line of another program. Like --help, it is honored wherever it is
+// given, even if the rest of the command line would not parse.
+pub fn requested(args: &[OsString]) -> bool {
+    args.iter()
+        .skip(1)
+        .take_while(|arg| *arg != "--")
+        .any(|arg| *arg == "--help-json")
+}
+
+// Describe a command, its options and all its subcommands.
+pub fn describe(command: &Command) -> Json {
+    let about = command.get_about().map(|about| about.to_string());
+    let arguments = command
+        .get_arguments()
+        .filter(|arg| !arg.is_hide_set())
+        .map(describe_arg)
+        .collect::<Vec<_>>();
+    let subcommands = command
+        .get_subcommands()
+        .filter(|command| !command.is_hide_set() && command.get_name() != "help")
+        .map(describe)
+        .collect::<Vec<_>>();
+    json!({
+        "name": command.get_name(),
+        "about": about,
+        "version": command.get_version(),
+        "arguments": arguments,
+        "subcommands": subcommands,
+    })
+}
+
+fn describe_arg(arg: &Arg) -> Json {
+    let takes_value = arg.get_action().takes_values();
+    let value_names = arg
+        .get_value_names()
+        .map(|names| {
+            names
+                .iter()
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_else(|| {
+            if takes_value {
+                vec![arg.get_id().to_string().to_uppercase()]
+            } else {
+                Vec::new()
+            }
+        });
+    let possible_values = arg
+        .get_possible_values()
+        .iter()
+        .filter(|value| !value.is_hide_set())
+        .map(|value| value.get_name().to_string())
+        .collect::<Vec<_>>();
+    let default_values = arg
+        .get_default_values()
+        .iter()
+        .map(|value| value.to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    json!({
+        "id": arg.get_id().as_str(),
+        "long": arg.get_long(),
+        "short": arg.get_short().map(String::from),
+        "positional": arg.is_positional(),
+        "help": arg.get_help().map(|help| help.to_string()),
+        "env": arg.get_env().map(|env| env.to_string_lossy().into_owned()),
+        "required": arg.is_required_set(),
+        "global": arg.is_global_set(),
+        "takes_value": takes_value,
+        "multiple": matches!(arg.get_action(), ArgAction::Append | ArgAction::Count)
+            || arg.get_num_args().is_some_and(|n| n.max_values() > 1),
+        "value_names": value_names,
+        "possible_values": possible_values,
+        "default_values": default_values,
+    })
+}
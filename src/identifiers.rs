@@ -0,0 +1,148 @@
+use zbus::Result;
+
+// Decode a string of hex digit pairs.
+fn hex_bytes(digits: &str) -> Option<Vec<u8>> {
+    if !digits.len().is_multiple_of(2) || !digits.is_ascii() {
+        return None;
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).ok())
+        .collect()
+}
+
+// Parse a UUID written with or without hyphens and braces, such as
+// `{123e4567-e89b-12d3-a456-426614174000}` or `123e4567e89b12d3a456426614174000`.
+pub fn parse_uuid(value: &str) -> Result<[u8; 16]> {
+    let invalid = || {
+        zbus::Error::Failure(format!(
+            "Invalid UUID '{}': expected 32 hex digits such as 123e4567-e89b-12d3-a456-426614174000",
+            value
+        ))
+    };
+
+    let trimmed = value
+        .strip_prefix('{')
+        .and_then(|v| v.strip_suffix('}'))
+        .unwrap_or(value);
+    let digits = if trimmed.contains('-') {
+        // Hyphens are only accepted in the canonical 8-4-4-4-12 grouping.
+        let groups = trimmed.split('-').map(str::len).collect::<Vec<_>>();
+        if groups != [8, 4, 4, 4, 12] {
+            return Err(invalid());
+        }
+        trimmed.replace('-', "")
+    } else {
+        trimmed.to_string()
+    };
+
+    hex_bytes(&digits)
+        .and_then(|bytes| <[u8; 16]>::try_from(bytes).ok())
+        .ok_or_else(invalid)
+}
+
+// The canonical lowercase, hyphenated form of a UUID.
+pub fn format_uuid(bytes: &[u8; 16]) -> String {
+    let hex = bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+// A new random (version 4) UUID in canonical form, from the kernel's random
+// number generator.
+pub fn random_uuid() -> Result<String> {
+    let mut bytes = [0u8; 16];
+    std::fs::File::open("/dev/urandom")
+        .and_then(|mut random| std::io::Read::read_exact(&mut random, &mut bytes))
+        .map_err(|e| zbus::Error::Failure(format!("Failed to generate a UUID: {}", e)))?;
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    Ok(format_uuid(&bytes))
+}
+
+// Parse a MAC address of 6 bytes, or an 8-byte EUI-64, written as hex pairs
+// separated by `:` or `-`, such as `00:1a:2b:3c:4d:5e`.
+pub fn parse_mac(value: &str) -> Result<Vec<u8>> {
+    let bytes = value
+        .split([':', '-'])
+        .map(|pair| {
+            (pair.len() == 2)
+                .then(|| u8::from_str_radix(pair, 16).ok())
+                .flatten()
+        })
+        .collect::<Option<Vec<u8>>>()
+        .filter(|bytes| bytes.len() == 6 || bytes.len() == 8);
+    bytes.ok_or_else(|| {
+        zbus::Error::Failure(format!(
+            "Invalid MAC address '{}': expected 6 or 8 hex pairs such as 00:1A:2B:3C:4D:5E",
+            value
+        ))
+    })
+}
+
+// The uppercase, colon-separated form of a MAC address, as used by BlueZ.
+pub fn format_mac(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uuid() {
+        let canonical = "123e4567-e89b-12d3-a456-426614174000";
+        let bytes = parse_uuid(canonical).unwrap();
+        assert_eq!(bytes[0], 0x12);
+        assert_eq!(bytes[15], 0x00);
+        assert_eq!(format_uuid(&bytes), canonical);
+        assert_eq!(
+            parse_uuid("{123E4567-E89B-12D3-A456-426614174000}").unwrap(),
+            bytes
+        );
+        assert_eq!(
+            parse_uuid("123e4567e89b12d3a456426614174000").unwrap(),
+            bytes
+        );
+        assert!(parse_uuid("123e4567-e89b12d3-a456-426614174000").is_err());
+        assert!(parse_uuid("123e4567e89b12d3a45642661417400").is_err());
+        assert!(parse_uuid("123e4567e89b12d3a45642661417400g").is_err());
+    }
+
+    #[test]
+    fn test_random_uuid() {
+        let uuid = random_uuid().unwrap();
+        assert_eq!(format_uuid(&parse_uuid(&uuid).unwrap()), uuid);
+        assert_eq!(&uuid[14..15], "4", "Random UUIDs should be version 4");
+        assert!(
+            matches!(&uuid[19..20], "8" | "9" | "a" | "b"),
+            "Random UUIDs should have the RFC 4122 variant: {}",
+            uuid
+        );
+        assert_ne!(uuid, random_uuid().unwrap());
+    }
+
+    #[test]
+    fn test_mac() {
+        let bytes = parse_mac("00:1a:2b:3c:4d:5e").unwrap();
+        assert_eq!(bytes, vec![0x00, 0x1a, 0x2b, 0x3c, 0x4d, 0x5e]);
+        assert_eq!(parse_mac("00-1A-2B-3C-4D-5E").unwrap(), bytes);
+        assert_eq!(format_mac(&bytes), "00:1A:2B:3C:4D:5E");
+        assert_eq!(parse_mac("00:1a:2b:ff:fe:3c:4d:5e").unwrap().len(), 8);
+        assert!(parse_mac("00:1a:2b:3c:4d").is_err());
+        assert!(parse_mac("00:1a:2b:3c:4d:5").is_err());
+    }
+}
@@ -0,0 +1,602 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use zbus::Result;
+use zbus_xml::{ArgDirection, Method, Node};
+use zvariant::{Array, Dict, ObjectPath, Signature, Value};
+
+// Parse introspection XML.
+pub fn parse_node(path: &str, xml: &str) -> Result<Node<'static>> {
+    Node::from_reader(xml.as_bytes()).map_err(|e| {
+        zbus::Error::Failure(format!("Invalid introspection data for '{}': {}", path, e))
+    })
+}
+
+// Whether a name can be an element of an object path, as the names of child
+// nodes must be: one or more ASCII letters, digits and underscores.
+pub fn is_path_element(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || byte == b'_')
+}
+
+// Whether a child node's name can be joined onto its parent's path: one or
+// more path elements separated by `/`, as in `org/freedesktop/DBus`, which
+// dbus-daemon lists as the child of `/`.
+pub fn is_child_name(name: &str) -> bool {
+    name.split('/').all(is_path_element)
+}
+
+// The absolute paths of the children listed in a node's introspection data,
+// each name joined onto `path` element by element. Names that are not, such
+// as `/` or `a-b`, are left out: they would name the object itself or no
+// object at all.
+pub fn child_paths(path: &str, node: &Node) -> Vec<String> {
+    node.nodes()
+        .iter()
+        .filter_map(|child| child.name())
+        .filter(|name| is_child_name(name))
+        .map(|name| match path {
+            "/" => format!("/{}", name),
+            _ => format!("{}/{}", path, name),
+        })
+        .collect()
+}
+
+// The names of child nodes that `child_paths` leaves out.
+pub fn invalid_children<'a>(node: &'a Node) -> Vec<&'a str> {
+    node.nodes()
+        .iter()
+        .filter_map(|child| child.name())
+        .filter(|name| !is_child_name(name))
+        .collect()
+}
+
+// Draw an object tree as `busctl tree` does, each object below its parent
+// on a line of its own. `children` maps objects to their children, in the
+// order they are drawn.
+pub fn format_tree(root: &str, children: &BTreeMap<String, Vec<String>>) -> String {
+    let mut out = format!("{}\n", root);
+    draw_children(root, children, "", &mut out);
+    out
+}
+
+fn draw_children(
+    path: &str,
+    children: &BTreeMap<String, Vec<String>>,
+    prefix: &str,
+    out: &mut String,
+) {
+    let Some(paths) = children.get(path) else {
+        return;
+    };
+    for (i, child) in paths.iter().enumerate() {
+        let last = i + 1 == paths.len();
+        let (branch, indent) = match last {
+            true => ("└─ ", "   "),
+            false => ("├─ ", "│  "),
+        };
+        out.push_str(&format!("{}{}{}\n", prefix, branch, child));
+        draw_children(child, children, &format!("{}{}", prefix, indent), out);
+    }
+}
+
+// Where the introspection data of the object at `path` is saved under `dir`.
+// The directory layout mirrors the object tree, with each object's XML in an
+// `introspect.xml` file; the dot keeps it from clashing with path elements.
+pub fn node_file(dir: &Path, path: &str) -> PathBuf {
+    let mut file = dir.to_path_buf();
+    file.extend(path.split('/').filter(|element| !element.is_empty()));
+    file.join("introspect.xml")
+}
+
+// Write a node back out as introspection XML.
+pub fn node_xml(node: &Node) -> Result<String> {
+    let mut xml = Vec::new();
+    node.to_writer(&mut xml)
+        .map_err(|e| zbus::Error::Failure(format!("Failed to write XML: {}", e)))?;
+    Ok(String::from_utf8_lossy(&xml).into_owned())
+}
+
+// Bundle the introspection data of several objects into one document: a root
+// node holding one child per object, named with its absolute path. The result
+// is itself valid introspection XML, so existing tools can read it.
+pub fn bundle(service: &str, nodes: &[(String, Node)]) -> Result<String> {
+    let mut out = format!(
+        "<!-- Introspection bundle for {}, saved by zbusctl -->\n<node>\n",
+        service
+    );
+    for (path, node) in nodes {
+        let xml = node_xml(node)?;
+
+        // Replace the node's own name, if any, with its absolute path.
+        let rest = xml.strip_prefix("<node").unwrap_or(&xml);
+        let rest = match rest.strip_prefix(" name=\"") {
+            Some(named) => named.split_once('"').map_or("", |(_, rest)| rest),
+            None => rest,
+        };
+        out.push_str(&format!("  <node name=\"{}\"{}\n", path, rest));
+    }
+    out.push_str("</node>\n");
+    Ok(out)
+}
+
+// Load saved introspection data, keyed by object path: either a directory
+// written by `introspect --save-dir` or a file written by `introspect --bundle`.
+pub fn load_bundle(path: &Path) -> Result<BTreeMap<String, Node<'static>>> {
+    let read = |file: &Path| {
+        fs::read_to_string(file).map_err(|e| {
+            zbus::Error::Failure(format!("Failed to read '{}': {}", file.display(), e))
+        })
+    };
+
+    let mut nodes = BTreeMap::new();
+    if path.is_dir() {
+        let mut dirs = vec![path.to_path_buf()];
+        while let Some(dir) = dirs.pop() {
+            let entries = fs::read_dir(&dir).map_err(|e| {
+                zbus::Error::Failure(format!("Failed to read '{}': {}", dir.display(), e))
+            })?;
+            for entry in entries.flatten() {
+                let entry_path = entry.path();
+                if entry_path.is_dir() {
+                    dirs.push(entry_path);
+                } else if entry.file_name() == "introspect.xml" {
+                    let relative = dir.strip_prefix(path).unwrap_or(&dir);
+                    let elements = relative
+                        .iter()
+                        .map(|e| e.to_string_lossy())
+                        .collect::<Vec<_>>();
+                    let object = format!("/{}", elements.join("/"));
+                    let node = parse_node(&object, &read(&entry_path)?)?;
+                    nodes.insert(object, node);
+                }
+            }
+        }
+    } else {
+        let bundle = parse_node(&path.display().to_string(), &read(path)?)?;
+        for node in bundle.nodes() {
+            if let Some(name) = node.name().filter(|name| name.starts_with('/')) {
+                nodes.insert(name.to_string(), node.clone());
+            }
+        }
+    }
+
+    if nodes.is_empty() {
+        return Err(zbus::Error::Failure(format!(
+            "No introspection data found in '{}'",
+            path.display()
+        )));
+    }
+    Ok(nodes)
+}
+
+// The signature of the input arguments of a method, from its introspection
+// data. Method arguments without a direction are inputs.
+pub fn input_signature(args: &[zbus_xml::Arg]) -> String {
+    args.iter()
+        .filter(|arg| arg.direction().unwrap_or(ArgDirection::In) == ArgDirection::In)
+        .map(|arg| arg.ty().to_string())
+        .collect()
+}
+
+// The signature of the output arguments of a method, which its replies should
+// have.
+pub fn output_signature(args: &[zbus_xml::Arg]) -> String {
+    args.iter()
+        .filter(|arg| arg.direction() == Some(ArgDirection::Out))
+        .map(|arg| arg.ty().to_string())
+        .collect()
+}
+
+// Find a method in the introspection data of an object, explaining what is
+// missing if it isn't there.
+pub fn find_method<'a, 'n>(
+    node: &'a Node<'n>,
+    interface: &str,
+    method: &str,
+) -> std::result::Result<&'a Method<'n>, String> {
+    let names = |names: Vec<String>| {
+        if names.is_empty() {
+            "none".to_string()
+        } else {
+            names.join(", ")
+        }
+    };
+
+    let Some(iface) = node
+        .interfaces()
+        .iter()
+        .find(|i| i.name().as_str() == interface)
+    else {
+        let available = node.interfaces().iter().map(|i| i.name().to_string());
+        return Err(format!(
+            "The object has no interface '{}' (available: {})",
+            interface,
+            names(available.collect())
+        ));
+    };
+
+    iface
+        .methods()
+        .iter()
+        .find(|m| m.name().as_str() == method)
+        .ok_or_else(|| {
+            let available = iface.methods().iter().map(|m| m.name().to_string());
+            format!(
+                "Interface '{}' has no method '{}' (available: {})",
+                interface,
+                method,
+                names(available.collect())
+            )
+        })
+}
+
+// Check that a call to `interface.method` with arguments of type `signature`
+// matches the introspection data of the object it is sent to, explaining what
+// is wrong if not.
+pub fn verify_call(
+    node: &Node,
+    interface: &str,
+    method: &str,
+    signature: &str,
+) -> std::result::Result<(), String> {
+    let m = find_method(node, interface, method)?;
+    let expected = input_signature(m.args());
+    if expected != signature {
+        let params = m
+            .args()
+            .iter()
+            .filter(|arg| arg.direction().unwrap_or(ArgDirection::In) == ArgDirection::In)
+            .map(|arg| format!("{} {}", arg.ty().to_string(), arg.name().unwrap_or("_")))
+            .collect::<Vec<_>>();
+        return Err(format!(
+            "Method '{}.{}' takes '{}' ({}), but the arguments have signature '{}'",
+            interface,
+            method,
+            expected,
+            params.join(", "),
+            signature
+        ));
+    }
+    Ok(())
+}
+
+// Check that a reply from `interface.method` with body signature `signature`
+// matches the introspection data of the object that sent it, explaining what
+// is wrong if not.
+pub fn verify_reply(
+    node: &Node,
+    interface: &str,
+    method: &str,
+    signature: &str,
+) -> std::result::Result<(), String> {
+    let m = find_method(node, interface, method)?;
+    let expected = output_signature(m.args());
+    if expected != signature {
+        return Err(format!(
+            "Method '{}.{}' is introspected as returning '{}', but the reply has signature '{}'",
+            interface, method, expected, signature
+        ));
+    }
+    Ok(())
+}
+
+// The value sent for an input argument left out of a call by name: zero,
+// empty or false. Variants, structures and file descriptors have no value
+// that could stand for "not given", so they can't be left out.
+pub fn default_value(signature: &Signature) -> Option<Value<'static>> {
+    Some(match signature {
+        Signature::U8 => Value::U8(0),
+        Signature::Bool => Value::Bool(false),
+        Signature::I16 => Value::I16(0),
+        Signature::U16 => Value::U16(0),
+        Signature::I32 => Value::I32(0),
+        Signature::U32 => Value::U32(0),
+        Signature::I64 => Value::I64(0),
+        Signature::U64 => Value::U64(0),
+        Signature::F64 => Value::F64(0.0),
+        Signature::Str => Value::from(String::new()),
+        Signature::ObjectPath => Value::ObjectPath(ObjectPath::from_static_str_unchecked("/")),
+        Signature::Signature => Value::Signature(Signature::Unit),
+        Signature::Array(element) => Value::Array(Array::new(element)),
+        Signature::Dict { key, value } => Value::Dict(Dict::new(key, value)),
+        _ => return None,
+    })
+}
+
+// Put arguments given by name, as `NAME=TYPE:VALUE`, in the order a method
+// takes them. Returns the `TYPE:VALUE` arguments in order, and default values
+// for the arguments after the last one given, which may be left out if they
+// have a default.
+pub fn order_arguments(
+    method: &Method,
+    named: &[String],
+) -> std::result::Result<(Vec<String>, Vec<Value<'static>>), String> {
+    let params = method
+        .args()
+        .iter()
+        .filter(|arg| arg.direction().unwrap_or(ArgDirection::In) == ArgDirection::In)
+        .collect::<Vec<_>>();
+    let describe = || {
+        params
+            .iter()
+            .map(|arg| format!("{} {}", arg.ty().to_string(), arg.name().unwrap_or("_")))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let mut given = vec![None; params.len()];
+    for arg in named {
+        let (name, value) = arg
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid named argument '{}': expected NAME=TYPE:VALUE", arg))?;
+        let index = params
+            .iter()
+            .position(|param| param.name() == Some(name))
+            .ok_or_else(|| {
+                format!(
+                    "Method '{}' has no argument '{}' (arguments: {})",
+                    method.name(),
+                    name,
+                    describe()
+                )
+            })?;
+        if given[index].replace(value.to_string()).is_some() {
+            return Err(format!("Argument '{}' is given more than once", name));
+        }
+    }
+
+    let count = given.iter().rposition(Option::is_some).map_or(0, |i| i + 1);
+    let mut args = Vec::new();
+    for (param, value) in params.iter().zip(&given).take(count) {
+        args.push(value.clone().ok_or_else(|| {
+            format!(
+                "Missing argument '{}' of method '{}' (arguments: {})",
+                param.name().unwrap_or("_"),
+                method.name(),
+                describe()
+            )
+        })?);
+    }
+    let defaults = params[count..]
+        .iter()
+        .map(|param| {
+            default_value(param.ty()).ok_or_else(|| {
+                format!(
+                    "Missing argument '{}' of method '{}': a '{}' argument has no default",
+                    param.name().unwrap_or("_"),
+                    method.name(),
+                    param.ty().to_string()
+                )
+            })
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    Ok((args, defaults))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const XML: &str = r#"<!DOCTYPE node PUBLIC "-//freedesktop//DTD D-BUS Object Introspection 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/introspect.dtd">
+<node name="/org/example">
+  <interface name="org.example.Test">
+    <method name="Echo">
+      <arg name="s" type="s" direction="in"/>
+      <arg type="s" direction="out"/>
+    </method>
+  </interface>
+  <node name="a"/>
+  <node name="b"/>
+</node>
+"#;
+
+    #[test]
+    fn test_child_paths() {
+        let node = parse_node("/org/example", XML).unwrap();
+        assert_eq!(
+            child_paths("/org/example", &node),
+            vec!["/org/example/a", "/org/example/b"]
+        );
+        assert_eq!(child_paths("/", &node), vec!["/a", "/b"]);
+
+        let node = parse_node(
+            "/",
+            r#"<node><node name="/"/><node name=""/><node name="a/b"/><node name="a-b"/><node name="a//b"/><node name="/c"/><node name="ok"/></node>"#,
+        )
+        .unwrap();
+        assert_eq!(
+            child_paths("/", &node),
+            vec!["/a/b", "/ok"],
+            "Names that are not relative paths should be left out"
+        );
+        assert_eq!(child_paths("/x", &node), vec!["/x/a/b", "/x/ok"]);
+        assert_eq!(invalid_children(&node), vec!["/", "", "a-b", "a//b", "/c"]);
+    }
+
+    #[test]
+    fn test_format_tree() {
+        let children = BTreeMap::from([
+            ("/".to_string(), vec!["/org".to_string()]),
+            (
+                "/org".to_string(),
+                vec!["/org/a".to_string(), "/org/b".to_string()],
+            ),
+            ("/org/a".to_string(), vec!["/org/a/x".to_string()]),
+        ]);
+        assert_eq!(
+            format_tree("/", &children),
+            "/\n└─ /org\n   ├─ /org/a\n   │  └─ /org/a/x\n   └─ /org/b\n"
+        );
+        assert_eq!(
+            format_tree("/empty", &children),
+            "/empty\n",
+            "An object without children should be drawn alone"
+        );
+    }
+
+    #[test]
+    fn test_node_file() {
+        let dir = Path::new("out");
+        assert_eq!(node_file(dir, "/"), Path::new("out/introspect.xml"));
+        assert_eq!(
+            node_file(dir, "/org/example"),
+            Path::new("out/org/example/introspect.xml")
+        );
+    }
+
+    #[test]
+    fn test_bundle_is_introspection_xml() {
+        let node = parse_node("/org/example", XML).unwrap();
+        let empty = parse_node("/org/example/a", "<node/>").unwrap();
+        let bundle = bundle(
+            "org.example.Test",
+            &[
+                ("/org/example".to_string(), node),
+                ("/org/example/a".to_string(), empty),
+            ],
+        )
+        .unwrap();
+
+        let parsed = parse_node("bundle", &bundle).unwrap();
+        let names = parsed
+            .nodes()
+            .iter()
+            .map(|n| n.name().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["/org/example", "/org/example/a"]);
+        assert_eq!(
+            parsed.nodes()[0].interfaces()[0].name().as_str(),
+            "org.example.Test"
+        );
+    }
+
+    #[test]
+    fn test_verify_call() {
+        let node = parse_node("/org/example", XML).unwrap();
+        assert_eq!(verify_call(&node, "org.example.Test", "Echo", "s"), Ok(()));
+
+        let error = verify_call(&node, "org.example.Test", "Echo", "si").unwrap_err();
+        assert!(
+            error.contains("takes 's' (s s)") && error.contains("signature 'si'"),
+            "Unexpected error: {}",
+            error
+        );
+        let error = verify_call(&node, "org.example.Test", "Ech", "s").unwrap_err();
+        assert!(
+            error.contains("available: Echo"),
+            "Unexpected error: {}",
+            error
+        );
+        let error = verify_call(&node, "org.example.Nope", "Echo", "s").unwrap_err();
+        assert!(
+            error.contains("available: org.example.Test"),
+            "Unexpected error: {}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_verify_reply() {
+        let node = parse_node("/org/example", XML).unwrap();
+        assert_eq!(verify_reply(&node, "org.example.Test", "Echo", "s"), Ok(()));
+
+        let error = verify_reply(&node, "org.example.Test", "Echo", "u").unwrap_err();
+        assert!(
+            error.contains("returning 's'") && error.contains("signature 'u'"),
+            "Unexpected error: {}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_order_arguments() {
+        let xml = r#"<node>
+  <interface name="org.example.Test">
+    <method name="Configure">
+      <arg name="hostname" type="s" direction="in"/>
+      <arg name="port" type="u" direction="in"/>
+      <arg name="flags" type="u" direction="in"/>
+      <arg name="options" type="a{sv}" direction="in"/>
+      <arg name="result" type="b" direction="out"/>
+    </method>
+    <method name="Send">
+      <arg name="data" type="s"/>
+      <arg name="extra" type="v"/>
+    </method>
+  </interface>
+</node>"#;
+        let node = parse_node("/", xml).unwrap();
+        let configure = find_method(&node, "org.example.Test", "Configure").unwrap();
+        let named = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+
+        let (args, defaults) = order_arguments(
+            configure,
+            &named(&["port=uint32:80", "hostname=string:web01"]),
+        )
+        .unwrap();
+        assert_eq!(args, vec!["string:web01", "uint32:80"]);
+        assert_eq!(defaults.len(), 2, "flags and options should get defaults");
+        assert_eq!(defaults[0], Value::U32(0));
+        assert_eq!(defaults[1].value_signature().to_string(), "a{sv}");
+
+        let error = order_arguments(configure, &named(&["port=uint32:80"])).unwrap_err();
+        assert!(
+            error.contains("Missing argument 'hostname'"),
+            "Unexpected error: {}",
+            error
+        );
+        let error = order_arguments(configure, &named(&["host=string:a"])).unwrap_err();
+        assert!(
+            error.contains("no argument 'host'") && error.contains("s hostname, u port"),
+            "Unexpected error: {}",
+            error
+        );
+        assert!(
+            order_arguments(configure, &named(&["port=uint32:1", "port=uint32:2"])).is_err(),
+            "Repeated arguments should be rejected"
+        );
+
+        let send = find_method(&node, "org.example.Test", "Send").unwrap();
+        let error = order_arguments(send, &named(&["data=string:x"])).unwrap_err();
+        assert!(
+            error.contains("has no default"),
+            "Unexpected error: {}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_load_bundle() {
+        let dir = std::env::temp_dir().join(format!("zbusctl-bundle-{}", std::process::id()));
+        let node = parse_node("/org/example", XML).unwrap();
+
+        // A saved directory tree.
+        let file = node_file(&dir, "/org/example");
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(&file, XML).unwrap();
+        fs::write(node_file(&dir, "/"), "<node><node name=\"org\"/></node>").unwrap();
+        let nodes = load_bundle(&dir).unwrap();
+        assert_eq!(nodes.keys().collect::<Vec<_>>(), vec!["/", "/org/example"]);
+        assert_eq!(nodes["/org/example"], node);
+
+        // A bundle file.
+        let file = dir.join("bundle.xml");
+        fs::write(
+            &file,
+            bundle("org.example.Test", &[("/org/example".to_string(), node)]).unwrap(),
+        )
+        .unwrap();
+        let nodes = load_bundle(&file).unwrap();
+        assert_eq!(nodes.keys().collect::<Vec<_>>(), vec!["/org/example"]);
+        assert_eq!(
+            child_paths("/org/example", &nodes["/org/example"]),
+            vec!["/org/example/a", "/org/example/b"]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
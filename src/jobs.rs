@@ -0,0 +1,96 @@
+use std::future::{Future, poll_fn};
+use std::task::Poll;
+use tokio::sync::Semaphore;
+
+/// A limit on how many requests are in flight at once, shared by commands
+/// that make many independent calls, such as recursive introspection. Waiting
+/// requests start in the order they were submitted, so none is starved.
+#[derive(Debug)]
+pub struct Jobs {
+    semaphore: Semaphore,
+    limit: usize,
+}
+
+impl Jobs {
+    // A limit of `limit` concurrent requests; zero is treated as one.
+    pub fn new(limit: usize) -> Self {
+        let limit = limit.clamp(1, Semaphore::MAX_PERMITS);
+        Self {
+            semaphore: Semaphore::new(limit),
+            limit,
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    // Run a future once a request slot is free.
+    pub async fn run<F: Future>(&self, future: F) -> F::Output {
+        // The semaphore is never closed, so acquiring only fails in theory.
+        let _permit = self.semaphore.acquire().await.ok();
+        future.await
+    }
+
+    // Run futures concurrently, at most `limit` at a time, returning their
+    // outputs in the order the futures were given.
+    pub async fn run_all<F: Future>(&self, futures: impl IntoIterator<Item = F>) -> Vec<F::Output> {
+        let mut tasks = futures
+            .into_iter()
+            .map(|future| Box::pin(self.run(future)))
+            .collect::<Vec<_>>();
+        let mut outputs = tasks.iter().map(|_| None).collect::<Vec<_>>();
+        poll_fn(|cx| {
+            let mut done = true;
+            for (task, output) in tasks.iter_mut().zip(outputs.iter_mut()) {
+                if output.is_none() {
+                    match task.as_mut().poll(cx) {
+                        Poll::Ready(value) => *output = Some(value),
+                        Poll::Pending => done = false,
+                    }
+                }
+            }
+            if done { Poll::Ready(()) } else { Poll::Pending }
+        })
+        .await;
+        outputs.into_iter().flatten().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_lite::future::{block_on, yield_now};
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_run_all() {
+        let jobs = Jobs::new(2);
+        let running = RefCell::new(0);
+        let most = RefCell::new(0);
+        let started = RefCell::new(Vec::new());
+        let outputs = block_on(jobs.run_all((0..5).map(|i| {
+            let (running, most, started) = (&running, &most, &started);
+            async move {
+                started.borrow_mut().push(i);
+                *running.borrow_mut() += 1;
+                let now = *running.borrow();
+                most.replace_with(|most| now.max(*most));
+                // Later requests finish first, but outputs stay in order.
+                for _ in 0..(5 - i) {
+                    yield_now().await;
+                }
+                *running.borrow_mut() -= 1;
+                i * 10
+            }
+        })));
+        assert_eq!(outputs, vec![0, 10, 20, 30, 40]);
+        assert_eq!(*most.borrow(), 2, "At most 2 requests should run at once");
+        assert_eq!(
+            *started.borrow(),
+            vec![0, 1, 2, 3, 4],
+            "Requests should start in order"
+        );
+        assert_eq!(Jobs::new(0).limit(), 1);
+    }
+}
@@ -0,0 +1,301 @@
+use crate::audit::AuditRule;
+use crate::credentials::Credentials;
+use crate::events::{GapEvent, MessageEvent, MessageType};
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::os::unix::net::UnixDatagram;
+use std::path::Path;
+
+/// Where journald takes entries in its native protocol.
+pub const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// The `MESSAGE_ID` of entries for messages seen on the bus.
+pub const MESSAGE_SEEN: &str = "82f1f47ba6194d08a2c7d127dc4c4f1b";
+/// The `MESSAGE_ID` of entries for calls flagged by an audit rule, which
+/// alerting can match on.
+pub const CALL_FLAGGED: &str = "fc260216e201455c88392be696054527";
+/// The `MESSAGE_ID` of entries marking messages missed while reconnecting.
+pub const MESSAGES_MISSED: &str = "b6145d95555140f488a8126d039ff2fd";
+
+// Syslog priorities, as journald takes them in `PRIORITY`.
+const PRIORITY_WARNING: u8 = 4;
+const PRIORITY_NOTICE: u8 = 5;
+const PRIORITY_INFO: u8 = 6;
+
+/// A connection to the systemd journal, sending entries of structured
+/// fields as `sd_journal_send` does, without linking to libsystemd.
+#[derive(Debug)]
+pub struct Journal {
+    socket: UnixDatagram,
+}
+
+impl Journal {
+    pub fn connect() -> io::Result<Self> {
+        Self::connect_to(Path::new(JOURNAL_SOCKET))
+    }
+
+    pub fn connect_to(path: &Path) -> io::Result<Self> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(path)?;
+        Ok(Self { socket })
+    }
+
+    // Send an entry. Entries too big for a datagram go in a sealed memory
+    // file instead, passed to journald with the datagram, as libsystemd does.
+    pub fn send(&self, fields: &[(&str, String)]) -> io::Result<()> {
+        let entry = encode(fields);
+        match self.socket.send(&entry) {
+            Ok(_) => Ok(()),
+            Err(e) if matches!(e.raw_os_error(), Some(libc::EMSGSIZE | libc::ENOBUFS)) => {
+                self.send_file(&entry)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn send_file(&self, entry: &[u8]) -> io::Result<()> {
+        // SAFETY: the name is NUL-terminated and the flags are valid.
+        let fd = unsafe {
+            libc::memfd_create(
+                c"zbusctl-journal".as_ptr(),
+                libc::MFD_ALLOW_SEALING | libc::MFD_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: the descriptor was just created and nothing else owns it.
+        let mut file = unsafe { File::from_raw_fd(fd) };
+        file.write_all(entry)?;
+        let seals =
+            libc::F_SEAL_SHRINK | libc::F_SEAL_GROW | libc::F_SEAL_WRITE | libc::F_SEAL_SEAL;
+        // SAFETY: fcntl only adds seals to the descriptor, which `file` keeps open.
+        if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_ADD_SEALS, seals) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let fd_size = size_of::<libc::c_int>() as u32;
+        // SAFETY: CMSG_SPACE and CMSG_LEN only do arithmetic.
+        let (space, len) = unsafe { (libc::CMSG_SPACE(fd_size), libc::CMSG_LEN(fd_size)) };
+        // Words rather than bytes, for the alignment a cmsghdr needs.
+        let mut control = vec![0u64; (space as usize).div_ceil(size_of::<u64>())];
+        // SAFETY: an all-zero msghdr is a valid empty message.
+        let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+        msg.msg_control = control.as_mut_ptr().cast();
+        msg.msg_controllen = space as _;
+        // SAFETY: the control buffer has room for one header carrying one
+        // descriptor, and stays alive until sendmsg returns.
+        unsafe {
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = len as _;
+            std::ptr::write_unaligned(
+                libc::CMSG_DATA(cmsg).cast::<libc::c_int>(),
+                file.as_raw_fd(),
+            );
+            if libc::sendmsg(self.socket.as_raw_fd(), &msg, 0) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
+
+// Encode an entry in journald's native protocol: `NAME=value` lines, or the
+// name, a little-endian 64-bit length and the raw value for values that
+// contain a newline.
+pub fn encode(fields: &[(&str, String)]) -> Vec<u8> {
+    let mut entry = Vec::new();
+    for (name, value) in fields {
+        entry.extend_from_slice(name.as_bytes());
+        if value.contains('\n') {
+            entry.push(b'\n');
+            entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            entry.push(b'=');
+        }
+        entry.extend_from_slice(value.as_bytes());
+        entry.push(b'\n');
+    }
+    entry
+}
+
+// The fields of the entry for a message seen on the bus, with `message` as
+// the line people read. Header fields the message does not have are left
+// out, as are the audit fields of messages no rule flagged and the sender's
+// credentials when they were not looked up.
+pub fn event_fields(
+    event: &MessageEvent,
+    message: String,
+    audit: Option<&AuditRule>,
+    sender: Option<&Credentials>,
+) -> Vec<(&'static str, String)> {
+    let (message_id, priority) = match audit {
+        Some(_) => (CALL_FLAGGED, PRIORITY_WARNING),
+        None => (MESSAGE_SEEN, PRIORITY_INFO),
+    };
+    let message_type = match event.message_type {
+        MessageType::MethodCall => "method_call",
+        MessageType::MethodReturn => "method_return",
+        MessageType::Error => "error",
+        MessageType::Signal => "signal",
+    };
+    let mut fields = vec![
+        ("MESSAGE", message),
+        ("MESSAGE_ID", message_id.to_string()),
+        ("PRIORITY", priority.to_string()),
+        ("SYSLOG_IDENTIFIER", "zbusctl".to_string()),
+        ("DBUS_TYPE", message_type.to_string()),
+        ("DBUS_SERIAL", event.serial.to_string()),
+        ("DBUS_SIGNATURE", event.signature.clone()),
+        ("DBUS_BODY", event.body.to_string()),
+    ];
+    let optional = [
+        (
+            "DBUS_REPLY_SERIAL",
+            event.reply_serial.map(|s| s.to_string()),
+        ),
+        ("DBUS_SENDER", event.sender.clone()),
+        ("DBUS_DESTINATION", event.destination.clone()),
+        ("DBUS_PATH", event.path.clone()),
+        ("DBUS_INTERFACE", event.interface.clone()),
+        ("DBUS_MEMBER", event.member.clone()),
+        ("DBUS_ERROR_NAME", event.error_name.clone()),
+        ("AUDIT_REASON", audit.map(|rule| rule.reason.clone())),
+        (
+            "AUDIT_RULE",
+            audit.map(|rule| format!("{}.{}", rule.interface, rule.member)),
+        ),
+        (
+            "DBUS_SENDER_PID",
+            sender.and_then(|s| s.pid).map(|pid| pid.to_string()),
+        ),
+        (
+            "DBUS_SENDER_UID",
+            sender.and_then(|s| s.uid).map(|uid| uid.to_string()),
+        ),
+        (
+            "DBUS_SENDER_SECURITY_LABEL",
+            sender
+                .and_then(|s| s.security_label.as_ref())
+                .map(|label| label.label().to_string()),
+        ),
+    ];
+    fields.extend(
+        optional
+            .into_iter()
+            .filter_map(|(name, value)| Some((name, value?))),
+    );
+    fields
+}
+
+// The fields of the entry marking a gap in monitoring.
+pub fn gap_fields(gap: &GapEvent) -> Vec<(&'static str, String)> {
+    vec![
+        ("MESSAGE", gap.summary()),
+        ("MESSAGE_ID", MESSAGES_MISSED.to_string()),
+        ("PRIORITY", PRIORITY_NOTICE.to_string()),
+        ("SYSLOG_IDENTIFIER", "zbusctl".to_string()),
+        ("DBUS_GAP_SINCE", format!("{:.6}", gap.since)),
+        ("DBUS_GAP_REASON", gap.reason.clone()),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Seek, SeekFrom};
+    use zbus::message::Message;
+
+    #[test]
+    fn test_encode() {
+        let entry = encode(&[
+            ("MESSAGE", "hello".to_string()),
+            ("DBUS_BODY", "a\nb".to_string()),
+        ]);
+        let mut expected = b"MESSAGE=hello\nDBUS_BODY\n".to_vec();
+        expected.extend_from_slice(&3u64.to_le_bytes());
+        expected.extend_from_slice(b"a\nb\n");
+        assert_eq!(entry, expected);
+    }
+
+    #[test]
+    fn test_event_fields() {
+        let msg = Message::method_call("/org/freedesktop/login1", "PowerOff")
+            .unwrap()
+            .interface("org.freedesktop.login1.Manager")
+            .unwrap()
+            .build(&(true,))
+            .unwrap();
+        let event = MessageEvent::new(&msg);
+        let rule = AuditRule::new("org.freedesktop.login1.Manager", "PowerOff", "powers off");
+        let sender = Credentials {
+            pid: Some(42),
+            ..Default::default()
+        };
+        let fields = event_fields(&event, "PowerOff".to_string(), Some(&rule), Some(&sender));
+        let field = |name: &str| {
+            fields
+                .iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, value)| value.as_str())
+        };
+        assert_eq!(field("MESSAGE_ID"), Some(CALL_FLAGGED));
+        assert_eq!(field("PRIORITY"), Some("4"));
+        assert_eq!(field("DBUS_TYPE"), Some("method_call"));
+        assert_eq!(
+            field("DBUS_INTERFACE"),
+            Some("org.freedesktop.login1.Manager")
+        );
+        assert_eq!(field("AUDIT_REASON"), Some("powers off"));
+        assert_eq!(field("DBUS_SENDER_PID"), Some("42"));
+        assert_eq!(
+            field("DBUS_SENDER_UID"),
+            None,
+            "Unknown values should be left out"
+        );
+        assert_eq!(field("DBUS_DESTINATION"), None);
+    }
+
+    #[test]
+    fn test_send() {
+        let dir = std::env::temp_dir().join(format!("zbusctl-journal-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("socket");
+        let _ = std::fs::remove_file(&path);
+        let server = UnixDatagram::bind(&path).unwrap();
+        let journal = Journal::connect_to(&path).unwrap();
+
+        let fields = [("MESSAGE", "hello".to_string())];
+        journal.send(&fields).unwrap();
+        let mut buf = vec![0; 64];
+        let n = server.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], encode(&fields));
+
+        // Bigger than any datagram, so it has to go in a memory file.
+        let fields = [("MESSAGE", "x".repeat(16 << 20))];
+        journal.send(&fields).unwrap();
+        let fd_size = size_of::<libc::c_int>() as u32;
+        let space = unsafe { libc::CMSG_SPACE(fd_size) } as usize;
+        let mut control = vec![0u64; space.div_ceil(size_of::<u64>())];
+        let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+        msg.msg_control = control.as_mut_ptr().cast();
+        msg.msg_controllen = space as _;
+        let fd = unsafe {
+            assert!(libc::recvmsg(server.as_raw_fd(), &mut msg, 0) >= 0);
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            assert!(!cmsg.is_null(), "The entry should come with a descriptor");
+            std::ptr::read_unaligned(libc::CMSG_DATA(cmsg).cast::<libc::c_int>())
+        };
+        // The descriptor shares its offset with the sender's, left at the end.
+        let mut file = unsafe { File::from_raw_fd(fd) };
+        file.seek(SeekFrom::Start(0)).unwrap();
+        let mut entry = Vec::new();
+        file.read_to_end(&mut entry).unwrap();
+        assert_eq!(entry, encode(&fields));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,9 +1,68 @@
+use identifiers::{format_mac, format_uuid, parse_mac, parse_uuid};
 use std::any::type_name;
 use std::collections::HashMap;
 use std::hash::Hash;
 use std::str::FromStr;
+use units::{TimeUnit, parse_integer, parse_time};
 use zbus::Result;
-use zvariant::{ObjectPath, Signature, Structure, StructureBuilder};
+use zvariant::{Array, Dict, ObjectPath, Signature, Structure, StructureBuilder, Value};
+
+#[cfg(feature = "inspect")]
+pub mod access;
+#[cfg(feature = "inspect")]
+pub mod activation;
+#[cfg(feature = "capture")]
+pub mod audit;
+pub mod cache;
+#[cfg(feature = "scripting")]
+pub mod codec;
+#[cfg(any(feature = "capture", feature = "inspect"))]
+pub mod credentials;
+pub mod debug_log;
+pub mod events;
+#[cfg(any(feature = "inspect", feature = "serve"))]
+pub mod explain;
+#[cfg(feature = "scripting")]
+pub mod expression;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod format;
+pub mod golden;
+pub mod identifiers;
+pub mod introspect;
+pub mod jobs;
+#[cfg(feature = "capture")]
+pub mod journal;
+#[cfg(feature = "scripting")]
+pub mod message_file;
+pub mod numbers;
+#[cfg(feature = "webhook")]
+pub mod outbox;
+pub mod paginate;
+#[cfg(feature = "inspect")]
+pub mod policy;
+pub mod pool;
+pub mod project;
+pub mod queue;
+#[cfg(feature = "scripting")]
+pub mod readiness;
+pub mod redact;
+#[cfg(feature = "inspect")]
+pub mod script;
+#[cfg(feature = "capture")]
+pub mod scrub;
+#[cfg(feature = "serve")]
+pub mod serve;
+pub mod shapes;
+pub mod snapshot;
+#[cfg(feature = "serve")]
+pub mod store;
+pub mod template;
+pub mod units;
+#[cfg(feature = "scripting")]
+pub mod watcher;
+#[cfg(feature = "yaml")]
+pub mod yaml;
 
 // Parse a string to a value of type T.
 fn from_str<T>(v: &str) -> Result<T>
@@ -15,57 +74,97 @@ where
         .map_err(|e| zbus::Error::Failure(format!("Invalid {} '{}': {}", type_name::<T>(), v, e)))
 }
 
-// Build a dictionary from a list of key-value pairs.
-fn build_dict<K, V>(pairs: Vec<&str>) -> Result<HashMap<K, V>>
+// Build a dictionary from a list of key-value pairs. A repeated key is an
+// error, as it is most likely a mistake, unless `lenient` is set, when the
+// last value wins and the caller is warned. Values are parsed with `value`,
+// so integers take unit suffixes as they do elsewhere. The HashMap's order
+// does not reach the wire: zvariant keeps dictionary entries sorted by key.
+fn build_dict<K, V>(
+    pairs: Vec<&str>,
+    options: &ParseOptions,
+    value: impl Fn(&str) -> Result<V>,
+) -> Result<HashMap<K, V>>
 where
     K: FromStr + Eq + Hash,
-    V: FromStr,
     <K as FromStr>::Err: std::fmt::Display,
-    <V as FromStr>::Err: std::fmt::Display,
 {
     let mut dict = HashMap::new();
     for chunk in pairs.chunks(2) {
         let k = from_str::<K>(chunk[0])?;
-        let v = from_str::<V>(&chunk[1])?;
-        dict.insert(k, v);
+        let v = value(chunk[1])?;
+        if dict.insert(k, v).is_some() {
+            if !options.lenient {
+                return Err(zbus::Error::Failure(format!(
+                    "Duplicate dictionary key '{}' (pass --lenient to keep the last value)",
+                    chunk[0]
+                )));
+            }
+            if let Some(warn) = options.on_warning {
+                warn(&format!(
+                    "duplicate dictionary key '{}', keeping the last value",
+                    chunk[0]
+                ));
+            }
+        }
     }
     Ok(dict)
 }
 
+/// Options controlling how argument values are parsed.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ParseOptions {
+    /// The unit integers with a time suffix, such as `5s`, are converted to.
+    pub time_unit: TimeUnit,
+    /// Whether a repeated dictionary key keeps its last value, with a
+    /// warning, instead of failing.
+    pub lenient: bool,
+    /// Called with each warning about an argument, such as a repeated key
+    /// kept with `lenient`. Warnings are dropped without it.
+    pub on_warning: Option<fn(&str)>,
+}
+
 // Build a message body, parsing values from the arguments. Arguments encode the
 // type of the value into the string itself in the format "type:value". All
-// basic types are supported, as well as arrays of basic types.
+// basic types are supported, as well as arrays of basic types. Integers may
+// carry a time or size suffix, such as "uint64:5s" or "uint64:10MiB".
 pub fn build_body(args: Vec<&str>) -> Result<Structure<'static>> {
+    build_body_with(args, &ParseOptions::default())
+}
+
+// Build a message body like `build_body`, with non-default parsing options.
+pub fn build_body_with(args: Vec<&str>, options: &ParseOptions) -> Result<Structure<'static>> {
     let mut builder = StructureBuilder::new();
 
     for arg in args {
-        let (type_name, value) = {
-            let splits = arg.splitn(2, ':').collect::<Vec<&str>>();
-            (splits[0], splits[1])
-        };
+        let (type_name, value) = arg.split_once(':').ok_or_else(|| {
+            zbus::Error::Failure(format!(
+                "Invalid argument '{}': expected format: <type>:<value>",
+                arg
+            ))
+        })?;
 
         match type_name {
             // Basic types
             "int32" => {
-                builder = builder.add_field(from_str::<i32>(value)?);
+                builder = builder.add_field(parse_integer::<i32>(value, options.time_unit)?);
             }
             "uint32" => {
-                builder = builder.add_field(from_str::<u32>(value)?);
+                builder = builder.add_field(parse_integer::<u32>(value, options.time_unit)?);
             }
             "int64" => {
-                builder = builder.add_field(from_str::<i64>(value)?);
+                builder = builder.add_field(parse_integer::<i64>(value, options.time_unit)?);
             }
             "uint64" => {
-                builder = builder.add_field(from_str::<u64>(value)?);
+                builder = builder.add_field(parse_integer::<u64>(value, options.time_unit)?);
             }
             "int16" => {
-                builder = builder.add_field(from_str::<i16>(value)?);
+                builder = builder.add_field(parse_integer::<i16>(value, options.time_unit)?);
             }
             "uint16" => {
-                builder = builder.add_field(from_str::<u16>(value)?);
+                builder = builder.add_field(parse_integer::<u16>(value, options.time_unit)?);
             }
             "byte" => {
-                builder = builder.add_field(from_str::<u8>(value)?);
+                builder = builder.add_field(parse_integer::<u8>(value, options.time_unit)?);
             }
             "double" => {
                 builder = builder.add_field(from_str::<f64>(value)?);
@@ -87,6 +186,27 @@ pub fn build_body(args: Vec<&str>) -> Result<Structure<'static>> {
             "string" => {
                 builder = builder.add_field(value.to_string());
             }
+            // UUIDs are sent as canonical strings unless `bytes:` asks for a
+            // 16-byte array; MAC addresses as byte arrays unless `string:`
+            // asks for BlueZ-style strings.
+            "uuid" => match value.split_once(':') {
+                Some(("bytes", uuid)) => builder = builder.add_field(parse_uuid(uuid)?.to_vec()),
+                Some(("string", uuid)) => {
+                    builder = builder.add_field(format_uuid(&parse_uuid(uuid)?))
+                }
+                _ => builder = builder.add_field(format_uuid(&parse_uuid(value)?)),
+            },
+            "mac" => {
+                if let Some(mac) = value.strip_prefix("string:") {
+                    builder = builder.add_field(format_mac(&parse_mac(mac)?));
+                } else {
+                    let mac = value.strip_prefix("bytes:").unwrap_or(value);
+                    builder = builder.add_field(parse_mac(mac)?);
+                }
+            }
+            "timestamp" | "duration" => {
+                builder = builder.add_field(parse_time(type_name, value, options.time_unit)?);
+            }
             "array" => {
                 let (element_type, values) = {
                     let splits = value.splitn(2, ':').collect::<Vec<&str>>();
@@ -101,38 +221,52 @@ pub fn build_body(args: Vec<&str>) -> Result<Structure<'static>> {
 
                 match element_type {
                     "int32" => {
-                        let array: Result<Vec<i32>> =
-                            values.iter().map(|v| from_str::<i32>(v.trim())).collect();
+                        let array: Result<Vec<i32>> = values
+                            .iter()
+                            .map(|v| parse_integer::<i32>(v.trim(), options.time_unit))
+                            .collect();
                         builder = builder.add_field(array?);
                     }
                     "uint32" => {
-                        let array: Result<Vec<u32>> =
-                            values.iter().map(|v| from_str::<u32>(v.trim())).collect();
+                        let array: Result<Vec<u32>> = values
+                            .iter()
+                            .map(|v| parse_integer::<u32>(v.trim(), options.time_unit))
+                            .collect();
                         builder = builder.add_field(array?);
                     }
                     "int64" => {
-                        let array: Result<Vec<i64>> =
-                            values.iter().map(|v| from_str::<i64>(v.trim())).collect();
+                        let array: Result<Vec<i64>> = values
+                            .iter()
+                            .map(|v| parse_integer::<i64>(v.trim(), options.time_unit))
+                            .collect();
                         builder = builder.add_field(array?);
                     }
                     "uint64" => {
-                        let array: Result<Vec<u64>> =
-                            values.iter().map(|v| from_str::<u64>(v.trim())).collect();
+                        let array: Result<Vec<u64>> = values
+                            .iter()
+                            .map(|v| parse_integer::<u64>(v.trim(), options.time_unit))
+                            .collect();
                         builder = builder.add_field(array?);
                     }
                     "int16" => {
-                        let array: Result<Vec<i16>> =
-                            values.iter().map(|v| from_str::<i16>(v.trim())).collect();
+                        let array: Result<Vec<i16>> = values
+                            .iter()
+                            .map(|v| parse_integer::<i16>(v.trim(), options.time_unit))
+                            .collect();
                         builder = builder.add_field(array?);
                     }
                     "uint16" => {
-                        let array: Result<Vec<u16>> =
-                            values.iter().map(|v| from_str::<u16>(v.trim())).collect();
+                        let array: Result<Vec<u16>> = values
+                            .iter()
+                            .map(|v| parse_integer::<u16>(v.trim(), options.time_unit))
+                            .collect();
                         builder = builder.add_field(array?);
                     }
                     "byte" => {
-                        let array: Result<Vec<u8>> =
-                            values.iter().map(|v| from_str::<u8>(v.trim())).collect();
+                        let array: Result<Vec<u8>> = values
+                            .iter()
+                            .map(|v| parse_integer::<u8>(v.trim(), options.time_unit))
+                            .collect();
                         builder = builder.add_field(array?);
                     }
                     "double" => {
@@ -209,34 +343,67 @@ pub fn build_body(args: Vec<&str>) -> Result<Structure<'static>> {
                 // Build the dictionary based on key and value types
                 match (key_type, value_type) {
                     ("string", "int32") => {
-                        builder = builder.add_field(build_dict::<String, i32>(pairs)?);
+                        builder =
+                            builder.add_field(build_dict::<String, _>(pairs, options, |v| {
+                                parse_integer::<i32>(v, options.time_unit)
+                            })?);
                     }
                     ("string", "uint32") => {
-                        builder = builder.add_field(build_dict::<String, u32>(pairs)?);
+                        builder =
+                            builder.add_field(build_dict::<String, _>(pairs, options, |v| {
+                                parse_integer::<u32>(v, options.time_unit)
+                            })?);
                     }
                     ("string", "int64") => {
-                        builder = builder.add_field(build_dict::<String, i64>(pairs)?);
+                        builder =
+                            builder.add_field(build_dict::<String, _>(pairs, options, |v| {
+                                parse_integer::<i64>(v, options.time_unit)
+                            })?);
                     }
                     ("string", "uint64") => {
-                        builder = builder.add_field(build_dict::<String, u64>(pairs)?);
+                        builder =
+                            builder.add_field(build_dict::<String, _>(pairs, options, |v| {
+                                parse_integer::<u64>(v, options.time_unit)
+                            })?);
                     }
                     ("string", "int16") => {
-                        builder = builder.add_field(build_dict::<String, i16>(pairs)?);
+                        builder =
+                            builder.add_field(build_dict::<String, _>(pairs, options, |v| {
+                                parse_integer::<i16>(v, options.time_unit)
+                            })?);
                     }
                     ("string", "uint16") => {
-                        builder = builder.add_field(build_dict::<String, u16>(pairs)?);
+                        builder =
+                            builder.add_field(build_dict::<String, _>(pairs, options, |v| {
+                                parse_integer::<u16>(v, options.time_unit)
+                            })?);
                     }
                     ("string", "byte") => {
-                        builder = builder.add_field(build_dict::<String, u8>(pairs)?);
+                        builder =
+                            builder.add_field(build_dict::<String, _>(pairs, options, |v| {
+                                parse_integer::<u8>(v, options.time_unit)
+                            })?);
                     }
                     ("string", "double") => {
-                        builder = builder.add_field(build_dict::<String, f64>(pairs)?);
+                        builder = builder.add_field(build_dict::<String, _>(
+                            pairs,
+                            options,
+                            from_str::<f64>,
+                        )?);
                     }
                     ("string", "boolean") | ("string", "bool") => {
-                        builder = builder.add_field(build_dict::<String, bool>(pairs)?);
+                        builder = builder.add_field(build_dict::<String, _>(
+                            pairs,
+                            options,
+                            from_str::<bool>,
+                        )?);
                     }
                     ("string", "string") => {
-                        builder = builder.add_field(build_dict::<String, String>(pairs)?);
+                        builder = builder.add_field(build_dict::<String, _>(
+                            pairs,
+                            options,
+                            from_str::<String>,
+                        )?);
                     }
                     _ => {
                         return Err(zbus::Error::Failure(format!(
@@ -258,6 +425,159 @@ pub fn build_body(args: Vec<&str>) -> Result<Structure<'static>> {
     Ok(builder.build()?)
 }
 
+// Convert a parsed argument to the type a receiver expects, where nothing is
+// lost: an integer to another integer type it fits in, or to a double that
+// holds it exactly, and a string to an object path or signature it is valid
+// as. `None` if the value cannot be converted.
+pub fn coerce(value: &Value<'_>, signature: &str) -> Option<Value<'static>> {
+    let integer = match *value {
+        Value::U8(n) => Some(i128::from(n)),
+        Value::I16(n) => Some(i128::from(n)),
+        Value::U16(n) => Some(i128::from(n)),
+        Value::I32(n) => Some(i128::from(n)),
+        Value::U32(n) => Some(i128::from(n)),
+        Value::I64(n) => Some(i128::from(n)),
+        Value::U64(n) => Some(i128::from(n)),
+        _ => None,
+    };
+    if let Some(n) = integer {
+        return match signature {
+            "y" => u8::try_from(n).ok().map(Value::U8),
+            "n" => i16::try_from(n).ok().map(Value::I16),
+            "q" => u16::try_from(n).ok().map(Value::U16),
+            "i" => i32::try_from(n).ok().map(Value::I32),
+            "u" => u32::try_from(n).ok().map(Value::U32),
+            "x" => i64::try_from(n).ok().map(Value::I64),
+            "t" => u64::try_from(n).ok().map(Value::U64),
+            "d" => Some(n as f64).filter(|d| *d as i128 == n).map(Value::F64),
+            _ => None,
+        };
+    }
+    match (value, signature) {
+        (Value::Str(s), "o") => ObjectPath::try_from(s.to_string())
+            .ok()
+            .map(Value::ObjectPath),
+        (Value::Str(s), "g") => Signature::try_from(s.as_str()).ok().map(Value::Signature),
+        _ => None,
+    }
+}
+
+// Add to the current value of an array: the given value as one element,
+// converted to the element type like `coerce` does, or else every element of
+// the given array. For a dictionary, the entries of the given one are added,
+// replacing those with the same key.
+pub fn append_elements(current: &Value<'_>, given: &Value<'_>) -> Result<Value<'static>> {
+    match current {
+        Value::Array(array) => {
+            let mut result = Array::new(array.element_signature());
+            for element in array.inner() {
+                result.append(to_owned(element)?)?;
+            }
+            for element in elements(given, array.element_signature())? {
+                result.append(element)?;
+            }
+            Ok(Value::Array(result))
+        }
+        Value::Dict(dict) => {
+            let Signature::Dict { key, value } = dict.signature() else {
+                unreachable!("Incorrect `Dict` signature")
+            };
+            let Value::Dict(entries) = given else {
+                return Err(zbus::Error::Failure(format!(
+                    "Expected a dictionary to add to one of type '{}', got a value of type '{}'",
+                    dict.signature(),
+                    given.value_signature()
+                )));
+            };
+            let mut result = Dict::new(key, value);
+            for (k, v) in dict.iter() {
+                result.append(to_owned(k)?, to_owned(v)?)?;
+            }
+            for (k, v) in entries.iter() {
+                result.append(convert(k, key)?, convert(v, value)?)?;
+            }
+            Ok(Value::Dict(result))
+        }
+        _ => Err(not_a_container(current)),
+    }
+}
+
+// Remove from the current value of an array every element equal to the given
+// value, or to any element of the given array. For a dictionary, the given
+// values are keys, and their entries are removed. Values that are not there
+// are skipped.
+pub fn remove_elements(current: &Value<'_>, given: &Value<'_>) -> Result<Value<'static>> {
+    match current {
+        Value::Array(array) => {
+            let removed = elements(given, array.element_signature())?;
+            let mut result = Array::new(array.element_signature());
+            for element in array.inner() {
+                if !removed.contains(element) {
+                    result.append(to_owned(element)?)?;
+                }
+            }
+            Ok(Value::Array(result))
+        }
+        Value::Dict(dict) => {
+            let Signature::Dict { key, value } = dict.signature() else {
+                unreachable!("Incorrect `Dict` signature")
+            };
+            let removed = elements(given, key)?;
+            let mut result = Dict::new(key, value);
+            for (k, v) in dict.iter() {
+                if !removed.contains(k) {
+                    result.append(to_owned(k)?, to_owned(v)?)?;
+                }
+            }
+            Ok(Value::Dict(result))
+        }
+        _ => Err(not_a_container(current)),
+    }
+}
+
+// The given value as a list of elements of the given type: itself, if it
+// converts, or else each element of it, if it is an array.
+fn elements(given: &Value<'_>, element: &Signature) -> Result<Vec<Value<'static>>> {
+    match (convert(given, element), given) {
+        (Ok(value), _) => Ok(vec![value]),
+        (Err(_), Value::Array(array)) => array
+            .inner()
+            .iter()
+            .map(|item| convert(item, element))
+            .collect(),
+        (Err(e), _) => Err(e),
+    }
+}
+
+// A value as the given type: unchanged if it already is, wrapped if the type
+// is a variant, and otherwise converted like `coerce` does.
+fn convert(value: &Value<'_>, signature: &Signature) -> Result<Value<'static>> {
+    if value.value_signature() == signature {
+        return to_owned(value);
+    }
+    if *signature == Signature::Variant {
+        return Ok(Value::Value(Box::new(to_owned(value)?)));
+    }
+    coerce(value, &signature.to_string()).ok_or_else(|| {
+        zbus::Error::Failure(format!(
+            "Expected a value of type '{}', got one of type '{}' that cannot be converted",
+            signature,
+            value.value_signature()
+        ))
+    })
+}
+
+fn to_owned(value: &Value<'_>) -> Result<Value<'static>> {
+    Ok(Value::from(value.try_to_owned()?))
+}
+
+fn not_a_container(value: &Value<'_>) -> zbus::Error {
+    zbus::Error::Failure(format!(
+        "Expected an array or dictionary, got a value of type '{}'",
+        value.value_signature()
+    ))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -294,6 +614,61 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_dictionary_duplicate_keys() {
+        let args = vec!["dict:string:int32:a,1,b,2,a,3"];
+        let error = build_body(args.clone()).unwrap_err().to_string();
+        assert!(
+            error.contains("Duplicate dictionary key 'a'"),
+            "Repeated keys should be rejected: {}",
+            error
+        );
+
+        let options = ParseOptions {
+            lenient: true,
+            ..ParseOptions::default()
+        };
+        let body = build_body_with(args.clone(), &options).unwrap();
+        let dict = HashMap::<String, i32>::try_from(body.fields()[0].clone()).unwrap();
+        assert_eq!(dict["a"], 3, "The last value should win with lenient");
+        assert_eq!(dict.len(), 2);
+
+        static WARNINGS: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(Vec::new());
+        let options = ParseOptions {
+            on_warning: Some(|warning| WARNINGS.lock().unwrap().push(warning.to_string())),
+            ..options
+        };
+        build_body_with(args, &options).unwrap();
+        assert_eq!(
+            *WARNINGS.lock().unwrap(),
+            vec!["duplicate dictionary key 'a', keeping the last value".to_string()],
+            "The kept key should be reported to the caller"
+        );
+    }
+
+    #[test]
+    fn test_dictionary_unit_suffixes() {
+        let options = ParseOptions {
+            time_unit: TimeUnit::Msec,
+            ..ParseOptions::default()
+        };
+        let body = build_body_with(
+            vec![
+                "dict:string:uint32:a,5s,b,4KiB,c,7",
+                "dict:string:double:x,1.5e3",
+            ],
+            &options,
+        )
+        .unwrap();
+        let dict = HashMap::<String, u32>::try_from(body.fields()[0].clone()).unwrap();
+        assert_eq!(dict["a"], 5000);
+        assert_eq!(dict["b"], 4096);
+        assert_eq!(dict["c"], 7);
+        let dict = HashMap::<String, f64>::try_from(body.fields()[1].clone()).unwrap();
+        assert_eq!(dict["x"], 1500.0);
+        assert!(build_body(vec!["dict:string:byte:a,1KiB"]).is_err());
+    }
+
     #[test]
     fn test_dictionary_unsupported_types() {
         let args = vec!["dict:float:int32:1.0,1"];
@@ -303,4 +678,115 @@ mod tests {
             "Dictionary with unsupported key type should fail"
         );
     }
+
+    #[test]
+    fn test_argument_without_type() {
+        let args = vec!["hello"];
+        let result = build_body(args);
+        assert!(
+            result.is_err(),
+            "Argument without a type prefix should fail"
+        );
+    }
+
+    #[test]
+    fn test_unit_suffixes() {
+        let options = ParseOptions {
+            time_unit: TimeUnit::Msec,
+            ..ParseOptions::default()
+        };
+        let body = build_body_with(
+            vec!["uint64:5s", "array:uint64:1KiB,2", "double:1.5e3"],
+            &options,
+        )
+        .unwrap();
+        assert_eq!(body.fields()[0], zvariant::Value::U64(5000));
+        assert_eq!(body.fields()[1], zvariant::Value::from(vec![1024u64, 2]));
+        assert_eq!(body.fields()[2], zvariant::Value::F64(1500.0));
+    }
+
+    #[test]
+    fn test_coerce() {
+        assert_eq!(coerce(&Value::I32(5), "u"), Some(Value::U32(5)));
+        assert_eq!(coerce(&Value::U64(300), "q"), Some(Value::U16(300)));
+        assert_eq!(coerce(&Value::I32(2), "d"), Some(Value::F64(2.0)));
+        assert_eq!(
+            coerce(&Value::I32(-1), "u"),
+            None,
+            "Values that do not fit should not be converted"
+        );
+        assert_eq!(coerce(&Value::U64(u64::MAX), "d"), None);
+        assert_eq!(
+            coerce(&Value::from("/org/example"), "o"),
+            Some(Value::ObjectPath(
+                ObjectPath::try_from("/org/example").unwrap()
+            ))
+        );
+        assert_eq!(coerce(&Value::from("not a path"), "o"), None);
+        assert_eq!(coerce(&Value::from("5"), "u"), None);
+    }
+
+    #[test]
+    fn test_append_and_remove_elements() {
+        let tags = Value::from(vec!["a", "b", "a"]);
+        assert_eq!(
+            append_elements(&tags, &Value::from("c")).unwrap(),
+            Value::from(vec!["a", "b", "a", "c"])
+        );
+        assert_eq!(
+            append_elements(&tags, &Value::from(vec!["c", "d"])).unwrap(),
+            Value::from(vec!["a", "b", "a", "c", "d"])
+        );
+        assert_eq!(
+            remove_elements(&tags, &Value::from("a")).unwrap(),
+            Value::from(vec!["b"])
+        );
+        assert_eq!(
+            remove_elements(&tags, &Value::from("z")).unwrap(),
+            tags,
+            "Removing a missing element should leave the array as it was"
+        );
+        assert_eq!(
+            append_elements(&Value::from(vec![1u32]), &Value::I32(2)).unwrap(),
+            Value::from(vec![1u32, 2]),
+            "Elements should be converted to the element type"
+        );
+        assert!(append_elements(&tags, &Value::I32(1)).is_err());
+        assert!(append_elements(&Value::from("a"), &Value::from("b")).is_err());
+
+        let counts = Value::from(HashMap::from([("a", 1i32), ("b", 2)]));
+        assert_eq!(
+            append_elements(
+                &counts,
+                &Value::from(HashMap::from([("b", 3i32), ("c", 4)]))
+            )
+            .unwrap(),
+            Value::from(HashMap::from([("a", 1i32), ("b", 3), ("c", 4)]))
+        );
+        assert_eq!(
+            remove_elements(&counts, &Value::from("a")).unwrap(),
+            Value::from(HashMap::from([("b", 2i32)]))
+        );
+    }
+
+    #[test]
+    fn test_uuid_and_mac() {
+        let body = build_body(vec![
+            "uuid:123E4567E89B12D3A456426614174000",
+            "uuid:bytes:123e4567-e89b-12d3-a456-426614174000",
+            "mac:00:1a:2b:3c:4d:5e",
+            "mac:string:00-1a-2b-3c-4d-5e",
+        ])
+        .unwrap();
+        assert_eq!(
+            body.fields()[0],
+            zvariant::Value::from("123e4567-e89b-12d3-a456-426614174000")
+        );
+        assert_eq!(body.fields()[1].value_signature().to_string(), "ay");
+        assert_eq!(
+            body.fields()[2],
+            zvariant::Value::from(vec![0x00u8, 0x1a, 0x2b, 0x3c, 0x4d, 0x5e])
+        );
+        assert_eq!(body.fields()[3], zvariant::Value::from("00:1A:2B:3C:4D:5E"));
+    }
 }
@@ -1,104 +1,1048 @@
-use clap::{Args, Parser, Subcommand};
-use zbus::{Connection, Result};
-use zbusctl::build_body;
-use zvariant::Structure;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::io::IsTerminal;
+use std::time::Duration;
+use zbus::Result;
+use zbusctl::ParseOptions;
+#[cfg(feature = "cache")]
+use zbusctl::cache::Cache;
+use zbusctl::format::{FloatFormat, OutputFormat};
+use zbusctl::jobs::Jobs;
+use zbusctl::numbers::{NonFinite, Numbers};
+use zbusctl::project::ProjectConfig;
+use zbusctl::queue::BusTarget;
+use zbusctl::redact::{RedactRule, Redactor};
+use zbusctl::units::TimeUnit;
+
+use progress::{Progress, ProgressMode};
+
+mod commands;
+#[cfg(feature = "elevate")]
+mod elevate;
+mod help_json;
+#[cfg(feature = "namespaces")]
+mod namespace;
+mod pager;
+mod progress;
+#[cfg(feature = "capture")]
+mod sandbox;
+#[cfg(feature = "capture")]
+mod terminal;
 
 #[derive(Parser)]
 #[command(version = env!("CARGO_PKG_VERSION"))]
 #[command(about = "A command-line utility for interacting with D-Bus")]
 struct ZBusCtl {
+    #[command(flatten)]
+    global: GlobalArgs,
+
     #[command(subcommand)]
     command: Commands,
 }
 
+/// The message bus to connect to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Bus {
+    Session,
+    System,
+}
+
+impl Bus {
+    fn name(self) -> &'static str {
+        match self {
+            Bus::Session => "session",
+            Bus::System => "system",
+        }
+    }
+}
+
+// Options shared by every subcommand. Each can also be set through an
+// environment variable so containers and CI jobs can configure site-wide
+// defaults without wrapping the binary.
+#[derive(Args, Clone)]
+struct GlobalArgs {
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        env = "ZBUSCTL_BUS",
+        default_value_t = Bus::Session,
+        help = "Message bus to connect to"
+    )]
+    bus: Bus,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Use system bus instead of session bus (same as --bus system)"
+    )]
+    system: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "ADDRESS",
+        conflicts_with = "system",
+        help = "Talk to the process listening at ADDRESS directly, without a bus (such as unix:path=/run/app.sock)"
+    )]
+    #[cfg_attr(feature = "namespaces", arg(conflicts_with_all = ["netns", "mntns"]))]
+    peer: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        env = "ZBUSCTL_OUTPUT",
+        help = "Output format (default: tables or pretty JSON on a terminal, compact JSON otherwise)"
+    )]
+    output: Option<OutputFormat>,
+
+    #[arg(
+        long,
+        global = true,
+        env = "ZBUSCTL_TIMEOUT",
+        value_name = "SECONDS",
+        help = "Fail method calls that take longer than SECONDS"
+    )]
+    timeout: Option<f64>,
+
+    #[cfg(feature = "cache")]
+    #[arg(
+        long,
+        global = true,
+        env = "ZBUSCTL_CACHE",
+        value_name = "DURATION",
+        value_parser = parse_cache_ttl,
+        help = "Reuse introspection data and GetAll replies fetched within DURATION (such as 5s)"
+    )]
+    cache: Option<Duration>,
+
+    #[arg(
+        long,
+        global = true,
+        env = "ZBUSCTL_FLOAT_FORMAT",
+        default_value = "shortest",
+        value_name = "shortest|fixed:N|scientific",
+        help = "How to write floating point values in JSON output"
+    )]
+    float_format: FloatFormat,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        env = "ZBUSCTL_NON_FINITE",
+        default_value_t = NonFinite::Null,
+        help = "How to write NaN and infinite doubles, which JSON cannot represent"
+    )]
+    non_finite: NonFinite,
+
+    #[arg(
+        long,
+        global = true,
+        env = "ZBUSCTL_INT64_AS_STRING",
+        help = "Write 64-bit integers as strings in JSON and YAML, so consumers that read numbers as doubles keep them exact"
+    )]
+    int64_as_string: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        env = "ZBUSCTL_TIME_UNIT",
+        default_value_t = TimeUnit::Usec,
+        help = "Unit that integer arguments with a time suffix (5s, 250ms) are converted to"
+    )]
+    time_unit: TimeUnit,
+
+    #[cfg(feature = "elevate")]
+    #[arg(
+        long,
+        global = true,
+        help = "Run as root through systemd-run or pkexec (same as --run-as root)"
+    )]
+    as_root: bool,
+
+    #[cfg(feature = "elevate")]
+    #[arg(
+        long,
+        global = true,
+        value_name = "USER",
+        conflicts_with = "as_root",
+        help = "Run as USER through systemd-run or pkexec, unless already running as USER"
+    )]
+    run_as: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Keep the last value of a repeated dictionary key, with a warning, instead of failing"
+    )]
+    lenient: bool,
+
+    #[arg(
+        long,
+        global = true,
+        env = "ZBUSCTL_JOBS",
+        default_value_t = 4,
+        value_name = "N",
+        help = "Make at most N requests at once when a command needs many"
+    )]
+    jobs: usize,
+
+    #[arg(
+        long,
+        global = true,
+        env = "ZBUSCTL_CONNECTIONS",
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u16).range(1..),
+        value_name = "N",
+        help = "Spread the requests of a command that makes many over N connections to the bus"
+    )]
+    connections: u16,
+
+    #[cfg(feature = "namespaces")]
+    #[arg(
+        long,
+        global = true,
+        value_name = "PID",
+        help = "Connect from the network namespace of process PID, where its abstract sockets are"
+    )]
+    netns: Option<u32>,
+
+    #[cfg(feature = "namespaces")]
+    #[arg(
+        long,
+        global = true,
+        value_name = "PID",
+        help = "Find the bus socket in the filesystem of process PID, such as a container's"
+    )]
+    mntns: Option<u32>,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        env = "ZBUSCTL_PROGRESS",
+        default_value_t = ProgressMode::Auto,
+        help = "How to report progress on stderr when a command makes many requests"
+    )]
+    progress: ProgressMode,
+
+    #[arg(long, global = true, help = "Never pipe output through $PAGER")]
+    no_pager: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Never prompt or page output; implied when stdin is not a terminal"
+    )]
+    non_interactive: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Show the values the project's redaction rules would hide"
+    )]
+    no_redact: bool,
+
+    // The project's redaction rules, which have no option of their own.
+    #[arg(skip)]
+    redact: Vec<RedactRule>,
+
+    // The global options given on the command line, each with the words
+    // that give it, to pass on to the command line of a template.
+    #[arg(skip)]
+    given: Vec<(String, Vec<OsString>)>,
+}
+
+impl GlobalArgs {
+    fn bus(&self) -> Bus {
+        if self.system { Bus::System } else { self.bus }
+    }
+
+    // Whether the user can be interacted with: prompted, shown a pager, and so
+    // on. Scripts and CI jobs rarely have a terminal on stdin, so interaction
+    // is switched off automatically for them rather than risking a hang.
+    fn interactive(&self) -> bool {
+        !self.non_interactive && std::io::stdin().is_terminal()
+    }
+
+    // The user to run as, if it was asked for.
+    #[cfg(feature = "elevate")]
+    fn run_as(&self) -> Option<&str> {
+        if self.as_root {
+            Some("root")
+        } else {
+            self.run_as.as_deref()
+        }
+    }
+
+    fn parse_options(&self) -> ParseOptions {
+        ParseOptions {
+            time_unit: self.time_unit,
+            lenient: self.lenient,
+            on_warning: Some(commands::print_warning),
+        }
+    }
+
+    fn numbers(&self) -> Numbers {
+        Numbers {
+            non_finite: self.non_finite,
+            int64_as_string: self.int64_as_string,
+        }
+    }
+
+    fn jobs(&self) -> Jobs {
+        Jobs::new(self.jobs)
+    }
+
+    // Progress reporting for a command that makes many requests, counting
+    // them in `noun`s.
+    fn progress(&self, noun: &'static str) -> Progress {
+        Progress::new(self.progress, noun)
+    }
+
+    // A bus as these options reach it: from the environment and namespaces
+    // in effect, which decide which instance of the bus it is.
+    fn target(&self, bus: Bus) -> BusTarget {
+        let variable = match bus {
+            Bus::Session => "DBUS_SESSION_BUS_ADDRESS",
+            Bus::System => "DBUS_SYSTEM_BUS_ADDRESS",
+        };
+        let (netns, mntns) = self.namespaces();
+        BusTarget {
+            bus: bus.name().to_string(),
+            address: std::env::var(variable).ok(),
+            netns,
+            mntns,
+        }
+    }
+
+    // The processes whose network and mount namespaces to reach buses from.
+    #[cfg(feature = "namespaces")]
+    fn namespaces(&self) -> (Option<u32>, Option<u32>) {
+        (self.netns, self.mntns)
+    }
+
+    // Without namespace support, buses are reached from zbusctl's own.
+    #[cfg(not(feature = "namespaces"))]
+    fn namespaces(&self) -> (Option<u32>, Option<u32>) {
+        (None, None)
+    }
+
+    // The reply cache, with a part of its own for each bus, when --cache is
+    // given.
+    #[cfg(feature = "cache")]
+    fn cache(&self) -> Option<Cache> {
+        let ttl = self.cache?;
+        let dir = commands::cache_dir()?.join("replies");
+        let scope = match self.peer {
+            Some(ref peer) => format!("peer {}", peer),
+            None => self.target(self.bus()).describe(),
+        };
+        Some(Cache::new(dir, ttl).scoped(&scope))
+    }
+
+    // Without the cache, every reply is fetched.
+    #[cfg(not(feature = "cache"))]
+    fn cache(&self) -> Option<zbusctl::cache::Cache> {
+        None
+    }
+
+    // The redaction rules to apply to output and logs.
+    fn redactor(&self) -> Redactor {
+        match self.no_redact {
+            true => Redactor::default(),
+            false => Redactor::new(self.redact.clone()),
+        }
+    }
+
+    fn timeout(&self) -> Result<Option<Duration>> {
+        self.timeout
+            .map(|secs| {
+                Duration::try_from_secs_f64(secs)
+                    .map_err(|e| zbus::Error::Failure(format!("Invalid timeout '{}': {}", secs, e)))
+            })
+            .transpose()
+    }
+}
+
+#[cfg(feature = "cache")]
+fn parse_cache_ttl(value: &str) -> std::result::Result<Duration, String> {
+    zbusctl::units::parse_time("duration", value, TimeUnit::Nsec)
+        .map(Duration::from_nanos)
+        .map_err(|e| e.to_string())
+}
+
 #[derive(Subcommand)]
 enum Commands {
     // Call a D-Bus method
-    Call(CallArgs),
+    #[command(about = "Call a D-Bus method")]
+    Call(commands::call::CallArgs),
+
+    #[cfg(feature = "scripting")]
+    // Render a saved reply
+    #[command(about = "Render a reply saved with `call --save-reply`")]
+    Render(commands::call::RenderArgs),
+
+    #[cfg(feature = "queue")]
+    // Send queued calls
+    #[command(about = "Send the calls queued by `call --queue-if-unreachable`")]
+    FlushQueue(commands::queue::FlushQueueArgs),
+
+    #[cfg(feature = "scripting")]
+    // Emit a signal
+    #[command(about = "Emit a signal, with arguments built as for `call`")]
+    Emit(commands::emit::EmitArgs),
+
+    #[cfg(feature = "scripting")]
+    // Decode a raw marshaled body
+    #[command(about = "Decode raw marshaled D-Bus data and print it as JSON")]
+    Decode(commands::codec::DecodeArgs),
+
+    #[cfg(feature = "scripting")]
+    // Encode arguments into a raw marshaled body
+    #[command(about = "Marshal arguments into raw D-Bus data on stdout")]
+    Encode(commands::codec::EncodeArgs),
+
+    #[cfg(feature = "inspect")]
+    // Explain a type signature
+    #[command(about = "Describe a D-Bus type signature and show zbusctl arguments of that type")]
+    ExplainSignature(commands::explain::ExplainSignatureArgs),
+
+    #[cfg(feature = "inspect")]
+    // Show bus or connection statistics
+    #[command(about = "Show statistics from the bus's Debug.Stats interface")]
+    Stats(commands::stats::StatsArgs),
+
+    #[cfg(feature = "inspect")]
+    // List bus names
+    #[command(about = "List the names registered on the bus")]
+    Names(commands::names::NamesArgs),
+
+    #[cfg(feature = "inspect")]
+    // Show who owns a name
+    #[command(about = "Show the owner of a bus name and the credentials of its process")]
+    Status(commands::status::StatusArgs),
+
+    // Introspect objects
+    #[command(about = "Show the interfaces of an object, optionally saving its introspection XML")]
+    Introspect(commands::introspect::IntrospectArgs),
+
+    #[cfg(feature = "inspect")]
+    // Show the objects of a service
+    #[command(about = "Show the object paths of a service as a tree")]
+    Tree(commands::tree::TreeArgs),
+
+    #[cfg(feature = "capture")]
+    // Show bus traffic
+    #[command(about = "Show every message going through the bus, with decoded bodies")]
+    Monitor(commands::monitor::MonitorArgs),
+
+    // Emit the signals of a capture again
+    #[cfg(feature = "serve")]
+    #[command(about = "Emit the signals of a capture again, at their recorded pace or another")]
+    Replay(commands::replay::ReplayArgs),
+
+    // Serve properties from a file or the environment
+    #[cfg(feature = "serve")]
+    #[command(
+        about = "Serve properties from a file or the environment, writing changes back to the file"
+    )]
+    ServeProperties(commands::serve_properties::ServePropertiesArgs),
+
+    #[cfg(feature = "capture")]
+    // Remove content from a capture before sharing it
+    #[command(about = "Copy a capture without sensitive content, to attach to a bug report")]
+    CaptureScrub(commands::scrub::CaptureScrubArgs),
+
+    #[cfg(feature = "inspect")]
+    // Check which methods the current user may call
+    #[command(
+        about = "Report which methods a service lets the current user call, probing only those that can be probed safely"
+    )]
+    ProbeAccess(commands::access::ProbeAccessArgs),
+
+    #[cfg(feature = "inspect")]
+    // Inspect bus policy files
+    #[command(about = "Explain the bus policy rules that apply to a name")]
+    Policy(commands::policy::PolicyArgs),
+
+    #[cfg(feature = "inspect")]
+    // Inspect activation files
+    #[command(about = "Check the files that let the bus start services on demand")]
+    Activation(commands::activation::ActivationArgs),
+
+    // Set a property
+    #[command(about = "Set a property, wrapping the value in a variant")]
+    SetProperty(commands::props::SetPropertyArgs),
+
+    // Read all properties
+    #[command(about = "Print every property of an interface, or of a whole object")]
+    GetAllProperties(commands::props::GetAllPropertiesArgs),
+
+    #[cfg(feature = "props")]
+    // Compare property values with an earlier snapshot
+    #[command(
+        about = "Show properties of an object that changed since a snapshot, or between two snapshots"
+    )]
+    PropsDiff(commands::props::PropsDiffArgs),
+
+    #[cfg(feature = "props")]
+    // Back up and restore properties
+    #[command(about = "Export an object's writable properties to a file, or import them back")]
+    Props(commands::props::PropsArgs),
+
+    #[cfg(feature = "scripting")]
+    // Wait for a service to be ready
+    #[command(
+        about = "Wait until a service owns its name, answers Ping and has the expected properties"
+    )]
+    WaitUntilReady(commands::ready::WaitUntilReadyArgs),
+
+    #[cfg(feature = "scripting")]
+    // Wait for a name to be owned
+    #[command(
+        about = "Wait until a name has an owner on the bus (give up after --max-wait or --timeout seconds)"
+    )]
+    WaitForName(commands::ready::WaitForNameArgs),
+
+    #[cfg(feature = "scripting")]
+    // Watch properties change
+    #[command(
+        about = "Print the changes to an interface's properties, or wait until an expression over them holds"
+    )]
+    Watch(commands::watch::WatchArgs),
+
+    #[cfg(feature = "webhook")]
+    // Forward signals to an HTTP endpoint
+    #[command(
+        about = "POST every matching signal as JSON to an HTTP endpoint, with retries and a queue on disk"
+    )]
+    Webhook(commands::webhook::WebhookArgs),
+
+    #[cfg(feature = "inspect")]
+    // Check a method call against introspection data
+    #[command(
+        about = "Check that a method call matches the service's introspection data, without sending it"
+    )]
+    Verify(commands::verify::VerifyArgs),
+
+    #[cfg(feature = "inspect")]
+    // Check the zbusctl calls in shell scripts
+    #[command(about = "Check every zbusctl call in shell scripts, as `verify` would")]
+    LintScript(commands::lint::LintScriptArgs),
+
+    // Make zbusctl available to other programs over the bus
+    #[cfg(feature = "agent")]
+    #[command(
+        about = "Serve dev.zbusctl.Agent, so other programs can make calls through zbusctl over the bus"
+    )]
+    Agent(commands::agent::AgentArgs),
+
+    #[cfg(feature = "scripting")]
+    // Save and run parameterized command lines
+    #[command(about = "Save command lines with placeholders as named templates, and run them")]
+    Template(commands::template::TemplateArgs),
+
+    // Run a command on a private session bus
+    #[cfg(feature = "testbus")]
+    #[command(about = "Run a command on a private session bus that is torn down when it exits")]
+    Testbus(commands::testbus::TestbusArgs),
+
+    // Generate man pages and shell completions
+    #[cfg(feature = "dist")]
+    #[command(about = "Write man pages and shell completion scripts for packaging")]
+    DistAssets(commands::dist::DistAssetsArgs),
 }
 
-#[derive(Args)]
-struct CallArgs {
-    #[arg(long, help = "Use system bus instead of session bus")]
-    system: bool,
+#[cfg(feature = "capture")]
+impl Commands {
+    // Whether the command can write messages in the layout of `dbus-monitor`.
+    // Others are refused that format before they start rather than writing
+    // JSON in its place. A template is checked once it has been expanded.
+    fn writes_dbus_monitor(&self) -> bool {
+        match self {
+            Commands::Monitor(_) => true,
+            #[cfg(feature = "scripting")]
+            Commands::Template(_) => true,
+            _ => false,
+        }
+    }
 
-    #[arg(short, long, help = "D-Bus service name")]
-    service: String,
+    // Whether the command sandboxes itself, which it can only do while it
+    // runs on a single thread.
+    fn sandboxed(&self) -> bool {
+        matches!(self, Commands::Monitor(args) if args.sandboxed())
+    }
+}
 
-    #[arg(short, long, help = "D-Bus object path")]
-    object: String,
+fn main() -> Result<()> {
+    let project = ProjectConfig::discover()?
+        .map(|(_, config)| config)
+        .unwrap_or_default();
+    let args = std::env::args_os().collect::<Vec<_>>();
+    if help_json::requested(&args) {
+        // Pretty at a terminal and compact in pipes, before the global
+        // options that would say otherwise have been parsed.
+        let description = help_json::describe(&cli());
+        if std::io::stdout().is_terminal() {
+            println!("{:#}", description);
+        } else {
+            println!("{}", description);
+        }
+        return Ok(());
+    }
+    let args = parse_args(args, &project).unwrap_or_else(|e| e.exit());
+    #[cfg(feature = "capture")]
+    let single_threaded = args.command.sandboxed();
+    #[cfg(not(feature = "capture"))]
+    let single_threaded = false;
+    let mut runtime = match single_threaded {
+        true => tokio::runtime::Builder::new_current_thread(),
+        false => tokio::runtime::Builder::new_multi_thread(),
+    };
+    runtime
+        .enable_all()
+        .build()
+        .map_err(|e| zbus::Error::Failure(format!("Failed to start the runtime: {}", e)))?
+        .block_on(run(args))
+}
 
-    #[arg(short, long, help = "D-Bus interface name")]
-    interface: String,
+// The command-line interface: the derived commands plus the options handled
+// before parsing.
+fn cli() -> clap::Command {
+    help_json::with_flag(ZBusCtl::command())
+}
 
-    #[arg(short, long, help = "D-Bus method name")]
-    method: String,
+// Parse a command line with the project configuration applied: its aliases
+// expanded, its bus used unless another one was asked for and its redaction
+// rules kept for the command.
+fn parse_args(
+    args: Vec<OsString>,
+    project: &ProjectConfig,
+) -> std::result::Result<ZBusCtl, clap::Error> {
+    let matches = cli().try_get_matches_from(expand_alias(args, &project.aliases))?;
+    let mut args = ZBusCtl::from_arg_matches(&matches)?;
+    args.global.redact = project.redact.clone();
+    args.global.given = given_globals(&matches);
+    if let Some(ref bus) = project.bus
+        && matches.value_source("bus") == Some(ValueSource::DefaultValue)
+    {
+        args.global.bus = Bus::from_str(bus, true).map_err(|e| {
+            cli().error(
+                clap::error::ErrorKind::InvalidValue,
+                format!("invalid bus '{}' in the project configuration: {}", bus, e),
+            )
+        })?;
+    }
+    Ok(args)
+}
 
-    #[arg(help = "D-Bus method arguments")]
-    args: Option<Vec<String>>,
+// Parse the command line a template expands to. The global options the
+// template was applied with are kept, so `--output yaml template apply NAME`
+// prints YAML, except those the template gives itself, which it may rely on.
+#[cfg(feature = "scripting")]
+fn parse_template_args(
+    words: Vec<String>,
+    outer: &GlobalArgs,
+    project: &ProjectConfig,
+) -> std::result::Result<ZBusCtl, clap::Error> {
+    let program = || std::iter::once(OsString::from("zbusctl"));
+    let own = cli().try_get_matches_from(expand_alias(
+        program().chain(words.iter().map(OsString::from)).collect(),
+        &project.aliases,
+    ))?;
+    let own = given_globals(&own)
+        .into_iter()
+        .map(|(id, _)| id)
+        .collect::<std::collections::HashSet<_>>();
+    let kept = outer
+        .given
+        .iter()
+        .filter(|(id, _)| !own.contains(id))
+        .flat_map(|(_, words)| words.iter().cloned());
+    parse_args(
+        program()
+            .chain(kept)
+            .chain(words.into_iter().map(OsString::from))
+            .collect(),
+        project,
+    )
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let args = ZBusCtl::parse();
+// The global options set on a parsed command line, each with the words that
+// set it again.
+fn given_globals(matches: &ArgMatches) -> Vec<(String, Vec<OsString>)> {
+    cli()
+        .get_arguments()
+        .filter(|arg| arg.is_global_set())
+        .filter(|arg| matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine))
+        .filter_map(|arg| {
+            let id = arg.get_id().as_str();
+            let long = OsString::from(format!("--{}", arg.get_long()?));
+            let words = match arg.get_action().takes_values() {
+                true => matches
+                    .get_raw(id)
+                    .into_iter()
+                    .flatten()
+                    .flat_map(|value| [long.clone(), value.to_os_string()])
+                    .collect(),
+                false => vec![long],
+            };
+            Some((id.to_string(), words))
+        })
+        .collect()
+}
+
+// Replace a project alias with the command line it stands for. Aliases are
+// only looked for where the subcommand goes, after the global options, and
+// cannot hide built-in commands.
+fn expand_alias(args: Vec<OsString>, aliases: &BTreeMap<String, Vec<String>>) -> Vec<OsString> {
+    let command = ZBusCtl::command();
+    let mut i = 1;
+    while let Some(arg) = args.get(i).and_then(|arg| arg.to_str()) {
+        if arg == "--" || !arg.starts_with('-') {
+            break;
+        }
+        let takes_value = arg
+            .strip_prefix("--")
+            .filter(|long| !long.contains('='))
+            .and_then(|long| command.get_arguments().find(|a| a.get_long() == Some(long)))
+            .is_some_and(|a| a.get_action().takes_values());
+        i += if takes_value { 2 } else { 1 };
+    }
+
+    let Some(name) = args.get(i).and_then(|arg| arg.to_str()) else {
+        return args;
+    };
+    if command.find_subcommand(name).is_some() {
+        return args;
+    }
+    let Some(expansion) = aliases.get(name) else {
+        return args;
+    };
+    args[..i]
+        .iter()
+        .cloned()
+        .chain(expansion.iter().map(OsString::from))
+        .chain(args[i + 1..].iter().cloned())
+        .collect()
+}
+
+// Run a parsed command line: the one zbusctl was started with, or one from a
+// template.
+async fn run(args: ZBusCtl) -> Result<()> {
+    let global = &args.global;
+
+    #[cfg(feature = "elevate")]
+    if let Some(user) = global.run_as() {
+        let fail =
+            |e: std::io::Error| zbus::Error::Failure(format!("Failed to run as '{}': {}", user, e));
+        if !elevate::is_user(user).map_err(fail)? {
+            let status = elevate::run_as(user, global.interactive()).map_err(fail)?;
+            std::process::exit(status.code().unwrap_or(1));
+        }
+    }
+
+    #[cfg(feature = "capture")]
+    if global.output == Some(OutputFormat::DbusMonitor) && !args.command.writes_dbus_monitor() {
+        return Err(zbus::Error::Failure(
+            "Only monitor can write --output dbus-monitor".to_string(),
+        ));
+    }
 
     match args.command {
-        Commands::Call(call) => run_call_command(call).await?,
+        Commands::Call(call) => commands::call::run_call_command(global, call).await?,
+        #[cfg(feature = "scripting")]
+        Commands::Render(render) => commands::call::run_render_command(global, render).await?,
+        #[cfg(feature = "queue")]
+        Commands::FlushQueue(flush) => {
+            commands::queue::run_flush_queue_command(global, flush).await?
+        }
+        #[cfg(feature = "scripting")]
+        Commands::Emit(emit) => commands::emit::run_emit_command(global, emit).await?,
+        #[cfg(feature = "scripting")]
+        Commands::Decode(decode) => commands::codec::run_decode_command(global, decode).await?,
+        #[cfg(feature = "scripting")]
+        Commands::Encode(encode) => commands::codec::run_encode_command(global, encode).await?,
+        #[cfg(feature = "inspect")]
+        Commands::ExplainSignature(explain) => {
+            commands::explain::run_explain_signature_command(global, explain).await?
+        }
+        #[cfg(feature = "inspect")]
+        Commands::Stats(stats) => commands::stats::run_stats_command(global, stats).await?,
+        #[cfg(feature = "inspect")]
+        Commands::Names(names) => commands::names::run_names_command(global, names).await?,
+        #[cfg(feature = "inspect")]
+        Commands::Status(status) => commands::status::run_status_command(global, status).await?,
+        Commands::Introspect(introspect) => {
+            commands::introspect::run_introspect_command(global, introspect).await?
+        }
+        #[cfg(feature = "inspect")]
+        Commands::Tree(tree) => commands::tree::run_tree_command(global, tree).await?,
+        Commands::SetProperty(set) => {
+            commands::props::run_set_property_command(global, set).await?
+        }
+        Commands::GetAllProperties(get) => {
+            commands::props::run_get_all_properties_command(global, get).await?
+        }
+        #[cfg(feature = "props")]
+        Commands::PropsDiff(props) => {
+            commands::props::run_props_diff_command(global, props).await?
+        }
+        #[cfg(feature = "capture")]
+        Commands::Monitor(monitor) => {
+            commands::monitor::run_monitor_command(global, monitor).await?
+        }
+        #[cfg(feature = "serve")]
+        Commands::Replay(replay) => commands::replay::run_replay_command(global, replay).await?,
+        #[cfg(feature = "serve")]
+        Commands::ServeProperties(serve) => {
+            commands::serve_properties::run_serve_properties_command(global, serve).await?
+        }
+        #[cfg(feature = "capture")]
+        Commands::CaptureScrub(scrub) => {
+            commands::scrub::run_capture_scrub_command(global, scrub).await?
+        }
+        #[cfg(feature = "inspect")]
+        Commands::ProbeAccess(probe) => {
+            commands::access::run_probe_access_command(global, probe).await?
+        }
+        #[cfg(feature = "inspect")]
+        Commands::Policy(policy) => commands::policy::run_policy_command(global, policy).await?,
+        #[cfg(feature = "inspect")]
+        Commands::Activation(activation) => {
+            commands::activation::run_activation_command(global, activation).await?
+        }
+        #[cfg(feature = "props")]
+        Commands::Props(props) => commands::props::run_props_command(global, props).await?,
+        #[cfg(feature = "scripting")]
+        Commands::WaitUntilReady(ready) => {
+            commands::ready::run_wait_until_ready_command(global, ready).await?
+        }
+        #[cfg(feature = "scripting")]
+        Commands::WaitForName(wait) => {
+            commands::ready::run_wait_for_name_command(global, wait).await?
+        }
+        #[cfg(feature = "scripting")]
+        Commands::Watch(watch) => commands::watch::run_watch_command(global, watch).await?,
+        #[cfg(feature = "webhook")]
+        Commands::Webhook(webhook) => {
+            commands::webhook::run_webhook_command(global, webhook).await?
+        }
+        #[cfg(feature = "inspect")]
+        Commands::Verify(verify) => commands::verify::run_verify_command(global, verify).await?,
+        #[cfg(feature = "inspect")]
+        Commands::LintScript(lint) => commands::lint::run_lint_script_command(global, lint).await?,
+        #[cfg(feature = "agent")]
+        Commands::Agent(agent) => commands::agent::run_agent_command(global, agent).await?,
+        #[cfg(feature = "scripting")]
+        Commands::Template(template) => {
+            commands::template::run_template_command(global, template).await?
+        }
+        #[cfg(feature = "testbus")]
+        Commands::Testbus(testbus) => {
+            commands::testbus::run_testbus_command(global, testbus).await?
+        }
+        #[cfg(feature = "dist")]
+        Commands::DistAssets(dist) => commands::dist::run_dist_assets_command(global, dist).await?,
     }
 
     Ok(())
 }
 
-async fn run_call_command(args: CallArgs) -> Result<()> {
-    // Establish D-Bus connection
-    let connection = if args.system {
-        Connection::system().await?
-    } else {
-        Connection::session().await?
-    };
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::ffi::OsStringExt;
 
-    let body = if let Some(args) = args.args {
-        Some(build_body(args.iter().map(|s| s.as_str()).collect())?)
-    } else {
-        None
-    };
+    #[cfg(feature = "scripting")]
+    fn parse(words: &[&str]) -> ZBusCtl {
+        parse_args(
+            words.iter().map(OsString::from).collect(),
+            &ProjectConfig::default(),
+        )
+        .unwrap()
+    }
+
+    fn words(words: &[&str]) -> Vec<String> {
+        words.iter().map(|word| word.to_string()).collect()
+    }
 
-    // Make the D-Bus method call
-    let result = match body {
-        Some(ref body) => {
-            connection
-                .call_method(
-                    Some(args.service.as_str()),
-                    args.object.as_str(),
-                    Some(args.interface.as_str()),
-                    args.method.as_str(),
-                    body,
-                )
-                .await?
-        }
-        None => {
-            connection
-                .call_method(
-                    Some(args.service.as_str()),
-                    args.object.as_str(),
-                    Some(args.interface.as_str()),
-                    args.method.as_str(),
-                    &(),
-                )
-                .await?
+    #[cfg(all(feature = "inspect", feature = "scripting", feature = "yaml"))]
+    #[test]
+    fn test_template_keeps_global_options() {
+        let project = ProjectConfig::default();
+        for outer in [
+            parse(&[
+                "zbusctl", "--output", "yaml", "--system", "template", "list",
+            ]),
+            parse(&["zbusctl", "template", "list", "--output=yaml", "--system"]),
+        ] {
+            let args = parse_template_args(words(&["names"]), &outer.global, &project).unwrap();
+            assert_eq!(
+                args.global.output,
+                Some(OutputFormat::Yaml),
+                "The outer --output should survive"
+            );
+            assert_eq!(args.global.bus(), Bus::System);
         }
-    };
 
-    // Unpack the result body.
-    let result_body = result.body().clone();
-    let response = result_body.deserialize::<Structure>()?;
+        let outer = parse(&["zbusctl", "--output", "yaml", "template", "list"]);
+        let args = parse_template_args(
+            words(&["--output", "json", "names"]),
+            &outer.global,
+            &project,
+        )
+        .unwrap();
+        assert_eq!(
+            args.global.output,
+            Some(OutputFormat::Json),
+            "Options the template gives should be its own"
+        );
+    }
 
-    // Convert the response to a JSON object.
-    let response_json = serde_json::to_value(&response.fields()[0])
-        .map_err(|e| zbus::Error::Failure(format!("Failed to convert response to JSON: {}", e)))?;
+    #[cfg(feature = "scripting")]
+    #[test]
+    fn test_wait_for_name_timeout() {
+        let wait = |words: &[&str]| match parse(words) {
+            ZBusCtl {
+                global,
+                command: Commands::WaitForName(wait),
+            } => wait.max_wait(&global).unwrap(),
+            _ => panic!("Expected wait-for-name"),
+        };
+        assert_eq!(
+            wait(&[
+                "zbusctl",
+                "wait-for-name",
+                "org.example.Foo",
+                "--timeout",
+                "30"
+            ]),
+            Some(Duration::from_secs(30)),
+            "--timeout should limit the wait"
+        );
+        assert_eq!(
+            wait(&[
+                "zbusctl",
+                "--timeout",
+                "5",
+                "wait-for-name",
+                "org.example.Foo",
+                "--max-wait",
+                "30"
+            ]),
+            Some(Duration::from_secs(30)),
+            "--max-wait should take precedence over --timeout"
+        );
+        assert_eq!(wait(&["zbusctl", "wait-for-name", "org.example.Foo"]), None);
+    }
 
-    // Display the result
-    println!("{}", response_json);
+    #[cfg(feature = "capture")]
+    #[tokio::test]
+    async fn test_dbus_monitor_output_refused() {
+        let args = parse_args(
+            os_words(&[
+                "zbusctl",
+                "--output",
+                "dbus-monitor",
+                "call",
+                "-s",
+                "org.example.Foo",
+                "-o",
+                "/",
+                "-i",
+                "org.example.Foo",
+                "-m",
+                "Bar",
+            ]),
+            &ProjectConfig::default(),
+        )
+        .unwrap();
+        let error = run(args).await.unwrap_err();
+        assert!(
+            error.to_string().contains("Only monitor"),
+            "Unexpected error: {}",
+            error
+        );
+    }
 
-    Ok(())
+    fn expand(words: &[&str], aliases: &BTreeMap<String, Vec<String>>) -> Vec<OsString> {
+        expand_alias(words.iter().map(OsString::from).collect(), aliases)
+    }
+
+    fn os_words(words: &[&str]) -> Vec<OsString> {
+        words.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn test_expand_alias() {
+        let aliases = BTreeMap::from([
+            ("echo".to_string(), words(&["call", "-m", "Echo"])),
+            ("introspect".to_string(), words(&["status"])),
+            ("first".to_string(), words(&["second", "--verbose"])),
+            ("second".to_string(), words(&["first"])),
+        ]);
+
+        assert_eq!(
+            expand(
+                &["zbusctl", "--output", "json", "echo", "string:hi"],
+                &aliases
+            ),
+            os_words(&[
+                "zbusctl",
+                "--output",
+                "json",
+                "call",
+                "-m",
+                "Echo",
+                "string:hi"
+            ]),
+            "Aliases should be found after global options and their values"
+        );
+        assert_eq!(
+            expand(&["zbusctl", "--output=json", "echo"], &aliases),
+            os_words(&["zbusctl", "--output=json", "call", "-m", "Echo"])
+        );
+        assert_eq!(
+            expand(&["zbusctl", "introspect"], &aliases),
+            os_words(&["zbusctl", "introspect"]),
+            "Aliases should not hide built-in commands"
+        );
+        assert_eq!(
+            expand(&["zbusctl", "first", "x"], &aliases),
+            os_words(&["zbusctl", "second", "--verbose", "x"]),
+            "Aliases should be expanded once, not into other aliases"
+        );
+    }
+
+    #[test]
+    fn test_expand_alias_leaves_other_input() {
+        let aliases = BTreeMap::from([("echo".to_string(), words(&["call", "-m", "Echo"]))]);
+        for args in [
+            &["zbusctl"][..],
+            &["zbusctl", "--output"],
+            &["zbusctl", "--", "echo"],
+            &["zbusctl", "unknown", "echo"],
+            &["zbusctl", "call", "echo"],
+        ] {
+            assert_eq!(expand(args, &aliases), os_words(args));
+        }
+
+        let args = vec![
+            OsString::from("zbusctl"),
+            OsString::from_vec(vec![0xff, 0xfe]),
+            OsString::from("echo"),
+        ];
+        assert_eq!(
+            expand_alias(args.clone(), &aliases),
+            args,
+            "Arguments that are not UTF-8 should stop the search"
+        );
+    }
 }
@@ -0,0 +1,94 @@
+use std::fs;
+use std::path::Path;
+use zbus::Result;
+use zbus::message::{EndianSig, Message};
+use zvariant::Endian;
+use zvariant::serialized::{Context, Data};
+
+// Size of the fixed part of a message header, up to and including the length
+// of the header field array.
+const FIXED_HEADER_SIZE: usize = 16;
+
+// Write a complete message, header and body, to a file in wire format.
+pub fn save_message(path: &Path, msg: &Message) -> Result<()> {
+    fs::write(path, msg.data().bytes())
+        .map_err(|e| zbus::Error::Failure(format!("Failed to write '{}': {}", path.display(), e)))
+}
+
+// Read a message previously written by `save_message`. File descriptors are
+// not part of the wire format, so messages that carried any can be rendered
+// but the descriptors themselves are gone.
+pub fn load_message(path: &Path) -> Result<Message> {
+    let bytes = fs::read(path)
+        .map_err(|e| zbus::Error::Failure(format!("Failed to read '{}': {}", path.display(), e)))?;
+    message_from_bytes(bytes).map_err(|e| {
+        zbus::Error::Failure(format!("Invalid message in '{}': {}", path.display(), e))
+    })
+}
+
+// Parse a message from its wire format, checking that the lengths recorded in
+// the header match the data before handing it to zbus.
+pub fn message_from_bytes(bytes: Vec<u8>) -> Result<Message> {
+    if bytes.len() < FIXED_HEADER_SIZE {
+        return Err(zbus::Error::Failure(format!(
+            "message is {} bytes, shorter than a message header",
+            bytes.len()
+        )));
+    }
+
+    let endian = match EndianSig::try_from(bytes[0])? {
+        EndianSig::Big => Endian::Big,
+        EndianSig::Little => Endian::Little,
+    };
+    let body_len = endian.read_u32(&bytes[4..8]) as usize;
+    let fields_len = endian.read_u32(&bytes[12..16]) as usize;
+    let header_len = (FIXED_HEADER_SIZE + fields_len).next_multiple_of(8);
+    if header_len + body_len != bytes.len() {
+        return Err(zbus::Error::Failure(format!(
+            "message is {} bytes, but its header describes {} bytes",
+            bytes.len(),
+            header_len + body_len
+        )));
+    }
+
+    let data = Data::new(bytes, Context::new_dbus(endian, 0));
+    // SAFETY: the header lengths were checked against the data above; zbus
+    // validates the header fields and body while parsing them.
+    unsafe { Message::from_bytes(data) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_round_trip() {
+        let msg = Message::method_call("/org/example/Object", "Frobnicate")
+            .unwrap()
+            .destination("org.example.Service")
+            .unwrap()
+            .build(&("hello", 42u32))
+            .unwrap();
+
+        let parsed = message_from_bytes(msg.data().bytes().to_vec()).unwrap();
+        assert_eq!(parsed.header().member().unwrap().as_str(), "Frobnicate");
+        let (s, n): (String, u32) = parsed.body().deserialize().unwrap();
+        assert_eq!(s, "hello");
+        assert_eq!(n, 42);
+    }
+
+    #[test]
+    fn test_message_truncated() {
+        let msg = Message::method_call("/org/example/Object", "Frobnicate")
+            .unwrap()
+            .build(&("hello",))
+            .unwrap();
+        let mut bytes = msg.data().bytes().to_vec();
+        bytes.truncate(bytes.len() - 1);
+
+        assert!(
+            message_from_bytes(bytes).is_err(),
+            "Truncated message should fail"
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use std::fs::{self, File};
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixStream};
+use std::path::PathBuf;
+
+// Where a bus listens, as far as zbusctl can reach it inside namespaces: a
+// socket file or an abstract socket, the two kinds Unix bus addresses name.
+#[derive(Debug, PartialEq, Eq)]
+enum Socket {
+    Path(PathBuf),
+    Abstract(Vec<u8>),
+}
+
+// Parse the first usable entry of a D-Bus address such as
+// `unix:path=/run/dbus/system_bus_socket` or `unix:abstract=/tmp/dbus-x,guid=...`.
+fn parse_address(address: &str) -> Option<Socket> {
+    address.split(';').find_map(|entry| {
+        let options = entry.strip_prefix("unix:")?;
+        options.split(',').find_map(|option| {
+            let (key, value) = option.split_once('=')?;
+            let value = unescape(value);
+            match key {
+                "path" => Some(Socket::Path(PathBuf::from(
+                    String::from_utf8_lossy(&value).into_owned(),
+                ))),
+                "abstract" => Some(Socket::Abstract(value)),
+                _ => None,
+            }
+        })
+    })
+}
+
+// Undo the %XX escaping of D-Bus address values.
+fn unescape(value: &str) -> Vec<u8> {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+// A variable from the environment of process `pid`.
+fn process_env(pid: u32, name: &str) -> Option<String> {
+    let environ = fs::read(format!("/proc/{}/environ", pid)).ok()?;
+    environ.split(|&b| b == 0).find_map(|entry| {
+        let entry = std::str::from_utf8(entry).ok()?;
+        entry
+            .strip_prefix(name)?
+            .strip_prefix('=')
+            .map(str::to_string)
+    })
+}
+
+// The address of a bus as a process inside the namespaces sees it: from its
+// own environment, then from zbusctl's, then the well-known system socket.
+fn bus_address(pid: Option<u32>, system: bool) -> io::Result<String> {
+    let variable = if system {
+        "DBUS_SYSTEM_BUS_ADDRESS"
+    } else {
+        "DBUS_SESSION_BUS_ADDRESS"
+    };
+    pid.and_then(|pid| process_env(pid, variable))
+        .or_else(|| std::env::var(variable).ok())
+        .or_else(|| system.then(|| "unix:path=/var/run/dbus/system_bus_socket".to_string()))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{} is not set", variable)))
+}
+
+fn setns(file: &File, kind: libc::c_int) -> io::Result<()> {
+    // SAFETY: setns only reads the descriptor, which `file` keeps open.
+    if unsafe { libc::setns(file.as_raw_fd(), kind) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// Run `f` in the network namespace of process `pid`, on a thread of its own
+// that exits afterwards. No thread is moved back, so none, such as a tokio
+// worker, can be left in the namespace when moving back fails.
+fn in_netns<T: Send + 'static>(
+    pid: u32,
+    f: impl FnOnce() -> io::Result<T> + Send + 'static,
+) -> io::Result<T> {
+    let target = File::open(format!("/proc/{}/ns/net", pid))?;
+    std::thread::spawn(move || {
+        setns(&target, libc::CLONE_NEWNET)?;
+        f()
+    })
+    .join()
+    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+}
+
+// Open a connection to a bus inside other namespaces: the network namespace
+// of process `netns`, which holds abstract sockets, and the filesystem of
+// process `mntns`, reached through /proc/PID/root without entering its mount
+// namespace. The bus address is looked up in the environment of whichever
+// process is given.
+pub fn connect(netns: Option<u32>, mntns: Option<u32>, system: bool) -> io::Result<UnixStream> {
+    let address = bus_address(mntns.or(netns), system)?;
+    let socket = parse_address(&address).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "unsupported bus address '{}': expected a unix path or abstract socket",
+                address
+            ),
+        )
+    })?;
+    let open = move || match socket {
+        Socket::Path(ref path) => match mntns {
+            Some(pid) => {
+                let relative = path.strip_prefix("/").unwrap_or(path);
+                UnixStream::connect(PathBuf::from(format!("/proc/{}/root", pid)).join(relative))
+            }
+            None => UnixStream::connect(path),
+        },
+        Socket::Abstract(ref name) => {
+            UnixStream::connect_addr(&SocketAddr::from_abstract_name(name)?)
+        }
+    };
+    match netns {
+        Some(pid) => in_netns(pid, open),
+        None => open(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_address() {
+        assert_eq!(
+            parse_address("unix:path=/run/dbus/system_bus_socket"),
+            Some(Socket::Path(PathBuf::from("/run/dbus/system_bus_socket")))
+        );
+        assert_eq!(
+            parse_address("unix:abstract=/tmp/dbus-x,guid=0123"),
+            Some(Socket::Abstract(b"/tmp/dbus-x".to_vec()))
+        );
+        assert_eq!(
+            parse_address("unix:guid=0123,path=/tmp/my%20bus%2csocket"),
+            Some(Socket::Path(PathBuf::from("/tmp/my bus,socket"))),
+            "Escaped values should be unescaped, wherever the key is"
+        );
+        assert_eq!(
+            parse_address("tcp:host=localhost,port=1234;unix:abstract=%00x"),
+            Some(Socket::Abstract(vec![0, b'x'])),
+            "The first usable entry should be taken"
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_address() {
+        for address in [
+            "",
+            "unix:",
+            "unix:path",
+            "unix:tmpdir=/tmp",
+            "tcp:host=localhost,port=1234",
+            "path=/run/dbus/system_bus_socket",
+        ] {
+            assert_eq!(parse_address(address), None, "{:?}", address);
+        }
+    }
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!(unescape("/tmp/dbus-x"), b"/tmp/dbus-x");
+        assert_eq!(unescape("a%2Cb%2cc%25"), b"a,b,c%");
+        assert_eq!(unescape("%ff%00"), [0xff, 0]);
+        // Incomplete or invalid escapes are kept as they are.
+        assert_eq!(unescape("%zz%4"), b"%zz%4");
+        assert_eq!(unescape("%"), b"%");
+        assert_eq!(unescape("%é"), "%é".as_bytes());
+    }
+}
@@ -0,0 +1,602 @@
+use serde::ser::{
+    self, Serialize, SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant,
+    SerializeTuple, SerializeTupleStruct, SerializeTupleVariant, Serializer,
+};
+use std::str::FromStr;
+use zvariant::Signature;
+
+/// What to write for doubles JSON cannot represent: NaN and the infinities.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum NonFinite {
+    /// `null`, as serde_json writes them.
+    #[default]
+    Null,
+    /// The strings `"NaN"`, `"Infinity"` and `"-Infinity"`, as JavaScript
+    /// and Python spell them.
+    String,
+    /// Fail instead of writing something that is not the value.
+    Error,
+}
+
+impl NonFinite {
+    pub(crate) fn name(value: f64) -> &'static str {
+        if value.is_nan() {
+            "NaN"
+        } else if value > 0.0 {
+            "Infinity"
+        } else {
+            "-Infinity"
+        }
+    }
+
+    // The double a name written by the `String` policy stands for.
+    pub(crate) fn parse(name: &str) -> Option<f64> {
+        match name {
+            "NaN" => Some(f64::NAN),
+            "Infinity" => Some(f64::INFINITY),
+            "-Infinity" => Some(f64::NEG_INFINITY),
+            _ => None,
+        }
+    }
+
+    // What to write for a double JSON cannot represent.
+    fn to_json(self, value: f64) -> zbus::Result<serde_json::Value> {
+        match self {
+            NonFinite::Null => Ok(serde_json::Value::Null),
+            NonFinite::String => Ok(Self::name(value).into()),
+            NonFinite::Error => Err(zbus::Error::Failure(Self::error(value))),
+        }
+    }
+
+    fn error(value: f64) -> String {
+        format!(
+            "{} cannot be written as JSON; pass --non-finite null or --non-finite string",
+            Self::name(value)
+        )
+    }
+}
+
+/// How numbers that JSON consumers may not read back exactly are written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Numbers {
+    pub non_finite: NonFinite,
+    /// Write 64-bit integers as strings, since JavaScript and other
+    /// consumers that read every number as a double round those beyond 2^53.
+    pub int64_as_string: bool,
+}
+
+impl Numbers {
+    // Write a double as the policy says.
+    pub(crate) fn double(&self, value: f64) -> zbus::Result<serde_json::Value> {
+        match serde_json::Number::from_f64(value) {
+            Some(number) => Ok(number.into()),
+            None => self.non_finite.to_json(value),
+        }
+    }
+
+    // Apply the policy to a message body in the form `format::body_to_json`
+    // writes it: a list of `{"signature": ..., "value": ...}` fields. Only
+    // the 64-bit integers and the doubles the signatures name are changed;
+    // doubles JSON cannot represent are held there by name.
+    pub fn apply_to_body(&self, body: &mut serde_json::Value) -> zbus::Result<()> {
+        if let Some(fields) = body.as_array_mut() {
+            for field in fields {
+                self.apply_to_typed(field)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_to_typed(&self, field: &mut serde_json::Value) -> zbus::Result<()> {
+        let Some(signature) = field["signature"]
+            .as_str()
+            .and_then(|s| Signature::from_str(s).ok())
+        else {
+            return Ok(());
+        };
+        self.apply(&signature, &mut field["value"])
+    }
+
+    fn apply(&self, signature: &Signature, json: &mut serde_json::Value) -> zbus::Result<()> {
+        use serde_json::Value as Json;
+
+        match (signature, json) {
+            (Signature::U64 | Signature::I64, json @ Json::Number(_)) if self.int64_as_string => {
+                *json = Json::from(json.to_string());
+            }
+            (Signature::F64, json @ Json::String(_)) => {
+                if let Some(value) = json.as_str().and_then(NonFinite::parse) {
+                    *json = self.non_finite.to_json(value)?;
+                }
+            }
+            (Signature::Variant, json) => self.apply_to_typed(json)?,
+            (Signature::Array(element), Json::Array(items)) => {
+                for item in items {
+                    self.apply(element, item)?;
+                }
+            }
+            (Signature::Dict { value, .. }, Json::Object(entries)) => {
+                for (_, entry) in entries.iter_mut() {
+                    self.apply(value, entry)?;
+                }
+            }
+            (Signature::Structure(fields), Json::Array(items)) => {
+                for (field, item) in fields.iter().zip(items) {
+                    self.apply(field, item)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// A value to serialize with its numbers written as the policy says,
+/// wherever they are inside it. Only for `zvariant` values and what holds
+/// them: they serialize each integer as its own type, while JSON that has
+/// already been built holds every integer as a 64-bit one.
+pub struct WithNumbers<'a, T: ?Sized> {
+    pub value: &'a T,
+    pub policy: Numbers,
+}
+
+impl<T: Serialize + ?Sized> Serialize for WithNumbers<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.value.serialize(Wrap {
+            inner: serializer,
+            policy: self.policy,
+        })
+    }
+}
+
+// A serializer, or one of its compound serializers, that hands everything on
+// to the one it wraps except the numbers the policy is about.
+struct Wrap<S> {
+    inner: S,
+    policy: Numbers,
+}
+
+impl<S> Wrap<S> {
+    fn wrap<'a, T: ?Sized>(&self, value: &'a T) -> WithNumbers<'a, T> {
+        WithNumbers {
+            value,
+            policy: self.policy,
+        }
+    }
+}
+
+macro_rules! forward {
+    ($($method:ident($ty:ty)),* $(,)?) => {
+        $(
+            fn $method(self, v: $ty) -> Result<S::Ok, S::Error> {
+                self.inner.$method(v)
+            }
+        )*
+    };
+}
+
+impl<S: Serializer> Wrap<S> {
+    fn integer<I>(self, v: I) -> Result<S::Ok, S::Error>
+    where
+        I: Serialize + std::fmt::Display,
+    {
+        if self.policy.int64_as_string {
+            self.inner.serialize_str(&v.to_string())
+        } else {
+            v.serialize(self.inner)
+        }
+    }
+}
+
+impl<S: Serializer> Serializer for Wrap<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+    type SerializeSeq = Wrap<S::SerializeSeq>;
+    type SerializeTuple = Wrap<S::SerializeTuple>;
+    type SerializeTupleStruct = Wrap<S::SerializeTupleStruct>;
+    type SerializeTupleVariant = Wrap<S::SerializeTupleVariant>;
+    type SerializeMap = Wrap<S::SerializeMap>;
+    type SerializeStruct = Wrap<S::SerializeStruct>;
+    type SerializeStructVariant = Wrap<S::SerializeStructVariant>;
+
+    forward!(
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_char(char),
+        serialize_str(&str),
+        serialize_bytes(&[u8]),
+        serialize_unit_struct(&'static str),
+    );
+
+    fn serialize_i64(self, v: i64) -> Result<S::Ok, S::Error> {
+        self.integer(v)
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<S::Ok, S::Error> {
+        self.integer(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<S::Ok, S::Error> {
+        self.integer(v)
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<S::Ok, S::Error> {
+        self.integer(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<S::Ok, S::Error> {
+        self.serialize_f64(v as f64)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<S::Ok, S::Error> {
+        if v.is_finite() {
+            return self.inner.serialize_f64(v);
+        }
+        match self.policy.non_finite {
+            NonFinite::Null => self.inner.serialize_f64(v),
+            NonFinite::String => self.inner.serialize_str(NonFinite::name(v)),
+            NonFinite::Error => Err(ser::Error::custom(NonFinite::error(v))),
+        }
+    }
+
+    fn serialize_none(self) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_none()
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<S::Ok, S::Error> {
+        let value = self.wrap(value);
+        self.inner.serialize_some(&value)
+    }
+
+    fn serialize_unit(self) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+    ) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_unit_variant(name, index, variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        let value = self.wrap(value);
+        self.inner.serialize_newtype_struct(name, &value)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        let value = self.wrap(value);
+        self.inner
+            .serialize_newtype_variant(name, index, variant, &value)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, S::Error> {
+        let policy = self.policy;
+        let inner = self.inner.serialize_seq(len)?;
+        Ok(Wrap { inner, policy })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, S::Error> {
+        let policy = self.policy;
+        let inner = self.inner.serialize_tuple(len)?;
+        Ok(Wrap { inner, policy })
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, S::Error> {
+        let policy = self.policy;
+        let inner = self.inner.serialize_tuple_struct(name, len)?;
+        Ok(Wrap { inner, policy })
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, S::Error> {
+        let policy = self.policy;
+        let inner = self
+            .inner
+            .serialize_tuple_variant(name, index, variant, len)?;
+        Ok(Wrap { inner, policy })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, S::Error> {
+        let policy = self.policy;
+        let inner = self.inner.serialize_map(len)?;
+        Ok(Wrap { inner, policy })
+    }
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, S::Error> {
+        let policy = self.policy;
+        let inner = self.inner.serialize_struct(name, len)?;
+        Ok(Wrap { inner, policy })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant, S::Error> {
+        let policy = self.policy;
+        let inner = self
+            .inner
+            .serialize_struct_variant(name, index, variant, len)?;
+        Ok(Wrap { inner, policy })
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+}
+
+impl<S: SerializeSeq> SerializeSeq for Wrap<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), S::Error> {
+        let value = self.wrap(value);
+        self.inner.serialize_element(&value)
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.inner.end()
+    }
+}
+
+impl<S: SerializeTuple> SerializeTuple for Wrap<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), S::Error> {
+        let value = self.wrap(value);
+        self.inner.serialize_element(&value)
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.inner.end()
+    }
+}
+
+impl<S: SerializeTupleStruct> SerializeTupleStruct for Wrap<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), S::Error> {
+        let value = self.wrap(value);
+        self.inner.serialize_field(&value)
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.inner.end()
+    }
+}
+
+impl<S: SerializeTupleVariant> SerializeTupleVariant for Wrap<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), S::Error> {
+        let value = self.wrap(value);
+        self.inner.serialize_field(&value)
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.inner.end()
+    }
+}
+
+impl<S: SerializeMap> SerializeMap for Wrap<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), S::Error> {
+        let key = self.wrap(key);
+        self.inner.serialize_key(&key)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), S::Error> {
+        let value = self.wrap(value);
+        self.inner.serialize_value(&value)
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.inner.end()
+    }
+}
+
+impl<S: SerializeStruct> SerializeStruct for Wrap<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), S::Error> {
+        let value = self.wrap(value);
+        self.inner.serialize_field(key, &value)
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.inner.end()
+    }
+}
+
+impl<S: SerializeStructVariant> SerializeStructVariant for Wrap<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), S::Error> {
+        let value = self.wrap(value);
+        self.inner.serialize_field(key, &value)
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.inner.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_body;
+    use serde_json::json;
+    use std::collections::HashMap;
+    use zvariant::Value;
+
+    #[test]
+    fn test_non_finite() {
+        let body = build_body(vec!["array:double:NaN,inf,-inf,1.5"]).unwrap();
+        let value = &body.fields()[0];
+        let convert = |non_finite| {
+            let policy = Numbers {
+                non_finite,
+                ..Numbers::default()
+            };
+            serde_json::to_value(WithNumbers { value, policy }).map(|json| json["value"].clone())
+        };
+        assert_eq!(
+            convert(NonFinite::Null).unwrap(),
+            json!([null, null, null, 1.5])
+        );
+        assert_eq!(
+            convert(NonFinite::String).unwrap(),
+            json!(["NaN", "Infinity", "-Infinity", 1.5])
+        );
+        let error = convert(NonFinite::Error).unwrap_err().to_string();
+        assert!(error.contains("NaN cannot be written"), "{}", error);
+    }
+
+    #[test]
+    fn test_int64_as_string() {
+        let body = build_body(vec![
+            "uint64:18446744073709551615",
+            "int64:-9007199254740993",
+            "uint32:7",
+        ])
+        .unwrap();
+        let policy = Numbers {
+            int64_as_string: true,
+            ..Numbers::default()
+        };
+        let json = serde_json::to_value(WithNumbers {
+            value: body.fields(),
+            policy,
+        })
+        .unwrap();
+        assert_eq!(
+            json,
+            json!([
+                { "signature": "t", "value": "18446744073709551615" },
+                { "signature": "x", "value": "-9007199254740993" },
+                { "signature": "u", "value": 7 },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_int64_as_string_by_signature() {
+        let fields = [
+            Value::U64(u64::MAX),
+            Value::from(HashMap::from([
+                ("count", Value::from(-3i64)),
+                ("size", Value::from(7u32)),
+            ])),
+            Value::from(vec![1u32, 2]),
+        ];
+        let policy = Numbers {
+            int64_as_string: true,
+            ..Numbers::default()
+        };
+        let mut json = serde_json::to_value(&fields).unwrap();
+        policy.apply_to_body(&mut json).unwrap();
+        assert_eq!(json[0]["value"], "18446744073709551615");
+        assert_eq!(json[1]["value"]["count"]["value"], "-3");
+        assert_eq!(
+            json[1]["value"]["size"]["value"], 7,
+            "Only 64-bit integers should become strings"
+        );
+        assert_eq!(json[2]["value"], json!([1, 2]));
+
+        let plain = crate::format::value_to_json(&fields[1], policy).unwrap();
+        assert_eq!(plain, json!({ "count": "-3", "size": 7 }));
+        let parsed = crate::format::typed_json_to_value(&json[0]).unwrap();
+        assert_eq!(
+            parsed, fields[0],
+            "Integers written as strings should read back"
+        );
+    }
+
+    #[test]
+    fn test_non_finite_in_bodies() {
+        let msg = zbus::message::Message::signal("/org/example", "org.example.Test", "Read")
+            .unwrap()
+            .build(&(f64::NAN, Value::from(f64::NEG_INFINITY), 0.5))
+            .unwrap();
+        let body = crate::format::body_to_json(&msg.body());
+        assert_eq!(body[0]["value"], "NaN");
+        let parsed = crate::format::typed_json_to_value(&body[1]).unwrap();
+        assert_eq!(
+            parsed,
+            Value::Value(Box::new(Value::F64(f64::NEG_INFINITY))),
+            "Bodies should keep doubles JSON cannot represent"
+        );
+
+        let apply = |non_finite| {
+            let mut body = body.clone();
+            Numbers {
+                non_finite,
+                ..Numbers::default()
+            }
+            .apply_to_body(&mut body)
+            .map(|_| body)
+        };
+        let nulls = apply(NonFinite::Null).unwrap();
+        assert_eq!(nulls[0]["value"], json!(null));
+        assert_eq!(nulls[1]["value"]["value"], json!(null));
+        assert_eq!(nulls[2]["value"], 0.5);
+        let names = apply(NonFinite::String).unwrap();
+        assert_eq!(names[1]["value"]["value"], "-Infinity");
+        let error = apply(NonFinite::Error).unwrap_err().to_string();
+        assert!(error.contains("NaN cannot be written"), "{}", error);
+
+        let policy = Numbers {
+            non_finite: NonFinite::Error,
+            ..Numbers::default()
+        };
+        assert!(crate::format::value_to_json(&Value::F64(f64::INFINITY), policy).is_err());
+    }
+}
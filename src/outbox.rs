@@ -0,0 +1,156 @@
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use zbus::Result;
+
+/// A spool directory of payloads waiting to be delivered, such as signals
+/// for `webhook` to post, one file each, named so they sort in the order
+/// they were added. Payloads outlive the process that added them, so those
+/// not yet delivered when it stops are delivered by the next one. At most
+/// `capacity` are kept, the oldest making way for new ones, so a destination
+/// that stays unreachable cannot fill the disk. The files may hold what the
+/// payloads say about the bus, so only their owner may read them. One
+/// process at a time should deliver from a spool.
+pub struct Outbox {
+    dir: PathBuf,
+    capacity: usize,
+}
+
+// Tells apart the payloads added by one process in the same nanosecond.
+static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+impl Outbox {
+    pub fn new<P: Into<PathBuf>>(dir: P, capacity: usize) -> Self {
+        Self {
+            dir: dir.into(),
+            capacity,
+        }
+    }
+
+    // Add a payload, returning how many of the oldest were dropped to make
+    // room for it. The file is written under a temporary name first, so it
+    // is never delivered half written.
+    pub fn push(&self, payload: &str) -> Result<usize> {
+        let failed = |path: &Path, e: std::io::Error| {
+            zbus::Error::Failure(format!("Failed to write '{}': {}", path.display(), e))
+        };
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&self.dir)
+            .map_err(|e| failed(&self.dir, e))?;
+
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        let name = format!(
+            "{:020}-{}-{}",
+            nanos,
+            std::process::id(),
+            SEQUENCE.fetch_add(1, Ordering::Relaxed)
+        );
+        let temporary = self.dir.join(format!(".{}.tmp", name));
+        let path = self.dir.join(format!("{}.json", name));
+        fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&temporary)
+            .and_then(|mut file| file.write_all(payload.as_bytes()))
+            .map_err(|e| failed(&temporary, e))?;
+        fs::rename(&temporary, &path).map_err(|e| failed(&path, e))?;
+
+        let paths = self.paths()?;
+        let excess = paths.len().saturating_sub(self.capacity);
+        for path in &paths[..excess] {
+            self.remove(path)?;
+        }
+        Ok(excess)
+    }
+
+    // The file of the oldest payload, if there is one. A spool that was never
+    // written to is empty.
+    pub fn oldest(&self) -> Result<Option<PathBuf>> {
+        Ok(self.paths()?.into_iter().next())
+    }
+
+    // Take a payload off the spool once it has been delivered, or given up
+    // on. One that was dropped to make room meanwhile is already gone.
+    pub fn remove(&self, path: &Path) -> Result<()> {
+        match fs::remove_file(path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(zbus::Error::Failure(format!(
+                "Failed to remove '{}': {}",
+                path.display(),
+                e
+            ))),
+        }
+    }
+
+    // The files of the payloads, oldest first.
+    fn paths(&self) -> Result<Vec<PathBuf>> {
+        let read_dir = match fs::read_dir(&self.dir) {
+            Ok(read_dir) => read_dir,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(zbus::Error::Failure(format!(
+                    "Failed to read '{}': {}",
+                    self.dir.display(),
+                    e
+                )));
+            }
+        };
+        let mut paths = read_dir
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == "json")
+            })
+            .collect::<Vec<_>>();
+        paths.sort();
+        Ok(paths)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_outbox() {
+        let dir = std::env::temp_dir().join(format!("zbusctl-outbox-{}", std::process::id()));
+        let outbox = Outbox::new(&dir, 2);
+        assert!(
+            outbox.oldest().unwrap().is_none(),
+            "A missing spool should be empty"
+        );
+
+        assert_eq!(outbox.push("1").unwrap(), 0);
+        let first = outbox.oldest().unwrap().unwrap();
+        assert_eq!(
+            fs::metadata(&first).unwrap().permissions().mode() & 0o777,
+            0o600,
+            "Payloads should only be readable by their owner"
+        );
+        assert_eq!(outbox.push("2").unwrap(), 0);
+        assert_eq!(
+            outbox.push("3").unwrap(),
+            1,
+            "The oldest payload should make way for a new one"
+        );
+        let oldest = outbox.oldest().unwrap().unwrap();
+        assert_eq!(fs::read_to_string(&oldest).unwrap(), "2");
+
+        outbox.remove(&oldest).unwrap();
+        outbox.remove(&first).unwrap();
+        let oldest = outbox.oldest().unwrap().unwrap();
+        assert_eq!(fs::read_to_string(&oldest).unwrap(), "3");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
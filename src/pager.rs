@@ -0,0 +1,61 @@
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::process::{Command, Stdio};
+
+// Write command output to stdout, sending it through $PAGER first when stdout
+// is a terminal and the output would not fit on one screen, like git does.
+// Falls back to printing directly if the pager cannot be started.
+pub fn page(output: &str, enabled: bool) -> io::Result<()> {
+    let stdout = io::stdout();
+    if enabled && stdout.is_terminal() && exceeds_terminal(output) && run_pager(output).is_ok() {
+        return Ok(());
+    }
+
+    let mut stdout = stdout.lock();
+    stdout.write_all(output.as_bytes())?;
+    stdout.flush()
+}
+
+// Check whether the output has more lines than the terminal has rows.
+fn exceeds_terminal(output: &str) -> bool {
+    match terminal_rows() {
+        Some(rows) => output.lines().count() >= rows,
+        None => false,
+    }
+}
+
+fn terminal_rows() -> Option<usize> {
+    let mut size = libc::winsize {
+        ws_row: 0,
+        ws_col: 0,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    // SAFETY: TIOCGWINSZ only writes a `winsize` into the pointer we pass.
+    let ret = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) };
+    (ret == 0 && size.ws_row > 0).then_some(size.ws_row as usize)
+}
+
+fn run_pager(output: &str) -> io::Result<()> {
+    let pager = env::var("PAGER")
+        .ok()
+        .filter(|pager| !pager.trim().is_empty())
+        .unwrap_or_else(|| "less".to_string());
+
+    // Run through the shell so PAGER can carry its own arguments. Like git,
+    // default LESS to quit on short output and pass colors through.
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(&pager).stdin(Stdio::piped());
+    if env::var_os("LESS").is_none() {
+        command.env("LESS", "FRX");
+    }
+    let mut child = command.spawn()?;
+
+    // The pager owns the terminal from here on; if the user quits it early the
+    // pipe closes, which is not an error worth reporting.
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(output.as_bytes());
+    }
+    let _ = child.wait();
+    Ok(())
+}
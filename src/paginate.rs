@@ -0,0 +1,211 @@
+use std::str::FromStr;
+use zbus::Result;
+use zvariant::{Array, OwnedValue, Signature, Value};
+
+use crate::format::display_value;
+
+/// Where a paged method returns its continuation token and where the next
+/// call takes it, written `REPLY_FIELD=ARG` with zero-based indices.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Paginate {
+    pub token_field: usize,
+    pub token_arg: usize,
+}
+
+impl FromStr for Paginate {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (field, arg) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected REPLY_FIELD=ARG, got '{}'", s))?;
+        let index = |v: &str| {
+            v.parse::<usize>()
+                .map_err(|e| format!("invalid index '{}': {}", v, e))
+        };
+        Ok(Self {
+            token_field: index(field)?,
+            token_arg: index(arg)?,
+        })
+    }
+}
+
+// Whether a continuation token signals that there are no more pages. Services
+// use an empty string or path, zero, false or an empty array for this.
+pub fn is_last_page(token: &Value) -> bool {
+    match token {
+        Value::Str(s) => s.is_empty(),
+        Value::ObjectPath(p) => p.as_str() == "/",
+        Value::Bool(b) => !b,
+        Value::U8(v) => *v == 0,
+        Value::I16(v) => *v == 0,
+        Value::U16(v) => *v == 0,
+        Value::I32(v) => *v == 0,
+        Value::U32(v) => *v == 0,
+        Value::I64(v) => *v == 0,
+        Value::U64(v) => *v == 0,
+        Value::Array(a) => a.is_empty(),
+        Value::Value(v) => is_last_page(v),
+        _ => false,
+    }
+}
+
+// The arguments of the next call: the same as `args`, with the token argument
+// replaced by `token` and its type kept.
+pub fn next_args(args: &[String], token_arg: usize, token: &Value) -> Result<Vec<String>> {
+    let arg = args.get(token_arg).ok_or_else(|| {
+        zbus::Error::Failure(format!(
+            "Cannot paginate: the call has no argument {}",
+            token_arg
+        ))
+    })?;
+    let type_name = arg.split_once(':').map_or(arg.as_str(), |(t, _)| t);
+
+    let mut next = args.to_vec();
+    next[token_arg] = format!("{}:{}", type_name, display_value(token));
+    Ok(next)
+}
+
+/// The reply fields of every page, with array fields concatenated.
+#[derive(Debug, Default)]
+pub struct Pages {
+    token_field: usize,
+    fields: Vec<(Signature, Vec<OwnedValue>)>,
+    last_token: Option<OwnedValue>,
+}
+
+impl Pages {
+    pub fn new(token_field: usize) -> Self {
+        Self {
+            token_field,
+            ..Self::default()
+        }
+    }
+
+    // Add the fields of a reply and return its continuation token, or `None`
+    // after the last page. A service repeating the previous token would make
+    // us loop forever, so that is reported as an error instead.
+    pub fn append(&mut self, fields: &[Value]) -> Result<Option<OwnedValue>> {
+        let token = fields.get(self.token_field).ok_or_else(|| {
+            zbus::Error::Failure(format!(
+                "Cannot paginate: the reply has no field {}",
+                self.token_field
+            ))
+        })?;
+        let token = OwnedValue::try_from(token)?;
+
+        let data = fields
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != self.token_field)
+            .map(|(_, field)| field);
+        if self.fields.is_empty() {
+            for field in data.clone() {
+                let signature = match field {
+                    Value::Array(array) => array.element_signature().clone(),
+                    field => {
+                        return Err(zbus::Error::Failure(format!(
+                            "Cannot paginate: reply field of type '{}' is not an array",
+                            field.value_signature()
+                        )));
+                    }
+                };
+                self.fields.push((signature, Vec::new()));
+            }
+        }
+        for ((_, values), field) in self.fields.iter_mut().zip(data) {
+            if let Value::Array(array) = field {
+                for element in array.iter() {
+                    values.push(OwnedValue::try_from(element)?);
+                }
+            }
+        }
+
+        if is_last_page(&token) {
+            return Ok(None);
+        }
+        if self.last_token.as_ref() == Some(&token) {
+            return Err(zbus::Error::Failure(format!(
+                "Cannot paginate: the service returned the token '{}' twice",
+                display_value(&token)
+            )));
+        }
+        self.last_token = Some(token.try_clone()?);
+        Ok(Some(token))
+    }
+
+    // The concatenated data fields, without the token. A single data field is
+    // returned as is; several are returned as a structure.
+    pub fn into_value(self) -> Result<Value<'static>> {
+        let mut arrays = Vec::new();
+        for (signature, values) in self.fields {
+            let mut array = Array::new(&signature);
+            for value in values {
+                array.append(Value::from(value))?;
+            }
+            arrays.push(Value::Array(array));
+        }
+
+        if arrays.len() == 1 {
+            return Ok(arrays.remove(0));
+        }
+        let mut builder = zvariant::StructureBuilder::new();
+        for array in arrays {
+            builder = builder.append_field(array);
+        }
+        Ok(Value::Structure(builder.build()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_paginate() {
+        assert_eq!(
+            "1=0".parse::<Paginate>(),
+            Ok(Paginate {
+                token_field: 1,
+                token_arg: 0
+            })
+        );
+        assert!("1".parse::<Paginate>().is_err());
+        assert!("next=0".parse::<Paginate>().is_err());
+    }
+
+    #[test]
+    fn test_next_args_keeps_type() {
+        let args = vec!["uint32:10".to_string(), "string:".to_string()];
+        let next = next_args(&args, 1, &Value::from("page2")).unwrap();
+        assert_eq!(next, vec!["uint32:10", "string:page2"]);
+        assert!(next_args(&args, 2, &Value::from("x")).is_err());
+    }
+
+    #[test]
+    fn test_pages_concatenate() {
+        let mut pages = Pages::new(1);
+        let token = pages
+            .append(&[Value::from(vec![1u32, 2]), Value::from("next")])
+            .unwrap();
+        assert_eq!(token, Some(OwnedValue::from(zvariant::Str::from("next"))));
+        let token = pages
+            .append(&[Value::from(vec![3u32]), Value::from("")])
+            .unwrap();
+        assert_eq!(token, None, "An empty token should end pagination");
+
+        let value = pages.into_value().unwrap();
+        assert_eq!(value, Value::from(vec![1u32, 2, 3]));
+    }
+
+    #[test]
+    fn test_repeated_token() {
+        let mut pages = Pages::new(1);
+        let page = [Value::from(vec![1u32]), Value::from("same")];
+        pages.append(&page).unwrap();
+        assert!(
+            pages.append(&page).is_err(),
+            "A repeated token should fail instead of looping"
+        );
+    }
+}
@@ -0,0 +1,568 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use zbus::Result;
+
+/// An `<allow>` or `<deny>` rule from a message bus configuration file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rule {
+    pub file: PathBuf,
+    pub line: usize,
+    pub policy: Policy,
+    pub allow: bool,
+    pub attributes: Vec<(String, String)>,
+}
+
+impl Rule {
+    fn get(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", if self.allow { "allow" } else { "deny" })?;
+        for (key, value) in &self.attributes {
+            write!(f, " {}=\"{}\"", key, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// The `<policy>` element a rule is in, which says whose connections it
+/// applies to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Policy {
+    /// `context="default"`: everyone, before any other policy.
+    Default,
+    /// `group="..."`: members of a group, or everyone for `*`.
+    Group(String),
+    /// `user="..."`: one user, or everyone for `*`.
+    User(String),
+    /// `at_console="..."`: users logged in at the console, or everyone else.
+    AtConsole(bool),
+    /// `context="mandatory"`: everyone, after every other policy.
+    Mandatory,
+}
+
+impl Policy {
+    // The bus applies the rules of each kind of policy after those of the
+    // kinds before it, so that later ones override earlier ones.
+    fn rank(&self) -> u8 {
+        match self {
+            Policy::Default => 0,
+            Policy::Group(_) => 1,
+            Policy::User(_) => 2,
+            Policy::AtConsole(_) => 3,
+            Policy::Mandatory => 4,
+        }
+    }
+
+    // Whether the policy covers a user's connections. Nobody is taken to be
+    // at the console, which only old systems still track.
+    fn applies_to(&self, identity: &Identity) -> bool {
+        let is = |pattern: &str, name: &str, id: u32| {
+            pattern == "*" || pattern == name || pattern.parse() == Ok(id)
+        };
+        match self {
+            Policy::Default | Policy::Mandatory => true,
+            Policy::Group(group) => identity
+                .groups
+                .iter()
+                .any(|(name, gid)| is(group, name, *gid)),
+            Policy::User(user) => is(user, &identity.user, identity.uid),
+            Policy::AtConsole(at_console) => !at_console,
+        }
+    }
+}
+
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Policy::Default => write!(f, "context=\"default\""),
+            Policy::Group(group) => write!(f, "group=\"{}\"", group),
+            Policy::User(user) => write!(f, "user=\"{}\"", user),
+            Policy::AtConsole(at_console) => write!(f, "at_console=\"{}\"", at_console),
+            Policy::Mandatory => write!(f, "context=\"mandatory\""),
+        }
+    }
+}
+
+/// Who a connection belongs to: a user, with its ID, and the groups it is
+/// in, with theirs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Identity {
+    pub user: String,
+    pub uid: u32,
+    pub groups: Vec<(String, u32)>,
+}
+
+/// What the bus is asked to let a connection do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action<'a> {
+    /// Own a well-known name.
+    Own(&'a str),
+    /// Send a method call to a name, with any of its interface, member and
+    /// object path that are known.
+    Call {
+        destination: &'a str,
+        interface: Option<&'a str>,
+        member: Option<&'a str>,
+        path: Option<&'a str>,
+    },
+}
+
+/// A rule that plays a part in whether an action is allowed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Step<'a> {
+    pub rule: &'a Rule,
+    /// The attributes the rule also requires of the action, which weren't
+    /// given; the rule only applies to actions that have them.
+    pub conditions: Vec<String>,
+}
+
+/// Why an action is allowed or denied: the rules that apply to it, in the
+/// order the bus applies them. The last one without conditions decides; if
+/// there is none, the action is allowed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Explanation<'a> {
+    pub allowed: bool,
+    pub decided_by: Option<&'a Rule>,
+    pub steps: Vec<Step<'a>>,
+}
+
+// Explain how the rules of a bus decide whether a user may do something.
+pub fn explain<'a>(rules: &'a [Rule], identity: &Identity, action: Action) -> Explanation<'a> {
+    let mut ordered = rules
+        .iter()
+        .filter(|rule| rule.policy.applies_to(identity))
+        .collect::<Vec<_>>();
+    ordered.sort_by_key(|rule| rule.policy.rank());
+
+    let mut decided_by = None;
+    let mut steps = Vec::new();
+    for rule in ordered {
+        let Some(conditions) = conditions(rule, action) else {
+            continue;
+        };
+        if conditions.is_empty() {
+            decided_by = Some(rule);
+        }
+        steps.push(Step { rule, conditions });
+    }
+    Explanation {
+        allowed: decided_by.is_none_or(|rule| rule.allow),
+        decided_by,
+        steps,
+    }
+}
+
+// Whether a rule applies to an action: `None` if it doesn't, else the
+// conditions it puts on the parts of the action that aren't known. Rules
+// about receiving messages, connecting or anything else apply to neither.
+fn conditions(rule: &Rule, action: Action) -> Option<Vec<String>> {
+    let is_name = |pattern: &str, name: &str| pattern == "*" || pattern == name;
+    let has_prefix = |prefix: &str, name: &str| {
+        name == prefix
+            || name
+                .strip_prefix(prefix)
+                .is_some_and(|r| r.starts_with('.'))
+    };
+    match action {
+        Action::Own(name) => match (rule.get("own"), rule.get("own_prefix")) {
+            (Some(own), _) => is_name(own, name).then(Vec::new),
+            (None, Some(prefix)) => has_prefix(prefix, name).then(Vec::new),
+            (None, None) => None,
+        },
+        Action::Call {
+            destination,
+            interface,
+            member,
+            path,
+        } => {
+            if !rule
+                .attributes
+                .iter()
+                .any(|(key, _)| key.starts_with("send_"))
+            {
+                return None;
+            }
+            let mut conditions = Vec::new();
+            for (key, value) in &rule.attributes {
+                let known = match key.as_str() {
+                    "send_destination" if !is_name(value, destination) => return None,
+                    "send_destination_prefix" if !has_prefix(value, destination) => return None,
+                    "send_type" if value != "*" && value != "method_call" => return None,
+                    // Errors, broadcasts and replies are not method calls.
+                    "send_error" => return None,
+                    "send_broadcast" | "send_requested_reply" if value == "true" => return None,
+                    "send_interface" => interface,
+                    "send_member" => member,
+                    "send_path" => path,
+                    _ => continue,
+                };
+                match known {
+                    _ if value == "*" => {}
+                    Some(known) if known == value => {}
+                    Some(_) => return None,
+                    None => conditions.push(format!("{}=\"{}\"", key, value)),
+                }
+            }
+            Some(conditions)
+        }
+    }
+}
+
+// Read the rules of a bus from its main configuration file, such as
+// /usr/share/dbus-1/system.conf, and the files it includes, in the order the
+// bus reads them.
+pub fn load_config(path: &Path) -> Result<Vec<Rule>> {
+    let mut rules = Vec::new();
+    read_config(path, &mut rules, 0)?;
+    Ok(rules)
+}
+
+// Files may include each other, but not endlessly.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+fn read_config(path: &Path, rules: &mut Vec<Rule>, depth: usize) -> Result<()> {
+    if depth > MAX_INCLUDE_DEPTH {
+        return Err(zbus::Error::Failure(format!(
+            "Too many nested includes at '{}'",
+            path.display()
+        )));
+    }
+    let contents = fs::read_to_string(path)
+        .map_err(|e| zbus::Error::Failure(format!("Failed to read '{}': {}", path.display(), e)))?;
+    let root = parse_xml(&contents).map_err(|(line, reason)| {
+        zbus::Error::Failure(format!(
+            "Invalid bus configuration '{}' at line {}: {}",
+            path.display(),
+            line,
+            reason
+        ))
+    })?;
+    if root.name != "busconfig" {
+        return Err(zbus::Error::Failure(format!(
+            "Invalid bus configuration '{}': expected <busconfig>, found <{}>",
+            path.display(),
+            root.name
+        )));
+    }
+
+    // Included paths are relative to the including file.
+    let relative = |name: &str| match path.parent() {
+        Some(dir) => dir.join(name.trim()),
+        None => PathBuf::from(name.trim()),
+    };
+    for element in &root.children {
+        match element.name.as_str() {
+            "policy" => {
+                let policy = policy(element).map_err(|reason| {
+                    zbus::Error::Failure(format!(
+                        "Invalid policy in '{}' at line {}: {}",
+                        path.display(),
+                        element.line,
+                        reason
+                    ))
+                })?;
+                for rule in &element.children {
+                    if rule.name == "allow" || rule.name == "deny" {
+                        rules.push(Rule {
+                            file: path.to_path_buf(),
+                            line: rule.line,
+                            policy: policy.clone(),
+                            allow: rule.name == "allow",
+                            attributes: rule.attributes.clone(),
+                        });
+                    }
+                }
+            }
+            // SELinux contexts are not rules about names.
+            "include" if element.get("if_selinux_enabled") != Some("yes") => {
+                let file = relative(&element.text);
+                if !file.exists() && element.get("ignore_missing") == Some("yes") {
+                    continue;
+                }
+                read_config(&file, rules, depth + 1)?;
+            }
+            "includedir" => {
+                let dir = relative(&element.text);
+                let Ok(entries) = fs::read_dir(&dir) else {
+                    continue;
+                };
+                let mut files = entries
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|file| file.extension().is_some_and(|ext| ext == "conf"))
+                    .collect::<Vec<_>>();
+                files.sort();
+                for file in files {
+                    read_config(&file, rules, depth + 1)?;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn policy(element: &Element) -> std::result::Result<Policy, String> {
+    match element.attributes.first() {
+        Some((key, value)) => match (key.as_str(), value.as_str()) {
+            ("context", "default") => Ok(Policy::Default),
+            ("context", "mandatory") => Ok(Policy::Mandatory),
+            ("user", user) => Ok(Policy::User(user.to_string())),
+            ("group", group) => Ok(Policy::Group(group.to_string())),
+            ("at_console", value) => Ok(Policy::AtConsole(value == "true")),
+            (key, value) => Err(format!("unknown attribute {}=\"{}\"", key, value)),
+        },
+        None => Err("expected context, user, group or at_console".to_string()),
+    }
+}
+
+// An element of an XML document, with the text directly inside it.
+#[derive(Debug, Default)]
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    line: usize,
+    text: String,
+    children: Vec<Element>,
+}
+
+impl Element {
+    fn get(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+// Parse the elements of an XML document, which is all bus configuration
+// files need: declarations, doctypes and comments are skipped, and only the
+// predefined entities are understood. Errors carry the line they are on.
+fn parse_xml(contents: &str) -> std::result::Result<Element, (usize, String)> {
+    let line_at = |offset: usize| contents[..offset].matches('\n').count() + 1;
+    let mut stack = vec![Element::default()];
+    let mut rest = contents;
+    while let Some(start) = rest.find('<') {
+        let offset = contents.len() - rest.len() + start;
+        let line = line_at(offset);
+        if let Some(top) = stack.last_mut() {
+            top.text.push_str(&unescape(&rest[..start]));
+        }
+        rest = &rest[start..];
+
+        let skip = |rest: &str, end: &str| {
+            rest.find(end)
+                .map(|i| i + end.len())
+                .ok_or((line, format!("missing '{}'", end)))
+        };
+        if rest.starts_with("<!--") {
+            rest = &rest[skip(rest, "-->")?..];
+        } else if rest.starts_with("<?") {
+            rest = &rest[skip(rest, "?>")?..];
+        } else if rest.starts_with("<!") {
+            rest = &rest[skip(rest, ">")?..];
+        } else if let Some(tag) = rest.strip_prefix("</") {
+            let end = skip(tag, ">")?;
+            let name = tag[..end - 1].trim();
+            let element = stack
+                .pop()
+                .filter(|element| element.name == name && !stack.is_empty())
+                .ok_or((line, format!("unexpected </{}>", name)))?;
+            if let Some(parent) = stack.last_mut() {
+                parent.children.push(element);
+            }
+            rest = &tag[end..];
+        } else {
+            let (element, closed, len) = start_tag(&rest[1..]).map_err(|e| (line, e))?;
+            let element = Element { line, ..element };
+            rest = &rest[1 + len..];
+            match closed {
+                true => {
+                    if let Some(parent) = stack.last_mut() {
+                        parent.children.push(element);
+                    }
+                }
+                false => stack.push(element),
+            }
+        }
+    }
+    let line = line_at(contents.len());
+    match stack.pop() {
+        Some(mut document) if stack.is_empty() => match document.children.pop() {
+            Some(root) if document.children.is_empty() => Ok(root),
+            Some(_) => Err((line, "more than one root element".to_string())),
+            None => Err((line, "no root element".to_string())),
+        },
+        Some(element) => Err((line, format!("<{}> is not closed", element.name))),
+        None => Err((line, "no root element".to_string())),
+    }
+}
+
+// Parse a start tag after its `<`: the element, whether it closes itself
+// with `/>`, and the length of the tag.
+fn start_tag(tag: &str) -> std::result::Result<(Element, bool, usize), String> {
+    let name_end = tag
+        .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+        .ok_or("unterminated tag")?;
+    let mut element = Element {
+        name: tag[..name_end].to_string(),
+        ..Element::default()
+    };
+    let mut rest = &tag[name_end..];
+    loop {
+        rest = rest.trim_start();
+        if let Some(after) = rest.strip_prefix("/>") {
+            return Ok((element, true, tag.len() - after.len()));
+        }
+        if let Some(after) = rest.strip_prefix('>') {
+            return Ok((element, false, tag.len() - after.len()));
+        }
+        let (key, after) = rest
+            .split_once('=')
+            .ok_or_else(|| format!("invalid attribute in <{}>", element.name))?;
+        let after = after.trim_start();
+        let quote = after
+            .chars()
+            .next()
+            .filter(|c| *c == '"' || *c == '\'')
+            .ok_or_else(|| format!("unquoted attribute {} in <{}>", key.trim(), element.name))?;
+        let value_end = after[1..]
+            .find(quote)
+            .ok_or_else(|| format!("unterminated attribute {}", key.trim()))?;
+        element
+            .attributes
+            .push((key.trim().to_string(), unescape(&after[1..1 + value_end])));
+        rest = &after[value_end + 2..];
+    }
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE busconfig PUBLIC "-//freedesktop//DTD D-BUS Bus Configuration 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/busconfig.dtd">
+<busconfig>
+  <!-- Holes must be punched -->
+  <policy context="default">
+    <deny own="*"/>
+    <deny send_type="method_call"/>
+    <allow send_destination="org.example.Test"
+           send_interface="org.freedesktop.DBus.Properties" send_member="Get"/>
+    <allow receive_type="signal"/>
+  </policy>
+  <policy user="root">
+    <allow own="org.example.Test"/>
+    <allow send_destination="org.example.Test"/>
+  </policy>
+  <policy group="wheel">
+    <allow send_destination_prefix="org.example"/>
+  </policy>
+</busconfig>
+"#;
+
+    fn rules() -> Vec<Rule> {
+        let dir = std::env::temp_dir().join(format!("zbusctl-policy-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("system.conf");
+        fs::write(&path, CONFIG).unwrap();
+        let rules = load_config(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        rules
+    }
+
+    fn identity(user: &str, groups: &[&str]) -> Identity {
+        Identity {
+            user: user.to_string(),
+            uid: if user == "root" { 0 } else { 1000 },
+            groups: groups.iter().map(|g| (g.to_string(), 100)).collect(),
+        }
+    }
+
+    #[test]
+    fn test_load_config() {
+        let rules = rules();
+        assert_eq!(rules.len(), 7);
+        assert_eq!(
+            rules[2].to_string(),
+            "allow send_destination=\"org.example.Test\" send_interface=\"org.freedesktop.DBus.Properties\" send_member=\"Get\""
+        );
+        assert_eq!(rules[2].line, 9, "Rules should know the line they start on");
+        assert_eq!(rules[4].policy, Policy::User("root".to_string()));
+    }
+
+    #[test]
+    fn test_explain_own() {
+        let rules = rules();
+        let user = explain(
+            &rules,
+            &identity("alice", &[]),
+            Action::Own("org.example.Test"),
+        );
+        assert!(!user.allowed);
+        assert_eq!(user.decided_by.map(|r| r.line), Some(7));
+
+        let root = explain(
+            &rules,
+            &identity("root", &[]),
+            Action::Own("org.example.Test"),
+        );
+        assert!(root.allowed, "Later policies should override the default");
+        assert_eq!(root.steps.len(), 2);
+    }
+
+    #[test]
+    fn test_explain_call() {
+        let rules = rules();
+        let call = |interface, member| Action::Call {
+            destination: "org.example.Test",
+            interface,
+            member,
+            path: None,
+        };
+
+        let unknown = explain(&rules, &identity("alice", &[]), call(None, None));
+        assert!(!unknown.allowed);
+        assert_eq!(
+            unknown.steps[1].conditions,
+            vec![
+                "send_interface=\"org.freedesktop.DBus.Properties\"".to_string(),
+                "send_member=\"Get\"".to_string()
+            ],
+            "Rules about parts of the call not given should be conditional"
+        );
+        let get = explain(
+            &rules,
+            &identity("alice", &[]),
+            call(Some("org.freedesktop.DBus.Properties"), Some("Get")),
+        );
+        assert!(get.allowed);
+        let wheel = explain(&rules, &identity("alice", &["wheel"]), call(None, None));
+        assert!(wheel.allowed, "Group policies should apply to members");
+    }
+
+    #[test]
+    fn test_parse_xml_errors() {
+        assert_eq!(
+            parse_xml("<busconfig>\n<policy>\n</busconfig>").unwrap_err(),
+            (3, "unexpected </busconfig>".to_string())
+        );
+        assert!(parse_xml("<a x=1/>").is_err(), "Attributes must be quoted");
+    }
+}
@@ -0,0 +1,78 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use zbus::{Connection, Result};
+
+/// Connections to one bus, or proxies over them, handed out in turn to the
+/// requests of a command that makes many, such as a recursive walk, so they
+/// are spread over several connections instead of all waiting their turn
+/// on one. Each connection authenticates once, when it is opened, and is
+/// reused for every request it is given. Clones share the same connections
+/// and take turns with each other.
+#[derive(Debug)]
+pub struct Pool<T = Connection> {
+    members: Arc<[T]>,
+    next: Arc<AtomicUsize>,
+}
+
+impl<T> Clone for Pool<T> {
+    fn clone(&self) -> Self {
+        Self {
+            members: self.members.clone(),
+            next: self.next.clone(),
+        }
+    }
+}
+
+impl<T> Pool<T> {
+    // A pool of connections the caller opened, of which there must be one
+    // at least.
+    pub fn new(members: Vec<T>) -> Result<Self> {
+        if members.is_empty() {
+            return Err(zbus::Error::Failure(
+                "A connection pool needs at least one connection".to_string(),
+            ));
+        }
+        Ok(Self {
+            members: members.into(),
+            next: Arc::new(AtomicUsize::new(0)),
+        })
+    }
+
+    // The member for the next request, each in turn.
+    pub fn get(&self) -> &T {
+        let i = self.next.fetch_add(1, Ordering::Relaxed) % self.members.len();
+        &self.members[i]
+    }
+
+    // The first member, for requests that must all go over one connection,
+    // such as a call and the signals it causes.
+    pub fn first(&self) -> &T {
+        &self.members[0]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.members.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_takes_turns() {
+        let pool = Pool::new(vec![1, 2, 3]).unwrap();
+        let clone = pool.clone();
+        let turns = (0..4)
+            .map(|i| if i % 2 == 0 { pool.get() } else { clone.get() })
+            .copied()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            turns,
+            vec![1, 2, 3, 1],
+            "Clones should take turns with each other"
+        );
+        assert_eq!(*pool.first(), 1);
+        assert!(Pool::<u32>::new(Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,150 @@
+use clap::ValueEnum;
+use std::future::Future;
+use std::io::{self, IsTerminal, Write};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How commands that make many requests report their progress on stderr.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ProgressMode {
+    /// A bar when stderr is a terminal, nothing otherwise.
+    Auto,
+    /// A single line redrawn in place.
+    Bar,
+    /// A line every few seconds, for logs.
+    Plain,
+    /// No progress output.
+    None,
+}
+
+// Nothing is shown for operations that finish quickly, so short commands
+// don't flicker.
+const DELAY: Duration = Duration::from_millis(500);
+const BAR_INTERVAL: Duration = Duration::from_millis(100);
+const PLAIN_INTERVAL: Duration = Duration::from_secs(2);
+const BAR_WIDTH: usize = 20;
+
+struct State {
+    done: usize,
+    total: Option<usize>,
+    reported: Option<Instant>,
+    // The count last reported.
+    shown: usize,
+}
+
+// Counts completed requests and reports them, with the time elapsed, while a
+// command runs. Safe to share between the concurrent requests it counts.
+pub struct Progress {
+    mode: ProgressMode,
+    noun: &'static str,
+    started: Instant,
+    state: Mutex<State>,
+}
+
+impl Progress {
+    pub fn new(mode: ProgressMode, noun: &'static str) -> Self {
+        let mode = match mode {
+            ProgressMode::Auto if io::stderr().is_terminal() => ProgressMode::Bar,
+            ProgressMode::Auto => ProgressMode::None,
+            mode => mode,
+        };
+        Self {
+            mode,
+            noun,
+            started: Instant::now(),
+            state: Mutex::new(State {
+                done: 0,
+                total: None,
+                reported: None,
+                shown: 0,
+            }),
+        }
+    }
+
+    // Add to the number of requests expected, when it is known up front or
+    // grows as the command discovers more work.
+    pub fn expect(&self, count: usize) {
+        if let Ok(mut state) = self.state.lock() {
+            state.total = Some(state.total.unwrap_or(0) + count);
+        }
+    }
+
+    // Run a request, counting it once it completes.
+    pub async fn track<F: Future>(&self, future: F) -> F::Output {
+        let output = future.await;
+        self.advance();
+        output
+    }
+
+    fn advance(&self) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        state.done += 1;
+        let elapsed = self.started.elapsed();
+        let interval = match self.mode {
+            ProgressMode::Bar => BAR_INTERVAL,
+            ProgressMode::Plain => PLAIN_INTERVAL,
+            _ => return,
+        };
+        let due = match state.reported {
+            Some(reported) => reported.elapsed() >= interval,
+            None => elapsed >= DELAY,
+        };
+        if due {
+            state.reported = Some(Instant::now());
+            state.shown = state.done;
+            self.report(&state, elapsed);
+        }
+    }
+
+    fn report(&self, state: &State, elapsed: Duration) {
+        let count = match state.total {
+            Some(total) => format!("{}/{} {}", state.done, total, self.noun),
+            None => format!("{} {}", state.done, self.noun),
+        };
+        let line = format!("{}, {:.1}s", count, elapsed.as_secs_f64());
+        let mut stderr = io::stderr().lock();
+        let _ = match self.mode {
+            ProgressMode::Bar => {
+                let bar = match state.total {
+                    Some(total) if total > 0 => {
+                        let filled = (state.done.min(total) * BAR_WIDTH) / total;
+                        format!(
+                            "[{}{}] ",
+                            "#".repeat(filled),
+                            "-".repeat(BAR_WIDTH - filled)
+                        )
+                    }
+                    _ => String::new(),
+                };
+                write!(stderr, "\r\x1b[K{}{}", bar, line)
+            }
+            _ => writeln!(stderr, "{}", line),
+        };
+        let _ = stderr.flush();
+    }
+}
+
+// Reporting stops when the command is done with the progress, or gives up
+// with an error: the bar is cleared so what follows starts on a clean line,
+// and plain mode ends with the final count if it said anything at all.
+impl Drop for Progress {
+    fn drop(&mut self) {
+        let Ok(state) = self.state.lock() else {
+            return;
+        };
+        if state.reported.is_none() {
+            return;
+        }
+        match self.mode {
+            ProgressMode::Bar => {
+                let _ = write!(io::stderr(), "\r\x1b[K");
+            }
+            ProgressMode::Plain if state.shown != state.done => {
+                self.report(&state, self.started.elapsed())
+            }
+            _ => {}
+        }
+    }
+}
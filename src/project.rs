@@ -0,0 +1,156 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+#[cfg(feature = "project")]
+use std::{fs, path::Path};
+use zbus::Result;
+
+use crate::redact::RedactRule;
+use crate::template::Template;
+
+/// The name of a project's zbusctl configuration file.
+pub const PROJECT_FILE: &str = ".zbusctl.toml";
+
+/// Settings a project ships in `.zbusctl.toml` alongside its sources, so
+/// everyone working on it gets the same shortcuts:
+///
+/// ```toml
+/// bus = "system"
+///
+/// [aliases]
+/// ping = ["call", "-s", "org.example.Test", "-o", "/org/example/Test", "-m", "Ping"]
+///
+/// [templates.echo]
+/// description = "Echo a string"
+/// args = ["call", "-s", "org.example.Test", "-o", "/org/example/Test", "-m", "Echo", "string:{text}"]
+///
+/// [[redact]]
+/// interface = "org.freedesktop.Secret.*"
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProjectConfig {
+    /// The bus to use when neither `--bus` nor `ZBUSCTL_BUS` says otherwise.
+    #[serde(default)]
+    pub bus: Option<String>,
+    /// Commands that expand to the start of another command line.
+    #[serde(default)]
+    pub aliases: BTreeMap<String, Vec<String>>,
+    /// Templates, as `template save` would write them.
+    #[serde(default)]
+    pub templates: BTreeMap<String, Template>,
+    /// Values to hide from output and logs.
+    #[serde(default)]
+    pub redact: Vec<RedactRule>,
+}
+
+impl ProjectConfig {
+    #[cfg(feature = "project")]
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path).map_err(|e| {
+            zbus::Error::Failure(format!("Failed to read '{}': {}", path.display(), e))
+        })?;
+        let invalid = |e: &dyn std::fmt::Display| {
+            zbus::Error::Failure(format!(
+                "Invalid project configuration '{}': {}",
+                path.display(),
+                e
+            ))
+        };
+        let config = toml::from_str::<Self>(&contents).map_err(|e| invalid(&e))?;
+        if !config.redact.iter().all(RedactRule::is_valid) {
+            return Err(invalid(
+                &"a [[redact]] rule needs an interface, member or property",
+            ));
+        }
+        Ok(config)
+    }
+
+    // Find the configuration of the project `dir` is in: the first
+    // `.zbusctl.toml` in `dir` or one of its parents, like git finds its
+    // repository.
+    #[cfg(feature = "project")]
+    pub fn find(dir: &Path) -> Result<Option<(PathBuf, Self)>> {
+        for dir in dir.ancestors() {
+            let path = dir.join(PROJECT_FILE);
+            if path.is_file() {
+                let config = Self::load(&path)?;
+                return Ok(Some((path, config)));
+            }
+        }
+        Ok(None)
+    }
+
+    // The configuration of the project in the current directory, if any.
+    #[cfg(feature = "project")]
+    pub fn discover() -> Result<Option<(PathBuf, Self)>> {
+        match std::env::current_dir() {
+            Ok(dir) => Self::find(&dir),
+            // Without a current directory there is no project to be in.
+            Err(_) => Ok(None),
+        }
+    }
+
+    // Without project support, project files are not looked for at all.
+    #[cfg(not(feature = "project"))]
+    pub fn discover() -> Result<Option<(PathBuf, Self)>> {
+        Ok(None)
+    }
+}
+
+#[cfg(all(test, feature = "project"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find() {
+        let root = std::env::temp_dir().join(format!("zbusctl-project-{}", std::process::id()));
+        let nested = root.join("src").join("bin");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(
+            root.join(PROJECT_FILE),
+            r#"
+bus = "system"
+
+[aliases]
+ping = ["call", "-s", "org.example.Test", "-m", "Ping"]
+
+[templates.echo]
+args = ["call", "string:{text}"]
+
+[[redact]]
+interface = "org.example.Wifi"
+property = "Psk"
+"#,
+        )
+        .unwrap();
+
+        let (path, config) = ProjectConfig::find(&nested).unwrap().unwrap();
+        assert_eq!(path, root.join(PROJECT_FILE));
+        assert_eq!(config.bus.as_deref(), Some("system"));
+        assert_eq!(
+            config.aliases["ping"],
+            vec!["call", "-s", "org.example.Test", "-m", "Ping"]
+        );
+        assert_eq!(config.templates["echo"].args, vec!["call", "string:{text}"]);
+        assert_eq!(config.redact[0].property.as_deref(), Some("Psk"));
+
+        fs::write(root.join(PROJECT_FILE), "buss = \"system\"\n").unwrap();
+        let error = ProjectConfig::find(&nested).unwrap_err().to_string();
+        assert!(
+            error.contains("unknown field"),
+            "Typos should be reported: {}",
+            error
+        );
+
+        fs::write(root.join(PROJECT_FILE), "[[redact]]\n").unwrap();
+        let error = ProjectConfig::find(&nested).unwrap_err().to_string();
+        assert!(
+            error.contains("needs an interface"),
+            "Rules that would hide everything should be refused: {}",
+            error
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
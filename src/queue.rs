@@ -0,0 +1,372 @@
+use crate::events::MessageEvent;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use zbus::Result;
+use zbus::message::Message;
+use zvariant::StructureBuilder;
+
+/// The bus a call is for, as zbusctl was told to reach it: the same bus seen
+/// from another environment, such as another container or login session,
+/// is a different bus.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BusTarget {
+    /// `session` or `system`.
+    pub bus: String,
+    /// The address in the bus's environment variable, if it was set.
+    #[serde(default)]
+    pub address: Option<String>,
+    /// The process whose network namespace the bus was reached from.
+    #[serde(default)]
+    pub netns: Option<u32>,
+    /// The process whose filesystem the bus socket was found in.
+    #[serde(default)]
+    pub mntns: Option<u32>,
+}
+
+impl BusTarget {
+    // The target in a few words, unique to it, as `system unix:path=... netns 42`.
+    pub fn describe(&self) -> String {
+        let mut out = self.bus.clone();
+        if let Some(ref address) = self.address {
+            out.push_str(&format!(" {}", address));
+        }
+        if let Some(pid) = self.netns {
+            out.push_str(&format!(" netns {}", pid));
+        }
+        if let Some(pid) = self.mntns {
+            out.push_str(&format!(" mntns {}", pid));
+        }
+        out
+    }
+}
+
+/// A method call that could not be delivered, kept to be sent later.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct QueuedCall {
+    /// The bus the call was for; calls queued before the address and
+    /// namespaces were kept only name the bus.
+    #[serde(flatten)]
+    pub target: BusTarget,
+    /// Why it could not be delivered.
+    pub reason: String,
+    /// The call, as written to event logs; its timestamp is when it was
+    /// queued.
+    pub call: MessageEvent,
+}
+
+impl QueuedCall {
+    pub fn new(target: BusTarget, msg: &Message, reason: &str) -> Self {
+        Self {
+            target,
+            reason: reason.to_string(),
+            call: MessageEvent::new(msg),
+        }
+    }
+
+    // A new message for the call, to send on another connection than the one
+    // it was built for.
+    pub fn message(&self) -> Result<Message> {
+        let call = &self.call;
+        let (Some(path), Some(member)) = (call.path.as_deref(), call.member.as_deref()) else {
+            return Err(zbus::Error::Failure(
+                "A queued call has no path or member".to_string(),
+            ));
+        };
+        let mut builder = Message::method_call(path, member)?;
+        if let Some(ref destination) = call.destination {
+            builder = builder.destination(destination.as_str())?;
+        }
+        if let Some(ref interface) = call.interface {
+            builder = builder.interface(interface.as_str())?;
+        }
+        let body = call.body_values()?;
+        if body.is_empty() {
+            return builder.build(&());
+        }
+        let body = body
+            .into_iter()
+            .fold(StructureBuilder::new(), |body, field| {
+                body.append_field(field)
+            })
+            .build()?;
+        builder.build(&body)
+    }
+
+    // The call in a few words, as `service path interface.member`.
+    pub fn describe(&self) -> String {
+        let call = &self.call;
+        let or_dash = |name: &Option<String>| name.clone().unwrap_or_else(|| "-".to_string());
+        format!(
+            "{} {} {}.{}",
+            or_dash(&call.destination),
+            or_dash(&call.path),
+            or_dash(&call.interface),
+            or_dash(&call.member)
+        )
+    }
+}
+
+/// A spool directory of queued calls, one JSON file each, named so they sort
+/// in the order they were queued. The files hold the arguments of the calls
+/// as they were given, so only their owner may read them.
+pub struct Queue {
+    dir: PathBuf,
+}
+
+impl Queue {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    // Add a call to the queue, returning the file it is kept in. The file is
+    // written under a temporary name first, so a flush running at the same
+    // time never reads half of it.
+    pub fn push(&self, call: &QueuedCall) -> Result<PathBuf> {
+        let failed = |path: &Path, e: std::io::Error| {
+            zbus::Error::Failure(format!("Failed to write '{}': {}", path.display(), e))
+        };
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&self.dir)
+            .map_err(|e| failed(&self.dir, e))?;
+        let json = serde_json::to_string_pretty(call)
+            .map_err(|e| zbus::Error::Failure(format!("Failed to encode queued call: {}", e)))?;
+
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        let name = format!("{:020}-{}", nanos, std::process::id());
+        let temporary = self.dir.join(format!(".{}.tmp", name));
+        let path = self.dir.join(format!("{}.json", name));
+        fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&temporary)
+            .and_then(|mut file| file.write_all(format!("{}\n", json).as_bytes()))
+            .map_err(|e| failed(&temporary, e))?;
+        fs::rename(&temporary, &path).map_err(|e| failed(&path, e))?;
+        Ok(path)
+    }
+
+    // The queued calls, oldest first. A queue that was never written to is
+    // empty. Calls claimed by a flush that is no longer running are put back
+    // first.
+    pub fn entries(&self) -> Result<Vec<(PathBuf, QueuedCall)>> {
+        self.release_abandoned();
+        let read_dir = match fs::read_dir(&self.dir) {
+            Ok(read_dir) => read_dir,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(zbus::Error::Failure(format!(
+                    "Failed to read '{}': {}",
+                    self.dir.display(),
+                    e
+                )));
+            }
+        };
+        let mut paths = read_dir
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == "json")
+            })
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        paths
+            .into_iter()
+            .map(|path| {
+                let contents = fs::read_to_string(&path).map_err(|e| {
+                    zbus::Error::Failure(format!("Failed to read '{}': {}", path.display(), e))
+                })?;
+                let call = serde_json::from_str(&contents).map_err(|e| {
+                    zbus::Error::Failure(format!("Invalid queued call '{}': {}", path.display(), e))
+                })?;
+                Ok((path, call))
+            })
+            .collect()
+    }
+
+    // Claim a queued call to send it, so a flush running at the same time
+    // cannot send it too: the file is renamed to one marked with our process
+    // ID, which only one of them can do. `None` if another flush claimed or
+    // removed it first.
+    pub fn claim(&self, path: &Path) -> Result<Option<PathBuf>> {
+        let Some(name) = path.file_stem().and_then(|name| name.to_str()) else {
+            return Ok(None);
+        };
+        let claimed = self
+            .dir
+            .join(format!(".{}.{}.claim", name, std::process::id()));
+        match fs::rename(path, &claimed) {
+            Ok(()) => Ok(Some(claimed)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(zbus::Error::Failure(format!(
+                "Failed to claim '{}': {}",
+                path.display(),
+                e
+            ))),
+        }
+    }
+
+    // Put a claimed call back on the queue, for the next flush.
+    pub fn release(&self, claimed: &Path, path: &Path) -> Result<()> {
+        fs::rename(claimed, path).map_err(|e| {
+            zbus::Error::Failure(format!("Failed to requeue '{}': {}", path.display(), e))
+        })
+    }
+
+    // Put back the calls claimed by flushes that stopped before sending
+    // them, such as ones that were killed.
+    fn release_abandoned(&self) {
+        let Ok(read_dir) = fs::read_dir(&self.dir) else {
+            return;
+        };
+        for entry in read_dir.flatten() {
+            let file_name = entry.file_name();
+            let Some((name, pid)) = file_name
+                .to_str()
+                .and_then(|name| name.strip_prefix('.')?.strip_suffix(".claim"))
+                .and_then(|name| name.rsplit_once('.'))
+            else {
+                continue;
+            };
+            let running = pid
+                .parse::<u32>()
+                .is_ok_and(|pid| Path::new(&format!("/proc/{}", pid)).exists());
+            if !running {
+                let _ = fs::rename(entry.path(), self.dir.join(format!("{}.json", name)));
+            }
+        }
+    }
+
+    // Take a call off the queue once it has been sent.
+    pub fn remove(&self, path: &Path) -> Result<()> {
+        fs::remove_file(path).map_err(|e| {
+            zbus::Error::Failure(format!("Failed to remove '{}': {}", path.display(), e))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_old_queued_call() {
+        let mut json = serde_json::to_value(QueuedCall::new(
+            BusTarget::default(),
+            &Message::method_call("/", "Ping")
+                .unwrap()
+                .build(&())
+                .unwrap(),
+            "unreachable",
+        ))
+        .unwrap();
+        let object = json.as_object_mut().unwrap();
+        object.insert("bus".to_string(), "session".into());
+        for field in ["address", "netns", "mntns"] {
+            object.remove(field);
+        }
+        let call = serde_json::from_value::<QueuedCall>(json).unwrap();
+        assert_eq!(
+            call.target.describe(),
+            "session",
+            "Calls queued with only a bus name should still load"
+        );
+    }
+
+    #[test]
+    fn test_queue() {
+        let dir = std::env::temp_dir().join(format!("zbusctl-queue-{}", std::process::id()));
+        let queue = Queue::new(&dir);
+        assert!(
+            queue.entries().unwrap().is_empty(),
+            "A missing queue should be empty"
+        );
+
+        let call = |member: &str| {
+            Message::method_call("/org/example/Test", member)
+                .unwrap()
+                .destination("org.example.Test")
+                .unwrap()
+                .interface("org.example.Test")
+                .unwrap()
+                .build(&("hi", 7u32))
+                .unwrap()
+        };
+        let target = BusTarget {
+            bus: "system".to_string(),
+            address: Some("unix:path=/run/dbus/system_bus_socket".to_string()),
+            netns: Some(42),
+            mntns: None,
+        };
+        let first = queue
+            .push(&QueuedCall::new(
+                target.clone(),
+                &call("First"),
+                "unreachable",
+            ))
+            .unwrap();
+        queue
+            .push(&QueuedCall::new(
+                target.clone(),
+                &call("Second"),
+                "unreachable",
+            ))
+            .unwrap();
+        assert_eq!(
+            fs::metadata(&first).unwrap().permissions().mode() & 0o777,
+            0o600,
+            "Queued calls should only be readable by their owner"
+        );
+
+        let entries = queue.entries().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].0, first, "Calls should come out in order");
+        assert_eq!(
+            entries[1].1.describe(),
+            "org.example.Test /org/example/Test org.example.Test.Second"
+        );
+
+        assert_eq!(
+            entries[0].1.target, target,
+            "The bus should be kept with its address and namespaces"
+        );
+
+        let message = entries[0].1.message().unwrap();
+        assert_eq!(message.header().member().unwrap().as_str(), "First");
+        let (text, number): (String, u32) = message.body().deserialize().unwrap();
+        assert_eq!((text.as_str(), number), ("hi", 7));
+
+        let claimed = queue.claim(&first).unwrap().unwrap();
+        assert!(
+            queue.claim(&first).unwrap().is_none(),
+            "A call should only be claimed once"
+        );
+        assert_eq!(
+            queue.entries().unwrap().len(),
+            1,
+            "A claimed call should be off the queue while its flush runs"
+        );
+        queue.release(&claimed, &first).unwrap();
+        let claimed = queue.claim(&first).unwrap().unwrap();
+        queue.remove(&claimed).unwrap();
+        assert_eq!(queue.entries().unwrap().len(), 1);
+
+        // A claim left by a flush that is gone is put back.
+        let second = &queue.entries().unwrap()[0].0;
+        let name = second.file_stem().unwrap().to_str().unwrap();
+        fs::rename(second, dir.join(format!(".{}.{}.claim", name, u32::MAX))).unwrap();
+        assert_eq!(queue.entries().unwrap().len(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,91 @@
+use std::str::FromStr;
+use zvariant::Value;
+
+use crate::format::display_value;
+
+/// A property that must have a given value before a service counts as ready,
+/// written `INTERFACE.PROPERTY=VALUE`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PropertyCondition {
+    pub interface: String,
+    pub property: String,
+    pub value: String,
+}
+
+impl FromStr for PropertyCondition {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || format!("expected INTERFACE.PROPERTY=VALUE, got '{}'", s);
+        let (name, value) = s.split_once('=').ok_or_else(invalid)?;
+        let (interface, property) = name.rsplit_once('.').ok_or_else(invalid)?;
+        if interface.is_empty() || property.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Self {
+            interface: interface.to_string(),
+            property: property.to_string(),
+            value: value.to_string(),
+        })
+    }
+}
+
+impl PropertyCondition {
+    // Whether a property value satisfies the condition. Values are compared
+    // as they are shown in tables, so `State=activated` or `Count=3` match
+    // without type prefixes.
+    pub fn matches(&self, value: &Value) -> bool {
+        display_value(value) == self.value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_condition() {
+        let condition = "org.freedesktop.NetworkManager.State=70"
+            .parse::<PropertyCondition>()
+            .unwrap();
+        assert_eq!(condition.interface, "org.freedesktop.NetworkManager");
+        assert_eq!(condition.property, "State");
+        assert_eq!(condition.value, "70");
+
+        let condition = "org.example.Test.Name=a=b"
+            .parse::<PropertyCondition>()
+            .unwrap();
+        assert_eq!(condition.value, "a=b", "Only the first '=' should split");
+        assert_eq!(
+            "org.example.Test.Name="
+                .parse::<PropertyCondition>()
+                .unwrap()
+                .value,
+            ""
+        );
+
+        assert!("State=70".parse::<PropertyCondition>().is_err());
+        assert!(
+            "org.example.Test.Name"
+                .parse::<PropertyCondition>()
+                .is_err()
+        );
+        assert!("org.example.=1".parse::<PropertyCondition>().is_err());
+    }
+
+    #[test]
+    fn test_condition_matches() {
+        let condition = "org.example.Test.Count=3"
+            .parse::<PropertyCondition>()
+            .unwrap();
+        assert!(condition.matches(&Value::U32(3)));
+        assert!(condition.matches(&Value::Value(Box::new(Value::I64(3)))));
+        assert!(!condition.matches(&Value::U32(4)));
+
+        let condition = "org.example.Test.Name=ready"
+            .parse::<PropertyCondition>()
+            .unwrap();
+        assert!(condition.matches(&Value::from("ready")));
+        assert!(!condition.matches(&Value::from("starting")));
+    }
+}
@@ -0,0 +1,425 @@
+use crate::events::{MessageEvent, MessageType};
+use crate::snapshot::Snapshot;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use zvariant::{OwnedValue, Value};
+
+/// What hidden values are replaced with.
+pub const REDACTED: &str = "<redacted>";
+
+const PROPERTIES: &str = "org.freedesktop.DBus.Properties";
+
+// How long the redaction a call's reply needs is kept. By then the caller has
+// given up waiting, or no reply is coming at all.
+const PENDING_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Values to hide from output, as a project lists them in `.zbusctl.toml`:
+///
+/// ```toml
+/// [[redact]]
+/// interface = "org.freedesktop.Secret.*"
+///
+/// [[redact]]
+/// interface = "org.example.Wifi"
+/// property = "Psk"
+/// ```
+///
+/// Names may contain `*`, which stands for any run of characters. A rule
+/// with a `member` hides the arguments and replies of those methods and
+/// signals, one with a `property` the values of those properties, and one
+/// with only an `interface` both.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RedactRule {
+    #[serde(default)]
+    pub interface: Option<String>,
+    #[serde(default)]
+    pub member: Option<String>,
+    #[serde(default)]
+    pub property: Option<String>,
+}
+
+impl RedactRule {
+    // A rule without any name would hide everything, which is more likely a
+    // mistake than what was meant.
+    pub fn is_valid(&self) -> bool {
+        self.interface.is_some() || self.member.is_some() || self.property.is_some()
+    }
+
+    fn matches_member(&self, interface: Option<&str>, member: Option<&str>) -> bool {
+        self.property.is_none()
+            && matches(self.interface.as_deref(), interface)
+            && matches(self.member.as_deref(), member)
+    }
+
+    fn matches_property(&self, interface: &str, property: Option<&str>) -> bool {
+        self.member.is_none()
+            && matches(self.interface.as_deref(), Some(interface))
+            && (property.is_none() || matches(self.property.as_deref(), property))
+    }
+}
+
+fn matches(pattern: Option<&str>, name: Option<&str>) -> bool {
+    match pattern {
+        None => true,
+        Some(pattern) => name.is_some_and(|name| glob_match(pattern, name)),
+    }
+}
+
+// Match a name against a pattern in which `*` stands for any run of
+// characters, including none.
+pub(crate) fn glob_match(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == name,
+        Some((prefix, rest)) => {
+            let Some(name) = name.strip_prefix(prefix) else {
+                return false;
+            };
+            (0..=name.len())
+                .filter(|&i| name.is_char_boundary(i))
+                .any(|i| glob_match(rest, &name[i..]))
+        }
+    }
+}
+
+/// What to hide in a reply, which only the call it answers can tell.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Redaction {
+    /// Every value in it.
+    Body,
+    /// The hidden properties of this interface, in a property dictionary.
+    Properties(String),
+}
+
+// A call, by sender and serial, as its reply refers to it.
+type CallId = (Option<String>, u32);
+
+/// Applies redaction rules to what zbusctl prints and logs. Replies carry
+/// neither interface nor member, so the redactions a call's reply needs are
+/// kept until the reply is seen, or for a few minutes if it never is.
+#[derive(Debug, Default)]
+pub struct Redactor {
+    rules: Vec<RedactRule>,
+    pending: Mutex<HashMap<CallId, (Redaction, Instant)>>,
+}
+
+impl Redactor {
+    pub fn new(rules: Vec<RedactRule>) -> Self {
+        Self {
+            rules,
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    // Whether the arguments and replies of a method or signal are hidden.
+    pub fn redacts_member(&self, interface: Option<&str>, member: Option<&str>) -> bool {
+        self.rules
+            .iter()
+            .any(|rule| rule.matches_member(interface, member))
+    }
+
+    // Whether the value of a property is hidden.
+    pub fn redacts_property(&self, interface: &str, property: &str) -> bool {
+        self.rules
+            .iter()
+            .any(|rule| rule.matches_property(interface, Some(property)))
+    }
+
+    // What to hide in the reply to a method call. Replies to the
+    // org.freedesktop.DBus.Properties methods are judged by the properties
+    // they hold rather than by the method.
+    pub fn reply_redaction(&self, call: &MessageEvent) -> Option<Redaction> {
+        if call.interface.as_deref() == Some(PROPERTIES) {
+            let interface = string_arg(call, 0)?;
+            return match call.member.as_deref() {
+                Some("Get") => string_arg(call, 1)
+                    .filter(|property| self.redacts_property(interface, property))
+                    .map(|_| Redaction::Body),
+                Some("GetAll")
+                    if self
+                        .rules
+                        .iter()
+                        .any(|rule| rule.matches_property(interface, None)) =>
+                {
+                    Some(Redaction::Properties(interface.to_string()))
+                }
+                _ => None,
+            };
+        }
+        self.redacts_member(call.interface.as_deref(), call.member.as_deref())
+            .then_some(Redaction::Body)
+    }
+
+    // Hide the values of a message event. The redactions replies need are
+    // remembered from their calls, so events must be passed in the order
+    // they were seen.
+    pub fn redact_event(&self, event: &mut MessageEvent) {
+        if self.is_empty() {
+            return;
+        }
+        let properties = event.interface.as_deref() == Some(PROPERTIES);
+        match event.message_type {
+            MessageType::MethodCall => {
+                if let Some(redaction) = self.reply_redaction(event) {
+                    self.remember(
+                        (event.sender.clone(), event.serial),
+                        redaction,
+                        Instant::now(),
+                    );
+                }
+                if properties {
+                    if event.member.as_deref() == Some("Set")
+                        && let (Some(interface), Some(property)) =
+                            (string_arg(event, 0), string_arg(event, 1))
+                        && self.redacts_property(interface, property)
+                        && let Some(field) = event.body.get_mut(2)
+                    {
+                        field["value"] = REDACTED.into();
+                    }
+                } else if self.redacts_member(event.interface.as_deref(), event.member.as_deref()) {
+                    redact_fields(&mut event.body);
+                }
+            }
+            MessageType::Signal => {
+                if properties && event.member.as_deref() == Some("PropertiesChanged") {
+                    if let Some(interface) = string_arg(event, 0).map(str::to_string) {
+                        self.redact_dict(&interface, &mut event.body);
+                    }
+                } else if self.redacts_member(event.interface.as_deref(), event.member.as_deref()) {
+                    redact_fields(&mut event.body);
+                }
+            }
+            MessageType::MethodReturn | MessageType::Error => {
+                let Some(serial) = event.reply_serial else {
+                    return;
+                };
+                // Calls zbusctl sends itself are logged before the bus has
+                // given them a sender.
+                let redaction = {
+                    let mut pending = self.pending.lock().unwrap();
+                    pending
+                        .remove(&(event.destination.clone(), serial))
+                        .or_else(|| pending.remove(&(None, serial)))
+                };
+                if let Some((redaction, _)) = redaction {
+                    self.redact_reply(&redaction, &mut event.body);
+                }
+            }
+        }
+    }
+
+    // Keep the redaction a call's reply needs, dropping those kept for
+    // calls that have gone unanswered too long, so a long `monitor` does
+    // not collect them without end.
+    fn remember(&self, call: CallId, redaction: Redaction, now: Instant) {
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|_, (_, since)| now.duration_since(*since) < PENDING_TIMEOUT);
+        pending.insert(call, (redaction, now));
+    }
+
+    // Hide the values of a reply body, in the form message events record
+    // it.
+    pub fn redact_reply(&self, redaction: &Redaction, body: &mut serde_json::Value) {
+        match redaction {
+            Redaction::Body => redact_fields(body),
+            Redaction::Properties(interface) => self.redact_dict(interface, body),
+        }
+    }
+
+    // Hide the values of a reply, as decoded for printing.
+    pub fn redact_value(&self, redaction: &Redaction, value: &Value<'_>) -> Value<'static> {
+        match redaction {
+            Redaction::Body => Value::from(REDACTED),
+            Redaction::Properties(interface) => {
+                // Anything but a property dictionary is hidden whole.
+                let properties = value
+                    .try_to_owned()
+                    .ok()
+                    .and_then(|value| HashMap::<String, OwnedValue>::try_from(value).ok());
+                let Some(mut properties) = properties else {
+                    return Value::from(REDACTED);
+                };
+                for (property, value) in properties.iter_mut() {
+                    if self.redacts_property(interface, property) {
+                        *value = OwnedValue::from(zvariant::Str::from_static(REDACTED));
+                    }
+                }
+                Value::from(properties)
+            }
+        }
+    }
+
+    // Hide the values of properties in a snapshot.
+    pub fn redact_snapshot(&self, snapshot: &mut Snapshot) {
+        for (interface, properties) in snapshot.iter_mut() {
+            for (property, value) in properties.iter_mut() {
+                if self.redacts_property(interface, property) {
+                    *value = REDACTED.into();
+                }
+            }
+        }
+    }
+
+    // Hide the hidden properties of `interface` in the property dictionary
+    // that is the first field after the interface name, or the only field.
+    fn redact_dict(&self, interface: &str, body: &mut serde_json::Value) {
+        let field = match body.as_array().map(Vec::len) {
+            Some(1) => 0,
+            _ => 1,
+        };
+        let Some(properties) = body
+            .get_mut(field)
+            .and_then(|field| field["value"].as_object_mut())
+        else {
+            return;
+        };
+        for (property, value) in properties.iter_mut() {
+            if self.redacts_property(interface, property) {
+                value["value"] = REDACTED.into();
+            }
+        }
+    }
+}
+
+// A string argument of a message event.
+fn string_arg(event: &MessageEvent, index: usize) -> Option<&str> {
+    event.body.get(index)?["value"].as_str()
+}
+
+fn redact_fields(body: &mut serde_json::Value) {
+    if let Some(fields) = body.as_array_mut() {
+        for field in fields {
+            field["value"] = REDACTED.into();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zbus::message::Message;
+
+    fn redactor() -> Redactor {
+        Redactor::new(vec![
+            RedactRule {
+                interface: Some("org.freedesktop.Secret.*".to_string()),
+                ..RedactRule::default()
+            },
+            RedactRule {
+                interface: Some("org.example.Wifi".to_string()),
+                property: Some("Psk".to_string()),
+                ..RedactRule::default()
+            },
+        ])
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match(
+            "org.freedesktop.Secret.*",
+            "org.freedesktop.Secret.Item"
+        ));
+        assert!(glob_match("*Password*", "GetPasswords"));
+        assert!(glob_match("Psk", "Psk"));
+        assert!(!glob_match(
+            "org.freedesktop.Secret.*",
+            "org.freedesktop.Secrets"
+        ));
+        assert!(!glob_match("Get*Key", "GetKeys"));
+    }
+
+    #[test]
+    fn test_redact_event() {
+        let redactor = redactor();
+        let call = Message::method_call("/org/freedesktop/secrets", "GetSecrets")
+            .unwrap()
+            .interface("org.freedesktop.Secret.Service")
+            .unwrap()
+            .build(&("hunter2",))
+            .unwrap();
+        let reply = Message::method_return(&call.header())
+            .unwrap()
+            .build(&("hunter2",))
+            .unwrap();
+        let mut events = [MessageEvent::new(&call), MessageEvent::new(&reply)];
+        for event in events.iter_mut() {
+            redactor.redact_event(event);
+            assert_eq!(
+                event.body[0]["value"], REDACTED,
+                "Calls and their replies should be hidden"
+            );
+        }
+
+        let changed = Message::signal(
+            "/org/example/Wifi",
+            "org.freedesktop.DBus.Properties",
+            "PropertiesChanged",
+        )
+        .unwrap()
+        .build(&(
+            "org.example.Wifi",
+            HashMap::from([("Psk", Value::from("hunter2")), ("Ssid", Value::from("x"))]),
+            Vec::<&str>::new(),
+        ))
+        .unwrap();
+        let mut event = MessageEvent::new(&changed);
+        redactor.redact_event(&mut event);
+        let properties = &event.body[1]["value"];
+        assert_eq!(properties["Psk"]["value"], REDACTED);
+        assert_eq!(
+            properties["Ssid"]["value"], "x",
+            "Other properties should be left alone"
+        );
+    }
+
+    #[test]
+    fn test_redact_value() {
+        let redactor = redactor();
+        let get_all = Message::method_call("/org/example/Wifi", "GetAll")
+            .unwrap()
+            .interface(PROPERTIES)
+            .unwrap()
+            .build(&("org.example.Wifi",))
+            .unwrap();
+        let redaction = redactor
+            .reply_redaction(&MessageEvent::new(&get_all))
+            .unwrap();
+        assert_eq!(
+            redaction,
+            Redaction::Properties("org.example.Wifi".to_string())
+        );
+
+        let value = Value::from(HashMap::from([
+            ("Psk", Value::from("hunter2")),
+            ("Ssid", Value::from("x")),
+        ]));
+        let redacted =
+            HashMap::<String, OwnedValue>::try_from(redactor.redact_value(&redaction, &value))
+                .unwrap();
+        assert_eq!(*redacted["Psk"], Value::from(REDACTED));
+        assert_eq!(*redacted["Ssid"], Value::from("x"));
+    }
+
+    #[test]
+    fn test_pending_expiry() {
+        let redactor = redactor();
+        let start = Instant::now();
+        redactor.remember((None, 1), Redaction::Body, start);
+        redactor.remember((None, 2), Redaction::Body, start + PENDING_TIMEOUT / 2);
+        assert_eq!(redactor.pending.lock().unwrap().len(), 2);
+
+        redactor.remember((None, 3), Redaction::Body, start + PENDING_TIMEOUT);
+        let pending = redactor.pending.lock().unwrap();
+        assert!(
+            !pending.contains_key(&(None, 1)),
+            "Calls unanswered for too long should be forgotten"
+        );
+        assert!(pending.contains_key(&(None, 2)) && pending.contains_key(&(None, 3)));
+    }
+}
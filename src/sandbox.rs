@@ -0,0 +1,204 @@
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+// The filesystem accesses of the first Landlock ABI that run or write
+// anything: executing files, writing to them, and creating or removing files,
+// directories, links, devices, sockets and pipes. Reading is left alone.
+const ACCESS_EXECUTE: u64 = 1 << 0;
+const ACCESS_WRITE_FILE: u64 = 1 << 1;
+const ACCESS_REMOVE_DIR: u64 = 1 << 4;
+const ACCESS_REMOVE_FILE: u64 = 1 << 5;
+const ACCESS_MAKE_CHAR: u64 = 1 << 6;
+const ACCESS_MAKE_DIR: u64 = 1 << 7;
+const ACCESS_MAKE_REG: u64 = 1 << 8;
+const ACCESS_MAKE_SOCK: u64 = 1 << 9;
+const ACCESS_MAKE_FIFO: u64 = 1 << 10;
+const ACCESS_MAKE_BLOCK: u64 = 1 << 11;
+const ACCESS_MAKE_SYM: u64 = 1 << 12;
+// Truncating files, which the third ABI added.
+const ACCESS_TRUNCATE: u64 = 1 << 14;
+
+const CREATE_RULESET_VERSION: libc::c_uint = 1 << 0;
+const RULE_PATH_BENEATH: libc::c_int = 1;
+
+#[repr(C)]
+struct RulesetAttr {
+    handled_access_fs: u64,
+}
+
+#[repr(C, packed)]
+struct PathBeneathAttr {
+    allowed_access: u64,
+    parent_fd: i32,
+}
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xc000_003e;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xc000_00b7;
+// Syscalls of the x32 ABI, which have numbers of their own on x86_64.
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+const SECCOMP_SET_MODE_FILTER: libc::c_uint = 1;
+
+// Keep this process, from now on, from running programs, through a seccomp
+// filter that fails `execve` and `execveat` with EPERM, and from changing the
+// filesystem, through Landlock, except to create and write files in `dirs`.
+// Reading is still allowed, as is connecting to sockets, such as the bus's,
+// and everything done through descriptors already open, such as writing to
+// stdout or to a log opened earlier. There is no way back.
+//
+// Landlock confines only the calling thread and the threads it starts later,
+// so this must be called while it is the only thread. Either mechanism being
+// unavailable, as on kernels before 5.13, is an error, so that nothing runs
+// less confined than it was asked to.
+pub fn restrict(dirs: &[&Path]) -> io::Result<()> {
+    let threads = fs::read_dir("/proc/self/task")?.count();
+    if threads != 1 {
+        return Err(io::Error::other(format!(
+            "{} threads are running, and only the one sandboxing itself may be",
+            threads
+        )));
+    }
+    // Without this an unprivileged process may do neither, and a program
+    // started before could not gain privileges the sandbox takes away.
+    if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    landlock(dirs).map_err(|e| io::Error::new(e.kind(), format!("Landlock: {}", e)))?;
+    deny_exec().map_err(|e| io::Error::new(e.kind(), format!("seccomp: {}", e)))
+}
+
+fn landlock(dirs: &[&Path]) -> io::Result<()> {
+    let abi = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            std::ptr::null::<RulesetAttr>(),
+            0,
+            CREATE_RULESET_VERSION,
+        )
+    };
+    if abi < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut handled = ACCESS_EXECUTE
+        | ACCESS_WRITE_FILE
+        | ACCESS_REMOVE_DIR
+        | ACCESS_REMOVE_FILE
+        | ACCESS_MAKE_CHAR
+        | ACCESS_MAKE_DIR
+        | ACCESS_MAKE_REG
+        | ACCESS_MAKE_SOCK
+        | ACCESS_MAKE_FIFO
+        | ACCESS_MAKE_BLOCK
+        | ACCESS_MAKE_SYM;
+    if abi >= 3 {
+        handled |= ACCESS_TRUNCATE;
+    }
+    let attr = RulesetAttr {
+        handled_access_fs: handled,
+    };
+    let ruleset = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            &attr,
+            size_of::<RulesetAttr>(),
+            0,
+        )
+    };
+    if ruleset < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: the kernel just returned this descriptor, and nothing else
+    // owns it.
+    let ruleset = unsafe { OwnedFd::from_raw_fd(ruleset as libc::c_int) };
+    for dir in dirs {
+        allow(&ruleset, dir, ACCESS_WRITE_FILE | ACCESS_MAKE_REG)
+            .map_err(|e| io::Error::new(e.kind(), format!("'{}': {}", dir.display(), e)))?;
+    }
+    if unsafe { libc::syscall(libc::SYS_landlock_restrict_self, ruleset.as_raw_fd(), 0) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// Allow `access` to everything below the directory `dir`.
+fn allow(ruleset: &OwnedFd, dir: &Path, access: u64) -> io::Result<()> {
+    let path = CString::new(dir.as_os_str().as_bytes())?;
+    let fd = unsafe { libc::open(path.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: as above, a descriptor just opened and owned by nothing else.
+    let dir = unsafe { OwnedFd::from_raw_fd(fd) };
+    let attr = PathBeneathAttr {
+        allowed_access: access,
+        parent_fd: dir.as_raw_fd(),
+    };
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_add_rule,
+            ruleset.as_raw_fd(),
+            RULE_PATH_BENEATH,
+            &attr,
+            0,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// Fail every attempt to run a program with EPERM. A syscall made for another
+// architecture than this process's is refused outright, as its numbers mean
+// other calls.
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+fn deny_exec() -> io::Result<()> {
+    let statement = |code: u32, k: u32| libc::sock_filter {
+        code: code as u16,
+        jt: 0,
+        jf: 0,
+        k,
+    };
+    let jump = |k: u32, jt: u8, condition: u32| libc::sock_filter {
+        code: (libc::BPF_JMP | condition | libc::BPF_K) as u16,
+        jt,
+        jf: 0,
+        k,
+    };
+    let load = libc::BPF_LD | libc::BPF_W | libc::BPF_ABS;
+    let ret = libc::BPF_RET | libc::BPF_K;
+    let mut filter = [
+        statement(load, std::mem::offset_of!(libc::seccomp_data, arch) as u32),
+        jump(AUDIT_ARCH, 1, libc::BPF_JEQ),
+        statement(ret, libc::SECCOMP_RET_KILL_PROCESS),
+        statement(load, std::mem::offset_of!(libc::seccomp_data, nr) as u32),
+        jump(X32_SYSCALL_BIT, 3, libc::BPF_JGE),
+        jump(libc::SYS_execve as u32, 2, libc::BPF_JEQ),
+        jump(libc::SYS_execveat as u32, 1, libc::BPF_JEQ),
+        statement(ret, libc::SECCOMP_RET_ALLOW),
+        statement(ret, libc::SECCOMP_RET_ERRNO | libc::EPERM as u32),
+    ];
+    let program = libc::sock_fprog {
+        len: filter.len() as u16,
+        filter: filter.as_mut_ptr(),
+    };
+    // SAFETY: the kernel copies the program, which outlives the call.
+    let ret = unsafe { libc::syscall(libc::SYS_seccomp, SECCOMP_SET_MODE_FILTER, 0, &program) };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn deny_exec() -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "not supported on this architecture",
+    ))
+}
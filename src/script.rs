@@ -0,0 +1,212 @@
+/// A zbusctl command found in a shell script.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Invocation {
+    /// The line the command starts on, counting from 1.
+    pub line: usize,
+    /// The command's words with quotes removed, starting with the program.
+    pub words: Vec<String>,
+    /// Whether any word depends on variables, command substitution or globs,
+    /// so the words are not what the shell would actually run.
+    pub dynamic: bool,
+}
+
+// One shell word with quotes removed.
+#[derive(Default)]
+struct Word {
+    text: String,
+    dynamic: bool,
+}
+
+// The commands split so far, and the one being read.
+#[derive(Default)]
+struct Splitter {
+    commands: Vec<(usize, Vec<Word>)>,
+    words: Vec<Word>,
+    word: Option<Word>,
+    line: usize,
+    start: usize,
+}
+
+impl Splitter {
+    fn word(&mut self) -> &mut Word {
+        self.word.get_or_insert_default()
+    }
+
+    fn end_word(&mut self) {
+        if let Some(word) = self.word.take() {
+            if self.words.is_empty() {
+                self.start = self.line;
+            }
+            self.words.push(word);
+        }
+    }
+
+    fn end_command(&mut self) {
+        self.end_word();
+        if !self.words.is_empty() {
+            let words = std::mem::take(&mut self.words);
+            self.commands.push((self.start, words));
+        }
+    }
+}
+
+// Split a shell script into simple commands, each a list of words. This covers
+// the quoting, comments, line continuations and command separators that
+// scripts commonly use; it is not a full shell parser, and anything it can't
+// evaluate statically is flagged as dynamic instead.
+fn commands(script: &str) -> Vec<(usize, Vec<Word>)> {
+    let mut s = Splitter {
+        line: 1,
+        ..Splitter::default()
+    };
+    let mut chars = script.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => {
+                s.end_command();
+                s.line += 1;
+            }
+            ' ' | '\t' => s.end_word(),
+            ';' | '&' | '|' | '(' | ')' => s.end_command(),
+            // Braces only group commands at the start of a word.
+            '{' | '}' if s.word.is_none() => s.end_command(),
+            '#' if s.word.is_none() => {
+                while chars.peek().is_some_and(|&c| c != '\n') {
+                    chars.next();
+                }
+            }
+            '\\' => match chars.next() {
+                Some('\n') => s.line += 1,
+                Some(c) => s.word().text.push(c),
+                None => {}
+            },
+            '\'' => {
+                let w = s.word.get_or_insert_default();
+                for c in chars.by_ref() {
+                    if c == '\'' {
+                        break;
+                    }
+                    if c == '\n' {
+                        s.line += 1;
+                    }
+                    w.text.push(c);
+                }
+            }
+            '"' => {
+                let w = s.word.get_or_insert_default();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => match chars.next() {
+                            Some('\n') => s.line += 1,
+                            Some(c @ ('"' | '\\' | '$' | '`')) => w.text.push(c),
+                            Some(c) => {
+                                w.text.push('\\');
+                                w.text.push(c);
+                            }
+                            None => {}
+                        },
+                        '$' | '`' => {
+                            w.dynamic = true;
+                            w.text.push(c);
+                        }
+                        '\n' => {
+                            s.line += 1;
+                            w.text.push(c);
+                        }
+                        c => w.text.push(c),
+                    }
+                }
+            }
+            '$' | '`' | '*' | '?' | '~' => {
+                let w = s.word.get_or_insert_default();
+                w.dynamic = true;
+                w.text.push(c);
+            }
+            c => s.word().text.push(c),
+        }
+    }
+    s.end_command();
+    s.commands
+}
+
+// Find the zbusctl commands in a shell script. Commands may be prefixed by
+// variable assignments, `sudo`, `env` or `exec`.
+pub fn find_invocations(script: &str) -> Vec<Invocation> {
+    commands(script)
+        .into_iter()
+        .filter_map(|(line, words)| {
+            let program = words.iter().position(|w| {
+                !(w.text.contains('=') && !w.text.starts_with('-')
+                    || ["sudo", "env", "exec", "command"].contains(&w.text.as_str()))
+            })?;
+            let name = words[program].text.rsplit('/').next()?;
+            if name != "zbusctl" {
+                return None;
+            }
+            let words = &words[program..];
+            Some(Invocation {
+                line,
+                dynamic: words.iter().any(|w| w.dynamic),
+                words: words.iter().map(|w| w.text.clone()).collect(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_invocations() {
+        let script = r#"#!/bin/sh
+set -e
+# zbusctl call in a comment is ignored
+zbusctl call -s org.example.Test -o /org/example/Test \
+    -i org.example.Test -m Echo 'string:hello world' "int32:1"
+echo done; sudo /usr/bin/zbusctl --system names
+"#;
+        let invocations = find_invocations(script);
+        assert_eq!(invocations.len(), 2);
+        assert_eq!(invocations[0].line, 4);
+        assert_eq!(
+            invocations[0].words,
+            vec![
+                "zbusctl",
+                "call",
+                "-s",
+                "org.example.Test",
+                "-o",
+                "/org/example/Test",
+                "-i",
+                "org.example.Test",
+                "-m",
+                "Echo",
+                "string:hello world",
+                "int32:1"
+            ]
+        );
+        assert!(!invocations[0].dynamic);
+        assert_eq!(invocations[1].line, 6);
+        assert_eq!(
+            invocations[1].words,
+            vec!["/usr/bin/zbusctl", "--system", "names"]
+        );
+    }
+
+    #[test]
+    fn test_dynamic_words() {
+        let invocations = find_invocations(
+            "SVC=org.example.Test\nzbusctl call -s \"$SVC\" -o / -i a.b -m C\nX=1 zbusctl names | wc -l\n",
+        );
+        assert_eq!(invocations.len(), 2);
+        assert!(
+            invocations[0].dynamic,
+            "Variables should mark the invocation as dynamic"
+        );
+        assert_eq!(invocations[1].words, vec!["zbusctl", "names"]);
+        assert!(!invocations[1].dynamic);
+    }
+}
@@ -0,0 +1,318 @@
+use crate::events::{MessageEvent, MessageType};
+use crate::redact::glob_match;
+use std::collections::HashMap;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::str::FromStr;
+use zvariant::Signature;
+
+const PROPERTIES: &str = "org.freedesktop.DBus.Properties";
+
+// How long, in capture time, a dropped call is waited on for its reply
+// before it is forgotten.
+const DROPPED_TIMEOUT: f64 = 300.0;
+
+/// Removes content from the events of a capture, so it can be shared in a
+/// bug report, while keeping the sequence of messages and the shape of their
+/// bodies.
+///
+/// Messages on dropped interfaces are left out, along with the replies to
+/// calls on them and property calls and `PropertiesChanged` signals for
+/// them. With `hash_strings`, every string, object path and byte array in
+/// the bodies that remain, dictionary keys included, is replaced with a hash
+/// of it, the same for the same value throughout a capture, so values can
+/// still be told apart and followed from one message to the next. The hash
+/// is keyed anew for each scrubber, so short values cannot be found by
+/// hashing guesses. Signature values are emptied.
+///
+/// What survives hashing: the headers of every message (type, serial,
+/// sender, destination, path, interface, member and error name), the
+/// signature of each body and the types recorded in its variants, numbers,
+/// booleans, file descriptor indexes, and the length of every array, byte
+/// arrays included.
+#[derive(Debug, Default)]
+pub struct Scrubber {
+    /// Interfaces to leave out; `*` stands for any run of characters.
+    pub drop_interfaces: Vec<String>,
+    pub hash_strings: bool,
+    /// The calls left out, by sender and serial, whose replies go too, with
+    /// the time each was captured at.
+    dropped_calls: HashMap<(Option<String>, u32), f64>,
+    hasher: RandomState,
+}
+
+impl Scrubber {
+    pub fn new(drop_interfaces: Vec<String>, hash_strings: bool) -> Self {
+        Self {
+            drop_interfaces,
+            hash_strings,
+            dropped_calls: HashMap::new(),
+            hasher: RandomState::new(),
+        }
+    }
+
+    // Scrub one event, in the order of the capture. `None` if it is left
+    // out.
+    pub fn scrub(&mut self, mut event: MessageEvent) -> Option<MessageEvent> {
+        match event.message_type {
+            MessageType::MethodCall | MessageType::Signal if self.drops(&event) => {
+                if event.message_type == MessageType::MethodCall {
+                    self.remember(event.sender.clone(), event.serial, event.timestamp);
+                }
+                return None;
+            }
+            MessageType::MethodReturn | MessageType::Error => {
+                // Calls zbusctl sends itself are logged before the bus has
+                // given them a sender.
+                if let Some(serial) = event.reply_serial
+                    && (self
+                        .dropped_calls
+                        .remove(&(event.destination.clone(), serial))
+                        .is_some()
+                        || self.dropped_calls.remove(&(None, serial)).is_some())
+                {
+                    return None;
+                }
+            }
+            _ => {}
+        }
+        if self.hash_strings
+            && let Some(fields) = event.body.as_array_mut()
+        {
+            for field in fields {
+                if let Some(signature) = field["signature"]
+                    .as_str()
+                    .and_then(|s| Signature::from_str(s).ok())
+                {
+                    hash_strings(&self.hasher, &signature, &mut field["value"]);
+                }
+            }
+        }
+        Some(event)
+    }
+
+    // Note a dropped call, forgetting the ones left unanswered for longer
+    // than a reply could take, so captures of any length can be scrubbed.
+    fn remember(&mut self, sender: Option<String>, serial: u32, timestamp: f64) {
+        self.dropped_calls
+            .retain(|_, since| timestamp - *since < DROPPED_TIMEOUT);
+        self.dropped_calls.insert((sender, serial), timestamp);
+    }
+
+    // Whether a call or signal is on a dropped interface, or is about the
+    // properties of one.
+    fn drops(&self, event: &MessageEvent) -> bool {
+        let dropped = |interface: &str| {
+            self.drop_interfaces
+                .iter()
+                .any(|pattern| glob_match(pattern, interface))
+        };
+        if event.interface.as_deref() == Some(PROPERTIES) {
+            return event
+                .body
+                .get(0)
+                .and_then(|field| field["value"].as_str())
+                .is_some_and(dropped);
+        }
+        event.interface.as_deref().is_some_and(dropped)
+    }
+}
+
+// Replace the strings, object paths and byte arrays in a value, as its
+// event records it, with hashes of them, and empty its signatures. Object
+// paths keep their elements, each hashed, so they are still valid paths, and
+// byte arrays keep their length.
+fn hash_strings(hasher: &RandomState, signature: &Signature, json: &mut serde_json::Value) {
+    use serde_json::Value as Json;
+
+    match (signature, json) {
+        (Signature::Str, json @ Json::String(_)) => {
+            *json = Json::from(hash(hasher, json.as_str().unwrap_or_default()));
+        }
+        (Signature::ObjectPath, json @ Json::String(_)) => {
+            *json = Json::from(hash_path(hasher, json.as_str().unwrap_or_default()));
+        }
+        (Signature::Signature, json @ Json::String(_)) => {
+            *json = Json::from("");
+        }
+        (Signature::Array(element), Json::Array(items))
+            if *element.signature() == Signature::U8 =>
+        {
+            let bytes = items
+                .iter()
+                .map(|item| item.as_u64().unwrap_or_default() as u8)
+                .collect::<Vec<_>>();
+            *items = hash_bytes(hasher, &bytes)
+                .into_iter()
+                .map(Json::from)
+                .collect();
+        }
+        (Signature::Variant, json) => {
+            if let Some(inner) = json["signature"]
+                .as_str()
+                .and_then(|s| Signature::from_str(s).ok())
+            {
+                hash_strings(hasher, &inner, &mut json["value"]);
+            }
+        }
+        (Signature::Array(element), Json::Array(items)) => {
+            for item in items {
+                hash_strings(hasher, element, item);
+            }
+        }
+        (Signature::Dict { key, value }, Json::Object(entries)) => {
+            let hash_key = |k: String| match key.signature() {
+                Signature::Str => hash(hasher, &k),
+                Signature::ObjectPath => hash_path(hasher, &k),
+                Signature::Signature => String::new(),
+                _ => k,
+            };
+            *entries = std::mem::take(entries)
+                .into_iter()
+                .map(|(k, mut entry)| {
+                    hash_strings(hasher, value, &mut entry);
+                    (hash_key(k), entry)
+                })
+                .collect();
+        }
+        (Signature::Structure(fields), Json::Array(items)) => {
+            for (field, item) in fields.iter().zip(items) {
+                hash_strings(hasher, field, item);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn hash(hasher: &RandomState, s: &str) -> String {
+    format!("{:016x}", hasher.hash_one(s))
+}
+
+fn hash_path(hasher: &RandomState, path: &str) -> String {
+    let hashed = path
+        .split('/')
+        .filter(|element| !element.is_empty())
+        .map(|element| format!("/h{}", hash(hasher, element)))
+        .collect::<String>();
+    if hashed.is_empty() {
+        "/".to_string()
+    } else {
+        hashed
+    }
+}
+
+// As many bytes as are given, drawn from hashes of them all.
+fn hash_bytes(hasher: &RandomState, bytes: &[u8]) -> Vec<u8> {
+    let seed = hasher.hash_one(bytes);
+    (0..bytes.len())
+        .map(|i| hasher.hash_one((seed, i / 8)).to_le_bytes()[i % 8])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use zbus::message::Message;
+    use zvariant::{ObjectPath, Value};
+
+    fn event(msg: Message, sender: &str) -> MessageEvent {
+        let mut event = MessageEvent::new(&msg);
+        event.sender = Some(sender.to_string());
+        event
+    }
+
+    #[test]
+    fn test_drop_interfaces() {
+        let mut scrubber = Scrubber::new(vec!["org.freedesktop.secrets*".to_string()], false);
+        let call = Message::method_call("/org/freedesktop/secrets", "OpenSession")
+            .unwrap()
+            .interface("org.freedesktop.secrets.Service")
+            .unwrap()
+            .build(&())
+            .unwrap();
+        let reply = Message::method_return(&call.header())
+            .unwrap()
+            .build(&())
+            .unwrap();
+        let mut reply = event(reply, ":1.2");
+        reply.destination = Some(":1.1".to_string());
+        let get = Message::method_call("/org/freedesktop/secrets", "GetAll")
+            .unwrap()
+            .interface(PROPERTIES)
+            .unwrap()
+            .build(&("org.freedesktop.secrets.Service",))
+            .unwrap();
+        let other = Message::signal("/org/example", "org.example.Test", "Changed")
+            .unwrap()
+            .build(&())
+            .unwrap();
+
+        assert!(scrubber.scrub(event(call, ":1.1")).is_none());
+        assert!(
+            scrubber.scrub(reply).is_none(),
+            "Replies to dropped calls should be dropped"
+        );
+        assert!(scrubber.scrub(event(get, ":1.1")).is_none());
+        assert!(scrubber.scrub(event(other, ":1.1")).is_some());
+    }
+
+    #[test]
+    fn test_hash_strings() {
+        let mut scrubber = Scrubber::new(Vec::new(), true);
+        let msg = Message::signal("/org/example", "org.example.Test", "Changed")
+            .unwrap()
+            .build(&(
+                "secret",
+                ObjectPath::try_from("/org/example/secret").unwrap(),
+                HashMap::from([
+                    ("Name", Value::from("secret")),
+                    ("Count", Value::from(3u32)),
+                ]),
+                b"secret".to_vec(),
+                Signature::from_str("s").unwrap(),
+            ))
+            .unwrap();
+        let scrubbed = scrubber.scrub(event(msg, ":1.1")).unwrap();
+        let values = scrubbed.body_values().unwrap();
+
+        let Value::Str(string) = &values[0] else {
+            panic!("Strings should stay strings: {:?}", values[0]);
+        };
+        assert_ne!(string.as_str(), "secret");
+        let Value::ObjectPath(path) = &values[1] else {
+            panic!("Object paths should stay valid paths: {:?}", values[1]);
+        };
+        assert_eq!(path.as_str().matches('/').count(), 3);
+        let dict = HashMap::<String, Value>::try_from(values[2].try_clone().unwrap()).unwrap();
+        assert!(
+            !dict.contains_key("Name"),
+            "Dictionary keys should be hashed"
+        );
+        assert!(
+            dict.values()
+                .any(|value| *value == Value::from(string.as_str())),
+            "The same string should get the same hash"
+        );
+        assert!(dict.values().any(|value| *value == Value::from(3u32)));
+        let bytes = Vec::<u8>::try_from(values[3].try_clone().unwrap()).unwrap();
+        assert_eq!(bytes.len(), 6, "Byte arrays should keep their length");
+        assert_ne!(bytes, b"secret");
+        assert_eq!(values[4], Value::from(Signature::from_str("").unwrap()));
+    }
+
+    #[test]
+    fn test_forget_unanswered_calls() {
+        let mut scrubber = Scrubber::new(Vec::new(), false);
+        scrubber.remember(None, 1, 100.0);
+        scrubber.remember(None, 2, 100.0 + DROPPED_TIMEOUT / 2.0);
+        assert_eq!(scrubber.dropped_calls.len(), 2);
+
+        scrubber.remember(None, 3, 100.0 + DROPPED_TIMEOUT);
+        assert!(
+            !scrubber.dropped_calls.contains_key(&(None, 1)),
+            "Calls unanswered for too long should be forgotten"
+        );
+        assert_eq!(scrubber.dropped_calls.len(), 2);
+    }
+}
@@ -0,0 +1,948 @@
+use crate::events::{MessageEvent, MessageType, parse_event, parse_gap, parse_note};
+use crate::explain::split_signature;
+use futures_lite::StreamExt;
+use std::collections::HashMap;
+use std::fs;
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use zbus::message::{Message, Type};
+use zbus::names::InterfaceName;
+use zbus::{Connection, DBusError, MessageStream, Result};
+use zvariant::{ObjectPath, OwnedObjectPath, Signature, Structure, StructureBuilder, Value};
+
+/// The reply body of a method, or `None` for methods without return values.
+/// Errors are sent back to the caller as D-Bus error replies.
+pub type Reply = zbus::fdo::Result<Option<Structure<'static>>>;
+
+/// A method implementation. It receives the method call and returns the
+/// future of its reply. Each call is answered in a task of its own, so a slow
+/// method does not hold up calls from other clients.
+pub type Handler =
+    Box<dyn Fn(Message) -> Pin<Box<dyn Future<Output = Reply> + Send>> + Send + Sync>;
+
+/// Make a [`Handler`] of an async function or closure.
+pub fn handler<F, Fut>(method: F) -> Handler
+where
+    F: Fn(Message) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Reply> + Send + 'static,
+{
+    Box::new(move |call| Box::pin(method(call)))
+}
+
+/// A method served by [`serve_object`]: its handler, and the signature of
+/// the arguments it takes when it declares one. Calls whose bodies have
+/// another signature are answered with InvalidArgs before reaching the
+/// handler, as a real service would answer them.
+pub struct Method {
+    signature: Option<String>,
+    args: Vec<Signature>,
+    handler: Handler,
+}
+
+impl Method {
+    // A method taking arguments of `signature`, such as `ssu`, or none for
+    // an empty one.
+    pub fn new(signature: &str, handler: Handler) -> Result<Self> {
+        let args = split_signature(signature)?;
+        Ok(Self {
+            signature: Some(args.iter().map(|arg| arg.to_string()).collect()),
+            args,
+            handler,
+        })
+    }
+
+    // A method taking any arguments, such as one standing in for a method
+    // whose signature is not known.
+    pub fn any(handler: Handler) -> Self {
+        Self {
+            signature: None,
+            args: Vec::new(),
+            handler,
+        }
+    }
+}
+
+/// An interface to serve with [`serve_object`]: its name, the methods it
+/// answers calls to, and the properties introspection lists for it, each
+/// with its signature. Reading and setting the properties is left to an
+/// `org.freedesktop.DBus.Properties` interface served alongside it, such as
+/// the one [`Store::serve`](crate::store::Store::serve) returns.
+pub struct Interface {
+    name: String,
+    methods: HashMap<String, Method>,
+    properties: Vec<(String, String)>,
+}
+
+impl Interface {
+    pub fn new(name: &str, methods: HashMap<&str, Method>) -> Result<Self> {
+        Ok(Self {
+            name: InterfaceName::try_from(name)?.to_string(),
+            methods: methods
+                .into_iter()
+                .map(|(name, method)| (name.to_string(), method))
+                .collect(),
+            properties: Vec::new(),
+        })
+    }
+
+    // List properties, by name and signature, as readable and writable in
+    // the introspection data.
+    pub fn with_properties(mut self, properties: Vec<(String, String)>) -> Self {
+        self.properties = properties;
+        self
+    }
+}
+
+/// The interfaces served at a path on a connection by [`serve_object`]. They
+/// are removed from the bus when this is dropped.
+pub struct ServedObject {
+    task: tokio::task::JoinHandle<zbus::Error>,
+}
+
+impl ServedObject {
+    // Wait until serving stops by itself, as it does when the connection is
+    // lost, returning why. A program serving until interrupted should wait
+    // for this too, or it would go on running with nothing served.
+    pub async fn closed(&mut self) -> zbus::Error {
+        match (&mut self.task).await {
+            Ok(error) => error,
+            Err(e) => zbus::Error::Failure(format!("Serving stopped: {}", e)),
+        }
+    }
+}
+
+impl Drop for ServedObject {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+// The interfaces at one path, and how to answer calls to their methods.
+struct Object {
+    path: OwnedObjectPath,
+    interfaces: Vec<Interface>,
+    on_warning: Option<fn(&str)>,
+}
+
+impl Object {
+    // Build the reply to a method call sent to the connection. Every call
+    // gets one, so no caller waits for a reply that never comes: calls to
+    // the interfaces served are answered by their handlers, `Peer` calls and
+    // `Introspect` as a service would answer them, and anything else with
+    // the error saying what is missing.
+    async fn reply(&self, call: Message) -> Result<Message> {
+        let header = call.header();
+        let member = header.member().map(|m| m.to_string()).unwrap_or_default();
+        let path = header.path().map(|p| p.as_str()).unwrap_or_default();
+        let interface = match header.interface().map(|i| i.as_str()) {
+            Some("org.freedesktop.DBus.Peer") => return peer(&header, &member),
+            Some("org.freedesktop.DBus.Introspectable") if member == "Introspect" => {
+                let xml = if path == self.path.as_str() {
+                    self.introspect()
+                } else if let Some(child) = self.child_of(path) {
+                    format!(
+                        "{}  <node name=\"{}\"/>\n</node>\n",
+                        INTROSPECT_HEADER, child
+                    )
+                } else {
+                    return zbus::fdo::Error::UnknownObject(format!(
+                        "No object at path '{}'",
+                        path
+                    ))
+                    .create_reply(&header);
+                };
+                return Message::method_return(&header)?.build(&(xml,));
+            }
+            _ if path != self.path.as_str() => {
+                return zbus::fdo::Error::UnknownObject(format!("No object at path '{}'", path))
+                    .create_reply(&header);
+            }
+            Some("org.freedesktop.DBus.Introspectable") => {
+                return zbus::fdo::Error::UnknownMethod(format!(
+                    "Unknown method '{}' on interface 'org.freedesktop.DBus.Introspectable'",
+                    member
+                ))
+                .create_reply(&header);
+            }
+            Some(name) => match self.interfaces.iter().find(|i| i.name == name) {
+                Some(interface) => interface,
+                None => {
+                    return zbus::fdo::Error::UnknownInterface(format!(
+                        "Unknown interface '{}'",
+                        name
+                    ))
+                    .create_reply(&header);
+                }
+            },
+            // Calls without an interface are matched by member name alone.
+            None => match self
+                .interfaces
+                .iter()
+                .find(|i| i.methods.contains_key(member.as_str()))
+            {
+                Some(interface) => interface,
+                None => {
+                    return zbus::fdo::Error::UnknownMethod(format!("Unknown method '{}'", member))
+                        .create_reply(&header);
+                }
+            },
+        };
+
+        let result = match interface.methods.get(member.as_str()) {
+            Some(method) => {
+                let signature = call.body().signature().to_string_no_parens();
+                match &method.signature {
+                    Some(expected) if *expected != signature => {
+                        if let Some(warn) = self.on_warning {
+                            warn(&format!(
+                                "rejected a call to {}.{} from {}: it has arguments of signature '{}', expected '{}'",
+                                interface.name,
+                                member,
+                                header.sender().map_or("-", |s| s.as_str()),
+                                signature,
+                                expected
+                            ));
+                        }
+                        Err(zbus::fdo::Error::InvalidArgs(format!(
+                            "Method '{}' takes arguments of signature '{}', not '{}'",
+                            member, expected, signature
+                        )))
+                    }
+                    _ => (method.handler)(call.clone()).await,
+                }
+            }
+            None => Err(zbus::fdo::Error::UnknownMethod(format!(
+                "Unknown method '{}' on interface '{}'",
+                member, interface.name
+            ))),
+        };
+        match result {
+            Ok(Some(body)) => Message::method_return(&header)?.build(&body),
+            Ok(None) => Message::method_return(&header)?.build(&()),
+            Err(error) => error.create_reply(&header),
+        }
+    }
+
+    // Introspection data for the object, with every interface served at its
+    // path. Methods only declare the arguments they take, so no return
+    // values are listed.
+    fn introspect(&self) -> String {
+        let mut xml = String::from(INTROSPECT_HEADER);
+        for interface in &self.interfaces {
+            let mut methods = interface.methods.iter().collect::<Vec<_>>();
+            methods.sort_by_key(|(name, _)| name.as_str());
+
+            xml.push_str(&format!("  <interface name=\"{}\">\n", interface.name));
+            for (name, method) in methods {
+                if method.args.is_empty() {
+                    xml.push_str(&format!("    <method name=\"{}\"/>\n", name));
+                    continue;
+                }
+                xml.push_str(&format!("    <method name=\"{}\">\n", name));
+                for arg in &method.args {
+                    xml.push_str(&format!("      <arg type=\"{}\" direction=\"in\"/>\n", arg));
+                }
+                xml.push_str("    </method>\n");
+            }
+            for (name, signature) in &interface.properties {
+                xml.push_str(&format!(
+                    "    <property name=\"{}\" type=\"{}\" access=\"readwrite\"/>\n",
+                    name, signature
+                ));
+            }
+            xml.push_str("  </interface>\n");
+        }
+        xml.push_str("</node>\n");
+        xml
+    }
+
+    // The element below `path` on the way to the object, if `path` is one
+    // of its ancestors, so introspecting from `/` finds it.
+    fn child_of(&self, path: &str) -> Option<&str> {
+        let rest = if path == "/" {
+            self.path.as_str().strip_prefix('/')
+        } else {
+            self.path.as_str().strip_prefix(path)?.strip_prefix('/')
+        }?;
+        rest.split('/').next().filter(|child| !child.is_empty())
+    }
+}
+
+const INTROSPECT_HEADER: &str = "<!DOCTYPE node PUBLIC \"-//freedesktop//DTD D-BUS Object Introspection 1.0//EN\"\n \"http://www.freedesktop.org/standards/dbus/1.0/introspect.dtd\">\n<node>\n";
+
+// Answer a call to `org.freedesktop.DBus.Peer`, which every object has, such
+// as the `Ping` of `wait-until-ready`.
+fn peer(header: &zbus::message::Header<'_>, member: &str) -> Result<Message> {
+    match member {
+        "Ping" => Message::method_return(header)?.build(&()),
+        "GetMachineId" => match machine_id() {
+            Some(id) => Message::method_return(header)?.build(&(id,)),
+            None => zbus::fdo::Error::Failed("Failed to read the machine ID".to_string())
+                .create_reply(header),
+        },
+        _ => zbus::fdo::Error::UnknownMethod(format!(
+            "Unknown method '{}' on interface 'org.freedesktop.DBus.Peer'",
+            member
+        ))
+        .create_reply(header),
+    }
+}
+
+// The ID of this machine, where systemd or D-Bus keeps it.
+fn machine_id() -> Option<String> {
+    ["/etc/machine-id", "/var/lib/dbus/machine-id"]
+        .iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .map(|id| id.trim().to_string())
+        .find(|id| !id.is_empty())
+}
+
+// Serve interfaces at `path` on `connection`, answering each method call with
+// the handler registered for its member. Calls to unknown members of the
+// interfaces get an UnknownMethod error, and calls with arguments other than
+// a method declares get InvalidArgs. `Introspect` is answered once for the
+// path, listing every interface, and on the paths above it with the way
+// down to it; `org.freedesktop.DBus.Peer` is answered on any path. Every
+// other call gets UnknownObject, UnknownInterface or UnknownMethod. Serving
+// stops when the connection is lost, which `ServedObject::closed` reports.
+//
+// This works on the raw message stream, so it doesn't need the derive macros,
+// but as it answers every method call the connection receives, it must not
+// be combined with the connection's ObjectServer or another call to this.
+// Requesting a well-known name is up to the caller.
+pub async fn serve_object(
+    connection: &Connection,
+    path: &str,
+    interfaces: Vec<Interface>,
+) -> Result<ServedObject> {
+    serve_object_with(connection, path, interfaces, None).await
+}
+
+// Serve interfaces like `serve_object`, calling `on_warning` with each
+// problem met while serving that no caller is told about, such as a call
+// rejected for its arguments. Warnings are dropped without it.
+pub async fn serve_object_with(
+    connection: &Connection,
+    path: &str,
+    interfaces: Vec<Interface>,
+    on_warning: Option<fn(&str)>,
+) -> Result<ServedObject> {
+    let object = Arc::new(Object {
+        path: ObjectPath::try_from(path)?.into(),
+        interfaces,
+        on_warning,
+    });
+
+    // Subscribe before returning so no call sent afterwards is missed.
+    let mut stream = MessageStream::from(connection);
+    let connection = connection.clone();
+    let task = tokio::spawn(async move {
+        while let Some(call) = stream.next().await {
+            // The connection stops reading after failing to read a message,
+            // so nothing more will come.
+            let call = match call {
+                Ok(call) => call,
+                Err(e) => {
+                    return zbus::Error::Failure(format!(
+                        "Stopped serving {}: the connection failed: {}",
+                        object.path.as_str(),
+                        e
+                    ));
+                }
+            };
+            if call.message_type() != Type::MethodCall {
+                continue;
+            }
+            let object = object.clone();
+            let connection = connection.clone();
+            tokio::spawn(async move {
+                if let Ok(reply) = object.reply(call).await {
+                    // The caller may have gone away; there is no one to report to.
+                    let _ = connection.send(&reply).await;
+                }
+            });
+        }
+        zbus::Error::Failure(format!(
+            "Stopped serving {}: the connection was closed",
+            object.path.as_str()
+        ))
+    });
+
+    Ok(ServedObject { task })
+}
+
+/// A signal seen in a capture, to be emitted again.
+#[derive(Debug)]
+pub struct CapturedSignal {
+    /// How long after the signal before it this one was seen. The first has
+    /// none, and is emitted as soon as the replay is triggered.
+    pub delay: Duration,
+    pub path: String,
+    pub interface: String,
+    pub member: String,
+    pub body: Vec<Value<'static>>,
+}
+
+impl CapturedSignal {
+    // A new message for the signal, with a serial of its own, so a capture
+    // can be replayed any number of times.
+    pub fn message(&self) -> Result<Message> {
+        let builder = Message::signal(
+            self.path.as_str(),
+            self.interface.as_str(),
+            self.member.as_str(),
+        )?;
+        if self.body.is_empty() {
+            return builder.build(&());
+        }
+        let body = self
+            .body
+            .iter()
+            .try_fold(StructureBuilder::new(), |body, field| {
+                Ok::<_, zbus::Error>(body.append_field(field.try_clone()?))
+            })?
+            .build()?;
+        builder.build(&body)
+    }
+}
+
+/// The signals of a capture written by `monitor` or `call --debug-log`, to
+/// be emitted again in the same order and at the same intervals, so client
+/// developers can reproduce a sequence of events seen on a real system.
+#[derive(Debug)]
+pub struct Replay {
+    signals: Vec<CapturedSignal>,
+    pacing: Pacing,
+}
+
+/// How long a replay waits between signals.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pacing {
+    /// The recorded delays divided by the factor, so `2.0` replays twice as
+    /// fast as captured and `0.5` half as fast.
+    Speed(f64),
+    /// Every signal at once, one after another.
+    NoDelay,
+}
+
+impl Default for Pacing {
+    fn default() -> Self {
+        Self::Speed(1.0)
+    }
+}
+
+impl FromStr for Pacing {
+    type Err = String;
+
+    // A speed factor, such as `2x` or `0.5`.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let factor = s.strip_suffix('x').unwrap_or(s);
+        match factor.parse::<f64>() {
+            Ok(factor) if factor.is_finite() && factor > 0.0 => Ok(Self::Speed(factor)),
+            _ => Err(format!(
+                "Invalid speed '{}': expected a factor above zero, such as 2x or 0.5x",
+                s
+            )),
+        }
+    }
+}
+
+impl Replay {
+    // Load the signals of a capture file. Other messages in it, and the
+    // markers of gaps and notes in it, are skipped.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path).map_err(|e| {
+            zbus::Error::Failure(format!("Failed to read '{}': {}", path.display(), e))
+        })?;
+        let events = contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| {
+                !line.trim().is_empty() && parse_gap(line).is_none() && parse_note(line).is_none()
+            })
+            .map(|(i, line)| {
+                parse_event(line).map_err(|e| {
+                    zbus::Error::Failure(format!("'{}' line {}: {}", path.display(), i + 1, e))
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Self::from_events(events)
+    }
+
+    // Keep the signals of a sequence of events, timed by their timestamps.
+    // Signals carry their own path, interface and member, and are replayed
+    // as broadcasts whoever they were sent to.
+    pub fn from_events(events: impl IntoIterator<Item = MessageEvent>) -> Result<Self> {
+        let mut signals = Vec::new();
+        let mut previous: Option<f64> = None;
+        for event in events {
+            if event.message_type != MessageType::Signal {
+                continue;
+            }
+            let body = event.body_values();
+            let (Some(path), Some(interface), Some(member)) =
+                (event.path, event.interface, event.member)
+            else {
+                return Err(zbus::Error::Failure(format!(
+                    "Signal #{} in the capture has no path, interface or member",
+                    event.serial
+                )));
+            };
+            let body = body.map_err(|e| {
+                zbus::Error::Failure(format!("Signal {}.{}: {}", interface, member, e))
+            })?;
+            let delay = match previous {
+                Some(previous) => Duration::try_from_secs_f64(
+                    (event.timestamp - previous).max(0.0),
+                )
+                .map_err(|e| {
+                    zbus::Error::Failure(format!(
+                        "Signal #{} in the capture is too long after the one before it: {}",
+                        event.serial, e
+                    ))
+                })?,
+                None => Duration::ZERO,
+            };
+            previous = Some(event.timestamp);
+            signals.push(CapturedSignal {
+                delay,
+                path,
+                interface,
+                member,
+                body,
+            });
+        }
+        Ok(Self {
+            signals,
+            pacing: Pacing::default(),
+        })
+    }
+
+    // Replay at another pace than the capture's.
+    pub fn with_pacing(mut self, pacing: Pacing) -> Self {
+        self.pacing = pacing;
+        self
+    }
+
+    pub fn signals(&self) -> &[CapturedSignal] {
+        &self.signals
+    }
+
+    // How long to wait before emitting a signal, at the replay's pace. A
+    // pace so slow the wait cannot be represented is an error.
+    pub fn delay(&self, signal: &CapturedSignal) -> Result<Duration> {
+        match self.pacing {
+            Pacing::Speed(factor) => {
+                Duration::try_from_secs_f64(signal.delay.as_secs_f64() / factor).map_err(|e| {
+                    zbus::Error::Failure(format!(
+                        "Invalid speed {:?}x for a gap of {} seconds: {}",
+                        factor,
+                        signal.delay.as_secs_f64(),
+                        e
+                    ))
+                })
+            }
+            Pacing::NoDelay => Ok(Duration::ZERO),
+        }
+    }
+
+    // Emit the signals on `connection`, waiting between them as long as the
+    // capture did, or as its pacing says.
+    pub async fn emit(&self, connection: &Connection) -> Result<()> {
+        for signal in &self.signals {
+            tokio::time::sleep(self.delay(signal)?).await;
+            connection.send(&signal.message()?).await?;
+        }
+        Ok(())
+    }
+
+    // A method handler that starts emitting the signals whenever it is
+    // called, replying at once rather than when they have all been sent.
+    // Calls while a replay is running start another one alongside it. A
+    // replay that fails after the reply is reported to `on_warning`, if
+    // given.
+    pub fn trigger(
+        self: Arc<Self>,
+        connection: &Connection,
+        on_warning: Option<fn(&str)>,
+    ) -> Handler {
+        let connection = connection.clone();
+        handler(move |_call| {
+            let replay = self.clone();
+            let connection = connection.clone();
+            tokio::spawn(async move {
+                if let Err(e) = replay.emit(&connection).await
+                    && let Some(warn) = on_warning
+                {
+                    warn(&format!("failed to replay signals: {}", e));
+                }
+            });
+            async { Ok(None) }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_body;
+
+    fn object() -> Object {
+        let mut methods: HashMap<&str, Method> = HashMap::new();
+        methods.insert(
+            "Ping",
+            Method::any(handler(|_| async {
+                Ok(Some(build_body(vec!["string:pong"])?))
+            })),
+        );
+        methods.insert(
+            "Reset",
+            Method::new("", handler(|_| async { Ok(None) })).unwrap(),
+        );
+        methods.insert(
+            "Echo",
+            Method::new(
+                "su",
+                handler(|call| async move {
+                    let (value, _): (String, u32) = call.body().deserialize()?;
+                    Ok(Some(build_body(vec![&format!("string:{}", value)])?))
+                }),
+            )
+            .unwrap(),
+        );
+        let stop = HashMap::from([("Stop", Method::any(handler(|_| async { Ok(None) })))]);
+        Object {
+            path: ObjectPath::try_from("/org/example/Debug").unwrap().into(),
+            interfaces: vec![
+                Interface::new("org.example.Debug", methods).unwrap(),
+                Interface::new("org.example.Control", stop)
+                    .unwrap()
+                    .with_properties(vec![("Level".to_string(), "u".to_string())]),
+            ],
+            on_warning: None,
+        }
+    }
+
+    fn call(path: &str, interface: &str, member: &str) -> Message {
+        Message::method_call(path, member)
+            .unwrap()
+            .interface(interface)
+            .unwrap()
+            .build(&())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_reply_from_handler() {
+        let reply = object()
+            .reply(call("/org/example/Debug", "org.example.Debug", "Ping"))
+            .await
+            .unwrap();
+        assert_eq!(reply.message_type(), Type::MethodReturn);
+        let (value,): (String,) = reply.body().deserialize().unwrap();
+        assert_eq!(value, "pong");
+
+        let reply = object()
+            .reply(call("/org/example/Debug", "org.example.Debug", "Reset"))
+            .await
+            .unwrap();
+        assert!(
+            reply.body().data().is_empty(),
+            "Handlers returning None should produce an empty reply"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unknown_method() {
+        let reply = object()
+            .reply(call("/org/example/Debug", "org.example.Debug", "Nope"))
+            .await
+            .unwrap();
+        assert_eq!(
+            reply.header().error_name().map(|e| e.as_str()),
+            Some("org.freedesktop.DBus.Error.UnknownMethod")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_args() {
+        let echo = |body: &(&str, u32)| {
+            Message::method_call("/org/example/Debug", "Echo")
+                .unwrap()
+                .interface("org.example.Debug")
+                .unwrap()
+                .build(body)
+                .unwrap()
+        };
+        let reply = object().reply(echo(&("hi", 1))).await.unwrap();
+        let (value,): (String,) = reply.body().deserialize().unwrap();
+        assert_eq!(value, "hi");
+
+        let reply = object()
+            .reply(call("/org/example/Debug", "org.example.Debug", "Echo"))
+            .await
+            .unwrap();
+        assert_eq!(
+            reply.header().error_name().map(|e| e.as_str()),
+            Some("org.freedesktop.DBus.Error.InvalidArgs")
+        );
+        let (error,): (String,) = reply.body().deserialize().unwrap();
+        assert_eq!(
+            error,
+            "Method 'Echo' takes arguments of signature 'su', not ''"
+        );
+
+        static WARNINGS: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(Vec::new());
+        let object = Object {
+            on_warning: Some(|warning| WARNINGS.lock().unwrap().push(warning.to_string())),
+            ..object()
+        };
+        object
+            .reply(call("/org/example/Debug", "org.example.Debug", "Echo"))
+            .await
+            .unwrap();
+        assert_eq!(
+            *WARNINGS.lock().unwrap(),
+            vec![
+                "rejected a call to org.example.Debug.Echo from -: it has arguments of signature '', expected 'su'"
+                    .to_string()
+            ],
+            "The rejected call should be reported to the caller"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_answers_every_call() {
+        let object = object();
+        let error = |reply: Message| reply.header().error_name().map(|e| e.as_str().to_string());
+        let reply = object
+            .reply(call("/org/example/Control", "org.example.Control", "Stop"))
+            .await
+            .unwrap();
+        assert_eq!(
+            error(reply).as_deref(),
+            Some("org.freedesktop.DBus.Error.UnknownObject")
+        );
+        let reply = object
+            .reply(call("/org/example/Debug", "org.example.Other", "Ping"))
+            .await
+            .unwrap();
+        assert_eq!(
+            error(reply).as_deref(),
+            Some("org.freedesktop.DBus.Error.UnknownInterface")
+        );
+        let restart = Message::method_call("/org/example/Debug", "Restart")
+            .unwrap()
+            .build(&())
+            .unwrap();
+        assert_eq!(
+            error(object.reply(restart).await.unwrap()).as_deref(),
+            Some("org.freedesktop.DBus.Error.UnknownMethod")
+        );
+
+        let stop = Message::method_call("/org/example/Debug", "Stop")
+            .unwrap()
+            .build(&())
+            .unwrap();
+        assert_eq!(
+            object.reply(stop).await.unwrap().message_type(),
+            Type::MethodReturn,
+            "Calls without an interface should be matched by member"
+        );
+        for path in ["/org/example/Debug", "/"] {
+            let reply = object
+                .reply(call(path, "org.freedesktop.DBus.Peer", "Ping"))
+                .await
+                .unwrap();
+            assert_eq!(
+                reply.message_type(),
+                Type::MethodReturn,
+                "Ping should be answered on {}",
+                path
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_introspect_parents() {
+        let object = object();
+        for (path, child) in [("/", "org"), ("/org", "example"), ("/org/example", "Debug")] {
+            let reply = object
+                .reply(call(
+                    path,
+                    "org.freedesktop.DBus.Introspectable",
+                    "Introspect",
+                ))
+                .await
+                .unwrap();
+            let (xml,): (String,) = reply.body().deserialize().unwrap();
+            assert!(
+                xml.contains(&format!("<node name=\"{}\"/>", child)),
+                "{} should list {}: {}",
+                path,
+                child,
+                xml
+            );
+        }
+        for path in ["/org/ex", "/org/example/Debug/Child", "/com"] {
+            let reply = object
+                .reply(call(
+                    path,
+                    "org.freedesktop.DBus.Introspectable",
+                    "Introspect",
+                ))
+                .await
+                .unwrap();
+            assert_eq!(
+                reply.header().error_name().map(|e| e.as_str()),
+                Some("org.freedesktop.DBus.Error.UnknownObject"),
+                "{} should not be an object",
+                path
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_closed_when_connection_lost() {
+        let (server, client) = tokio::net::UnixStream::pair().unwrap();
+        let guid = zbus::Guid::generate();
+        let (server, client) = tokio::try_join!(
+            zbus::connection::Builder::unix_stream(server)
+                .server(guid)
+                .unwrap()
+                .p2p()
+                .build(),
+            zbus::connection::Builder::unix_stream(client).p2p().build(),
+        )
+        .unwrap();
+        let mut served = serve_object(&server, "/org/example/Debug", Vec::new())
+            .await
+            .unwrap();
+
+        let reply = client
+            .call_method(
+                None::<&str>,
+                "/",
+                Some("org.freedesktop.DBus.Peer"),
+                "Ping",
+                &(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(reply.message_type(), Type::MethodReturn);
+
+        drop(client);
+        let error = tokio::time::timeout(Duration::from_secs(5), served.closed())
+            .await
+            .expect("Serving should stop when the connection is lost");
+        assert!(
+            error
+                .to_string()
+                .contains("Stopped serving /org/example/Debug"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_replay_from_events() {
+        let signal = |member: &str, value: &str| {
+            Message::signal("/org/example/Debug", "org.example.Debug", member)
+                .unwrap()
+                .build(&(value,))
+                .unwrap()
+        };
+        let mut events = [
+            MessageEvent::new(&signal("Started", "a")),
+            MessageEvent::new(&call("/org/example/Debug", "org.example.Debug", "Ping")),
+            MessageEvent::new(&signal("Finished", "b")),
+        ];
+        events[0].timestamp = 100.0;
+        events[1].timestamp = 100.5;
+        events[2].timestamp = 101.25;
+
+        let replay = Replay::from_events(events).unwrap();
+        let signals = replay.signals();
+        assert_eq!(signals.len(), 2, "Only signals should be replayed");
+        assert_eq!(signals[0].delay, Duration::ZERO);
+        assert_eq!(signals[1].delay, Duration::from_millis(1250));
+        assert_eq!(signals[1].member, "Finished");
+        assert_eq!(signals[1].body, vec![Value::from("b")]);
+
+        assert_eq!(
+            replay.delay(&signals[1]).unwrap(),
+            Duration::from_millis(1250)
+        );
+
+        let message = signals[1].message().unwrap();
+        assert_eq!(message.message_type(), Type::Signal);
+        let (value,): (String,) = message.body().deserialize().unwrap();
+        assert_eq!(value, "b");
+    }
+
+    #[test]
+    fn test_replay_pacing() {
+        assert_eq!("2x".parse(), Ok(Pacing::Speed(2.0)));
+        assert_eq!("0.5".parse(), Ok(Pacing::Speed(0.5)));
+        for bad in ["0x", "-1x", "fast", "infx"] {
+            assert!(bad.parse::<Pacing>().is_err(), "{} should be rejected", bad);
+        }
+
+        let signal = Message::signal("/org/example/Debug", "org.example.Debug", "Tick")
+            .unwrap()
+            .build(&())
+            .unwrap();
+        let mut events = [MessageEvent::new(&signal), MessageEvent::new(&signal)];
+        events[0].timestamp = 10.0;
+        events[1].timestamp = 13.0;
+        let replay = Replay::from_events(events)
+            .unwrap()
+            .with_pacing(Pacing::Speed(2.0));
+        assert_eq!(
+            replay.delay(&replay.signals()[1]).unwrap(),
+            Duration::from_millis(1500)
+        );
+        let replay = replay.with_pacing(Pacing::NoDelay);
+        assert_eq!(replay.delay(&replay.signals()[1]).unwrap(), Duration::ZERO);
+
+        let replay = replay.with_pacing("1e-300x".parse().unwrap());
+        assert!(
+            replay.delay(&replay.signals()[1]).is_err(),
+            "A wait too long to represent should be an error, not a panic"
+        );
+        assert_eq!(
+            replay.delay(&replay.signals()[0]).unwrap(),
+            Duration::ZERO,
+            "The first signal should not wait at any pace"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_introspect_lists_methods() {
+        let reply = object()
+            .reply(call(
+                "/org/example/Debug",
+                "org.freedesktop.DBus.Introspectable",
+                "Introspect",
+            ))
+            .await
+            .unwrap();
+        let (xml,): (String,) = reply.body().deserialize().unwrap();
+        assert_eq!(
+            xml.matches("<interface name=\"org.example.Debug\">")
+                .count(),
+            1
+        );
+        assert!(xml.contains(
+            "<interface name=\"org.example.Control\">\n    <method name=\"Stop\"/>\n    <property name=\"Level\" type=\"u\" access=\"readwrite\"/>"
+        ));
+        assert!(xml.contains("<method name=\"Ping\"/>\n    <method name=\"Reset\"/>"));
+        assert!(xml.contains(
+            "<method name=\"Echo\">\n      <arg type=\"s\" direction=\"in\"/>\n      <arg type=\"u\" direction=\"in\"/>\n    </method>"
+        ));
+    }
+}
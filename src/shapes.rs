@@ -0,0 +1,269 @@
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+use zbus::Result;
+use zvariant::Value;
+
+use crate::format::value_to_json;
+use crate::numbers::Numbers;
+
+/// A well-known reply shape: an array of structures whose fields have names.
+///
+/// Shapes are matched against the reply signature and, when given, the
+/// interface and member of the call. Generic signatures such as `a(ss)` should
+/// always be restricted to a member so unrelated replies are not renamed.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct Shape {
+    pub name: String,
+    pub signature: String,
+    #[serde(default)]
+    pub interface: Option<String>,
+    #[serde(default)]
+    pub member: Option<String>,
+    pub fields: Vec<String>,
+}
+
+/// A set of shapes, consulted in order.
+#[derive(Clone, Debug, Default)]
+pub struct Registry {
+    shapes: Vec<Shape>,
+}
+
+// Name, signature, interface, member and field names of a built-in shape.
+type BuiltinShape = (
+    &'static str,
+    &'static str,
+    Option<&'static str>,
+    Option<&'static str>,
+    &'static [&'static str],
+);
+
+// Built-in shapes for common system services.
+const BUILTIN: &[BuiltinShape] = &[
+    (
+        "systemd-unit",
+        "a(ssssssouso)",
+        Some("org.freedesktop.systemd1.Manager"),
+        None,
+        &[
+            "name",
+            "description",
+            "load_state",
+            "active_state",
+            "sub_state",
+            "following",
+            "unit_path",
+            "job_id",
+            "job_type",
+            "job_path",
+        ],
+    ),
+    (
+        "systemd-unit-file",
+        "a(ss)",
+        Some("org.freedesktop.systemd1.Manager"),
+        Some("ListUnitFiles"),
+        &["path", "state"],
+    ),
+    (
+        "systemd-job",
+        "a(usssoo)",
+        Some("org.freedesktop.systemd1.Manager"),
+        Some("ListJobs"),
+        &["id", "unit", "type", "state", "job_path", "unit_path"],
+    ),
+    (
+        "logind-session",
+        "a(susso)",
+        Some("org.freedesktop.login1.Manager"),
+        None,
+        &["session_id", "uid", "user", "seat_id", "session_path"],
+    ),
+    (
+        "logind-user",
+        "a(uso)",
+        Some("org.freedesktop.login1.Manager"),
+        Some("ListUsers"),
+        &["uid", "name", "user_path"],
+    ),
+    (
+        "logind-seat",
+        "a(so)",
+        Some("org.freedesktop.login1.Manager"),
+        Some("ListSeats"),
+        &["seat_id", "seat_path"],
+    ),
+    (
+        "networkmanager-ip4-route",
+        "aau",
+        Some("org.freedesktop.NetworkManager.IP4Config"),
+        Some("Routes"),
+        &["destination", "prefix", "next_hop", "metric"],
+    ),
+];
+
+impl Registry {
+    // A registry holding only the built-in shapes.
+    pub fn builtin() -> Self {
+        let shapes = BUILTIN
+            .iter()
+            .map(|(name, signature, interface, member, fields)| Shape {
+                name: name.to_string(),
+                signature: signature.to_string(),
+                interface: interface.map(str::to_string),
+                member: member.map(str::to_string),
+                fields: fields.iter().map(|f| f.to_string()).collect(),
+            })
+            .collect();
+        Self { shapes }
+    }
+
+    // Add shapes from a JSON registry file holding an array of shape objects.
+    // Shapes from files take precedence over those already registered.
+    pub fn load_file(&mut self, path: &Path) -> Result<()> {
+        let contents = fs::read_to_string(path).map_err(|e| {
+            zbus::Error::Failure(format!("Failed to read '{}': {}", path.display(), e))
+        })?;
+        let shapes: Vec<Shape> = serde_json::from_str(&contents).map_err(|e| {
+            zbus::Error::Failure(format!(
+                "Invalid formats registry '{}': {}",
+                path.display(),
+                e
+            ))
+        })?;
+        self.shapes.splice(0..0, shapes);
+        Ok(())
+    }
+
+    // Find the shape matching a value returned by the given call.
+    pub fn find(
+        &self,
+        value: &Value,
+        interface: Option<&str>,
+        member: Option<&str>,
+    ) -> Option<&Shape> {
+        let signature = value.value_signature().to_string();
+        self.shapes.iter().find(|shape| {
+            shape.signature == signature
+                && shape
+                    .interface
+                    .as_deref()
+                    .is_none_or(|i| Some(i) == interface)
+                && shape.member.as_deref().is_none_or(|m| Some(m) == member)
+        })
+    }
+}
+
+impl Shape {
+    // Split an array matching this shape into rows of named JSON values, with
+    // numbers written as `numbers` says. Each element may be a structure or,
+    // for shapes like `aau`, an array.
+    pub fn rows(
+        &self,
+        value: &Value,
+        numbers: Numbers,
+    ) -> Result<Vec<Vec<(String, serde_json::Value)>>> {
+        let elements = match value {
+            Value::Array(array) => array.inner(),
+            Value::Value(inner) => return self.rows(inner, numbers),
+            _ => return Ok(Vec::new()),
+        };
+
+        elements
+            .iter()
+            .map(|element| {
+                let fields = match element {
+                    Value::Structure(structure) => structure.fields(),
+                    Value::Array(array) => array.inner(),
+                    _ => std::slice::from_ref(element),
+                };
+                fields
+                    .iter()
+                    .enumerate()
+                    .map(|(i, field)| {
+                        let name = self
+                            .fields
+                            .get(i)
+                            .cloned()
+                            .unwrap_or_else(|| format!("field{}", i));
+                        Ok((name, value_to_json(field, numbers)?))
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_files() -> Value<'static> {
+        Value::from(vec![(
+            "/usr/lib/systemd/system/foo.service".to_string(),
+            "enabled".to_string(),
+        )])
+    }
+
+    #[test]
+    fn test_find_requires_member() {
+        let registry = Registry::builtin();
+        let value = unit_files();
+        let shape = registry.find(
+            &value,
+            Some("org.freedesktop.systemd1.Manager"),
+            Some("ListUnitFiles"),
+        );
+        assert_eq!(shape.map(|s| s.name.as_str()), Some("systemd-unit-file"));
+        assert!(
+            registry
+                .find(&value, Some("org.example.Other"), Some("ListUnitFiles"))
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_rows_named_fields() {
+        let registry = Registry::builtin();
+        let value = unit_files();
+        let shape = registry
+            .find(
+                &value,
+                Some("org.freedesktop.systemd1.Manager"),
+                Some("ListUnitFiles"),
+            )
+            .unwrap();
+        let rows = shape.rows(&value, Numbers::default()).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0][0].0, "path");
+        assert_eq!(
+            rows[0][1],
+            ("state".to_string(), serde_json::json!("enabled"))
+        );
+    }
+
+    #[test]
+    fn test_registry_file_takes_precedence() {
+        let dir = std::env::temp_dir().join(format!("zbusctl-shapes-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("formats.json");
+        fs::write(
+            &path,
+            r#"[{"name": "custom", "signature": "a(ss)", "member": "ListUnitFiles", "fields": ["a", "b"]}]"#,
+        )
+        .unwrap();
+
+        let mut registry = Registry::builtin();
+        registry.load_file(&path).unwrap();
+        let value = unit_files();
+        let shape = registry
+            .find(
+                &value,
+                Some("org.freedesktop.systemd1.Manager"),
+                Some("ListUnitFiles"),
+            )
+            .unwrap();
+        assert_eq!(shape.name, "custom");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,107 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use zbus::Result;
+
+/// The properties of one object at a point in time, as JSON values keyed by
+/// interface and then property name.
+pub type Snapshot = BTreeMap<String, BTreeMap<String, Value>>;
+
+/// A property whose value differs between two snapshots. A missing `before`
+/// or `after` means the property, or its whole interface, was added or
+/// removed.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Change {
+    pub interface: String,
+    pub property: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<Value>,
+}
+
+// Read a snapshot saved with `props-diff --save`.
+pub fn load_snapshot(path: &Path) -> Result<Snapshot> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| zbus::Error::Failure(format!("Failed to read '{}': {}", path.display(), e)))?;
+    serde_json::from_str(&contents)
+        .map_err(|e| zbus::Error::Failure(format!("Invalid snapshot '{}': {}", path.display(), e)))
+}
+
+// The properties that differ between two snapshots, ordered by interface and
+// property name. Properties with equal values are left out.
+pub fn diff(before: &Snapshot, after: &Snapshot) -> Vec<Change> {
+    let empty = BTreeMap::new();
+    let mut interfaces = before.keys().chain(after.keys()).collect::<Vec<_>>();
+    interfaces.sort();
+    interfaces.dedup();
+
+    let mut changes = Vec::new();
+    for interface in interfaces {
+        let old = before.get(interface).unwrap_or(&empty);
+        let new = after.get(interface).unwrap_or(&empty);
+        let mut properties = old.keys().chain(new.keys()).collect::<Vec<_>>();
+        properties.sort();
+        properties.dedup();
+
+        for property in properties {
+            let (old, new) = (old.get(property), new.get(property));
+            if old != new {
+                changes.push(Change {
+                    interface: interface.clone(),
+                    property: property.clone(),
+                    before: old.cloned(),
+                    after: new.cloned(),
+                });
+            }
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn snapshot(value: Value) -> Snapshot {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_diff() {
+        let before = snapshot(json!({
+            "org.example.A": {"Count": 1, "Name": "a", "Old": true},
+            "org.example.Gone": {"X": 1},
+        }));
+        let after = snapshot(json!({
+            "org.example.A": {"Count": 2, "Name": "a", "New": [1]},
+        }));
+
+        let changes = diff(&before, &after)
+            .into_iter()
+            .map(|c| (c.property, c.before, c.after))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            vec![
+                ("Count".to_string(), Some(json!(1)), Some(json!(2))),
+                ("New".to_string(), None, Some(json!([1]))),
+                ("Old".to_string(), Some(json!(true)), None),
+                ("X".to_string(), Some(json!(1)), None),
+            ],
+            "Unchanged properties should be left out"
+        );
+    }
+
+    #[test]
+    fn test_diff_identical() {
+        let before = snapshot(json!({"org.example.A": {"Tags": ["x", "y"]}}));
+        assert!(
+            diff(&before, &before.clone()).is_empty(),
+            "Identical snapshots should have no changes"
+        );
+    }
+}
@@ -0,0 +1,385 @@
+use crate::format::typed_json_to_value;
+use crate::serve::{Interface, Method, handler};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use zbus::fdo::Error as FdoError;
+use zbus::message::Message;
+use zbus::{Connection, Result};
+use zvariant::{OwnedObjectPath, StructureBuilder, Value};
+
+const PROPERTIES_INTERFACE: &str = "org.freedesktop.DBus.Properties";
+
+/// Where the properties a [`Store`] serves are kept.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Backend {
+    /// A file laid out as `props export` writes it, by interface and then
+    /// property, each value with its signature: JSON, or the same layout in
+    /// TOML for files ending in `.toml`. Setting a property rewrites it.
+    File(PathBuf),
+    /// The environment variables whose names start with `prefix`, as string
+    /// properties of `interface` named by the rest of each name. A process
+    /// cannot change the environment others see, so values set are only
+    /// kept while serving.
+    Env { prefix: String, interface: String },
+}
+
+// Property values by interface and then name.
+type Values = BTreeMap<String, BTreeMap<String, Value<'static>>>;
+
+// The layout of a properties file, as `props export` writes it.
+type Saved = BTreeMap<String, BTreeMap<String, serde_json::Value>>;
+
+/// Properties loaded from a [`Backend`], served over D-Bus with
+/// [`Store::serve`] so other processes can read them, and written back to
+/// the backend when a client sets one. Clients can only set properties to
+/// values of the type they already have, and cannot add new ones.
+#[derive(Debug)]
+pub struct Store {
+    backend: Backend,
+    values: Mutex<Values>,
+}
+
+impl Store {
+    pub fn load(backend: Backend) -> Result<Self> {
+        let values = match &backend {
+            Backend::File(path) => load_file(path)?,
+            Backend::Env { prefix, interface } => {
+                let values = std::env::vars()
+                    .filter_map(|(name, value)| {
+                        let name = name.strip_prefix(prefix.as_str())?;
+                        Some((name.to_string(), Value::from(value)))
+                    })
+                    .filter(|(name, _)| !name.is_empty())
+                    .collect();
+                BTreeMap::from([(interface.clone(), values)])
+            }
+        };
+        Ok(Self {
+            backend,
+            values: Mutex::new(values),
+        })
+    }
+
+    // The properties of each interface, by name and signature.
+    pub fn properties(&self) -> BTreeMap<String, Vec<(String, String)>> {
+        let values = self.values.lock().unwrap();
+        values
+            .iter()
+            .map(|(interface, properties)| {
+                let properties = properties
+                    .iter()
+                    .map(|(name, value)| (name.clone(), value.value_signature().to_string()))
+                    .collect();
+                (interface.clone(), properties)
+            })
+            .collect()
+    }
+
+    pub fn get(&self, interface: &str, name: &str) -> zbus::fdo::Result<Value<'static>> {
+        let values = self.values.lock().unwrap();
+        let value = interface_values(&values, interface)?
+            .get(name)
+            .ok_or_else(|| unknown_property(interface, name))?;
+        Ok(value.try_clone().map_err(zbus::Error::from)?)
+    }
+
+    pub fn get_all(&self, interface: &str) -> zbus::fdo::Result<HashMap<String, Value<'static>>> {
+        let values = self.values.lock().unwrap();
+        interface_values(&values, interface)?
+            .iter()
+            .map(|(name, value)| Ok((name.clone(), value.try_clone()?)))
+            .collect::<Result<_>>()
+            .map_err(FdoError::from)
+    }
+
+    // Set a property to a value of the type it has, writing the new values
+    // back to the backend before they are served.
+    pub fn set(&self, interface: &str, name: &str, value: Value<'static>) -> zbus::fdo::Result<()> {
+        let mut values = self.values.lock().unwrap();
+        let current = interface_values(&values, interface)?
+            .get(name)
+            .ok_or_else(|| unknown_property(interface, name))?;
+        if current.value_signature() != value.value_signature() {
+            return Err(FdoError::InvalidArgs(format!(
+                "Property '{}' has signature '{}', not '{}'",
+                name,
+                current.value_signature(),
+                value.value_signature()
+            )));
+        }
+
+        let mut updated = values
+            .iter()
+            .map(|(interface, properties)| {
+                let properties = properties
+                    .iter()
+                    .map(|(name, value)| Ok((name.clone(), value.try_clone()?)))
+                    .collect::<Result<_>>()?;
+                Ok((interface.clone(), properties))
+            })
+            .collect::<Result<Values>>()?;
+        if let Some(properties) = updated.get_mut(interface) {
+            properties.insert(name.to_string(), value);
+        }
+        if let Backend::File(path) = &self.backend {
+            save_file(path, &updated).map_err(|e| FdoError::Failed(e.to_string()))?;
+        }
+        *values = updated;
+        Ok(())
+    }
+
+    // The interfaces to serve the store at `path` with `serve_object`: the
+    // Properties interface, announcing each change with PropertiesChanged
+    // on `connection`, and the interfaces the properties belong to, so
+    // introspection lists them.
+    pub fn serve(self: &Arc<Self>, connection: &Connection, path: &str) -> Result<Vec<Interface>> {
+        let path = OwnedObjectPath::try_from(path)?;
+        let mut methods = HashMap::new();
+
+        let store = self.clone();
+        methods.insert(
+            "Get",
+            Method::new(
+                "ss",
+                handler(move |call: Message| {
+                    let store = store.clone();
+                    async move {
+                        let (interface, name): (String, String) = call.body().deserialize()?;
+                        let value = store.get(&interface, &name)?;
+                        let body = StructureBuilder::new()
+                            .add_field(value)
+                            .build()
+                            .map_err(zbus::Error::from)?;
+                        Ok(Some(body))
+                    }
+                }),
+            )?,
+        );
+        let store = self.clone();
+        methods.insert(
+            "GetAll",
+            Method::new(
+                "s",
+                handler(move |call: Message| {
+                    let store = store.clone();
+                    async move {
+                        let interface: String = call.body().deserialize()?;
+                        let body = StructureBuilder::new()
+                            .add_field(store.get_all(&interface)?)
+                            .build()
+                            .map_err(zbus::Error::from)?;
+                        Ok(Some(body))
+                    }
+                }),
+            )?,
+        );
+        let store = self.clone();
+        let connection = connection.clone();
+        methods.insert(
+            "Set",
+            Method::new(
+                "ssv",
+                handler(move |call: Message| {
+                    let store = store.clone();
+                    let connection = connection.clone();
+                    let path = path.clone();
+                    async move {
+                        let body = call.body();
+                        let (interface, name, value): (String, String, Value) =
+                            body.deserialize()?;
+                        let value = value.try_into_owned().map_err(zbus::Error::from)?.into();
+                        store.set(&interface, &name, value)?;
+                        let changed =
+                            HashMap::from([(name.as_str(), store.get(&interface, &name)?)]);
+                        let signal = Message::signal(
+                            path.as_str(),
+                            PROPERTIES_INTERFACE,
+                            "PropertiesChanged",
+                        )?
+                        .build(&(
+                            interface.as_str(),
+                            changed,
+                            Vec::<&str>::new(),
+                        ))?;
+                        connection.send(&signal).await?;
+                        Ok(None)
+                    }
+                }),
+            )?,
+        );
+
+        let mut interfaces = vec![Interface::new(PROPERTIES_INTERFACE, methods)?];
+        for (interface, properties) in self.properties() {
+            interfaces
+                .push(Interface::new(&interface, HashMap::new())?.with_properties(properties));
+        }
+        Ok(interfaces)
+    }
+}
+
+fn interface_values<'a>(
+    values: &'a Values,
+    interface: &str,
+) -> zbus::fdo::Result<&'a BTreeMap<String, Value<'static>>> {
+    values.get(interface).ok_or_else(|| {
+        FdoError::UnknownInterface(format!("No properties on interface '{}'", interface))
+    })
+}
+
+fn unknown_property(interface: &str, name: &str) -> FdoError {
+    FdoError::UnknownProperty(format!(
+        "Unknown property '{}' on interface '{}'",
+        name, interface
+    ))
+}
+
+fn is_toml(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "toml")
+}
+
+fn load_file(path: &Path) -> Result<Values> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| zbus::Error::Failure(format!("Failed to read '{}': {}", path.display(), e)))?;
+    let invalid = |e: &dyn std::fmt::Display| {
+        zbus::Error::Failure(format!(
+            "Invalid properties file '{}': {}",
+            path.display(),
+            e
+        ))
+    };
+    let saved: Saved = if is_toml(path) {
+        toml::from_str(&contents).map_err(|e| invalid(&e))?
+    } else {
+        serde_json::from_str(&contents).map_err(|e| invalid(&e))?
+    };
+    saved
+        .into_iter()
+        .map(|(interface, properties)| {
+            let properties = properties
+                .into_iter()
+                .map(|(name, json)| {
+                    let value = typed_json_to_value(&json)
+                        .map_err(|e| invalid(&format!("{}.{}: {}", interface, name, e)))?;
+                    Ok((name, value))
+                })
+                .collect::<Result<_>>()?;
+            Ok((interface, properties))
+        })
+        .collect()
+}
+
+// Write the values to a file in the same format as it was read, through a
+// temporary file renamed over it, so readers never see it half-written.
+fn save_file(path: &Path, values: &Values) -> Result<()> {
+    let failed = |e: &dyn std::fmt::Display| {
+        zbus::Error::Failure(format!("Failed to write '{}': {}", path.display(), e))
+    };
+    let saved = values
+        .iter()
+        .map(|(interface, properties)| {
+            let properties = properties
+                .iter()
+                .map(|(name, value)| Ok((name.clone(), serde_json::to_value(value)?)))
+                .collect::<serde_json::Result<_>>()?;
+            Ok((interface.clone(), properties))
+        })
+        .collect::<serde_json::Result<Saved>>()
+        .map_err(|e| failed(&e))?;
+    let contents = if is_toml(path) {
+        toml::to_string_pretty(&saved).map_err(|e| failed(&e))?
+    } else {
+        serde_json::to_string_pretty(&saved).map_err(|e| failed(&e))? + "\n"
+    };
+
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    fs::write(&temporary, contents).map_err(|e| failed(&e))?;
+    fs::rename(&temporary, path).map_err(|e| failed(&e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(name: &str, contents: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("zbusctl-store-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_file_backend() {
+        let path = file(
+            "config.json",
+            r#"{"org.example.Config": {"Level": {"signature": "u", "value": 3}, "Name": {"signature": "s", "value": "a"}}}"#,
+        );
+        let store = Store::load(Backend::File(path.clone())).unwrap();
+        assert_eq!(
+            store.properties()["org.example.Config"],
+            vec![
+                ("Level".to_string(), "u".to_string()),
+                ("Name".to_string(), "s".to_string())
+            ]
+        );
+        assert_eq!(
+            store.get("org.example.Config", "Level").unwrap(),
+            Value::U32(3)
+        );
+
+        store
+            .set("org.example.Config", "Level", Value::U32(7))
+            .unwrap();
+        let reloaded = Store::load(Backend::File(path.clone())).unwrap();
+        assert_eq!(
+            reloaded.get("org.example.Config", "Level").unwrap(),
+            Value::U32(7),
+            "Setting a property should write it back"
+        );
+
+        assert!(matches!(
+            store.set("org.example.Config", "Level", Value::from("high")),
+            Err(FdoError::InvalidArgs(_))
+        ));
+        assert!(matches!(
+            store.set("org.example.Config", "Missing", Value::U32(1)),
+            Err(FdoError::UnknownProperty(_))
+        ));
+        assert!(matches!(
+            store.get_all("org.example.Other"),
+            Err(FdoError::UnknownInterface(_))
+        ));
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_toml_file_backend() {
+        let path = file(
+            "config.toml",
+            "[\"org.example.Config\"]\nTags = { signature = \"as\", value = [\"a\", \"b\"] }\n",
+        );
+        let store = Store::load(Backend::File(path.clone())).unwrap();
+        store
+            .set(
+                "org.example.Config",
+                "Tags",
+                Value::from(vec!["c".to_string()]),
+            )
+            .unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        assert!(
+            contents.contains("[\"org.example.Config\".Tags]"),
+            "{}",
+            contents
+        );
+        assert!(contents.contains("value = [\"c\"]"), "{}", contents);
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,199 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+use zbus::Result;
+
+/// A saved zbusctl invocation whose arguments may contain `{name}`
+/// placeholders, filled in when it is applied. `{{` and `}}` stand for
+/// literal braces, as in `signature:a{{sv}}`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Template {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub args: Vec<String>,
+}
+
+// The pieces of a templated word: literal text and placeholder names.
+enum Piece<'a> {
+    Text(&'a str),
+    Brace(char),
+    Var(&'a str),
+}
+
+fn pieces(word: &str) -> Result<Vec<Piece<'_>>> {
+    let invalid = |reason: &str| {
+        zbus::Error::Failure(format!("Invalid template argument '{}': {}", word, reason))
+    };
+    let mut pieces = Vec::new();
+    let mut rest = word;
+    while let Some(i) = rest.find(['{', '}']) {
+        pieces.push(Piece::Text(&rest[..i]));
+        let brace = rest[i..].chars().next().unwrap_or_default();
+        rest = &rest[i + 1..];
+        if rest.starts_with(brace) {
+            pieces.push(Piece::Brace(brace));
+            rest = &rest[1..];
+            continue;
+        }
+        if brace == '}' {
+            return Err(invalid("unmatched '}', write '}}' for a literal brace"));
+        }
+        let end = rest
+            .find('}')
+            .ok_or_else(|| invalid("unterminated placeholder"))?;
+        let name = &rest[..end];
+        let valid = name
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            return Err(invalid(&format!(
+                "'{{{}}}' is not a placeholder name, write '{{{{' for a literal brace",
+                name
+            )));
+        }
+        pieces.push(Piece::Var(name));
+        rest = &rest[end + 1..];
+    }
+    pieces.push(Piece::Text(rest));
+    Ok(pieces)
+}
+
+impl Template {
+    // Read a template saved with `template save`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path).map_err(|e| {
+            zbus::Error::Failure(format!("Failed to read '{}': {}", path.display(), e))
+        })?;
+        serde_json::from_str(&contents).map_err(|e| {
+            zbus::Error::Failure(format!("Invalid template '{}': {}", path.display(), e))
+        })
+    }
+
+    // The names of the placeholders used in the arguments.
+    pub fn placeholders(&self) -> Result<BTreeSet<String>> {
+        let mut names = BTreeSet::new();
+        for word in &self.args {
+            for piece in pieces(word)? {
+                if let Piece::Var(name) = piece {
+                    names.insert(name.to_string());
+                }
+            }
+        }
+        Ok(names)
+    }
+
+    // The arguments with every placeholder replaced by its value. Missing
+    // values are an error, and so are values for placeholders the template
+    // doesn't have, which are most likely typos.
+    pub fn apply(&self, vars: &BTreeMap<String, String>) -> Result<Vec<String>> {
+        let placeholders = self.placeholders()?;
+        let missing = placeholders
+            .iter()
+            .filter(|name| !vars.contains_key(*name))
+            .map(|name| name.as_str())
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            return Err(zbus::Error::Failure(format!(
+                "Missing template values: {} (set them with --var NAME=VALUE)",
+                missing.join(", ")
+            )));
+        }
+        let unused = vars
+            .keys()
+            .filter(|name| !placeholders.contains(*name))
+            .map(|name| name.as_str())
+            .collect::<Vec<_>>();
+        if !unused.is_empty() {
+            return Err(zbus::Error::Failure(format!(
+                "The template has no placeholders named: {}",
+                unused.join(", ")
+            )));
+        }
+
+        self.args
+            .iter()
+            .map(|word| {
+                Ok(pieces(word)?
+                    .into_iter()
+                    .map(|piece| match piece {
+                        Piece::Text(text) => text.to_string(),
+                        Piece::Brace(brace) => brace.to_string(),
+                        Piece::Var(name) => vars[name].clone(),
+                    })
+                    .collect())
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(args: &[&str]) -> Template {
+        Template {
+            description: None,
+            args: args.iter().map(|a| a.to_string()).collect(),
+        }
+    }
+
+    fn vars(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_apply() {
+        let t = template(&[
+            "call",
+            "-o",
+            "/org/example/{name}",
+            "string:{host}:{port}",
+            "signature:a{{sv}}",
+        ]);
+        assert_eq!(
+            t.placeholders().unwrap().into_iter().collect::<Vec<_>>(),
+            vec!["host", "name", "port"]
+        );
+        assert_eq!(
+            t.apply(&vars(&[
+                ("name", "Test"),
+                ("host", "web01"),
+                ("port", "80")
+            ]))
+            .unwrap(),
+            vec![
+                "call",
+                "-o",
+                "/org/example/Test",
+                "string:web01:80",
+                "signature:a{sv}"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_apply_errors() {
+        let t = template(&["string:{host}"]);
+        let error = t.apply(&vars(&[])).unwrap_err().to_string();
+        assert!(error.contains("Missing template values: host"), "{}", error);
+        let error = t
+            .apply(&vars(&[("host", "a"), ("hots", "b")]))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("no placeholders named: hots"), "{}", error);
+
+        for bad in ["a{sv}x{", "a}", "{1x}", "{}"] {
+            assert!(
+                template(&[bad]).placeholders().is_err(),
+                "'{}' should be rejected",
+                bad
+            );
+        }
+    }
+}
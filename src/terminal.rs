@@ -0,0 +1,50 @@
+use std::io::{self, Read};
+use tokio::sync::mpsc;
+
+// Keys pressed at the terminal on stdin, read as they are pressed rather than
+// a line at a time, and not echoed. Ctrl-C arrives as a key too, rather than
+// interrupting, so the terminal is always put back as it was, which happens
+// when this is dropped.
+pub struct Keys {
+    saved: libc::termios,
+    keys: mpsc::UnboundedReceiver<u8>,
+}
+
+impl Keys {
+    pub fn open() -> io::Result<Self> {
+        let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut saved) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut keys = saved;
+        keys.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+        keys.c_cc[libc::VMIN] = 1;
+        keys.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &keys) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // Reading stdin blocks, so it has a thread of its own, which is not
+        // waited for on exit as the runtime's blocking tasks would be.
+        let (sender, keys) = mpsc::unbounded_channel();
+        std::thread::spawn(move || {
+            for key in io::stdin().lock().bytes() {
+                match key {
+                    Ok(key) if sender.send(key).is_ok() => {}
+                    _ => break,
+                }
+            }
+        });
+        Ok(Self { saved, keys })
+    }
+
+    // The next key pressed, or `None` once stdin is closed.
+    pub async fn next(&mut self) -> Option<u8> {
+        self.keys.recv().await
+    }
+}
+
+impl Drop for Keys {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.saved) };
+    }
+}
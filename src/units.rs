@@ -0,0 +1,364 @@
+use std::any::type_name;
+use std::str::FromStr;
+use zbus::Result;
+
+/// The unit integer arguments with a time suffix are converted to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum TimeUnit {
+    Nsec,
+    /// Microseconds, as used by most systemd and logind APIs.
+    #[default]
+    Usec,
+    Msec,
+    Sec,
+}
+
+impl TimeUnit {
+    fn nanoseconds(self) -> i128 {
+        match self {
+            TimeUnit::Nsec => 1,
+            TimeUnit::Usec => 1_000,
+            TimeUnit::Msec => 1_000_000,
+            TimeUnit::Sec => 1_000_000_000,
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        [
+            TimeUnit::Nsec,
+            TimeUnit::Usec,
+            TimeUnit::Msec,
+            TimeUnit::Sec,
+        ]
+        .into_iter()
+        .find(|unit| unit.name() == name)
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            TimeUnit::Nsec => "nsec",
+            TimeUnit::Usec => "usec",
+            TimeUnit::Msec => "msec",
+            TimeUnit::Sec => "sec",
+        }
+    }
+}
+
+// Time suffixes, in nanoseconds.
+const TIME_SUFFIXES: &[(&str, i128)] = &[
+    ("ns", 1),
+    ("nsec", 1),
+    ("us", 1_000),
+    ("usec", 1_000),
+    ("ms", 1_000_000),
+    ("msec", 1_000_000),
+    ("s", 1_000_000_000),
+    ("sec", 1_000_000_000),
+    ("m", 60_000_000_000),
+    ("min", 60_000_000_000),
+    ("h", 3_600_000_000_000),
+    ("d", 86_400_000_000_000),
+];
+
+// Size suffixes, in bytes. Decimal and binary prefixes are both accepted.
+const SIZE_SUFFIXES: &[(&str, i128)] = &[
+    ("B", 1),
+    ("K", 1_000),
+    ("KB", 1_000),
+    ("KiB", 1 << 10),
+    ("M", 1_000_000),
+    ("MB", 1_000_000),
+    ("MiB", 1 << 20),
+    ("G", 1_000_000_000),
+    ("GB", 1_000_000_000),
+    ("GiB", 1 << 30),
+    ("T", 1_000_000_000_000),
+    ("TB", 1_000_000_000_000),
+    ("TiB", 1 << 40),
+];
+
+// Parse an integer argument, which may carry a time suffix (`5s`, `250ms`),
+// converted to `time_unit`, or a size suffix (`10MiB`), converted to bytes.
+// Parsing never depends on the locale: the decimal separator is always `.`.
+pub fn parse_integer<T>(value: &str, time_unit: TimeUnit) -> Result<T>
+where
+    T: FromStr + TryFrom<i128>,
+    <T as FromStr>::Err: std::fmt::Display,
+{
+    let invalid = |reason: String| {
+        zbus::Error::Failure(format!(
+            "Invalid {} '{}': {}",
+            type_name::<T>(),
+            value,
+            reason
+        ))
+    };
+
+    let split = value
+        .find(|c: char| c.is_ascii_alphabetic())
+        .unwrap_or(value.len());
+    let (number, suffix) = value.split_at(split);
+    if suffix.is_empty() {
+        return T::from_str(value).map_err(|e| invalid(e.to_string()));
+    }
+
+    let (scale, divisor, unit) =
+        if let Some((_, ns)) = TIME_SUFFIXES.iter().find(|(s, _)| *s == suffix) {
+            (*ns, time_unit.nanoseconds(), time_unit.name())
+        } else if let Some((_, bytes)) = SIZE_SUFFIXES.iter().find(|(s, _)| *s == suffix) {
+            (*bytes, 1, "bytes")
+        } else {
+            return Err(invalid(format!("unknown unit suffix '{}'", suffix)));
+        };
+
+    // Whole numbers are converted exactly; fractions such as `1.5s` go through
+    // floating point and must still come out whole in the target unit.
+    let converted = match number.parse::<i128>() {
+        Ok(n) => n
+            .checked_mul(scale)
+            .filter(|scaled| scaled % divisor == 0)
+            .map(|scaled| scaled / divisor),
+        Err(_) => {
+            let n = number.parse::<f64>().map_err(|e| invalid(e.to_string()))?;
+            let scaled = n * scale as f64 / divisor as f64;
+            (scaled.fract() == 0.0 && scaled.abs() < i128::MAX as f64).then_some(scaled as i128)
+        }
+    };
+    let converted = converted.ok_or_else(|| invalid(format!("not a whole number of {}", unit)))?;
+    T::try_from(converted).map_err(|_| invalid(format!("{} {} is out of range", converted, unit)))
+}
+
+// Parse a duration made of one or more numbers with time suffixes, such as
+// `90s`, `1.5h` or `1h 30min`, into nanoseconds.
+fn parse_duration(value: &str) -> std::result::Result<i128, String> {
+    let mut total = 0i128;
+    let mut rest = value.trim();
+    if rest.is_empty() {
+        return Err("empty duration".to_string());
+    }
+    while !rest.is_empty() {
+        let number_end = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(rest.len());
+        let (number, tail) = rest.split_at(number_end);
+        let suffix_end = tail
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(tail.len());
+        let (suffix, tail) = tail.split_at(suffix_end);
+
+        let scale = TIME_SUFFIXES
+            .iter()
+            .find(|(s, _)| *s == suffix)
+            .map(|(_, ns)| *ns)
+            .ok_or_else(|| match suffix {
+                "" => format!("missing unit after '{}'", number),
+                _ => format!("unknown unit suffix '{}'", suffix),
+            })?;
+        let n = number
+            .parse::<f64>()
+            .map_err(|e| format!("invalid number '{}': {}", number, e))?;
+        let nanoseconds = (n * scale as f64).round();
+        total = Some(nanoseconds)
+            .filter(|ns| *ns < i128::MAX as f64)
+            .and_then(|ns| total.checked_add(ns as i128))
+            .ok_or_else(|| "duration is too long".to_string())?;
+        rest = tail.trim_start();
+    }
+    Ok(total)
+}
+
+// Days since 1970-01-01 of a date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+// Parse an RFC 3339 timestamp, such as `2024-05-01T10:00:00Z` or
+// `2024-05-01 12:00:00.5+02:00`, into nanoseconds since the Unix epoch.
+fn parse_timestamp(value: &str) -> std::result::Result<i128, String> {
+    let invalid = || "expected an RFC 3339 timestamp like 2024-05-01T10:00:00Z".to_string();
+    let field = |s: &str, len: usize| {
+        (s.len() == len && s.bytes().all(|b| b.is_ascii_digit()))
+            .then(|| s.parse::<i64>().ok())
+            .flatten()
+            .ok_or_else(invalid)
+    };
+
+    if value.len() < 20 || !value.is_char_boundary(10) || !value.is_char_boundary(19) {
+        return Err(invalid());
+    }
+    let (date, rest) = value.split_at(10);
+    let (separator, rest) = rest.split_at(1);
+    let (time, zone) = rest.split_at(8);
+    if !matches!(separator, "T" | "t" | " ") {
+        return Err(invalid());
+    }
+
+    let mut date_parts = date.split('-');
+    let year = field(date_parts.next().unwrap_or_default(), 4)?;
+    let month = field(date_parts.next().unwrap_or_default(), 2)?;
+    let day = field(date_parts.next().unwrap_or_default(), 2)?;
+    let mut time_parts = time.split(':');
+    let hour = field(time_parts.next().unwrap_or_default(), 2)?;
+    let minute = field(time_parts.next().unwrap_or_default(), 2)?;
+    let second = field(time_parts.next().unwrap_or_default(), 2)?;
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return Err(invalid());
+    }
+
+    // Optional fraction of a second, then the offset from UTC.
+    let (fraction, zone) = match zone.strip_prefix('.') {
+        Some(rest) => {
+            let end = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let digits = &rest[..end.min(9)];
+            let nanos = format!("{:0<9}", digits)
+                .parse::<i128>()
+                .map_err(|_| invalid())?;
+            (nanos, &rest[end..])
+        }
+        None => (0, zone),
+    };
+    let offset = match zone {
+        "Z" | "z" => 0,
+        _ => {
+            let sign = match zone.get(..1) {
+                Some("+") => 1,
+                Some("-") => -1,
+                _ => return Err(invalid()),
+            };
+            let (hours, minutes) = zone[1..].split_once(':').ok_or_else(invalid)?;
+            sign * (field(hours, 2)? * 3600 + field(minutes, 2)? * 60)
+        }
+    };
+
+    let seconds =
+        days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second - offset;
+    Ok(seconds as i128 * 1_000_000_000 + fraction)
+}
+
+// Parse a `timestamp:` or `duration:` argument value into an integer in a time
+// unit. The value may start with the unit to use, as in `usec:90s`; otherwise
+// `default_unit` applies.
+pub fn parse_time(kind: &str, value: &str, default_unit: TimeUnit) -> Result<u64> {
+    let (unit, value) = value
+        .split_once(':')
+        .and_then(|(unit, rest)| Some((TimeUnit::from_name(unit)?, rest)))
+        .unwrap_or((default_unit, value));
+    let nanoseconds = match kind {
+        "timestamp" => parse_timestamp(value),
+        _ => parse_duration(value),
+    }
+    .map_err(|reason| zbus::Error::Failure(format!("Invalid {} '{}': {}", kind, value, reason)))?;
+
+    u64::try_from(nanoseconds / unit.nanoseconds()).map_err(|_| {
+        let reason = match nanoseconds < 0 {
+            true => "must not be negative",
+            false => "too long",
+        };
+        zbus::Error::Failure(format!("Invalid {} '{}': {}", kind, value, reason))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_suffixes() {
+        assert_eq!(
+            parse_integer::<u64>("5s", TimeUnit::Usec).unwrap(),
+            5_000_000
+        );
+        assert_eq!(parse_integer::<u64>("1.5min", TimeUnit::Sec).unwrap(), 90);
+        assert_eq!(parse_integer::<u64>("250ms", TimeUnit::Msec).unwrap(), 250);
+        assert_eq!(parse_integer::<i64>("-2h", TimeUnit::Sec).unwrap(), -7200);
+        assert!(
+            parse_integer::<u64>("5us", TimeUnit::Msec).is_err(),
+            "Fractions of the target unit should be rejected"
+        );
+    }
+
+    #[test]
+    fn test_size_suffixes() {
+        assert_eq!(
+            parse_integer::<u64>("10MiB", TimeUnit::Usec).unwrap(),
+            10 * 1024 * 1024
+        );
+        assert_eq!(parse_integer::<u32>("2KB", TimeUnit::Usec).unwrap(), 2000);
+        assert!(
+            parse_integer::<u16>("1GiB", TimeUnit::Usec).is_err(),
+            "Values that don't fit the type should be rejected"
+        );
+        assert!(parse_integer::<u32>("3parsecs", TimeUnit::Usec).is_err());
+    }
+
+    #[test]
+    fn test_timestamps() {
+        assert_eq!(
+            parse_time("timestamp", "usec:2024-05-01T10:00:00Z", TimeUnit::Sec).unwrap(),
+            1_714_557_600_000_000
+        );
+        assert_eq!(
+            parse_time("timestamp", "2024-05-01 12:00:00.25+02:00", TimeUnit::Msec).unwrap(),
+            1_714_557_600_250
+        );
+        assert_eq!(
+            parse_time("timestamp", "1970-01-01T00:00:00Z", TimeUnit::Usec).unwrap(),
+            0
+        );
+        assert!(parse_time("timestamp", "1969-12-31T23:59:59Z", TimeUnit::Usec).is_err());
+        assert!(parse_time("timestamp", "2024-13-01T00:00:00Z", TimeUnit::Usec).is_err());
+        assert!(parse_time("timestamp", "2024-05-01T10:00:00", TimeUnit::Usec).is_err());
+    }
+
+    #[test]
+    fn test_durations() {
+        assert_eq!(
+            parse_time("duration", "1h 30min", TimeUnit::Sec).unwrap(),
+            5400
+        );
+        assert_eq!(
+            parse_time("duration", "msec:1.5s", TimeUnit::Sec).unwrap(),
+            1500
+        );
+        assert_eq!(
+            parse_time("duration", "2m5s", TimeUnit::Usec).unwrap(),
+            125_000_000
+        );
+        assert!(parse_time("duration", "5", TimeUnit::Usec).is_err());
+        assert!(parse_time("duration", "5 parsecs", TimeUnit::Usec).is_err());
+        for value in [
+            "9000000000000000000000000000000s",
+            "1500000000000000000000000d 1500000000000000000000000d",
+        ] {
+            let error = parse_time("duration", value, TimeUnit::Usec).unwrap_err();
+            assert!(
+                error.to_string().contains("too long"),
+                "Unexpected error: {}",
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn test_plain_numbers() {
+        assert_eq!(parse_integer::<i32>("-42", TimeUnit::Usec).unwrap(), -42);
+        let error = parse_integer::<u8>("300", TimeUnit::Usec).unwrap_err();
+        assert!(
+            error.to_string().contains("Invalid u8 '300'"),
+            "Unexpected error: {}",
+            error
+        );
+    }
+}
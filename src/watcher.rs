@@ -0,0 +1,368 @@
+use futures_lite::{Stream, StreamExt};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
+use tokio::task::JoinHandle;
+use zbus::message::{Message, Type};
+use zbus::names::InterfaceName;
+use zbus::{Connection, MatchRule, MessageStream, Result};
+use zvariant::{ObjectPath, OwnedObjectPath, OwnedValue};
+
+/// A change to a watched property, as announced by `PropertiesChanged`.
+#[derive(Debug, PartialEq)]
+pub enum PropertyChange {
+    /// The property has a new value.
+    Value(OwnedValue),
+    /// The property changed, but the service did not say to what; read it
+    /// again to find out.
+    Invalidated,
+}
+
+// The object and interface a match rule covers.
+type Key = (OwnedObjectPath, String);
+
+struct Subscriber {
+    id: u64,
+    property: String,
+    sender: UnboundedSender<PropertyChange>,
+}
+
+#[derive(Default)]
+struct Shared {
+    subscribers: HashMap<Key, Vec<Subscriber>>,
+    // One task per key, reading the signals its match rule lets through.
+    tasks: HashMap<Key, JoinHandle<()>>,
+}
+
+/// Delivers property changes to any number of subscribers over one
+/// connection. However many subscribers watch properties of the same object
+/// and interface, the bus is asked for its `PropertiesChanged` signals once,
+/// and the match rule is removed when the last of them goes away. Clones share
+/// their subscriptions.
+///
+/// ```no_run
+/// # async fn example(connection: zbus::Connection) -> zbus::Result<()> {
+/// use zbusctl::watcher::PropertyWatcher;
+///
+/// let watcher = PropertyWatcher::new(&connection);
+/// let mut state = watcher
+///     .subscribe("/org/freedesktop/NetworkManager", "org.freedesktop.NetworkManager", "State")
+///     .await?;
+/// while let Some(change) = state.next().await {
+///     println!("{:?}", change);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct PropertyWatcher {
+    connection: Connection,
+    shared: Arc<Mutex<Shared>>,
+    next_id: Arc<AtomicU64>,
+}
+
+/// The changes to one property, until dropped.
+pub struct PropertySubscription {
+    receiver: UnboundedReceiver<PropertyChange>,
+    key: Key,
+    id: u64,
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl PropertySubscription {
+    // The next change, or `None` once the connection is gone.
+    pub async fn next(&mut self) -> Option<PropertyChange> {
+        self.receiver.recv().await
+    }
+}
+
+impl Drop for PropertySubscription {
+    fn drop(&mut self) {
+        if let Ok(mut shared) = self.shared.lock() {
+            unsubscribe(&mut shared, &self.key, self.id);
+        }
+    }
+}
+
+fn unsubscribe(shared: &mut Shared, key: &Key, id: u64) {
+    let Some(subscribers) = shared.subscribers.get_mut(key) else {
+        return;
+    };
+    subscribers.retain(|subscriber| subscriber.id != id);
+    if subscribers.is_empty() {
+        shared.subscribers.remove(key);
+        // Dropping the task's stream removes its match rule.
+        if let Some(task) = shared.tasks.remove(key) {
+            task.abort();
+        }
+    }
+}
+
+impl PropertyWatcher {
+    pub fn new(connection: &Connection) -> Self {
+        Self {
+            connection: connection.clone(),
+            shared: Arc::default(),
+            next_id: Arc::default(),
+        }
+    }
+
+    // Watch one property of an object. Changes are announced by whichever
+    // service emits them from that object path.
+    pub async fn subscribe(
+        &self,
+        path: &str,
+        interface: &str,
+        property: &str,
+    ) -> Result<PropertySubscription> {
+        let path = OwnedObjectPath::from(ObjectPath::try_from(path)?.into_owned());
+        InterfaceName::try_from(interface)?;
+        let key = (path, interface.to_string());
+        let (sender, receiver) = unbounded_channel();
+        let subscription = PropertySubscription {
+            receiver,
+            key: key.clone(),
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            shared: self.shared.clone(),
+        };
+        let subscriber = Subscriber {
+            id: subscription.id,
+            property: property.to_string(),
+            sender,
+        };
+
+        let Some(subscriber) = self.join(&key, subscriber)? else {
+            return Ok(subscription);
+        };
+        // The match rule is added without holding the lock, so another
+        // subscriber may have added the same one meanwhile; then ours goes
+        // unused.
+        let stream = MessageStream::for_match_rule(rule(&key)?, &self.connection, None).await?;
+        let mut shared = self.lock()?;
+        match shared.subscribers.get_mut(&key) {
+            Some(subscribers) => subscribers.push(subscriber),
+            None => {
+                let task = tokio::spawn(forward(stream, key.clone(), self.shared.clone()));
+                shared.tasks.insert(key.clone(), task);
+                shared.subscribers.insert(key, vec![subscriber]);
+            }
+        }
+        Ok(subscription)
+    }
+
+    // Add a subscriber to the match rule already set up for its key, or hand
+    // it back if there is none.
+    fn join(&self, key: &Key, subscriber: Subscriber) -> Result<Option<Subscriber>> {
+        match self.lock()?.subscribers.get_mut(key) {
+            Some(subscribers) => {
+                subscribers.push(subscriber);
+                Ok(None)
+            }
+            None => Ok(Some(subscriber)),
+        }
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Shared>> {
+        self.shared
+            .lock()
+            .map_err(|_| zbus::Error::Failure("Property watcher state is poisoned".to_string()))
+    }
+}
+
+// The `PropertiesChanged` signals of one interface of one object.
+fn rule(key: &Key) -> Result<MatchRule<'static>> {
+    Ok(MatchRule::builder()
+        .msg_type(Type::Signal)
+        .interface("org.freedesktop.DBus.Properties")?
+        .member("PropertiesChanged")?
+        .path(key.0.clone())?
+        .arg(0, key.1.clone())?
+        .build())
+}
+
+// Deliver the signals of one match rule until the connection is gone, then
+// drop the key's subscribers, which closes their channels and so ends their
+// subscriptions.
+async fn forward<S>(mut stream: S, key: Key, shared: Arc<Mutex<Shared>>)
+where
+    S: Stream<Item = Result<Message>> + Unpin,
+{
+    while let Some(message) = stream.next().await {
+        let Ok(message) = message else {
+            continue;
+        };
+        if let Ok(shared) = shared.lock()
+            && let Some(subscribers) = shared.subscribers.get(&key)
+        {
+            deliver(subscribers, &message);
+        }
+    }
+    if let Ok(mut shared) = shared.lock() {
+        close(&mut shared, &key);
+    }
+}
+
+// Forget the subscribers of a key whose connection is gone, unless a later
+// subscription has already replaced its task.
+fn close(shared: &mut Shared, key: &Key) {
+    let current = tokio::task::try_id();
+    if current.is_some() && shared.tasks.get(key).map(JoinHandle::id) != current {
+        return;
+    }
+    shared.tasks.remove(key);
+    shared.subscribers.remove(key);
+}
+
+// Send the changes in a `PropertiesChanged` signal to the subscribers of the
+// properties it mentions. Signals that don't parse are ignored.
+fn deliver(subscribers: &[Subscriber], message: &Message) {
+    let Ok((_, changed, invalidated)) =
+        message
+            .body()
+            .deserialize::<(String, HashMap<String, OwnedValue>, Vec<String>)>()
+    else {
+        return;
+    };
+    for subscriber in subscribers {
+        let change = match changed.get(&subscriber.property) {
+            Some(value) => match value.try_clone() {
+                Ok(value) => PropertyChange::Value(value),
+                Err(_) => PropertyChange::Invalidated,
+            },
+            None if invalidated.contains(&subscriber.property) => PropertyChange::Invalidated,
+            None => continue,
+        };
+        // A subscription being dropped is removed right after.
+        let _ = subscriber.sender.send(change);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subscriber(id: u64, property: &str) -> (Subscriber, UnboundedReceiver<PropertyChange>) {
+        let (sender, receiver) = unbounded_channel();
+        let subscriber = Subscriber {
+            id,
+            property: property.to_string(),
+            sender,
+        };
+        (subscriber, receiver)
+    }
+
+    fn properties_changed(changed: &[(&str, u32)], invalidated: &[&str]) -> Message {
+        let changed = changed
+            .iter()
+            .map(|(name, value)| (name.to_string(), OwnedValue::from(*value)))
+            .collect::<HashMap<_, _>>();
+        let invalidated = invalidated
+            .iter()
+            .map(|name| name.to_string())
+            .collect::<Vec<_>>();
+        Message::signal(
+            "/org/example/Test",
+            "org.freedesktop.DBus.Properties",
+            "PropertiesChanged",
+        )
+        .unwrap()
+        .build(&("org.example.Test", changed, invalidated))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_deliver() {
+        let (count, mut count_changes) = subscriber(0, "Count");
+        let (also_count, mut also_count_changes) = subscriber(1, "Count");
+        let (name, mut name_changes) = subscriber(2, "Name");
+        let (tags, mut tags_changes) = subscriber(3, "Tags");
+        let subscribers = [count, also_count, name, tags];
+
+        deliver(
+            &subscribers,
+            &properties_changed(&[("Count", 8)], &["Name"]),
+        );
+        assert_eq!(
+            count_changes.try_recv().ok(),
+            Some(PropertyChange::Value(OwnedValue::from(8u32)))
+        );
+        assert_eq!(
+            also_count_changes.try_recv().ok(),
+            Some(PropertyChange::Value(OwnedValue::from(8u32))),
+            "Every subscriber to a property should get its changes"
+        );
+        assert_eq!(
+            name_changes.try_recv().ok(),
+            Some(PropertyChange::Invalidated)
+        );
+        assert!(
+            tags_changes.try_recv().is_err(),
+            "Subscribers should only get changes to their property"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_subscription_ends_with_stream() {
+        // A match rule's stream ends when its connection is closed.
+        let key = (
+            OwnedObjectPath::try_from("/org/example/Test").unwrap(),
+            "org.example.Test".to_string(),
+        );
+        let (sender, receiver) = unbounded_channel();
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        let mut count = PropertySubscription {
+            receiver,
+            key: key.clone(),
+            id: 0,
+            shared: shared.clone(),
+        };
+        let stream = futures_lite::stream::iter([Ok(properties_changed(&[("Count", 8)], &[]))]);
+        {
+            let mut state = shared.lock().unwrap();
+            state.subscribers.insert(
+                key.clone(),
+                vec![Subscriber {
+                    id: 0,
+                    property: "Count".to_string(),
+                    sender,
+                }],
+            );
+            let task = tokio::spawn(forward(stream, key.clone(), shared.clone()));
+            state.tasks.insert(key, task);
+        }
+
+        assert_eq!(
+            count.next().await,
+            Some(PropertyChange::Value(OwnedValue::from(8u32)))
+        );
+        assert_eq!(
+            count.next().await,
+            None,
+            "A subscription should end when its stream does"
+        );
+        let state = shared.lock().unwrap();
+        assert!(state.subscribers.is_empty() && state.tasks.is_empty());
+    }
+
+    #[test]
+    fn test_unsubscribe() {
+        let key = (
+            OwnedObjectPath::try_from("/org/example/Test").unwrap(),
+            "org.example.Test".to_string(),
+        );
+        let mut shared = Shared::default();
+        shared.subscribers.insert(
+            key.clone(),
+            vec![subscriber(0, "Count").0, subscriber(1, "Name").0],
+        );
+
+        unsubscribe(&mut shared, &key, 0);
+        assert_eq!(shared.subscribers[&key].len(), 1);
+        unsubscribe(&mut shared, &key, 1);
+        assert!(
+            !shared.subscribers.contains_key(&key),
+            "The match rule should go with the last subscriber"
+        );
+    }
+}
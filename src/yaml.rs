@@ -0,0 +1,189 @@
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::format::{FloatFormat, format_float};
+
+// Repeated arrays and objects at least this long in compact JSON are written
+// once with an anchor and referred to with aliases afterwards. Smaller ones
+// are cheaper to repeat than to alias.
+const MIN_ANCHOR_SIZE: usize = 32;
+
+// Identify a container by its compact JSON, so equal sub-structures share an
+// anchor however they were reached.
+fn key(value: &Value) -> Option<String> {
+    match value {
+        Value::Array(a) if !a.is_empty() => serde_json::to_string(value).ok(),
+        Value::Object(o) if !o.is_empty() => serde_json::to_string(value).ok(),
+        _ => None,
+    }
+    .filter(|key| key.len() >= MIN_ANCHOR_SIZE)
+}
+
+// Count how often each large container occurs. The inside of a repeat is not
+// visited, since it will be written as an alias and its contents never
+// appear again.
+fn count(value: &Value, counts: &mut HashMap<String, usize>) {
+    if let Some(key) = key(value) {
+        let seen = counts.entry(key).or_insert(0);
+        *seen += 1;
+        if *seen > 1 {
+            return;
+        }
+    }
+    match value {
+        Value::Array(items) => items.iter().for_each(|item| count(item, counts)),
+        Value::Object(map) => map.values().for_each(|item| count(item, counts)),
+        _ => {}
+    }
+}
+
+struct Writer {
+    floats: FloatFormat,
+    counts: HashMap<String, usize>,
+    anchors: HashMap<String, usize>,
+    out: String,
+}
+
+impl Writer {
+    // Write a value after a `key:` or `-` prefix already on the line.
+    fn value(&mut self, value: &Value, indent: usize) {
+        if let Some(key) = key(value).filter(|key| self.counts.get(key) > Some(&1)) {
+            if let Some(anchor) = self.anchors.get(&key) {
+                self.out.push_str(&format!(" *a{}\n", anchor));
+                return;
+            }
+            let anchor = self.anchors.len() + 1;
+            self.anchors.insert(key, anchor);
+            self.out.push_str(&format!(" &a{}\n", anchor));
+            self.block(value, indent);
+            return;
+        }
+
+        match value {
+            Value::Array(items) if !items.is_empty() => {
+                self.out.push('\n');
+                self.block(value, indent);
+            }
+            Value::Object(map) if !map.is_empty() => {
+                self.out.push('\n');
+                self.block(value, indent);
+            }
+            scalar => {
+                self.out.push(' ');
+                self.out.push_str(&self.scalar(scalar));
+                self.out.push('\n');
+            }
+        }
+    }
+
+    // Write the entries of a non-empty container, one per line.
+    fn block(&mut self, value: &Value, indent: usize) {
+        let pad = " ".repeat(indent);
+        match value {
+            Value::Array(items) => {
+                for item in items {
+                    self.out.push_str(&pad);
+                    self.out.push('-');
+                    self.value(item, indent + 2);
+                }
+            }
+            Value::Object(map) => {
+                for (key, item) in map {
+                    self.out.push_str(&pad);
+                    self.out.push_str(&quote(key));
+                    self.out.push(':');
+                    self.value(item, indent + 2);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn scalar(&self, value: &Value) -> String {
+        match value {
+            Value::Null => "null".to_string(),
+            Value::Bool(b) => b.to_string(),
+            Value::Number(n) => match n.as_f64() {
+                Some(f) if n.is_f64() => format_float(f, self.floats),
+                _ => n.to_string(),
+            },
+            Value::String(s) => quote(s),
+            Value::Array(_) => "[]".to_string(),
+            Value::Object(_) => "{}".to_string(),
+        }
+    }
+}
+
+// Write a string plainly when YAML would read it back as the same string, and
+// JSON-quoted (which YAML accepts) otherwise.
+fn quote(s: &str) -> String {
+    let plain = s
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '/' || c == '_')
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '/' | '-'))
+        && !matches!(
+            s.to_ascii_lowercase().as_str(),
+            "true" | "false" | "yes" | "no" | "on" | "off" | "y" | "n" | "null" | "nan" | "inf"
+        );
+    if plain {
+        s.to_string()
+    } else {
+        serde_json::to_string(s).unwrap_or_default()
+    }
+}
+
+// Write a value as a YAML document. Large sub-structures that repeat are
+// written once, with an anchor, and referred to by aliases afterwards.
+pub fn to_yaml(value: &Value, floats: FloatFormat) -> String {
+    let mut counts = HashMap::new();
+    count(value, &mut counts);
+    let mut writer = Writer {
+        floats,
+        counts,
+        anchors: HashMap::new(),
+        out: String::from("---"),
+    };
+    writer.value(value, 0);
+    writer.out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_block_layout() {
+        let value = json!({
+            "name": "a.service",
+            "tags": ["x", "true", ""],
+            "nested": {"empty": [], "pi": 3.5},
+        });
+        assert_eq!(
+            to_yaml(&value, FloatFormat::Shortest),
+            "---\nname: a.service\nnested:\n  empty: []\n  pi: 3.5\ntags:\n  - x\n  - \"true\"\n  - \"\"\n"
+        );
+        assert_eq!(to_yaml(&json!(1), FloatFormat::Shortest), "--- 1\n");
+    }
+
+    #[test]
+    fn test_repeated_values_use_aliases() {
+        let device = json!({"driver": "e1000e", "state": "activated", "mtu": 1500});
+        let value = json!([device, device, {"other": device}]);
+        let yaml = to_yaml(&value, FloatFormat::Shortest);
+        assert_eq!(
+            yaml,
+            "---\n- &a1\n  driver: e1000e\n  mtu: 1500\n  state: activated\n- *a1\n-\n  other: *a1\n"
+        );
+    }
+
+    #[test]
+    fn test_nested_repeats_share_one_anchor() {
+        let inner = json!({"path": "/org/freedesktop/NetworkManager/Devices/1"});
+        let outer = json!({"device": inner, "ip4": "/org/freedesktop/NetworkManager/IP4Config/1"});
+        let yaml = to_yaml(&json!([outer, outer]), FloatFormat::Shortest);
+        assert_eq!(yaml.matches('&').count(), 1, "Unexpected YAML:\n{}", yaml);
+    }
+}